@@ -0,0 +1,41 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use secure_notebook::{generate_profile, minify_profile, Permissions, DEFAULT_SANDBOX_PROFILE};
+use std::path::PathBuf;
+
+fn permissions_with_paths(count: usize) -> Permissions {
+    let paths = |prefix: &str| {
+        (0..count)
+            .map(|i| PathBuf::from(format!("/datasets/{prefix}/shard-{i:06}/part.parquet")))
+            .collect()
+    };
+
+    let mut permissions = Permissions::new();
+    permissions.allow_read = paths("read");
+    permissions.deny_read = paths("secret");
+    permissions.allow_write = paths("out");
+    permissions.allow_net = true;
+    permissions.allow_run = vec![PathBuf::from("/usr/bin/python3")];
+    permissions
+}
+
+fn bench_generate_profile(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_profile");
+    for count in [100, 10_000, 50_000] {
+        let permissions = permissions_with_paths(count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &permissions, |b, p| {
+            b.iter(|| generate_profile(black_box(DEFAULT_SANDBOX_PROFILE), black_box(p)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_minify_profile(c: &mut Criterion) {
+    let profile = generate_profile(DEFAULT_SANDBOX_PROFILE, &permissions_with_paths(10_000))
+        .expect("profile generation failed");
+    c.bench_function("minify_profile/10000", |b| {
+        b.iter(|| minify_profile(black_box(&profile)))
+    });
+}
+
+criterion_group!(benches, bench_generate_profile, bench_minify_profile);
+criterion_main!(benches);
@@ -0,0 +1,8 @@
+fn main() {
+    #[cfg(feature = "napi")]
+    napi_build::setup();
+
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/secure_notebook.proto")
+        .expect("Failed to compile proto/secure_notebook.proto");
+}
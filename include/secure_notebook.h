@@ -0,0 +1,43 @@
+/* C interface to secure_notebook. Build the crate with `--features capi`. */
+#ifndef SECURE_NOTEBOOK_H
+#define SECURE_NOTEBOOK_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct SnPermissions SnPermissions;
+
+/* Message of the last error on this thread, or NULL. Do not free. */
+const char *sn_last_error(void);
+void sn_string_free(char *string);
+
+SnPermissions *sn_permissions_new(void);
+void sn_permissions_free(SnPermissions *permissions);
+
+/* Each returns 0 on success and -1 on failure. */
+int sn_permissions_allow_read(SnPermissions *permissions, const char *path);
+int sn_permissions_deny_read(SnPermissions *permissions, const char *path);
+int sn_permissions_allow_write(SnPermissions *permissions, const char *path);
+int sn_permissions_deny_write(SnPermissions *permissions, const char *path);
+int sn_permissions_allow_run(SnPermissions *permissions, const char *path);
+int sn_permissions_deny_run(SnPermissions *permissions, const char *path);
+int sn_permissions_set_allow_net(SnPermissions *permissions, int allow);
+int sn_permissions_set_allow_gpu(SnPermissions *permissions, int allow);
+
+/* Profile for the permissions; template may be NULL for the default profile.
+ * Free with sn_string_free. Returns NULL on failure. */
+char *sn_generate_profile(const char *template_, const SnPermissions *permissions);
+
+/* Start program under profile; returns its pid or -1. Reap it with waitpid. */
+int64_t sn_spawn_sandboxed(const char *profile, const char *program,
+                           const char *const *argv, size_t argc);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SECURE_NOTEBOOK_H */
@@ -0,0 +1,291 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::manager::{SessionInfo, SessionManager};
+use crate::notebook::{CellResult, NotebookSession};
+use crate::policy::LayeredPolicy;
+use crate::violations::{Violation, ViolationMonitor};
+use crate::Permissions;
+
+/// Body of `POST /sessions`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateSession {
+    pub user: String,
+    /// Requested permissions, layered over the administrator's base policy.
+    #[serde(default)]
+    pub policy: Permissions,
+}
+
+/// Response to `POST /sessions`: the only place the session's token is returned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatedSession {
+    #[serde(flatten)]
+    pub session: SessionInfo,
+    pub token: String,
+}
+
+/// Body of `POST /sessions/{user}/execute`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecuteRequest {
+    pub code: String,
+}
+
+/// Body of error responses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+/// Shared state behind the API.
+pub struct ApiState {
+    manager: Mutex<SessionManager>,
+    base: Permissions,
+    kernels: Mutex<HashMap<String, Arc<Mutex<NotebookSession>>>>,
+    results: Mutex<HashMap<String, Vec<CellResult>>>,
+    monitor: Option<Mutex<ViolationMonitor>>,
+    violations: Mutex<Vec<Violation>>,
+}
+
+impl ApiState {
+    /// Serve the sessions of `manager`. Requested policies cannot override the denies
+    /// in `base`.
+    pub fn new(manager: SessionManager, base: Permissions) -> Self {
+        Self {
+            manager: Mutex::new(manager),
+            base,
+            kernels: Mutex::new(HashMap::new()),
+            results: Mutex::new(HashMap::new()),
+            monitor: None,
+            violations: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Report the denials `monitor` sees from `GET /violations`.
+    pub fn with_violations(mut self, monitor: ViolationMonitor) -> Self {
+        self.monitor = Some(Mutex::new(monitor));
+        self
+    }
+
+    /// Run code posted for `user` in the kernel `session` is connected to.
+    pub fn attach_kernel(&self, user: &str, session: NotebookSession) {
+        lock(&self.kernels).insert(user.to_string(), Arc::new(Mutex::new(session)));
+    }
+
+    /// Every session, ordered by user.
+    pub(crate) fn list(&self) -> Vec<SessionInfo> {
+        lock(&self.manager).list()
+    }
+
+    /// Start a session with `policy` layered over the base policy.
+    pub(crate) fn create(&self, user: &str, policy: Permissions) -> Result<CreatedSession> {
+        let permissions = LayeredPolicy::new(self.base.clone(), policy).merge();
+        let mut manager = lock(&self.manager);
+        let session = manager.start(user, &permissions)?;
+        let token = manager
+            .token(user)
+            .expect("session was just started")
+            .secret()
+            .to_string();
+        Ok(CreatedSession { session, token })
+    }
+
+    pub(crate) fn inspect(&self, user: &str) -> Result<SessionInfo> {
+        lock(&self.manager)
+            .inspect(user)
+            .ok_or_else(|| no_session(user))
+    }
+
+    pub(crate) fn terminate(&self, user: &str) -> Result<()> {
+        lock(&self.kernels).remove(user);
+        lock(&self.manager).terminate(user)
+    }
+
+    /// Run `code` in the kernel attached for `user`, recording the result.
+    pub(crate) fn execute(&self, user: &str, code: &str) -> Result<CellResult> {
+        let kernel = lock(&self.kernels)
+            .get(user)
+            .cloned()
+            .ok_or_else(|| no_session(user))?;
+        let result = lock(&kernel).run_cell(code)?;
+        lock(&self.results)
+            .entry(user.to_string())
+            .or_default()
+            .push(result.clone());
+        Ok(result)
+    }
+
+    pub(crate) fn results(&self, user: &str) -> Vec<CellResult> {
+        lock(&self.results).get(user).cloned().unwrap_or_default()
+    }
+
+    /// Sandbox denials seen so far.
+    pub(crate) fn violations(&self) -> Vec<Violation> {
+        let mut violations = lock(&self.violations);
+        if let Some(monitor) = &self.monitor {
+            let monitor = lock(monitor);
+            while let Some(violation) = monitor.try_next() {
+                violations.push(violation);
+            }
+        }
+        violations.clone()
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().expect("API state poisoned")
+}
+
+/// A [`SecureNotebookError`] as an HTTP response.
+#[derive(Debug)]
+pub struct ApiError(pub SecureNotebookError);
+
+impl From<SecureNotebookError> for ApiError {
+    fn from(error: SecureNotebookError) -> Self {
+        Self(error)
+    }
+}
+
+impl ApiError {
+    fn status(&self) -> StatusCode {
+        match &self.0 {
+            SecureNotebookError::InvalidPath { .. } | SecureNotebookError::InvalidPolicy(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            SecureNotebookError::InvalidState(_) => StatusCode::CONFLICT,
+            SecureNotebookError::KernelTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            SecureNotebookError::Unsupported(_) => StatusCode::NOT_IMPLEMENTED,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            error: self.0.to_string(),
+        };
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+type ApiResult<T> = std::result::Result<T, ApiError>;
+
+/// Routes of the management API.
+///
+/// | Method | Path | |
+/// |---|---|---|
+/// | `GET` | `/sessions` | list sessions |
+/// | `POST` | `/sessions` | start a session with a policy, returning its token |
+/// | `GET` | `/sessions/{user}` | inspect a session |
+/// | `DELETE` | `/sessions/{user}` | terminate a session |
+/// | `POST` | `/sessions/{user}/execute` | run code in the session's kernel |
+/// | `GET` | `/sessions/{user}/results` | results of the code run so far |
+/// | `GET` | `/violations` | sandbox denials seen so far |
+///
+/// The API does no authentication of its own; bind it to localhost or put it behind an
+/// authenticating proxy.
+pub fn router(state: Arc<ApiState>) -> Router {
+    Router::new()
+        .route("/sessions", get(list_sessions).post(create_session))
+        .route(
+            "/sessions/:user",
+            get(inspect_session).delete(terminate_session),
+        )
+        .route("/sessions/:user/execute", post(execute))
+        .route("/sessions/:user/results", get(results))
+        .route("/violations", get(violations))
+        .with_state(state)
+}
+
+/// Serve the API on `addr` until the server fails.
+pub async fn serve(state: Arc<ApiState>, addr: SocketAddr) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .io_context(|| format!("Failed to bind {addr}"))?;
+    axum::serve(listener, router(state))
+        .await
+        .io_context(|| "API server failed")
+}
+
+/// Run blocking crate calls off the async runtime.
+async fn blocking<T: Send + 'static>(
+    task: impl FnOnce() -> Result<T> + Send + 'static,
+) -> ApiResult<T> {
+    tokio::task::spawn_blocking(task)
+        .await
+        .map_err(|e| SecureNotebookError::InvalidState(format!("Task failed: {e}")))?
+        .map_err(ApiError)
+}
+
+async fn list_sessions(State(state): State<Arc<ApiState>>) -> Json<Vec<SessionInfo>> {
+    Json(state.list())
+}
+
+async fn create_session(
+    State(state): State<Arc<ApiState>>,
+    Json(request): Json<CreateSession>,
+) -> ApiResult<(StatusCode, Json<CreatedSession>)> {
+    let created = blocking(move || state.create(&request.user, request.policy)).await?;
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+async fn inspect_session(
+    State(state): State<Arc<ApiState>>,
+    Path(user): Path<String>,
+) -> ApiResult<Json<SessionInfo>> {
+    Ok(Json(state.inspect(&user)?))
+}
+
+async fn terminate_session(
+    State(state): State<Arc<ApiState>>,
+    Path(user): Path<String>,
+) -> ApiResult<StatusCode> {
+    blocking(move || state.terminate(&user)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn execute(
+    State(state): State<Arc<ApiState>>,
+    Path(user): Path<String>,
+    Json(request): Json<ExecuteRequest>,
+) -> ApiResult<Json<CellResult>> {
+    let result = blocking(move || state.execute(&user, &request.code)).await?;
+    Ok(Json(result))
+}
+
+async fn results(
+    State(state): State<Arc<ApiState>>,
+    Path(user): Path<String>,
+) -> Json<Vec<CellResult>> {
+    Json(state.results(&user))
+}
+
+async fn violations(State(state): State<Arc<ApiState>>) -> Json<Vec<Violation>> {
+    Json(state.violations())
+}
+
+fn no_session(user: &str) -> SecureNotebookError {
+    SecureNotebookError::InvalidState(format!("No session or kernel for {user}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_error_status() {
+        let error = ApiError(SecureNotebookError::InvalidPolicy("bad".to_string()));
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+        let error = ApiError(SecureNotebookError::KernelTimeout(Duration::from_secs(1)));
+        assert_eq!(error.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::extension::{
+    generate_extension_permissions, issue_file_extension, READ_EXTENSION_CLASS,
+    READ_WRITE_EXTENSION_CLASS,
+};
+use crate::grants::{Grant, PermissionDelta};
+use crate::session::{JupyterSession, SessionConfig};
+use crate::violations::Violation;
+use crate::{generate_profile, Permissions};
+
+/// Operator decision for a requested grant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Grant,
+    Deny,
+}
+
+/// Where a grant request came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    /// The sandbox denied an operation.
+    Violation(Violation),
+    /// Notebook code asked for the permission explicitly.
+    Kernel,
+    /// A cell ran `%request_permission` with these arguments, e.g. `net api.github.com`.
+    Magic(String),
+}
+
+/// Decides whether a requested grant should be applied.
+pub trait Approver {
+    fn decide(&mut self, grant: &Grant, origin: &Origin) -> Decision;
+}
+
+impl<F> Approver for F
+where
+    F: FnMut(&Grant, &Origin) -> Decision,
+{
+    fn decide(&mut self, grant: &Grant, origin: &Origin) -> Decision {
+        self(grant, origin)
+    }
+}
+
+/// Asks the operator on the terminal.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TerminalPrompt;
+
+impl Approver for TerminalPrompt {
+    fn decide(&mut self, grant: &Grant, origin: &Origin) -> Decision {
+        match origin {
+            Origin::Violation(violation) => print!(
+                "{}({}) was denied {} {}. Grant {}? [y/N] ",
+                violation.process,
+                violation.pid,
+                violation.operation,
+                violation.target.as_deref().unwrap_or(""),
+                describe(grant)
+            ),
+            Origin::Kernel => print!("The kernel requests {}. Grant it? [y/N] ", describe(grant)),
+            Origin::Magic(line) => print!(
+                "A cell ran %request_permission {line}. Grant {}? [y/N] ",
+                describe(grant)
+            ),
+        }
+        let _ = std::io::stdout().flush();
+
+        let mut answer = String::new();
+        if std::io::stdin().lock().read_line(&mut answer).is_err() {
+            return Decision::Deny;
+        }
+        match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => Decision::Grant,
+            _ => Decision::Deny,
+        }
+    }
+}
+
+/// `grant` as the operator is asked about it.
+///
+/// Network grants are not scoped to hosts, so a request naming one, like
+/// `%request_permission net api.github.com`, is spelled out as granting all of them.
+fn describe(grant: &Grant) -> String {
+    match grant {
+        Grant::Net => "network access to all hosts".to_string(),
+        grant => format!("{grant:?}"),
+    }
+}
+
+/// How an approved grant is applied to the running session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrantStrategy {
+    /// Regenerate the profile and restart the server.
+    Restart,
+    /// Issue an extension token for the kernel to consume, without restarting.
+    /// Network and exec grants still fall back to a restart.
+    ExtensionToken,
+}
+
+/// A grant that was approved by the operator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApprovedGrant {
+    pub grant: Grant,
+    /// Extension token to hand to the kernel, when applied via [`GrantStrategy::ExtensionToken`].
+    pub token: Option<String>,
+}
+
+/// How [`ApprovalSession::apply_policy`] brought the session up to date.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyUpdate {
+    /// The new policy is equivalent to the current one.
+    Unchanged,
+    /// Every change was a file grant, applied through extension tokens the kernel has to
+    /// consume. Kernel state is kept.
+    Extended(Vec<ApprovedGrant>),
+    /// The server was shut down and restarted under the new profile.
+    Restarted,
+}
+
+/// A session that asks an operator about violations not covered by the policy.
+pub struct ApprovalSession<A: Approver> {
+    session: JupyterSession,
+    template: String,
+    permissions: Permissions,
+    approver: A,
+    strategy: GrantStrategy,
+    asked: HashSet<Grant>,
+}
+
+impl<A: Approver> ApprovalSession<A> {
+    /// Start the server under the given permissions.
+    pub fn start(
+        template: &str,
+        permissions: Permissions,
+        approver: A,
+        strategy: GrantStrategy,
+        config: SessionConfig,
+    ) -> Result<Self> {
+        let mut template = template.to_string();
+        if strategy == GrantStrategy::ExtensionToken {
+            template.push_str(&generate_extension_permissions());
+        }
+        let profile = generate_profile(&template, &permissions)?;
+        let session = JupyterSession::spawn(&profile, config)?;
+
+        Ok(Self {
+            session,
+            template,
+            permissions,
+            approver,
+            strategy,
+            asked: HashSet::new(),
+        })
+    }
+
+    /// Underlying Jupyter session.
+    pub fn session(&self) -> &JupyterSession {
+        &self.session
+    }
+
+    /// Permissions including every approved grant.
+    pub fn permissions(&self) -> &Permissions {
+        &self.permissions
+    }
+
+    /// Ask the operator about a violation and apply the grant if approved.
+    ///
+    /// Violations already covered by the policy (explicitly denied or already allowed)
+    /// and grants the operator was already asked about are ignored.
+    pub fn handle(&mut self, violation: &Violation) -> Result<Option<ApprovedGrant>> {
+        let Some(grant) = violation.to_grant() else {
+            return Ok(None);
+        };
+        if is_covered(&self.permissions, &grant) || !self.asked.insert(grant.clone()) {
+            return Ok(None);
+        }
+
+        self.request_grant(grant, &Origin::Violation(violation.clone()))
+    }
+
+    /// Ask the operator about a grant and apply it to the running session if approved.
+    pub fn request_grant(
+        &mut self,
+        grant: Grant,
+        origin: &Origin,
+    ) -> Result<Option<ApprovedGrant>> {
+        if self.approver.decide(&grant, origin) == Decision::Deny {
+            return Ok(None);
+        }
+
+        grant.apply(&mut self.permissions);
+        let token = match self.strategy {
+            GrantStrategy::ExtensionToken => extension_token(&grant)?,
+            GrantStrategy::Restart => None,
+        };
+        if token.is_none() {
+            let profile = generate_profile(&self.template, &self.permissions)?;
+            self.session.restart(&profile)?;
+        }
+
+        Ok(Some(ApprovedGrant { grant, token }))
+    }
+
+    /// Switch the running session to `permissions`.
+    ///
+    /// With [`GrantStrategy::ExtensionToken`], a policy that only adds read or write paths
+    /// is applied by issuing a token per path, so running kernels keep their state.
+    /// Anything else (removals, new denies, network or exec changes) needs a new profile,
+    /// and the server is shut down gracefully and restarted under it.
+    pub fn apply_policy(&mut self, permissions: Permissions) -> Result<PolicyUpdate> {
+        let delta = PermissionDelta::between(&self.permissions, &permissions);
+        if delta.is_empty() {
+            return Ok(PolicyUpdate::Unchanged);
+        }
+
+        let extendable = self.strategy == GrantStrategy::ExtensionToken
+            && delta.is_addition_only()
+            && delta
+                .added
+                .iter()
+                .all(|grant| matches!(grant, Grant::Read(_) | Grant::Write(_)));
+        let update = if extendable {
+            let mut approved = Vec::with_capacity(delta.added.len());
+            for grant in delta.added {
+                let token = extension_token(&grant)?;
+                approved.push(ApprovedGrant { grant, token });
+            }
+            PolicyUpdate::Extended(approved)
+        } else {
+            let profile = generate_profile(&self.template, &permissions)?;
+            self.session.graceful_restart(&profile)?;
+            PolicyUpdate::Restarted
+        };
+
+        // Grants that were taken away may be asked about again.
+        for grant in &delta.removed {
+            self.asked.remove(grant);
+        }
+        self.permissions = permissions;
+        Ok(update)
+    }
+}
+
+/// Extension token covering a file grant, `None` for grants tokens cannot carry.
+fn extension_token(grant: &Grant) -> Result<Option<String>> {
+    match grant {
+        Grant::Read(path) => issue_file_extension(READ_EXTENSION_CLASS, path).map(Some),
+        Grant::Write(path) => issue_file_extension(READ_WRITE_EXTENSION_CLASS, path).map(Some),
+        Grant::Net | Grant::Gpu | Grant::Run(_) => Ok(None),
+    }
+}
+
+/// Whether the policy already says something about the grant.
+pub(crate) fn is_covered(permissions: &Permissions, grant: &Grant) -> bool {
+    is_allowed(permissions, grant) || is_denied(permissions, grant)
+}
+
+/// Whether the policy already allows the grant.
+pub(crate) fn is_allowed(permissions: &Permissions, grant: &Grant) -> bool {
+    match grant {
+        Grant::Read(path) => is_under(path, &permissions.allow_read),
+        Grant::Write(path) => is_under(path, &permissions.allow_write),
+        Grant::Net => permissions.allow_net,
+        Grant::Gpu => permissions.allow_gpu,
+        Grant::Run(program) => permissions.allow_run.contains(program),
+    }
+}
+
+/// Whether the policy explicitly denies the grant.
+pub(crate) fn is_denied(permissions: &Permissions, grant: &Grant) -> bool {
+    match grant {
+        Grant::Read(path) => is_under(path, &permissions.deny_read),
+        Grant::Write(path) => is_under(path, &permissions.deny_write),
+        Grant::Net | Grant::Gpu => false,
+        Grant::Run(program) => permissions.deny_run.contains(program),
+    }
+}
+
+fn is_under(path: &Path, roots: &[PathBuf]) -> bool {
+    roots.iter().any(|root| path.starts_with(root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_covered() {
+        let mut permissions = Permissions::new();
+        permissions.allow_read.push(PathBuf::from("/data"));
+        permissions.deny_write.push(PathBuf::from("/data/raw"));
+
+        assert!(is_covered(
+            &permissions,
+            &Grant::Read(PathBuf::from("/data/x.csv"))
+        ));
+        assert!(is_covered(
+            &permissions,
+            &Grant::Write(PathBuf::from("/data/raw/x.csv"))
+        ));
+        assert!(!is_covered(
+            &permissions,
+            &Grant::Write(PathBuf::from("/data/out.csv"))
+        ));
+        assert!(!is_covered(&permissions, &Grant::Net));
+    }
+
+    #[test]
+    fn test_describe_net_grant() {
+        assert_eq!(describe(&Grant::Net), "network access to all hosts");
+        assert_eq!(
+            describe(&Grant::Read(PathBuf::from("/data"))),
+            "Read(\"/data\")"
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::violations::Violation;
+use crate::Permissions;
+
+/// Size and modification time of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FileState {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+/// State of every file under some directories, for finding what a run changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileSnapshot {
+    files: BTreeMap<PathBuf, FileState>,
+}
+
+impl FileSnapshot {
+    /// Walk `roots`, skipping anything that cannot be read.
+    pub fn capture(roots: &[PathBuf]) -> Self {
+        let mut snapshot = Self::default();
+        for root in roots {
+            snapshot.walk(root);
+        }
+        snapshot
+    }
+
+    fn walk(&mut self, path: &Path) {
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            return;
+        };
+        if metadata.is_dir() {
+            for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+                self.walk(&entry.path());
+            }
+        } else {
+            let state = FileState {
+                len: metadata.len(),
+                modified: metadata.modified().ok(),
+            };
+            self.files.insert(path.to_path_buf(), state);
+        }
+    }
+
+    /// Files created, modified or deleted between `self` and `after`, ordered by path.
+    pub fn changes(&self, after: &FileSnapshot) -> Vec<FileChange> {
+        let mut changes = Vec::new();
+        for (path, state) in &after.files {
+            let kind = match self.files.get(path) {
+                None => ChangeKind::Created,
+                Some(before) if before != state => ChangeKind::Modified,
+                Some(_) => continue,
+            };
+            changes.push(FileChange {
+                path: path.clone(),
+                kind,
+            });
+        }
+        for path in self.files.keys() {
+            if !after.files.contains_key(path) {
+                changes.push(FileChange {
+                    path: path.clone(),
+                    kind: ChangeKind::Deleted,
+                });
+            }
+        }
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        changes
+    }
+}
+
+/// How a file changed during a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChange {
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+}
+
+/// What a sandboxed run was allowed to do and what it did.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub session: String,
+    pub started_at: SystemTime,
+    pub finished_at: Option<SystemTime>,
+    /// [`Permissions::fingerprint_hex`] of `permissions`.
+    pub fingerprint: String,
+    /// The effective policy.
+    pub permissions: Permissions,
+    pub profile: String,
+    pub violations: Vec<Violation>,
+    /// Changes under the writable paths.
+    pub file_changes: Vec<FileChange>,
+}
+
+impl fmt::Display for AuditRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = |time: SystemTime| {
+            time.duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs())
+        };
+        writeln!(f, "session {}", self.session)?;
+        writeln!(f, "started  {} (unix time)", time(self.started_at))?;
+        match self.finished_at {
+            Some(finished_at) => writeln!(f, "finished {} (unix time)", time(finished_at))?,
+            None => writeln!(f, "finished -")?,
+        }
+        writeln!(f, "policy   {}", self.fingerprint)?;
+
+        let fields = [
+            ("allow_read", &self.permissions.allow_read),
+            ("deny_read", &self.permissions.deny_read),
+            ("allow_write", &self.permissions.allow_write),
+            ("deny_write", &self.permissions.deny_write),
+            ("allow_run", &self.permissions.allow_run),
+            ("deny_run", &self.permissions.deny_run),
+        ];
+        for (field, paths) in fields {
+            for path in paths {
+                writeln!(f, "  {field} {}", path.display())?;
+            }
+        }
+        writeln!(f, "  allow_net {}", self.permissions.allow_net)?;
+        writeln!(f, "  allow_gpu {}", self.permissions.allow_gpu)?;
+
+        writeln!(f, "violations ({})", self.violations.len())?;
+        for violation in &self.violations {
+            write!(
+                f,
+                "  {}({}) {}",
+                violation.process, violation.pid, violation.operation
+            )?;
+            match &violation.target {
+                Some(target) => writeln!(f, " {target}")?,
+                None => writeln!(f)?,
+            }
+        }
+        writeln!(f, "file changes ({})", self.file_changes.len())?;
+        for change in &self.file_changes {
+            let kind = match change.kind {
+                ChangeKind::Created => "created ",
+                ChangeKind::Modified => "modified",
+                ChangeKind::Deleted => "deleted ",
+            };
+            writeln!(f, "  {kind} {}", change.path.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// An audit record being collected while a run is in progress.
+#[derive(Debug)]
+pub struct AuditRun {
+    record: AuditRecord,
+    roots: Vec<PathBuf>,
+    before: FileSnapshot,
+}
+
+impl AuditRun {
+    /// Start auditing `session`, snapshotting the writable paths of `permissions`.
+    pub fn start(session: &str, profile: &str, permissions: &Permissions) -> Self {
+        let roots = permissions.allow_write.clone();
+        Self {
+            record: AuditRecord {
+                session: session.to_string(),
+                started_at: SystemTime::now(),
+                finished_at: None,
+                fingerprint: permissions.fingerprint_hex(),
+                permissions: permissions.clone(),
+                profile: profile.to_string(),
+                violations: Vec::new(),
+                file_changes: Vec::new(),
+            },
+            before: FileSnapshot::capture(&roots),
+            roots,
+        }
+    }
+
+    pub fn record_violation(&mut self, violation: Violation) {
+        self.record.violations.push(violation);
+    }
+
+    /// Finish the record with the files changed since [`AuditRun::start`].
+    pub fn finish(mut self) -> AuditRecord {
+        let after = FileSnapshot::capture(&self.roots);
+        self.record.file_changes = self.before.changes(&after);
+        self.record.finished_at = Some(SystemTime::now());
+        self.record
+    }
+}
+
+/// Directory of audit records, one JSON file per session.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    dir: PathBuf,
+}
+
+impl AuditLog {
+    /// Keep records in `dir`, creating it if needed.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).io_context(|| format!("Failed to create {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    /// `~/Library/Logs/secure_notebook/audit`.
+    pub fn default_dir() -> Option<PathBuf> {
+        std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join("Library/Logs/secure_notebook/audit"))
+    }
+
+    fn path(&self, session: &str) -> Result<PathBuf> {
+        if session.is_empty() || session.contains(['/', '\0']) || session.starts_with('.') {
+            return Err(SecureNotebookError::InvalidPath {
+                path: PathBuf::from(session),
+                reason: "session ids must be plain file names".to_string(),
+            });
+        }
+        Ok(self.dir.join(format!("{session}.json")))
+    }
+
+    /// Save `record`, replacing any earlier record of the same session.
+    pub fn save(&self, record: &AuditRecord) -> Result<()> {
+        let path = self.path(&record.session)?;
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, serde_json::to_vec_pretty(record)?)
+            .io_context(|| format!("Failed to write {}", partial.display()))?;
+        fs::rename(&partial, &path).io_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Record of `session`.
+    pub fn load(&self, session: &str) -> Result<AuditRecord> {
+        let path = self.path(session)?;
+        match fs::read(&path) {
+            Ok(contents) => Ok(serde_json::from_slice(&contents)?),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Err(
+                SecureNotebookError::InvalidState(format!("No audit record for {session}")),
+            ),
+            Err(error) => Err(error).io_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Sessions with a record, sorted.
+    pub fn sessions(&self) -> Result<Vec<String>> {
+        let entries = fs::read_dir(&self.dir)
+            .io_context(|| format!("Failed to read {}", self.dir.display()))?;
+        let mut sessions: Vec<String> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                name.strip_suffix(".json").map(str::to_string)
+            })
+            .collect();
+        sessions.sort();
+        Ok(sessions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_audit_run_reports_file_changes() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let work = dir.path().join("work");
+        fs::create_dir_all(&work)?;
+        fs::write(work.join("kept"), "a")?;
+        fs::write(work.join("removed"), "a")?;
+
+        let permissions = Permissions {
+            allow_write: vec![work.clone()],
+            ..Permissions::default()
+        };
+        let run = AuditRun::start("run-1", "(version 1)\n", &permissions);
+        fs::write(work.join("created"), "b")?;
+        fs::remove_file(work.join("removed"))?;
+        let record = run.finish();
+
+        assert_eq!(
+            record.file_changes,
+            [
+                FileChange {
+                    path: work.join("created"),
+                    kind: ChangeKind::Created
+                },
+                FileChange {
+                    path: work.join("removed"),
+                    kind: ChangeKind::Deleted
+                },
+            ]
+        );
+        assert!(record.to_string().contains("deleted  "));
+
+        let log = AuditLog::open(dir.path().join("audit"))?;
+        log.save(&record)?;
+        assert_eq!(log.sessions()?, ["run-1"]);
+        assert!(log.load("../run-1").is_err());
+        Ok(())
+    }
+}
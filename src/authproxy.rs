@@ -0,0 +1,341 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{self, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use crate::error::{IoContext, Result};
+use crate::netguard::{read_head, respond, tunnel, EgressLimiter, EgressLimits};
+
+/// Cookie carrying the client's token, for browsers that cannot set headers.
+pub const TOKEN_COOKIE: &str = "secure_notebook_token";
+
+/// Decides whether a client credential is valid.
+pub trait Authenticator: Send + Sync {
+    /// The subject `credential` belongs to, or `None` to reject it.
+    fn authenticate(&self, credential: &str) -> Option<String>;
+}
+
+/// A fixed set of tokens, each belonging to a subject.
+#[derive(Debug, Clone, Default)]
+pub struct StaticTokens {
+    tokens: HashMap<String, String>,
+}
+
+impl StaticTokens {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept `token` as `subject`.
+    pub fn with_token(mut self, token: &str, subject: &str) -> Self {
+        self.tokens.insert(token.to_string(), subject.to_string());
+        self
+    }
+}
+
+impl Authenticator for StaticTokens {
+    fn authenticate(&self, credential: &str) -> Option<String> {
+        self.tokens.get(credential).cloned()
+    }
+}
+
+/// OIDC access tokens checked against the provider's introspection endpoint (RFC 7662).
+#[derive(Debug, Clone)]
+pub struct OidcIntrospection {
+    pub url: String,
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+#[derive(Deserialize)]
+struct Introspection {
+    active: bool,
+    sub: Option<String>,
+}
+
+impl Authenticator for OidcIntrospection {
+    fn authenticate(&self, credential: &str) -> Option<String> {
+        let response = ureq::post(&self.url)
+            .send_form(&[
+                ("token", credential),
+                ("client_id", &self.client_id),
+                ("client_secret", &self.client_secret),
+            ])
+            .ok()?;
+        let introspection: Introspection =
+            serde_json::from_str(&response.into_string().ok()?).ok()?;
+        introspection
+            .active
+            .then(|| introspection.sub.unwrap_or_default())
+    }
+}
+
+/// Reverse proxy authenticating clients in front of a server bound to localhost.
+///
+/// HTTP requests and WebSocket upgrades are forwarded once the client presents a valid
+/// credential: an `Authorization: Bearer`/`token` header, a `token` query parameter or
+/// the [`TOKEN_COOKIE`] cookie. The server's own token is added on the way in, so it
+/// never leaves the host and the profile needs no inbound rule beyond loopback.
+pub struct AuthProxy {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl std::fmt::Debug for AuthProxy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthProxy")
+            .field("addr", &self.addr)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AuthProxy {
+    /// Listen on `bind` and forward authenticated requests to `upstream`.
+    ///
+    /// `upstream_token` is the Jupyter server's token, sent with every forwarded request.
+    pub fn start(
+        bind: SocketAddr,
+        upstream: SocketAddr,
+        upstream_token: Option<String>,
+        authenticator: Arc<dyn Authenticator>,
+    ) -> Result<Self> {
+        let listener =
+            TcpListener::bind(bind).io_context(|| format!("Failed to bind the proxy to {bind}"))?;
+        let addr = listener
+            .local_addr()
+            .io_context(|| "Failed to get the proxy address")?;
+        let upstream_token = Arc::new(upstream_token);
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let Ok(stream) = stream else { continue };
+                    let authenticator = Arc::clone(&authenticator);
+                    let upstream_token = Arc::clone(&upstream_token);
+                    std::thread::spawn(move || {
+                        let _ = handle(
+                            stream,
+                            upstream,
+                            upstream_token.as_deref(),
+                            authenticator.as_ref(),
+                        );
+                    });
+                }
+            })
+        };
+
+        Ok(Self {
+            addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Address the proxy listens on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stop accepting connections. Open connections run until their peers close them.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            // wake up the accept loop
+            let _ = TcpStream::connect(self.addr);
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for AuthProxy {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn handle(
+    client: TcpStream,
+    upstream: SocketAddr,
+    upstream_token: Option<&str>,
+    authenticator: &dyn Authenticator,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(client.try_clone()?);
+    let head = read_head(&mut reader)?;
+    let mut client = client;
+
+    let authenticated = credential(&head)
+        .and_then(|credential| authenticator.authenticate(&credential))
+        .is_some();
+    if !authenticated {
+        return respond(&mut client, "401 Unauthorized");
+    }
+
+    let mut upstream = match TcpStream::connect(upstream) {
+        Ok(upstream) => upstream,
+        Err(_) => return respond(&mut client, "502 Bad Gateway"),
+    };
+    upstream.write_all(forwarded_head(&head, upstream_token).as_bytes())?;
+    // bytes the client sent after the head are still buffered in the reader
+    upstream.write_all(reader.buffer())?;
+
+    let unlimited = Arc::new(EgressLimiter::new(EgressLimits::default()));
+    tunnel(reader.into_inner(), client, upstream, unlimited)
+}
+
+/// The client's credential, from the `Authorization` header, query or cookie.
+fn credential(head: &str) -> Option<String> {
+    let mut lines = head.lines();
+    let target = lines.next()?.split_whitespace().nth(1)?;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.trim().eq_ignore_ascii_case("authorization") {
+            let (scheme, token) = value.split_once(' ')?;
+            if scheme.eq_ignore_ascii_case("bearer") || scheme.eq_ignore_ascii_case("token") {
+                return Some(token.trim().to_string());
+            }
+        } else if name.trim().eq_ignore_ascii_case("cookie") {
+            let cookie = value.split(';').find_map(|cookie| {
+                let (name, value) = cookie.trim().split_once('=')?;
+                (name == TOKEN_COOKIE).then(|| value.to_string())
+            });
+            if cookie.is_some() {
+                return cookie;
+            }
+        }
+    }
+    let (_, query) = target.split_once('?')?;
+    query.split('&').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        (name == "token").then(|| value.to_string())
+    })
+}
+
+/// The request head with the client's credentials replaced by the server's token.
+///
+/// Plain requests get `Connection: close`, so every request on a connection is
+/// authenticated; WebSocket upgrades keep their headers.
+fn forwarded_head(head: &str, upstream_token: Option<&str>) -> String {
+    let mut lines = head.lines();
+    let request_line = lines.next().unwrap_or_default();
+    let is_upgrade = head.lines().skip(1).any(|line| {
+        line.split(':')
+            .next()
+            .is_some_and(|name| name.trim().eq_ignore_ascii_case("upgrade"))
+    });
+
+    let mut forwarded = strip_token_param(request_line);
+    forwarded.push_str("\r\n");
+    for line in lines {
+        let name = line.split(':').next().unwrap_or_default().trim();
+        if line.is_empty()
+            || name.eq_ignore_ascii_case("authorization")
+            || name.eq_ignore_ascii_case("cookie")
+            || (!is_upgrade && name.eq_ignore_ascii_case("connection"))
+        {
+            continue;
+        }
+        forwarded.push_str(line);
+        forwarded.push_str("\r\n");
+    }
+    if let Some(token) = upstream_token {
+        forwarded.push_str(&format!("Authorization: token {token}\r\n"));
+    }
+    if !is_upgrade {
+        forwarded.push_str("Connection: close\r\n");
+    }
+    forwarded.push_str("\r\n");
+    forwarded
+}
+
+/// Remove the client's `token` parameter, so Jupyter does not mistake it for its own.
+fn strip_token_param(request_line: &str) -> String {
+    let mut parts = request_line.splitn(3, ' ');
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return request_line.to_string();
+    };
+    let Some((path, query)) = target.split_once('?') else {
+        return request_line.to_string();
+    };
+    let query: Vec<&str> = query
+        .split('&')
+        .filter(|pair| pair.split('=').next() != Some("token"))
+        .collect();
+    if query.is_empty() {
+        format!("{method} {path} {version}")
+    } else {
+        format!("{method} {path}?{} {version}", query.join("&"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, Read};
+    use std::net::Shutdown;
+
+    #[test]
+    fn test_credential_sources() {
+        let head = "GET /api HTTP/1.1\r\nAuthorization: Bearer abc\r\n\r\n";
+        assert_eq!(credential(head).as_deref(), Some("abc"));
+        let head = "GET /api/kernels/1/channels?token=xyz HTTP/1.1\r\n\r\n";
+        assert_eq!(credential(head).as_deref(), Some("xyz"));
+        let head = format!("GET / HTTP/1.1\r\nCookie: a=b; {TOKEN_COOKIE}=c\r\n\r\n");
+        assert_eq!(credential(&head).as_deref(), Some("c"));
+        assert_eq!(credential("GET / HTTP/1.1\r\n\r\n"), None);
+    }
+
+    #[test]
+    fn test_proxy_requires_credential() -> Result<()> {
+        let upstream = TcpListener::bind("127.0.0.1:0")?;
+        let upstream_addr = upstream.local_addr()?;
+        std::thread::spawn(move || {
+            if let Ok((stream, _)) = upstream.accept() {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut head = String::new();
+                while reader.read_line(&mut head).unwrap_or(0) > 2 {}
+                let mut stream = stream;
+                let _ = write!(stream, "HTTP/1.1 200 OK\r\n\r\n{head}");
+            }
+        });
+
+        let authenticator = Arc::new(StaticTokens::new().with_token("secret", "alice"));
+        let proxy = AuthProxy::start(
+            "127.0.0.1:0".parse().unwrap(),
+            upstream_addr,
+            Some("jupyter".to_string()),
+            authenticator,
+        )?;
+        let request = |head: &str| -> io::Result<String> {
+            let mut stream = TcpStream::connect(proxy.addr())?;
+            stream.write_all(head.as_bytes())?;
+            stream.shutdown(Shutdown::Write)?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            Ok(response)
+        };
+
+        assert!(request("GET /api HTTP/1.1\r\n\r\n")?.starts_with("HTTP/1.1 401"));
+        let response = request("GET /api?token=secret HTTP/1.1\r\n\r\n")?;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("GET /api HTTP/1.1\r\n"));
+        assert!(response.contains("Authorization: token jupyter\r\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use crate::diagnostics::parse_launch_error;
+use crate::error::{Result, SecureNotebookError};
+use crate::probe::run_with_timeout;
+use crate::session::sandboxed_command;
+
+/// Where `sandbox-exec` is installed on macOS.
+pub const SANDBOX_EXEC_PATH: &str = "/usr/bin/sandbox-exec";
+
+/// Mechanism used to apply a profile to a new process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// Run the program under `sandbox-exec -p`. Deprecated by Apple but still shipped.
+    SandboxExec,
+    /// Call `sandbox_init` in the child between `fork` and `exec`.
+    SandboxInit,
+}
+
+/// Program run by [`validate_on_host`], which does nothing and exits successfully.
+pub const NOOP_PROGRAM: &str = "/usr/bin/true";
+
+/// How long [`validate_on_host`] waits for the no-op program.
+pub const VALIDATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Backends tried in order when none are configured.
+pub const DEFAULT_BACKENDS: &[Backend] = &[Backend::SandboxExec, Backend::SandboxInit];
+
+impl Backend {
+    /// Whether the backend can be used on this machine.
+    pub fn is_available(self) -> bool {
+        match self {
+            Backend::SandboxExec => Path::new(SANDBOX_EXEC_PATH).is_file(),
+            Backend::SandboxInit => cfg!(target_os = "macos"),
+        }
+    }
+}
+
+/// Backends of `preferred` that can be used on this machine, in order.
+pub fn detect_backends(preferred: &[Backend]) -> Vec<Backend> {
+    preferred
+        .iter()
+        .copied()
+        .filter(|backend| backend.is_available())
+        .collect()
+}
+
+/// Build a command running `program` under `profile` with the first available backend.
+///
+/// Fails with guidance on what to install or configure if none of `preferred` works here.
+pub fn sandboxed_command_with(
+    profile: &str,
+    program: &Path,
+    preferred: &[Backend],
+) -> Result<Command> {
+    match detect_backends(preferred).first() {
+        Some(Backend::SandboxExec) => Ok(sandboxed_command(profile, program)),
+        Some(Backend::SandboxInit) => sandbox_init_command(profile, program),
+        None => Err(SecureNotebookError::Unsupported(format!(
+            "No sandbox backend available (tried {preferred:?}). Notebooks can only be \
+             sandboxed on macOS; check that {SANDBOX_EXEC_PATH} exists or enable \
+             Backend::SandboxInit."
+        ))),
+    }
+}
+
+/// Apply `profile` to a no-op program, so compile and apply errors surface before a real
+/// launch.
+///
+/// Errors are the same structured errors a failed Jupyter launch produces.
+pub fn validate_on_host(profile: &str) -> Result<()> {
+    validate_on_host_with(profile, DEFAULT_BACKENDS)
+}
+
+/// [`validate_on_host`] with the first available backend of `preferred`.
+pub fn validate_on_host_with(profile: &str, preferred: &[Backend]) -> Result<()> {
+    let mut command = sandboxed_command_with(profile, Path::new(NOOP_PROGRAM), preferred)?;
+    match run_with_timeout(&mut command, VALIDATE_TIMEOUT)? {
+        (true, _) => Ok(()),
+        (false, stderr) => Err(parse_launch_error(&stderr, profile, None)),
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod ffi {
+    use std::ffi::c_char;
+
+    extern "C" {
+        pub fn sandbox_init(profile: *const c_char, flags: u64, errorbuf: *mut *mut c_char) -> i32;
+        pub fn sandbox_free_error(errorbuf: *mut c_char);
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn sandbox_init_command(profile: &str, program: &Path) -> Result<Command> {
+    use std::ffi::CString;
+    use std::os::unix::process::CommandExt;
+
+    let profile = CString::new(crate::minify_profile(profile)).map_err(|_| {
+        SecureNotebookError::InvalidPolicy("Profile contains a NUL byte".to_string())
+    })?;
+
+    let mut command = Command::new(program);
+    // SAFETY: the closure only calls `sandbox_init` with a string allocated before the
+    // fork, and builds its error without allocating.
+    unsafe {
+        command.pre_exec(move || {
+            let mut error = std::ptr::null_mut();
+            if ffi::sandbox_init(profile.as_ptr(), 0, &mut error) != 0 {
+                if !error.is_null() {
+                    ffi::sandbox_free_error(error);
+                }
+                // EPERM
+                return Err(std::io::Error::from_raw_os_error(1));
+            }
+            Ok(())
+        });
+    }
+    Ok(command)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn sandbox_init_command(_profile: &str, program: &Path) -> Result<Command> {
+    Err(SecureNotebookError::Unsupported(format!(
+        "sandbox_init is only available on macOS (requested for {})",
+        program.display()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_backend_available() {
+        let error = sandboxed_command_with("(version 1)", Path::new("true"), &[]).unwrap_err();
+        assert!(matches!(error, SecureNotebookError::Unsupported(_)));
+        assert!(matches!(
+            validate_on_host_with("(version 1)", &[]),
+            Err(SecureNotebookError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,148 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::{generate_profile, Permissions, Result};
+
+/// In-memory cache of rendered profiles, keyed by the permissions fingerprint,
+/// the template and the kind of every allowed path.
+#[derive(Debug, Default)]
+pub struct ProfileCache {
+    profiles: Mutex<HashMap<[u8; 32], Arc<String>>>,
+}
+
+impl ProfileCache {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rendered profile for the permissions, generating it on a miss.
+    pub fn get_or_generate(
+        &self,
+        template: &str,
+        permissions: &Permissions,
+    ) -> Result<Arc<String>> {
+        self.get_or_generate_with(template, permissions, |_| Ok(()))
+    }
+
+    /// Rendered profile for the permissions, generating and validating it on a miss.
+    ///
+    /// Profiles that fail validation are not cached.
+    pub fn get_or_generate_with<F>(
+        &self,
+        template: &str,
+        permissions: &Permissions,
+        validate: F,
+    ) -> Result<Arc<String>>
+    where
+        F: FnOnce(&str) -> Result<()>,
+    {
+        let key = cache_key(template, permissions);
+        if let Some(profile) = self.lock().get(&key) {
+            return Ok(Arc::clone(profile));
+        }
+
+        let profile = generate_profile(template, permissions)?;
+        validate(&profile)?;
+
+        let profile = Arc::new(profile);
+        self.lock().insert(key, Arc::clone(&profile));
+        Ok(profile)
+    }
+
+    /// Number of cached profiles.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Drop every cached profile.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<[u8; 32], Arc<String>>> {
+        self.profiles.lock().expect("profile cache poisoned")
+    }
+}
+
+/// Hash of the permissions fingerprint, the template and the allowed path kinds.
+///
+/// Allowed directories render as `subpath` and anything else as `literal`, so a path
+/// replaced by one of the other kind must miss.
+pub fn cache_key(template: &str, permissions: &Permissions) -> [u8; 32] {
+    let canonical = permissions.canonicalized();
+    let mut hasher = Sha256::new();
+    hasher.update(permissions.fingerprint());
+    hasher.update(template.as_bytes());
+    for path in canonical.allow_read.iter().chain(&canonical.allow_write) {
+        hasher.update([path.is_dir() as u8]);
+    }
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SecureNotebookError;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_equivalent_permissions_share_an_entry() {
+        let cache = ProfileCache::new();
+        let template = "(version 1)\n(deny default)\n";
+
+        let mut first = Permissions::new();
+        first.allow_run = vec![PathBuf::from("python"), PathBuf::from("jupyter")];
+        let mut second = Permissions::new();
+        second.allow_run = vec![
+            PathBuf::from("jupyter"),
+            PathBuf::from("python"),
+            PathBuf::from("python"),
+        ];
+
+        let profile = cache.get_or_generate(template, &first).unwrap();
+        let cached = cache
+            .get_or_generate_with(template, &second, |_| panic!("should not revalidate"))
+            .unwrap();
+        assert!(Arc::ptr_eq(&profile, &cached));
+        assert_eq!(cache.len(), 1);
+
+        assert!(cache
+            .get_or_generate_with("(version 1)\n", &first, |_| {
+                Err(SecureNotebookError::ProfileCompile {
+                    message: "invalid".to_string(),
+                    location: None,
+                })
+            })
+            .is_err());
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_path_kind_changes_miss() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("data");
+        std::fs::create_dir(&path).unwrap();
+
+        let cache = ProfileCache::new();
+        let template = "(version 1)\n";
+        let mut permissions = Permissions::new();
+        permissions.allow_read.push(path.clone());
+
+        let profile = cache.get_or_generate(template, &permissions).unwrap();
+        assert!(profile.contains("(subpath "));
+
+        std::fs::remove_dir(&path).unwrap();
+        std::fs::write(&path, "").unwrap();
+        let profile = cache.get_or_generate(template, &permissions).unwrap();
+        assert!(profile.contains("(literal "));
+        assert_eq!(cache.len(), 2);
+    }
+}
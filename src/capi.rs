@@ -0,0 +1,288 @@
+//! Stable C ABI for hosts that are not written in Rust.
+//!
+//! Functions return `0` (or a non-null pointer) on success and `-1` (or null) on
+//! failure, with the message available from [`sn_last_error`]. Strings returned by the
+//! library are freed with [`sn_string_free`]. See `include/secure_notebook.h`.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::path::PathBuf;
+use std::ptr;
+
+use crate::backend::{sandboxed_command_with, DEFAULT_BACKENDS};
+use crate::{generate_profile, Permissions, DEFAULT_SANDBOX_PROFILE};
+
+/// Opaque handle to a [`Permissions`].
+pub struct SnPermissions(Permissions);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(message: impl Into<String>) {
+    let message = CString::new(message.into().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Borrow a C string as UTF-8, recording an error for null or invalid input.
+unsafe fn str_arg<'a>(value: *const c_char, name: &str) -> Option<&'a str> {
+    if value.is_null() {
+        set_error(format!("{name} is null"));
+        return None;
+    }
+    match CStr::from_ptr(value).to_str() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            set_error(format!("{name} is not valid UTF-8"));
+            None
+        }
+    }
+}
+
+/// Message of the last error on this thread, or null. Valid until the next call that
+/// fails on this thread; do not free it.
+#[no_mangle]
+pub extern "C" fn sn_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Free a string returned by the library.
+///
+/// # Safety
+///
+/// `string` must be null or a pointer returned by this library, not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn sn_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// New, empty permissions. Free with [`sn_permissions_free`].
+#[no_mangle]
+pub extern "C" fn sn_permissions_new() -> *mut SnPermissions {
+    Box::into_raw(Box::new(SnPermissions(Permissions::new())))
+}
+
+/// Free permissions created by [`sn_permissions_new`].
+///
+/// # Safety
+///
+/// `permissions` must be null or a pointer from [`sn_permissions_new`], not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn sn_permissions_free(permissions: *mut SnPermissions) {
+    if !permissions.is_null() {
+        drop(Box::from_raw(permissions));
+    }
+}
+
+unsafe fn push_path(
+    permissions: *mut SnPermissions,
+    path: *const c_char,
+    list: fn(&mut Permissions) -> &mut Vec<PathBuf>,
+) -> c_int {
+    let Some(permissions) = permissions.as_mut() else {
+        set_error("permissions is null");
+        return -1;
+    };
+    let Some(path) = str_arg(path, "path") else {
+        return -1;
+    };
+    list(&mut permissions.0).push(PathBuf::from(path));
+    0
+}
+
+macro_rules! path_setter {
+    ($name:ident, $field:ident, $doc:literal) => {
+        #[doc = $doc]
+        ///
+        /// # Safety
+        ///
+        /// `permissions` must come from [`sn_permissions_new`] and `path` must be a
+        /// NUL-terminated string.
+        #[no_mangle]
+        pub unsafe extern "C" fn $name(
+            permissions: *mut SnPermissions,
+            path: *const c_char,
+        ) -> c_int {
+            push_path(permissions, path, |permissions| &mut permissions.$field)
+        }
+    };
+}
+
+path_setter!(
+    sn_permissions_allow_read,
+    allow_read,
+    "Allow reading `path`."
+);
+path_setter!(sn_permissions_deny_read, deny_read, "Deny reading `path`.");
+path_setter!(
+    sn_permissions_allow_write,
+    allow_write,
+    "Allow writing `path`."
+);
+path_setter!(
+    sn_permissions_deny_write,
+    deny_write,
+    "Deny writing `path`."
+);
+path_setter!(
+    sn_permissions_allow_run,
+    allow_run,
+    "Allow running the program at `path`."
+);
+path_setter!(
+    sn_permissions_deny_run,
+    deny_run,
+    "Deny running the program at `path`."
+);
+
+/// Allow (`allow != 0`) or deny network access.
+///
+/// # Safety
+///
+/// `permissions` must come from [`sn_permissions_new`].
+#[no_mangle]
+pub unsafe extern "C" fn sn_permissions_set_allow_net(
+    permissions: *mut SnPermissions,
+    allow: c_int,
+) -> c_int {
+    let Some(permissions) = permissions.as_mut() else {
+        set_error("permissions is null");
+        return -1;
+    };
+    permissions.0.allow_net = allow != 0;
+    0
+}
+
+/// Allow (`allow != 0`) or deny Metal GPU access.
+///
+/// # Safety
+///
+/// `permissions` must come from [`sn_permissions_new`].
+#[no_mangle]
+pub unsafe extern "C" fn sn_permissions_set_allow_gpu(
+    permissions: *mut SnPermissions,
+    allow: c_int,
+) -> c_int {
+    let Some(permissions) = permissions.as_mut() else {
+        set_error("permissions is null");
+        return -1;
+    };
+    permissions.0.allow_gpu = allow != 0;
+    0
+}
+
+/// Generate the profile for `permissions` on `template`, or on the default profile if
+/// `template` is null. Free the result with [`sn_string_free`].
+///
+/// # Safety
+///
+/// `permissions` must come from [`sn_permissions_new`] and `template` must be null or a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sn_generate_profile(
+    template: *const c_char,
+    permissions: *const SnPermissions,
+) -> *mut c_char {
+    let Some(permissions) = permissions.as_ref() else {
+        set_error("permissions is null");
+        return ptr::null_mut();
+    };
+    let template = if template.is_null() {
+        DEFAULT_SANDBOX_PROFILE
+    } else {
+        match str_arg(template, "template") {
+            Some(template) => template,
+            None => return ptr::null_mut(),
+        }
+    };
+    match generate_profile(template, &permissions.0).map(CString::new) {
+        Ok(Ok(profile)) => profile.into_raw(),
+        Ok(Err(_)) => {
+            set_error("profile contains a NUL byte");
+            ptr::null_mut()
+        }
+        Err(error) => {
+            set_error(error.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Start `program` with `argc` arguments from `argv` under `profile`, returning its
+/// process id or `-1`.
+///
+/// The process is not waited for; reap it with `waitpid`.
+///
+/// # Safety
+///
+/// `profile` and `program` must be NUL-terminated strings, and `argv` must point to
+/// `argc` of them (or be null if `argc` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn sn_spawn_sandboxed(
+    profile: *const c_char,
+    program: *const c_char,
+    argv: *const *const c_char,
+    argc: usize,
+) -> i64 {
+    let (Some(profile), Some(program)) = (str_arg(profile, "profile"), str_arg(program, "program"))
+    else {
+        return -1;
+    };
+    let mut args = Vec::with_capacity(argc);
+    if argc > 0 {
+        if argv.is_null() {
+            set_error("argv is null");
+            return -1;
+        }
+        for index in 0..argc {
+            let Some(arg) = str_arg(*argv.add(index), "argument") else {
+                return -1;
+            };
+            args.push(arg);
+        }
+    }
+
+    let spawned = sandboxed_command_with(profile, program.as_ref(), DEFAULT_BACKENDS).and_then(
+        |mut command| {
+            command
+                .args(args)
+                .spawn()
+                .map_err(|source| crate::SecureNotebookError::SpawnFailed {
+                    program: PathBuf::from(program),
+                    source,
+                })
+        },
+    );
+    match spawned {
+        Ok(child) => child.id().into(),
+        Err(error) => {
+            set_error(error.to_string());
+            -1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_profile_through_c_abi() {
+        unsafe {
+            let permissions = sn_permissions_new();
+            assert_eq!(sn_permissions_allow_read(permissions, c"/data".as_ptr()), 0);
+            assert_eq!(sn_permissions_allow_read(permissions, ptr::null()), -1);
+            assert_eq!(CStr::from_ptr(sn_last_error()).to_str(), Ok("path is null"));
+
+            let profile = sn_generate_profile(c"(version 1)\n".as_ptr(), permissions);
+            assert!(!profile.is_null());
+            assert!(CStr::from_ptr(profile)
+                .to_string_lossy()
+                .contains("\"/data\""));
+            sn_string_free(profile);
+            sn_permissions_free(permissions);
+        }
+    }
+}
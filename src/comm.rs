@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::approval::{is_allowed, is_denied, ApprovalSession, Approver, Origin};
+use crate::error::{Result, SecureNotebookError};
+use crate::grants::Grant;
+
+/// Comm target registered by the kernel shim.
+pub const COMM_TARGET: &str = "secure_notebook";
+
+/// Python code run in the kernel at session start, providing `secure_notebook.request`.
+pub const KERNEL_SHIM: &str = include_str!("secure_notebook.py");
+
+/// A permission request sent by notebook code over the comm channel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PermissionRequest {
+    pub request_id: String,
+    /// One of `read`, `write`, `net` or `run`.
+    pub kind: String,
+    /// Path or program. For `net`, a host that is only shown to the operator: network
+    /// grants cover all hosts.
+    pub target: Option<String>,
+    /// Arguments of the `%request_permission` magic that sent the request, if any.
+    #[serde(default)]
+    pub magic: Option<String>,
+}
+
+impl PermissionRequest {
+    /// Parse the `data` field of a `comm_msg`.
+    pub fn from_json(data: &str) -> Result<Self> {
+        Ok(serde_json::from_str(data)?)
+    }
+
+    /// Where the request came from, for the approver.
+    pub fn origin(&self) -> Origin {
+        match &self.magic {
+            Some(line) => Origin::Magic(line.clone()),
+            None => Origin::Kernel,
+        }
+    }
+
+    /// Grant the request asks for.
+    pub fn to_grant(&self) -> Result<Grant> {
+        let target = || {
+            self.target.as_ref().map(PathBuf::from).ok_or_else(|| {
+                SecureNotebookError::InvalidPolicy(format!(
+                    "Permission request for {} needs a target",
+                    self.kind
+                ))
+            })
+        };
+        Ok(match self.kind.as_str() {
+            "read" => Grant::Read(target()?),
+            "write" => Grant::Write(target()?),
+            "net" => Grant::Net,
+            "run" => Grant::Run(target()?),
+            kind => {
+                return Err(SecureNotebookError::InvalidPolicy(format!(
+                    "Unknown permission kind: {kind}"
+                )))
+            }
+        })
+    }
+}
+
+/// Answer sent back to the kernel over the comm channel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PermissionResponse {
+    pub request_id: String,
+    pub granted: bool,
+    /// Extension token for the kernel to consume.
+    pub token: Option<String>,
+    pub error: Option<String>,
+}
+
+impl PermissionResponse {
+    fn denied(request: &PermissionRequest) -> Self {
+        Self {
+            request_id: request.request_id.clone(),
+            granted: false,
+            token: None,
+            error: None,
+        }
+    }
+
+    fn error(request: &PermissionRequest, error: SecureNotebookError) -> Self {
+        Self {
+            error: Some(error.to_string()),
+            ..Self::denied(request)
+        }
+    }
+}
+
+/// Handle a permission request from the kernel, asking the session's approver if needed.
+///
+/// Requests for operations the policy explicitly denies are refused without asking.
+pub fn handle_request<A: Approver>(
+    session: &mut ApprovalSession<A>,
+    request: &PermissionRequest,
+) -> PermissionResponse {
+    let grant = match request.to_grant() {
+        Ok(grant) => grant,
+        Err(e) => return PermissionResponse::error(request, e),
+    };
+    if is_denied(session.permissions(), &grant) {
+        return PermissionResponse::denied(request);
+    }
+    if is_allowed(session.permissions(), &grant) {
+        return PermissionResponse {
+            granted: true,
+            ..PermissionResponse::denied(request)
+        };
+    }
+
+    match session.request_grant(grant, &request.origin()) {
+        Ok(Some(approved)) => PermissionResponse {
+            granted: true,
+            token: approved.token,
+            ..PermissionResponse::denied(request)
+        },
+        Ok(None) => PermissionResponse::denied(request),
+        Err(e) => PermissionResponse::error(request, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(kind: &str, target: Option<&str>) -> PermissionRequest {
+        PermissionRequest {
+            request_id: "1".to_string(),
+            kind: kind.to_string(),
+            target: target.map(str::to_string),
+            magic: None,
+        }
+    }
+
+    #[test]
+    fn test_request_to_grant() {
+        assert_eq!(
+            request("read", Some("/data/x.csv")).to_grant().unwrap(),
+            Grant::Read(PathBuf::from("/data/x.csv"))
+        );
+        assert_eq!(request("net", None).to_grant().unwrap(), Grant::Net);
+        assert!(request("write", None).to_grant().is_err());
+        assert!(request("mount", Some("/")).to_grant().is_err());
+    }
+
+    #[test]
+    fn test_magic_origin() {
+        let mut request = request("net", Some("api.github.com"));
+        assert_eq!(request.origin(), Origin::Kernel);
+        request.magic = Some("net api.github.com".to_string());
+        assert_eq!(
+            request.origin(),
+            Origin::Magic("net api.github.com".to_string())
+        );
+        assert_eq!(request.to_grant().unwrap(), Grant::Net);
+    }
+}
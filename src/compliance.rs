@@ -0,0 +1,262 @@
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::evaluator::{evaluate, parse_rules, Action, Rule};
+use crate::presets::{Preset, DENY_REMOTE_NETWORK};
+use crate::{generate_profile, Permissions};
+
+/// Programs that run arbitrary commands, defeating an enumerated exec allowlist.
+pub const COMMAND_INTERPRETERS: &[&str] = &[
+    "/bin/sh",
+    "/bin/bash",
+    "/bin/zsh",
+    "/bin/dash",
+    "/bin/csh",
+    "/bin/tcsh",
+    "/bin/ksh",
+    "/usr/bin/osascript",
+    "/usr/bin/env",
+];
+
+/// A control requirement a policy can be checked against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Control {
+    /// No outbound connections beyond loopback.
+    NoExternalNetwork,
+    /// No access to the directories personal data usually lives in.
+    NoPiiDirectories,
+    /// Only programs named one by one in the policy may be executed, and no shells.
+    AuditedExec,
+}
+
+impl Control {
+    pub const ALL: [Control; 3] = [
+        Control::NoExternalNetwork,
+        Control::NoPiiDirectories,
+        Control::AuditedExec,
+    ];
+
+    /// Identifier used in reports, e.g. `no-external-network`.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Control::NoExternalNetwork => "no-external-network",
+            Control::NoPiiDirectories => "no-pii-directories",
+            Control::AuditedExec => "audited-exec",
+        }
+    }
+
+    /// Preset that makes a policy satisfy the control.
+    ///
+    /// Generated allows come after denies, so the preset's denies do not win over an
+    /// allow covering the same paths; [`compliance_report`] flags such policies.
+    pub fn preset(&self, home: &Path) -> Preset {
+        let mut preset = Preset {
+            name: format!("compliance:{}", self.id()),
+            ..Preset::default()
+        };
+        match self {
+            Control::NoExternalNetwork => preset.rules = DENY_REMOTE_NETWORK.to_string(),
+            Control::NoPiiDirectories => {
+                preset.permissions.deny_read = pii_directories(home);
+                preset.permissions.deny_write = pii_directories(home);
+            }
+            Control::AuditedExec => {
+                preset.permissions.deny_run =
+                    COMMAND_INTERPRETERS.iter().map(PathBuf::from).collect();
+            }
+        }
+        preset
+    }
+}
+
+impl fmt::Display for Control {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+/// Directories under `home` that typically hold personal data.
+pub fn pii_directories(home: &Path) -> Vec<PathBuf> {
+    [
+        "Desktop",
+        "Documents",
+        "Downloads",
+        "Pictures",
+        "Movies",
+        "Library/Mail",
+        "Library/Messages",
+        "Library/Calendars",
+        "Library/Application Support/AddressBook",
+    ]
+    .iter()
+    .map(|dir| home.join(dir))
+    .collect()
+}
+
+/// Whether the effective policy satisfies one control, and why.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ControlResult {
+    pub control: Control,
+    pub satisfied: bool,
+    pub reason: String,
+}
+
+/// Which controls a policy satisfies.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComplianceReport {
+    /// [`Permissions::fingerprint_hex`] of the checked policy.
+    pub fingerprint: String,
+    pub results: Vec<ControlResult>,
+}
+
+impl ComplianceReport {
+    pub fn all_satisfied(&self) -> bool {
+        self.results.iter().all(|result| result.satisfied)
+    }
+}
+
+impl fmt::Display for ComplianceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "policy {}", self.fingerprint)?;
+        for result in &self.results {
+            let status = if result.satisfied { "pass" } else { "FAIL" };
+            writeln!(f, "[{status}] {}: {}", result.control, result.reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the profile generated from `template` and `permissions` against `controls`.
+///
+/// Decisions are made by evaluating the generated profile, so rules in the template
+/// count as well. Rules with filters the evaluator does not model are ignored, which
+/// makes the check conservative for allows and optimistic for such denies.
+pub fn compliance_report(
+    template: &str,
+    permissions: &Permissions,
+    controls: &[Control],
+    home: &Path,
+) -> Result<ComplianceReport> {
+    let rules = parse_rules(&generate_profile(template, permissions)?)?;
+    let results = controls
+        .iter()
+        .map(|&control| {
+            let failure = match control {
+                Control::NoExternalNetwork => check_network(&rules),
+                Control::NoPiiDirectories => check_pii(&rules, home),
+                Control::AuditedExec => check_exec(&rules, permissions),
+            };
+            ControlResult {
+                control,
+                satisfied: failure.is_none(),
+                reason: failure.unwrap_or_else(|| satisfied_reason(control)),
+            }
+        })
+        .collect();
+    Ok(ComplianceReport {
+        fingerprint: permissions.fingerprint_hex(),
+        results,
+    })
+}
+
+fn satisfied_reason(control: Control) -> String {
+    match control {
+        Control::NoExternalNetwork => "outbound network is not allowed",
+        Control::NoPiiDirectories => "personal directories are neither readable nor writable",
+        Control::AuditedExec => "every executable program is listed, and none is a shell",
+    }
+    .to_string()
+}
+
+fn is_allowed(rules: &[Rule], operation: &str, path: Option<&Path>) -> bool {
+    evaluate(rules, operation, path) == Some(Action::Allow)
+}
+
+fn check_network(rules: &[Rule]) -> Option<String> {
+    is_allowed(rules, "network-outbound", None).then(|| "outbound network is allowed".to_string())
+}
+
+fn check_pii(rules: &[Rule], home: &Path) -> Option<String> {
+    let exposed: Vec<String> = pii_directories(home)
+        .into_iter()
+        .filter(|dir| {
+            let file = dir.join("file");
+            ["file-read-data", "file-write-data"]
+                .iter()
+                .any(|operation| is_allowed(rules, operation, Some(&file)))
+        })
+        .map(|dir| dir.display().to_string())
+        .collect();
+    (!exposed.is_empty()).then(|| format!("accessible: {}", exposed.join(", ")))
+}
+
+fn check_exec(rules: &[Rule], permissions: &Permissions) -> Option<String> {
+    let unlisted: Vec<String> = permissions
+        .allow_run
+        .iter()
+        .filter(|program| {
+            let text = program.to_string_lossy();
+            text.contains(['*', '?', '[']) || text.ends_with('/') || program.is_relative()
+        })
+        .map(|program| program.display().to_string())
+        .collect();
+    if !unlisted.is_empty() {
+        return Some(format!("not single programs: {}", unlisted.join(", ")));
+    }
+    let shells: Vec<&str> = COMMAND_INTERPRETERS
+        .iter()
+        .copied()
+        .filter(|shell| is_allowed(rules, "process-exec", Some(Path::new(shell))))
+        .collect();
+    (!shells.is_empty()).then(|| format!("command interpreters allowed: {}", shells.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const TEMPLATE: &str = "(version 1)\n(deny default)\n";
+
+    #[test]
+    fn test_presets_satisfy_their_controls() -> Result<()> {
+        let home = tempdir().unwrap();
+        let documents = home.path().join("Documents");
+        std::fs::create_dir(&documents)?;
+        let mut permissions = Permissions::new();
+        permissions.allow_read.push(documents);
+        permissions.allow_net = true;
+
+        let report = compliance_report(TEMPLATE, &permissions, &Control::ALL, home.path())?;
+        assert!(!report.results[0].satisfied);
+        assert!(!report.results[1].satisfied);
+        assert!(report.results[2].satisfied);
+
+        let mut permissions = Permissions::new();
+        for control in Control::ALL {
+            control.preset(home.path()).apply(&mut permissions);
+        }
+        let report = compliance_report(TEMPLATE, &permissions, &Control::ALL, home.path())?;
+        assert!(report.all_satisfied(), "{report}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_audited_exec_rejects_globs() -> Result<()> {
+        let mut permissions = Permissions::new();
+        permissions
+            .allow_run
+            .push(PathBuf::from("/usr/local/bin/*"));
+        let report = compliance_report(
+            TEMPLATE,
+            &permissions,
+            &[Control::AuditedExec],
+            Path::new("/Users/alice"),
+        )?;
+        assert!(!report.all_satisfied());
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::SecureNotebookError;
+
+/// Position of an error in the generated (unminified) profile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileLocation {
+    /// 1-based line in the generated profile.
+    pub line: usize,
+    /// 1-based column in the generated profile.
+    pub column: usize,
+    /// The offending line of the profile.
+    pub snippet: String,
+}
+
+/// Turn the stderr of a failed `sandbox-exec` launch into a structured error.
+///
+/// `sandbox-exec` reports profile errors as `sandbox-exec: <input string>:1:42: message`,
+/// where the position refers to the minified profile it was given. The position is
+/// mapped back onto `profile` so it points at the generated rule.
+pub fn parse_launch_error(stderr: &str, profile: &str, status: Option<i32>) -> SecureNotebookError {
+    let Some(line) = stderr
+        .lines()
+        .find(|line| line.starts_with("sandbox-exec:"))
+    else {
+        return SecureNotebookError::LaunchFailed {
+            status,
+            stderr: stderr.to_string(),
+        };
+    };
+
+    let message = line.trim_start_matches("sandbox-exec:").trim();
+    match split_position(message) {
+        Some((line, column, message)) => SecureNotebookError::ProfileCompile {
+            message: message.to_string(),
+            location: locate_in_profile(profile, line, column),
+        },
+        None => SecureNotebookError::ProfileCompile {
+            message: message.to_string(),
+            location: None,
+        },
+    }
+}
+
+/// Split `<source>:line:column: message` into its parts.
+fn split_position(message: &str) -> Option<(usize, usize, &str)> {
+    let mut fields = message.splitn(4, ':');
+    let _source = fields.next()?;
+    let line = fields.next()?.trim().parse().ok()?;
+    let column = fields.next()?.trim().parse().ok()?;
+    let text = fields.next()?.trim();
+    Some((line, column, text))
+}
+
+/// Map a position in the minified profile back onto the generated profile.
+///
+/// The minified profile is every non-comment line, trimmed and joined by a space,
+/// so a position on its single line falls into exactly one original line.
+pub fn locate_in_profile(profile: &str, line: usize, column: usize) -> Option<ProfileLocation> {
+    if line != 1 {
+        let snippet = profile.lines().nth(line.checked_sub(1)?)?;
+        return Some(ProfileLocation {
+            line,
+            column: column + 1,
+            snippet: snippet.to_string(),
+        });
+    }
+
+    let mut offset = 0;
+    for (index, original) in profile.lines().enumerate() {
+        let code = original
+            .find(';')
+            .map_or(original, |index| &original[..index]);
+        let trimmed = code.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        let end = offset + trimmed.len();
+        if column <= end {
+            let indent = code.len() - code.trim_start().len();
+            return Some(ProfileLocation {
+                line: index + 1,
+                column: indent + column.saturating_sub(offset) + 1,
+                snippet: original.to_string(),
+            });
+        }
+        // joined with a single space
+        offset = end + 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::minify_profile;
+
+    #[test]
+    fn test_error_is_mapped_to_generated_line() {
+        let profile =
+            "(version 1)\n; comment\n(deny default)\n    (allow file-reed* (subpath \"/tmp\"))\n";
+        let minified = minify_profile(profile);
+        let column = minified.find("file-reed*").unwrap();
+        let stderr =
+            format!("sandbox-exec: <input string>:1:{column}: unknown operation: file-reed*\n");
+
+        match parse_launch_error(&stderr, profile, Some(65)) {
+            SecureNotebookError::ProfileCompile { message, location } => {
+                assert_eq!(message, "unknown operation: file-reed*");
+                let location = location.unwrap();
+                assert_eq!(location.line, 4);
+                assert_eq!(location.column, 12);
+                assert!(location.snippet.contains("file-reed*"));
+            }
+            error => panic!("unexpected error: {error:?}"),
+        }
+    }
+
+    #[test]
+    fn test_unrecognized_stderr() {
+        let error = parse_launch_error("jupyter-server: command not found\n", "", Some(127));
+        assert!(matches!(
+            error,
+            SecureNotebookError::LaunchFailed {
+                status: Some(127),
+                ..
+            }
+        ));
+    }
+}
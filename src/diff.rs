@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::error::Result;
+use crate::evaluator::canonical_rules;
+
+/// Whether a rule was added or removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added,
+    Removed,
+}
+
+/// A rule present in only one of two profiles.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleChange {
+    pub change: Change,
+    /// The rule in [`canonical_rules`] form.
+    pub rule: String,
+}
+
+impl fmt::Display for RuleChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = match self.change {
+            Change::Added => '+',
+            Change::Removed => '-',
+        };
+        write!(f, "{sign} {}", self.rule)
+    }
+}
+
+/// Rules removed from `old`, in order, then rules added in `new`, in order.
+///
+/// Rules are compared as a multiset, so moving a rule is not reported even though the
+/// last matching rule wins.
+pub fn diff_profiles(old: &str, new: &str) -> Result<Vec<RuleChange>> {
+    let old = canonical_rules(old)?;
+    let new = canonical_rules(new)?;
+
+    let mut unmatched_new: Vec<Option<&String>> = new.iter().map(Some).collect();
+    let mut changes = Vec::new();
+    for rule in &old {
+        match unmatched_new.iter_mut().find(|new| *new == &Some(rule)) {
+            Some(matched) => *matched = None,
+            None => changes.push(RuleChange {
+                change: Change::Removed,
+                rule: rule.clone(),
+            }),
+        }
+    }
+    changes.extend(unmatched_new.into_iter().flatten().map(|rule| RuleChange {
+        change: Change::Added,
+        rule: rule.clone(),
+    }));
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_profiles() -> Result<()> {
+        let old = "(version 1)\n(allow file-read*\n    (subpath \"/a\")\n    (subpath \"/b\")\n)\n";
+        let new = "(version 1)\n(allow file-read* (subpath \"/b\"))\n(allow network*)\n";
+        let changes: Vec<String> = diff_profiles(old, new)?
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "- (allow file-read* (subpath \"/a\"))",
+                "+ (allow network*)"
+            ]
+        );
+        Ok(())
+    }
+}
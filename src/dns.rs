@@ -0,0 +1,130 @@
+use std::net::{IpAddr, ToSocketAddrs};
+use std::time::SystemTime;
+
+use crate::error::{Result, SecureNotebookError};
+use crate::pf::PfAnchor;
+
+/// A host granted network access, with the addresses it resolved to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinnedHost {
+    pub host: String,
+    pub port: u16,
+    /// Resolved addresses, sorted and deduplicated.
+    pub addresses: Vec<IpAddr>,
+}
+
+/// Hostnames resolved when the profile is generated, so grants by name map onto
+/// addresses pf can enforce.
+///
+/// Seatbelt only filters remote addresses by `localhost` or `*`, so the profile rules can
+/// restrict ports while the addresses themselves go into a [`PfAnchor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinnedHosts {
+    pub hosts: Vec<PinnedHost>,
+    pub resolved_at: SystemTime,
+}
+
+impl PinnedHosts {
+    /// Resolve every `(host, port)` pair.
+    pub fn resolve(hosts: &[(&str, u16)]) -> Result<Self> {
+        let hosts = hosts
+            .iter()
+            .map(|&(host, port)| {
+                Ok(PinnedHost {
+                    host: host.to_string(),
+                    port,
+                    addresses: lookup(host, port)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            hosts,
+            resolved_at: SystemTime::now(),
+        })
+    }
+
+    /// Resolve the hosts again, returning whether any address changed.
+    ///
+    /// When it did, the profile and the pf anchor have to be regenerated.
+    pub fn refresh(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for pinned in &mut self.hosts {
+            let addresses = lookup(&pinned.host, pinned.port)?;
+            if addresses != pinned.addresses {
+                pinned.addresses = addresses;
+                changed = true;
+            }
+        }
+        self.resolved_at = SystemTime::now();
+        Ok(changed)
+    }
+
+    /// Profile rules allowing outbound connections on the pinned ports and DNS.
+    pub fn profile_rules(&self) -> String {
+        let mut ports: Vec<u16> = self.hosts.iter().map(|pinned| pinned.port).collect();
+        ports.push(53);
+        ports.sort_unstable();
+        ports.dedup();
+
+        let mut rules = String::from("(allow network-outbound\n");
+        for port in ports {
+            rules.push_str(&format!("    (remote ip \"*:{port}\")\n"));
+        }
+        rules.push_str(")\n");
+        rules
+    }
+
+    /// Allow the pinned addresses in `anchor`.
+    pub fn apply_to(&self, mut anchor: PfAnchor) -> Result<PfAnchor> {
+        for pinned in &self.hosts {
+            for address in &pinned.addresses {
+                anchor = anchor.allow(&address.to_string(), &[pinned.port])?;
+            }
+        }
+        Ok(anchor)
+    }
+}
+
+fn lookup(host: &str, port: u16) -> Result<Vec<IpAddr>> {
+    let mut addresses: Vec<IpAddr> = (host, port)
+        .to_socket_addrs()
+        .map_err(|error| {
+            SecureNotebookError::Network(format!("Failed to resolve {host}: {error}"))
+        })?
+        .map(|address| address.ip())
+        .collect();
+    addresses.sort();
+    addresses.dedup();
+    if addresses.is_empty() {
+        return Err(SecureNotebookError::Network(format!(
+            "{host} did not resolve to any address"
+        )));
+    }
+    Ok(addresses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pf::PfScope;
+
+    #[test]
+    fn test_pinned_hosts() -> Result<()> {
+        let mut pinned = PinnedHosts::resolve(&[("127.0.0.1", 443), ("::1", 5432)])?;
+        assert_eq!(
+            pinned.hosts[0].addresses,
+            vec!["127.0.0.1".parse::<IpAddr>().unwrap()]
+        );
+        assert!(!pinned.refresh()?);
+
+        let rules = pinned.profile_rules();
+        assert!(rules.contains("(remote ip \"*:53\")"));
+        assert!(rules.contains("(remote ip \"*:443\")"));
+
+        let anchor = pinned.apply_to(PfAnchor::new("churn", PfScope::User(502)))?;
+        let rendered = anchor.render();
+        assert!(rendered.contains("to 127.0.0.1 port 443 user 502"));
+        assert!(rendered.contains("to ::1 port 5432 user 502"));
+        Ok(())
+    }
+}
@@ -0,0 +1,414 @@
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use x25519_dalek::{PublicKey, SharedSecret, StaticSecret};
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::signing::{decode_hex, encode_hex};
+use crate::tokens::{random_bytes, write_private};
+
+/// Header of an encrypted file, authenticated along with the contents.
+pub const MAGIC: &[u8] = b"secure-notebook-encrypted-v1\n";
+/// Header of a file encrypted for a recipient's public key.
+pub const RECIPIENT_MAGIC: &[u8] = b"secure-notebook-recipient-v1\n";
+/// Extension appended to encrypted files (`analysis.ipynb` -> `analysis.ipynb.enc`).
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+
+const NONCE_BYTES: usize = 24;
+
+/// A 256-bit XChaCha20-Poly1305 key for notebooks at rest.
+///
+/// `Debug` redacts it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct NotebookKey([u8; 32]);
+
+impl NotebookKey {
+    /// Generate a random key.
+    pub fn generate() -> Result<Self> {
+        Ok(Self(random_bytes()?))
+    }
+
+    /// Read a hex encoded key from `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        load_hex_key(path).map(Self)
+    }
+
+    /// Save the key hex encoded to `path`, readable only by the current user.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_private(path, encode_hex(&self.0).as_bytes())
+    }
+
+    /// Encrypt `plaintext` under a fresh random nonce.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce: [u8; NONCE_BYTES] = random_bytes()?;
+        let ciphertext = self
+            .cipher()
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: MAGIC,
+                },
+            )
+            .map_err(|_| SecureNotebookError::InvalidState("Encryption failed".to_string()))?;
+
+        let mut sealed = Vec::with_capacity(MAGIC.len() + NONCE_BYTES + ciphertext.len());
+        sealed.extend_from_slice(MAGIC);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypt data written by [`Self::encrypt`].
+    pub fn decrypt(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        let body = sealed.strip_prefix(MAGIC).ok_or_else(|| {
+            SecureNotebookError::InvalidState("Not an encrypted notebook".to_string())
+        })?;
+        if body.len() < NONCE_BYTES {
+            return Err(SecureNotebookError::InvalidState(
+                "Encrypted notebook is truncated".to_string(),
+            ));
+        }
+        let (nonce, ciphertext) = body.split_at(NONCE_BYTES);
+        self.cipher()
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: MAGIC,
+                },
+            )
+            .map_err(|_| {
+                SecureNotebookError::InvalidState(
+                    "Decryption failed, the key is wrong or the file was tampered with".to_string(),
+                )
+            })
+    }
+
+    /// Encrypt the file at `path` into `destination`.
+    pub fn encrypt_file(&self, path: &Path, destination: &Path) -> Result<()> {
+        let plaintext =
+            std::fs::read(path).io_context(|| format!("Failed to read {}", path.display()))?;
+        write_atomic(destination, &self.encrypt(&plaintext)?)
+    }
+
+    /// Decrypt the file at `path`.
+    pub fn decrypt_file(&self, path: &Path) -> Result<Vec<u8>> {
+        let sealed =
+            std::fs::read(path).io_context(|| format!("Failed to read {}", path.display()))?;
+        self.decrypt(&sealed)
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(Key::from_slice(&self.0))
+    }
+}
+
+impl fmt::Debug for NotebookKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("NotebookKey(<redacted>)")
+    }
+}
+
+/// Public key of whoever may read a run's outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecipientKey(PublicKey);
+
+impl RecipientKey {
+    /// Parse a hex encoded X25519 public key.
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let bytes: [u8; 32] = decode_hex(hex.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| {
+                SecureNotebookError::InvalidState("Malformed recipient key".to_string())
+            })?;
+        Ok(Self(PublicKey::from(bytes)))
+    }
+
+    /// Read a hex encoded public key from `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        load_hex_key(path).map(|bytes| Self(PublicKey::from(bytes)))
+    }
+
+    pub fn to_hex(&self) -> String {
+        encode_hex(self.0.as_bytes())
+    }
+
+    /// Encrypt `plaintext` so only the holder of the matching [`IdentityKey`] can read it.
+    ///
+    /// Each call agrees on a one-off key with the recipient through an ephemeral X25519
+    /// key, which is stored in the output.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let ephemeral = StaticSecret::from(random_bytes::<32>()?);
+        let ephemeral_public = PublicKey::from(&ephemeral);
+        let key = derive_key(
+            &ephemeral.diffie_hellman(&self.0),
+            &ephemeral_public,
+            &self.0,
+        );
+
+        let mut sealed = RECIPIENT_MAGIC.to_vec();
+        sealed.extend_from_slice(ephemeral_public.as_bytes());
+        sealed.extend_from_slice(&key.encrypt(plaintext)?);
+        Ok(sealed)
+    }
+}
+
+/// Secret key that decrypts what was encrypted for its [`RecipientKey`].
+///
+/// `Debug` redacts it.
+#[derive(Clone)]
+pub struct IdentityKey(StaticSecret);
+
+impl IdentityKey {
+    /// Generate a random identity.
+    pub fn generate() -> Result<Self> {
+        Ok(Self(StaticSecret::from(random_bytes::<32>()?)))
+    }
+
+    /// Read a hex encoded secret key from `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        load_hex_key(path).map(|bytes| Self(StaticSecret::from(bytes)))
+    }
+
+    /// Save the secret key hex encoded to `path`, readable only by the current user.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_private(path, encode_hex(self.0.as_bytes()).as_bytes())
+    }
+
+    /// Public key to hand to the machines producing outputs.
+    pub fn recipient(&self) -> RecipientKey {
+        RecipientKey(PublicKey::from(&self.0))
+    }
+
+    /// Decrypt data written by [`RecipientKey::encrypt`].
+    pub fn decrypt(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        let body = sealed
+            .strip_prefix(RECIPIENT_MAGIC)
+            .filter(|body| body.len() >= 32)
+            .ok_or_else(|| {
+                SecureNotebookError::InvalidState("Not encrypted for a recipient".to_string())
+            })?;
+        let (ephemeral, ciphertext) = body.split_at(32);
+        let ephemeral = PublicKey::from(<[u8; 32]>::try_from(ephemeral).expect("split at 32"));
+        let recipient = PublicKey::from(&self.0);
+        derive_key(&self.0.diffie_hellman(&ephemeral), &ephemeral, &recipient).decrypt(ciphertext)
+    }
+}
+
+impl fmt::Debug for IdentityKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("IdentityKey(<redacted>)")
+    }
+}
+
+/// Symmetric key for one recipient encryption, bound to both public keys.
+fn derive_key(shared: &SharedSecret, ephemeral: &PublicKey, recipient: &PublicKey) -> NotebookKey {
+    let digest = Sha256::new()
+        .chain_update(RECIPIENT_MAGIC)
+        .chain_update(shared.as_bytes())
+        .chain_update(ephemeral.as_bytes())
+        .chain_update(recipient.as_bytes())
+        .finalize();
+    NotebookKey(digest.into())
+}
+
+/// Encrypt every file under `dir` for `recipient`, replacing each with its `.enc` copy.
+///
+/// Files that are already encrypted are left alone. Returns the encrypted copies.
+pub fn encrypt_outputs(dir: &Path, recipient: &RecipientKey) -> Result<Vec<PathBuf>> {
+    let mut encrypted = Vec::new();
+    let entries =
+        std::fs::read_dir(dir).io_context(|| format!("Failed to list {}", dir.display()))?;
+    for entry in entries {
+        let path = entry
+            .io_context(|| format!("Failed to list {}", dir.display()))?
+            .path();
+        if path.is_dir() {
+            encrypted.extend(encrypt_outputs(&path, recipient)?);
+            continue;
+        }
+        if path
+            .extension()
+            .is_some_and(|extension| extension == ENCRYPTED_EXTENSION)
+        {
+            continue;
+        }
+        let plaintext =
+            std::fs::read(&path).io_context(|| format!("Failed to read {}", path.display()))?;
+        let destination = encrypted_path(&path);
+        write_atomic(&destination, &recipient.encrypt(&plaintext)?)?;
+        std::fs::remove_file(&path)
+            .io_context(|| format!("Failed to remove {}", path.display()))?;
+        encrypted.push(destination);
+    }
+    Ok(encrypted)
+}
+
+/// Path of the encrypted copy of `path`.
+pub fn encrypted_path(path: &Path) -> PathBuf {
+    let mut encrypted = path.as_os_str().to_owned();
+    encrypted.push(".");
+    encrypted.push(ENCRYPTED_EXTENSION);
+    PathBuf::from(encrypted)
+}
+
+/// An encrypted notebook decrypted into a workspace for the length of a session.
+///
+/// [`Self::close`] encrypts any changes back and removes the plaintext; dropping the
+/// guard does the same, ignoring errors.
+#[derive(Debug)]
+pub struct DecryptedNotebook {
+    key: NotebookKey,
+    encrypted: PathBuf,
+    plaintext: PathBuf,
+    digest: [u8; 32],
+    closed: bool,
+}
+
+impl DecryptedNotebook {
+    /// Decrypt `encrypted` into `dir`, under its name without the `.enc` extension.
+    pub fn open(key: &NotebookKey, encrypted: &Path, dir: &Path) -> Result<Self> {
+        let name = encrypted
+            .file_name()
+            .map(Path::new)
+            .filter(|name| {
+                name.extension()
+                    .is_some_and(|extension| extension == ENCRYPTED_EXTENSION)
+            })
+            .and_then(Path::file_stem)
+            .ok_or_else(|| SecureNotebookError::InvalidPath {
+                path: encrypted.to_path_buf(),
+                reason: format!("encrypted notebooks end in .{ENCRYPTED_EXTENSION}"),
+            })?;
+        let plaintext = key.decrypt_file(encrypted)?;
+        let path = dir.join(name);
+        write_private(&path, &plaintext)?;
+
+        Ok(Self {
+            key: key.clone(),
+            encrypted: encrypted.to_path_buf(),
+            plaintext: path,
+            digest: Sha256::digest(&plaintext).into(),
+            closed: false,
+        })
+    }
+
+    /// Path of the decrypted notebook, to hand to the sandboxed server.
+    pub fn path(&self) -> &Path {
+        &self.plaintext
+    }
+
+    /// Encrypt the notebook back if the session changed it and remove the plaintext,
+    /// returning whether it changed.
+    pub fn close(mut self) -> Result<bool> {
+        self.seal()
+    }
+
+    fn seal(&mut self) -> Result<bool> {
+        if self.closed {
+            return Ok(false);
+        }
+        let plaintext = std::fs::read(&self.plaintext)
+            .io_context(|| format!("Failed to read {}", self.plaintext.display()))?;
+        let changed = <[u8; 32]>::from(Sha256::digest(&plaintext)) != self.digest;
+        if changed {
+            write_atomic(&self.encrypted, &self.key.encrypt(&plaintext)?)?;
+        }
+        std::fs::remove_file(&self.plaintext)
+            .io_context(|| format!("Failed to remove {}", self.plaintext.display()))?;
+        self.closed = true;
+        Ok(changed)
+    }
+}
+
+impl Drop for DecryptedNotebook {
+    fn drop(&mut self) {
+        let _ = self.seal();
+    }
+}
+
+fn load_hex_key(path: &Path) -> Result<[u8; 32]> {
+    let hex = std::fs::read_to_string(path)
+        .io_context(|| format!("Failed to read key {}", path.display()))?;
+    decode_hex(hex.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            SecureNotebookError::InvalidState(format!(
+                "{} does not hold a 256-bit hex key",
+                path.display()
+            ))
+        })
+}
+
+/// Write through a temporary file, so a crash never leaves a half-written file behind.
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let partial = path.with_extension("partial");
+    write_private(&partial, contents)?;
+    std::fs::rename(&partial, path).io_context(|| format!("Failed to replace {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_encrypt_roundtrip() -> Result<()> {
+        let key = NotebookKey::generate()?;
+        let sealed = key.encrypt(b"{\"cells\": []}")?;
+        assert!(sealed.starts_with(MAGIC));
+        assert_eq!(key.decrypt(&sealed)?, b"{\"cells\": []}");
+
+        let other = NotebookKey::generate()?;
+        assert!(other.decrypt(&sealed).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypted_notebook_is_sealed_again() -> Result<()> {
+        let store = tempdir().unwrap();
+        let workspace = tempdir().unwrap();
+        let key = NotebookKey::generate()?;
+        let encrypted = store.path().join("analysis.ipynb.enc");
+        std::fs::write(&encrypted, key.encrypt(b"before")?)?;
+
+        let notebook = DecryptedNotebook::open(&key, &encrypted, workspace.path())?;
+        let plaintext = notebook.path().to_path_buf();
+        assert_eq!(plaintext, workspace.path().join("analysis.ipynb"));
+        std::fs::write(&plaintext, "after")?;
+        assert!(notebook.close()?);
+
+        assert!(!plaintext.exists());
+        assert_eq!(key.decrypt_file(&encrypted)?, b"after");
+        Ok(())
+    }
+
+    #[test]
+    fn test_outputs_are_encrypted_for_recipient() -> Result<()> {
+        let dir = tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("figures"))?;
+        std::fs::write(dir.path().join("report.html"), "<p>results</p>")?;
+        std::fs::write(dir.path().join("figures/plot.png"), "png")?;
+
+        let identity = IdentityKey::generate()?;
+        let mut encrypted = encrypt_outputs(dir.path(), &identity.recipient())?;
+        encrypted.sort();
+        assert_eq!(
+            encrypted,
+            [
+                dir.path().join("figures/plot.png.enc"),
+                dir.path().join("report.html.enc")
+            ]
+        );
+        assert!(!dir.path().join("report.html").exists());
+        let sealed = std::fs::read(&encrypted[1])?;
+        assert_eq!(identity.decrypt(&sealed)?, b"<p>results</p>");
+        assert!(IdentityKey::generate()?.decrypt(&sealed).is_err());
+        Ok(())
+    }
+}
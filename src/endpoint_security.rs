@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use crate::approval::is_denied;
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::grants::Grant;
+use crate::Permissions;
+
+/// Endpoint Security event types subscribed to through `eslogger`.
+pub const ES_EVENTS: &[&str] = &["exec", "open", "create", "unlink", "rename", "fork", "exit"];
+
+/// A file or exec event from the notebook's process tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EsEvent {
+    /// Process id of the process that caused the event.
+    pub pid: u32,
+    /// Executable of the process that caused the event.
+    pub process: PathBuf,
+    /// Permission the event exercised.
+    pub grant: Grant,
+    /// Whether the process was killed because the policy denies the event.
+    pub blocked: bool,
+}
+
+/// What the monitor does with events from the notebook's process tree.
+#[derive(Debug, Clone)]
+pub enum EsMode {
+    /// Only report events.
+    Audit,
+    /// Report events and kill processes doing something `permissions` explicitly denies.
+    ///
+    /// Endpoint Security notify events arrive after the fact, so this stops a process
+    /// from continuing rather than preventing the operation itself.
+    Enforce(Permissions),
+}
+
+/// A parsed `eslogger` line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EsMessage {
+    Fork {
+        parent: u32,
+        child: u32,
+    },
+    Exit {
+        pid: u32,
+    },
+    Access {
+        pid: u32,
+        process: PathBuf,
+        grant: Grant,
+    },
+}
+
+/// Parse one line of `eslogger` JSON output.
+pub fn parse_es_message(line: &str) -> Option<EsMessage> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let pid = pid_at(&value, "/process/audit_token/pid")?;
+    let event = value.get("event")?;
+
+    if let Some(fork) = event.get("fork") {
+        let child = pid_at(fork, "/child/audit_token/pid")?;
+        return Some(EsMessage::Fork { parent: pid, child });
+    }
+    if event.get("exit").is_some() {
+        return Some(EsMessage::Exit { pid });
+    }
+
+    let grant = if let Some(exec) = event.get("exec") {
+        Grant::Run(path_at(exec, "/target/executable/path")?)
+    } else if let Some(open) = event.get("open") {
+        let path = path_at(open, "/file/path")?;
+        // FWRITE
+        let writes = open.get("fflag").and_then(Value::as_u64).unwrap_or(0) & 0x2 != 0;
+        if writes {
+            Grant::Write(path)
+        } else {
+            Grant::Read(path)
+        }
+    } else if let Some(create) = event.get("create") {
+        let path = path_at(create, "/destination/existing_file/path").or_else(|| {
+            let dir = path_at(create, "/destination/new_path/dir/path")?;
+            let name = create.pointer("/destination/new_path/filename")?.as_str()?;
+            Some(dir.join(name))
+        })?;
+        Grant::Write(path)
+    } else if let Some(unlink) = event.get("unlink") {
+        Grant::Write(path_at(unlink, "/target/path")?)
+    } else if let Some(rename) = event.get("rename") {
+        Grant::Write(path_at(rename, "/source/path")?)
+    } else {
+        return None;
+    };
+
+    Some(EsMessage::Access {
+        pid,
+        process: path_at(&value, "/process/executable/path").unwrap_or_default(),
+        grant,
+    })
+}
+
+fn pid_at(value: &Value, pointer: &str) -> Option<u32> {
+    value.pointer(pointer)?.as_u64()?.try_into().ok()
+}
+
+fn path_at(value: &Value, pointer: &str) -> Option<PathBuf> {
+    value.pointer(pointer)?.as_str().map(PathBuf::from)
+}
+
+/// Processes descending from the notebook server.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessTree {
+    pids: HashSet<u32>,
+}
+
+impl ProcessTree {
+    /// Track the tree rooted at `root`.
+    pub fn new(root: u32) -> Self {
+        Self {
+            pids: HashSet::from([root]),
+        }
+    }
+
+    /// Whether `pid` belongs to the tree.
+    pub fn contains(&self, pid: u32) -> bool {
+        self.pids.contains(&pid)
+    }
+
+    /// Update the tree, returning whether the message came from it.
+    pub fn observe(&mut self, message: &EsMessage) -> bool {
+        match *message {
+            EsMessage::Fork { parent, child } => {
+                if self.pids.contains(&parent) {
+                    self.pids.insert(child);
+                    return true;
+                }
+                false
+            }
+            EsMessage::Exit { pid } => self.pids.remove(&pid),
+            EsMessage::Access { pid, .. } => self.pids.contains(&pid),
+        }
+    }
+}
+
+/// Streams Endpoint Security events for a process tree using `eslogger`.
+///
+/// `eslogger` ships with macOS 13 and later, and has to run as root from a process with
+/// Full Disk Access.
+#[derive(Debug)]
+pub struct EsMonitor {
+    child: Child,
+    receiver: Receiver<EsEvent>,
+}
+
+impl EsMonitor {
+    /// Start monitoring the process tree rooted at `root`, e.g. the Jupyter server.
+    pub fn start(root: u32, mode: EsMode) -> Result<Self> {
+        let mut child = Command::new("eslogger")
+            .args(ES_EVENTS)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|source| SecureNotebookError::SpawnFailed {
+                program: PathBuf::from("eslogger"),
+                source,
+            })?;
+
+        let stdout = child.stdout.take().ok_or_else(|| {
+            SecureNotebookError::InvalidState("eslogger has no stdout".to_string())
+        })?;
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut tree = ProcessTree::new(root);
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                let Some(message) = parse_es_message(&line) else {
+                    continue;
+                };
+                if !tree.observe(&message) {
+                    continue;
+                }
+                let EsMessage::Access {
+                    pid,
+                    process,
+                    grant,
+                } = message
+                else {
+                    continue;
+                };
+
+                let blocked = match &mode {
+                    EsMode::Enforce(permissions) if is_denied(permissions, &grant) => {
+                        kill(pid);
+                        true
+                    }
+                    _ => false,
+                };
+                let event = EsEvent {
+                    pid,
+                    process,
+                    grant,
+                    blocked,
+                };
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self { child, receiver })
+    }
+
+    /// Next event, if one has been reported.
+    pub fn try_next(&self) -> Option<EsEvent> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Wait up to `timeout` for the next event.
+    pub fn next_timeout(&self, timeout: Duration) -> Option<EsEvent> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    /// Stop monitoring.
+    pub fn stop(&mut self) -> Result<()> {
+        self.child.kill().io_context(|| "Failed to kill eslogger")?;
+        self.child
+            .wait()
+            .io_context(|| "Failed to wait for eslogger")?;
+        Ok(())
+    }
+}
+
+fn kill(pid: u32) {
+    let _ = Command::new("kill")
+        .args(["-KILL", &pid.to_string()])
+        .status();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_tree() {
+        let mut tree = ProcessTree::new(100);
+        assert!(tree.observe(&EsMessage::Fork {
+            parent: 100,
+            child: 101
+        }));
+        assert!(!tree.observe(&EsMessage::Fork {
+            parent: 7,
+            child: 8
+        }));
+        assert!(tree.contains(101));
+        assert!(!tree.contains(8));
+
+        assert!(tree.observe(&EsMessage::Exit { pid: 101 }));
+        assert!(!tree.contains(101));
+    }
+}
@@ -0,0 +1,156 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::diagnostics::ProfileLocation;
+use crate::violations::Violation;
+
+/// Result type used throughout the crate.
+pub type Result<T, E = SecureNotebookError> = std::result::Result<T, E>;
+
+/// Errors returned by the crate, so embedders can match on failure modes.
+#[derive(Debug)]
+pub enum SecureNotebookError {
+    /// A path in the policy is missing or cannot be used in a rule.
+    InvalidPath { path: PathBuf, reason: String },
+    /// A policy document could not be parsed or is not acceptable.
+    InvalidPolicy(String),
+    /// The generated profile could not be compiled or applied.
+    ProfileCompile {
+        message: String,
+        /// Where in the generated profile the error is, when `sandbox-exec` reported it.
+        location: Option<ProfileLocation>,
+    },
+    /// The generated profile is larger than the allowed size.
+    ProfileTooLarge { size: usize, limit: usize },
+    /// The sandboxed process could not be started.
+    SpawnFailed { program: PathBuf, source: io::Error },
+    /// The sandboxed process exited right after launch.
+    LaunchFailed { status: Option<i32>, stderr: String },
+    /// The kernel did not answer in time.
+    KernelTimeout(Duration),
+    /// The kernel reported an error while executing code.
+    KernelError(String),
+    /// The sandbox denied an operation.
+    Violation(Violation),
+    /// A signature is missing, malformed or does not verify.
+    Signature(String),
+    /// A request to a remote service failed.
+    Network(String),
+    /// The operation is not available on this platform.
+    Unsupported(String),
+    /// The operation is not allowed in the current state.
+    InvalidState(String),
+    /// An I/O operation failed.
+    Io { context: String, source: io::Error },
+}
+
+impl fmt::Display for SecureNotebookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPath { path, reason } => {
+                write!(f, "Invalid path {}: {reason}", path.display())
+            }
+            Self::InvalidPolicy(message) => write!(f, "Invalid policy: {message}"),
+            Self::ProfileCompile {
+                message,
+                location: Some(location),
+            } => write!(
+                f,
+                "Profile failed to compile at line {}, column {}: {message}\n    {}",
+                location.line,
+                location.column,
+                location.snippet.trim()
+            ),
+            Self::ProfileCompile {
+                message,
+                location: None,
+            } => write!(f, "Profile failed to compile: {message}"),
+            Self::ProfileTooLarge { size, limit } => {
+                write!(
+                    f,
+                    "Profile is {size} bytes, more than the limit of {limit} bytes"
+                )
+            }
+            Self::SpawnFailed { program, source } => {
+                write!(f, "Failed to start {}: {source}", program.display())
+            }
+            Self::LaunchFailed { status, stderr } => match status {
+                Some(code) => write!(f, "Sandboxed process exited with status {code}: {stderr}"),
+                None => write!(f, "Sandboxed process was killed: {stderr}"),
+            },
+            Self::KernelTimeout(timeout) => write!(f, "Kernel did not answer within {timeout:?}"),
+            Self::KernelError(message) => write!(f, "Kernel error: {message}"),
+            Self::Violation(violation) => write!(
+                f,
+                "Sandbox denied {}({}) {} {}",
+                violation.process,
+                violation.pid,
+                violation.operation,
+                violation.target.as_deref().unwrap_or("")
+            ),
+            Self::Signature(message) => write!(f, "Signature check failed: {message}"),
+            Self::Network(message) => write!(f, "Network error: {message}"),
+            Self::Unsupported(message) => write!(f, "Unsupported: {message}"),
+            Self::InvalidState(message) => f.write_str(message),
+            Self::Io { context, source } => write!(f, "{context}: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for SecureNotebookError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::SpawnFailed { source, .. } | Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SecureNotebookError {
+    fn from(source: io::Error) -> Self {
+        Self::Io {
+            context: "I/O error".to_string(),
+            source,
+        }
+    }
+}
+
+impl From<serde_json::Error> for SecureNotebookError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::InvalidPolicy(error.to_string())
+    }
+}
+
+impl From<toml::de::Error> for SecureNotebookError {
+    fn from(error: toml::de::Error) -> Self {
+        Self::InvalidPolicy(error.to_string())
+    }
+}
+
+impl From<toml::ser::Error> for SecureNotebookError {
+    fn from(error: toml::ser::Error) -> Self {
+        Self::InvalidPolicy(error.to_string())
+    }
+}
+
+impl From<ureq::Error> for SecureNotebookError {
+    fn from(error: ureq::Error) -> Self {
+        Self::Network(error.to_string())
+    }
+}
+
+/// Attach a description of what was being done to I/O errors.
+pub(crate) trait IoContext<T> {
+    fn io_context<C: Into<String>, F: FnOnce() -> C>(self, context: F) -> Result<T>;
+}
+
+impl<T> IoContext<T> for std::result::Result<T, io::Error> {
+    fn io_context<C: Into<String>, F: FnOnce() -> C>(self, context: F) -> Result<T> {
+        self.map_err(|source| SecureNotebookError::Io {
+            context: context().into(),
+            source,
+        })
+    }
+}
@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::error::Result;
+use crate::probe::{run_with_timeout, PROBE_TIMEOUT};
+use crate::session::sandboxed_command;
+
+/// A known pattern for reaching a denied path from inside the sandbox.
+///
+/// The script runs as `/bin/sh -c <script> sh <target> <scratch>`, where `target` is a
+/// path the profile denies and `scratch` a directory it lets the notebook write to. It
+/// only exits successfully if it managed to read `target`, and never modifies it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EscapeAttempt {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub script: &'static str,
+}
+
+/// Escape patterns run by [`run_escape_battery`].
+pub const ESCAPE_ATTEMPTS: &[EscapeAttempt] = &[
+    EscapeAttempt {
+        name: "symlink",
+        description: "Read the target through a symlink in a writable directory",
+        script: r#"ln -s "$1" "$2/escape-link" && cat "$2/escape-link""#,
+    },
+    EscapeAttempt {
+        name: "hardlink",
+        description: "Read the target through a hard link in a writable directory",
+        script: r#"ln "$1" "$2/escape-hardlink" && cat "$2/escape-hardlink""#,
+    },
+    EscapeAttempt {
+        name: "copy",
+        description: "Copy the target into a writable directory first",
+        script: r#"cp "$1" "$2/escape-copy" && cat "$2/escape-copy""#,
+    },
+    EscapeAttempt {
+        name: "dot-dot",
+        description: "Reach the target through `..` components from a writable directory",
+        script: r#"cat "$2/../../../../../../../../../..$1""#,
+    },
+    EscapeAttempt {
+        name: "double-slash",
+        description: "Use a non-canonical spelling of the target",
+        script: r#"cat "$(dirname "$1")//./$(basename "$1")""#,
+    },
+    EscapeAttempt {
+        name: "case-folding",
+        description: "Rules match case-sensitively while APFS usually does not",
+        script: r#"cat "$(printf '%s' "$1" | tr '[:lower:]' '[:upper:]')""#,
+    },
+    EscapeAttempt {
+        name: "data-volume",
+        description: "Reach the target through the /System/Volumes/Data firmlink",
+        script: r#"cat "/System/Volumes/Data$1""#,
+    },
+    EscapeAttempt {
+        name: "descriptor",
+        description: "Open the target on a descriptor and read it through /dev/fd",
+        script: r#"exec 3<"$1" && cat /dev/fd/3"#,
+    },
+    EscapeAttempt {
+        name: "python",
+        description: "Read the target from an interpreter instead of a shell tool",
+        script: r#"/usr/bin/python3 -c 'import sys; sys.stdout.write(open(sys.argv[1]).read())' "$1""#,
+    },
+    EscapeAttempt {
+        name: "perl",
+        description: "Read the target from an interpreter instead of a shell tool",
+        script: r#"/usr/bin/perl -e 'open(F, $ARGV[0]) or exit 1; print <F>' "$1""#,
+    },
+    EscapeAttempt {
+        name: "clean-env",
+        description: "Drop the environment before reading the target",
+        script: r#"/usr/bin/env -i /bin/cat "$1""#,
+    },
+    EscapeAttempt {
+        name: "dyld-injection",
+        description: "Point the dynamic loader at a writable directory",
+        script: r#"DYLD_INSERT_LIBRARIES="$2/escape.dylib" DYLD_LIBRARY_PATH="$2" /bin/cat "$1""#,
+    },
+];
+
+/// Result of a single escape attempt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EscapeResult {
+    pub name: String,
+    pub blocked: bool,
+    /// Stderr of the attempt.
+    pub detail: String,
+}
+
+/// Which escape attempts a profile blocked.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EscapeReport {
+    pub results: Vec<EscapeResult>,
+}
+
+impl EscapeReport {
+    /// Whether every attempt was blocked.
+    pub fn all_blocked(&self) -> bool {
+        self.results.iter().all(|result| result.blocked)
+    }
+
+    /// Attempts that managed to read the target.
+    pub fn escaped(&self) -> impl Iterator<Item = &EscapeResult> {
+        self.results.iter().filter(|result| !result.blocked)
+    }
+}
+
+/// Run every known escape attempt against `profile`.
+///
+/// `target` must be a file the profile denies reading, and `scratch` a directory it
+/// allows writing to. Files created in `scratch` are left for the caller to clean up.
+pub fn run_escape_battery(profile: &str, target: &Path, scratch: &Path) -> Result<EscapeReport> {
+    run_escape_attempts(profile, ESCAPE_ATTEMPTS, target, scratch)
+}
+
+/// Run the given escape attempts against `profile`.
+pub fn run_escape_attempts(
+    profile: &str,
+    attempts: &[EscapeAttempt],
+    target: &Path,
+    scratch: &Path,
+) -> Result<EscapeReport> {
+    let mut results = Vec::with_capacity(attempts.len());
+    for attempt in attempts {
+        let mut command = sandboxed_command(profile, Path::new("/bin/sh"));
+        command
+            .args(["-c", attempt.script, "sh"])
+            .arg(target)
+            .arg(scratch);
+        let (success, detail) = run_with_timeout(&mut command, PROBE_TIMEOUT)?;
+        results.push(EscapeResult {
+            name: attempt.name.to_string(),
+            blocked: !success,
+            detail,
+        });
+    }
+    Ok(EscapeReport { results })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_attempt_names_are_unique() {
+        let names: HashSet<_> = ESCAPE_ATTEMPTS.iter().map(|attempt| attempt.name).collect();
+        assert_eq!(names.len(), ESCAPE_ATTEMPTS.len());
+    }
+}
@@ -0,0 +1,410 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::error::{Result, SecureNotebookError};
+
+/// Whether a rule allows or denies an operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Allow,
+    Deny,
+}
+
+/// Filter restricting which targets a rule applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    Subpath(PathBuf),
+    Literal(PathBuf),
+    /// `(require-any ...)`
+    Any(Vec<Filter>),
+    /// `(require-all ...)`
+    All(Vec<Filter>),
+    /// `(regex #"...")`, matched against the whole path string.
+    Regex(Pattern),
+    /// A filter the evaluator does not model, e.g. `extension`. Never matches.
+    Other(String),
+}
+
+/// Compiled pattern of a `regex` filter, compared by its source.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    /// Compile `pattern`; SBPL regexes use the same syntax for what profiles need.
+    pub fn new(pattern: &str) -> Result<Self> {
+        Regex::new(pattern).map(Self).map_err(|e| {
+            SecureNotebookError::InvalidPolicy(format!("Invalid regex {pattern:?}: {e}"))
+        })
+    }
+
+    /// The source of the pattern.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Pattern {}
+
+impl Filter {
+    /// Whether the filter matches `path`.
+    pub fn matches(&self, path: Option<&Path>) -> bool {
+        match self {
+            Filter::Subpath(root) => path.is_some_and(|path| path.starts_with(root)),
+            Filter::Literal(literal) => path == Some(literal.as_path()),
+            Filter::Any(filters) => filters.iter().any(|filter| filter.matches(path)),
+            Filter::All(filters) => filters.iter().all(|filter| filter.matches(path)),
+            Filter::Regex(pattern) => path
+                .and_then(Path::to_str)
+                .is_some_and(|path| pattern.0.is_match(path)),
+            Filter::Other(_) => false,
+        }
+    }
+}
+
+/// A single `(allow ...)` or `(deny ...)` rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub action: Action,
+    pub operations: Vec<String>,
+    /// The rule applies if any filter matches, or to everything if there are none.
+    pub filters: Vec<Filter>,
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |f: &mut fmt::Formatter<'_>, kind: &str, filters: &[Filter]| {
+            write!(f, "({kind}")?;
+            for filter in filters {
+                write!(f, " {filter}")?;
+            }
+            f.write_str(")")
+        };
+        match self {
+            Filter::Subpath(path) => write!(f, "(subpath {:?})", path.display().to_string()),
+            Filter::Literal(path) => write!(f, "(literal {:?})", path.display().to_string()),
+            Filter::Any(filters) => join(f, "require-any", filters),
+            Filter::All(filters) => join(f, "require-all", filters),
+            Filter::Regex(pattern) => write!(f, "(regex #\"{}\")", pattern.as_str()),
+            Filter::Other(kind) => write!(f, "({kind} ...)"),
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            Action::Allow => "allow",
+            Action::Deny => "deny",
+        };
+        write!(f, "({action} {}", self.operations.join(" "))?;
+        for filter in &self.filters {
+            write!(f, " {filter}")?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl Rule {
+    /// Whether the rule applies to `operation` on `path`.
+    pub fn matches(&self, operation: &str, path: Option<&Path>) -> bool {
+        let operation_matches = self.operations.iter().any(|pattern| {
+            pattern == "default"
+                || pattern == operation
+                || pattern
+                    .strip_suffix('*')
+                    .is_some_and(|prefix| operation.starts_with(prefix))
+        });
+        operation_matches
+            && (self.filters.is_empty() || self.filters.iter().any(|filter| filter.matches(path)))
+    }
+}
+
+/// Parse the rules of a profile, skipping forms other than `allow` and `deny`.
+pub fn parse_rules(profile: &str) -> Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+    for form in parse_forms(profile)? {
+        let Expr::List(items) = form else { continue };
+        let action = match items.first() {
+            Some(Expr::Atom(atom)) if atom == "allow" => Action::Allow,
+            Some(Expr::Atom(atom)) if atom == "deny" => Action::Deny,
+            _ => continue,
+        };
+
+        let mut operations = Vec::new();
+        let mut filters = Vec::new();
+        for item in &items[1..] {
+            match item {
+                Expr::Atom(operation) => operations.push(operation.clone()),
+                Expr::List(_) => filters.push(parse_filter(item)),
+                Expr::Str(value) | Expr::Regex(value) => filters.push(Filter::Other(value.clone())),
+            }
+        }
+        rules.push(Rule {
+            action,
+            operations,
+            filters,
+        });
+    }
+    Ok(rules)
+}
+
+/// Decide `operation` on `path` the way the sandbox does: the last matching rule wins.
+///
+/// Returns `None` when no rule matches.
+pub fn evaluate(rules: &[Rule], operation: &str, path: Option<&Path>) -> Option<Action> {
+    rules
+        .iter()
+        .rev()
+        .find(|rule| rule.matches(operation, path))
+        .map(|rule| rule.action)
+}
+
+/// Parse `profile` and evaluate `operation` on `path` against it.
+pub fn evaluate_profile(
+    profile: &str,
+    operation: &str,
+    path: Option<&Path>,
+) -> Result<Option<Action>> {
+    Ok(evaluate(&parse_rules(profile)?, operation, path))
+}
+
+/// The profile's rules in a canonical one-line form, whitespace and comments dropped.
+///
+/// Rules with several filters are split into one per filter, which matches the same
+/// operations, so adding a path to an allow block shows up as one new rule.
+pub fn canonical_rules(profile: &str) -> Result<Vec<String>> {
+    let mut rules = Vec::new();
+    for form in parse_forms(profile)? {
+        let Expr::List(items) = &form else { continue };
+        let is_rule = matches!(
+            items.first(),
+            Some(Expr::Atom(atom)) if atom == "allow" || atom == "deny"
+        );
+        let filters = items.iter().filter(|item| matches!(item, Expr::List(_)));
+        if !is_rule || filters.clone().count() < 2 {
+            rules.push(form.to_string());
+            continue;
+        }
+
+        let head: Vec<&Expr> = items
+            .iter()
+            .filter(|item| !matches!(item, Expr::List(_)))
+            .collect();
+        for filter in filters {
+            let mut rule = head.iter().map(|expr| expr.to_string()).collect::<Vec<_>>();
+            rule.push(filter.to_string());
+            rules.push(format!("({})", rule.join(" ")));
+        }
+    }
+    Ok(rules)
+}
+
+fn parse_filter(expr: &Expr) -> Filter {
+    let Expr::List(items) = expr else {
+        return Filter::Other(format!("{expr:?}"));
+    };
+    match (items.first(), items.get(1)) {
+        (Some(Expr::Atom(kind)), Some(Expr::Str(path))) if kind == "subpath" => {
+            Filter::Subpath(PathBuf::from(path))
+        }
+        (Some(Expr::Atom(kind)), Some(Expr::Str(path))) if kind == "literal" => {
+            Filter::Literal(PathBuf::from(path))
+        }
+        (Some(Expr::Atom(kind)), Some(Expr::Regex(pattern))) if kind == "regex" => {
+            Pattern::new(pattern).map_or_else(|_| Filter::Other(kind.clone()), Filter::Regex)
+        }
+        (Some(Expr::Atom(kind)), _) if kind == "require-any" => {
+            Filter::Any(items[1..].iter().map(parse_filter).collect())
+        }
+        (Some(Expr::Atom(kind)), _) if kind == "require-all" => {
+            Filter::All(items[1..].iter().map(parse_filter).collect())
+        }
+        (Some(Expr::Atom(kind)), _) => Filter::Other(kind.clone()),
+        _ => Filter::Other(String::new()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Atom(String),
+    Str(String),
+    /// `#"..."`, kept verbatim since backslashes escape regex characters.
+    Regex(String),
+    List(Vec<Expr>),
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Atom(atom) => f.write_str(atom),
+            Expr::Str(value) => write!(f, "{value:?}"),
+            Expr::Regex(value) => write!(f, "#\"{value}\""),
+            Expr::List(items) => {
+                f.write_str("(")?;
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+/// Parse the top-level s-expressions of a profile.
+fn parse_forms(profile: &str) -> Result<Vec<Expr>> {
+    let mut stack: Vec<Vec<Expr>> = vec![Vec::new()];
+    let mut chars = profile.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                // comment until the end of the line
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' => stack.push(Vec::new()),
+            ')' => {
+                let list = stack.pop().filter(|_| !stack.is_empty()).ok_or_else(|| {
+                    SecureNotebookError::InvalidPolicy("Unbalanced ')' in profile".to_string())
+                })?;
+                push(&mut stack, Expr::List(list));
+            }
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => value.extend(chars.next()),
+                        Some(c) => value.push(c),
+                        None => {
+                            return Err(SecureNotebookError::InvalidPolicy(
+                                "Unterminated string in profile".to_string(),
+                            ))
+                        }
+                    }
+                }
+                push(&mut stack, Expr::Str(value));
+            }
+            '#' if chars.peek() == Some(&'"') => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            value.push('\\');
+                            value.extend(chars.next());
+                        }
+                        Some(c) => value.push(c),
+                        None => {
+                            return Err(SecureNotebookError::InvalidPolicy(
+                                "Unterminated regex in profile".to_string(),
+                            ))
+                        }
+                    }
+                }
+                push(&mut stack, Expr::Regex(value));
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut atom = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || matches!(next, '(' | ')' | '"' | ';') {
+                        break;
+                    }
+                    atom.push(next);
+                    chars.next();
+                }
+                push(&mut stack, Expr::Atom(atom));
+            }
+        }
+    }
+
+    match (stack.pop(), stack.is_empty()) {
+        (Some(forms), true) => Ok(forms),
+        _ => Err(SecureNotebookError::InvalidPolicy(
+            "Unbalanced '(' in profile".to_string(),
+        )),
+    }
+}
+
+fn push(stack: &mut [Vec<Expr>], expr: Expr) {
+    stack
+        .last_mut()
+        .expect("parser stack always has a root")
+        .push(expr);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_matching_rule_wins() {
+        let profile = "(version 1)\n(deny default)\n(deny file-read* (subpath \"/data/secret\"))\n(allow file-read*\n    (subpath \"/data\")\n)\n(deny file-write* (literal \"/data/x\"))\n";
+        let read = |path: &str| {
+            evaluate_profile(profile, "file-read-data", Some(Path::new(path))).unwrap()
+        };
+
+        assert_eq!(read("/data/x"), Some(Action::Allow));
+        assert_eq!(read("/data/secret/key"), Some(Action::Allow));
+        assert_eq!(read("/etc/passwd"), Some(Action::Deny));
+        assert_eq!(
+            evaluate_profile(profile, "file-write-data", Some(Path::new("/data/x"))).unwrap(),
+            Some(Action::Deny)
+        );
+    }
+
+    #[test]
+    fn test_canonical_rules() {
+        let rules = canonical_rules(
+            "(version 1) ; comment\n(allow file-read*\n  (subpath \"/a\")\n  (literal \"/b\"))\n",
+        )
+        .unwrap();
+        assert_eq!(
+            rules,
+            [
+                "(version 1)",
+                "(allow file-read* (subpath \"/a\"))",
+                "(allow file-read* (literal \"/b\"))"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_regex_filters() {
+        let profile = "(deny default)\n(allow file-read-metadata\n    (regex #\"^/p/my\\.project(/[^/]+){0,1}$\")\n)\n";
+        let read = |path: &str| {
+            evaluate_profile(profile, "file-read-metadata", Some(Path::new(path))).unwrap()
+        };
+
+        assert_eq!(read("/p/my.project"), Some(Action::Allow));
+        assert_eq!(read("/p/my.project/src"), Some(Action::Allow));
+        assert_eq!(read("/p/my.project/src/lib.rs"), Some(Action::Deny));
+        assert_eq!(read("/p/myXproject"), Some(Action::Deny));
+        assert_eq!(
+            canonical_rules(profile).unwrap()[1],
+            "(allow file-read-metadata (regex #\"^/p/my\\.project(/[^/]+){0,1}$\"))"
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_profile() {
+        assert!(parse_rules("(allow default").is_err());
+        assert!(parse_rules("(allow default))").is_err());
+    }
+}
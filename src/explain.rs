@@ -0,0 +1,493 @@
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::evaluator::{parse_rules, Action, Rule};
+use crate::provenance::{Provenance, TrackedPermissions};
+use crate::{
+    generate_file_permissions, generate_network_permissions, generate_profile,
+    generate_run_permissions, Permissions, GPU_RULES,
+};
+
+/// An operation to explain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    Read(PathBuf),
+    Write(PathBuf),
+    Exec(PathBuf),
+    Network,
+    /// Any other sandbox operation, e.g. `("mach-lookup", None)`.
+    Other(String, Option<PathBuf>),
+}
+
+impl Operation {
+    /// Sandbox operation name, e.g. `file-read-data`.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Read(_) => "file-read-data",
+            Self::Write(_) => "file-write-data",
+            Self::Exec(_) => "process-exec",
+            Self::Network => "network-outbound",
+            Self::Other(name, _) => name,
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Read(path) | Self::Write(path) | Self::Exec(path) => Some(path),
+            Self::Network => None,
+            Self::Other(_, path) => path.as_deref(),
+        }
+    }
+}
+
+/// A field of [`Permissions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PermissionField {
+    AllowRead,
+    DenyRead,
+    AllowWrite,
+    DenyWrite,
+    AllowNet,
+    AllowGpu,
+    AllowRun,
+    DenyRun,
+}
+
+impl fmt::Display for PermissionField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::AllowRead => "allow_read",
+            Self::DenyRead => "deny_read",
+            Self::AllowWrite => "allow_write",
+            Self::DenyWrite => "deny_write",
+            Self::AllowNet => "allow_net",
+            Self::AllowGpu => "allow_gpu",
+            Self::AllowRun => "allow_run",
+            Self::DenyRun => "deny_run",
+        })
+    }
+}
+
+/// Where a rule of a profile came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Template,
+    /// Entry `index` of a [`Permissions`] field; always 0 for `allow_net` and
+    /// `allow_gpu`.
+    Permission {
+        field: PermissionField,
+        index: usize,
+    },
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Template => f.write_str("template"),
+            Self::Permission {
+                field: field @ (PermissionField::AllowNet | PermissionField::AllowGpu),
+                ..
+            } => write!(f, "Permissions.{field}"),
+            Self::Permission { field, index } => write!(f, "Permissions.{field}[{index}]"),
+        }
+    }
+}
+
+/// Which entries a generated rule was built from.
+#[derive(Debug, Clone, Copy)]
+enum RuleOrigin {
+    Template,
+    /// A rule for a single entry.
+    Entry(PermissionField, usize),
+    /// An allow block with one filter per entry, in order.
+    Block(PermissionField),
+}
+
+/// Why an operation was allowed or denied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub operation: Operation,
+    /// `None` when no rule matches.
+    pub action: Option<Action>,
+    pub rule: Option<Rule>,
+    /// Index of the rule among the profile's rules.
+    pub position: Option<usize>,
+    pub source: Option<Source>,
+    /// Where the permission behind the rule came from, if the profile tracks it.
+    pub provenance: Option<Provenance>,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.operation.name())?;
+        if let Some(path) = self.operation.path() {
+            write!(f, " {}", path.display())?;
+        }
+        match (self.action, self.position, self.source) {
+            (Some(action), Some(position), Some(source)) => {
+                let verdict = match action {
+                    Action::Allow => "allowed",
+                    Action::Deny => "denied",
+                };
+                write!(f, ": {verdict} by rule {position} from {source}")?;
+                match &self.provenance {
+                    Some(Provenance::Template) | None => Ok(()),
+                    Some(provenance) => write!(f, " ({provenance})"),
+                }
+            }
+            _ => f.write_str(": no rule matches"),
+        }
+    }
+}
+
+/// One rule, or one entry of an allow block, with where it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attribution {
+    /// Index of the rule among the profile's rules.
+    pub position: usize,
+    pub action: Action,
+    pub source: Source,
+    pub provenance: Option<Provenance>,
+}
+
+/// A generated profile that remembers where each rule came from.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    text: String,
+    template: String,
+    permissions: Permissions,
+    rules: Vec<(Rule, RuleOrigin)>,
+    tracked: Option<TrackedPermissions>,
+}
+
+impl Profile {
+    /// Generate the profile for `permissions` on `template`.
+    pub fn new(template: &str, permissions: &Permissions) -> Result<Self> {
+        let mut rules: Vec<_> = parse_rules(template)?
+            .into_iter()
+            .map(|rule| (rule, RuleOrigin::Template))
+            .collect();
+        push_section(
+            &mut rules,
+            &generate_file_permissions(
+                "file-read*",
+                &permissions.allow_read,
+                &permissions.deny_read,
+            )?,
+            PermissionField::DenyRead,
+            PermissionField::AllowRead,
+            !permissions.allow_read.is_empty(),
+        )?;
+        push_section(
+            &mut rules,
+            &generate_file_permissions(
+                "file-write*",
+                &permissions.allow_write,
+                &permissions.deny_write,
+            )?,
+            PermissionField::DenyWrite,
+            PermissionField::AllowWrite,
+            !permissions.allow_write.is_empty(),
+        )?;
+        for rule in parse_rules(&generate_network_permissions(permissions.allow_net))? {
+            rules.push((rule, RuleOrigin::Entry(PermissionField::AllowNet, 0)));
+        }
+        if permissions.allow_gpu {
+            for rule in parse_rules(GPU_RULES)? {
+                rules.push((rule, RuleOrigin::Entry(PermissionField::AllowGpu, 0)));
+            }
+        }
+        push_section(
+            &mut rules,
+            &generate_run_permissions(&permissions.allow_run, &permissions.deny_run)?,
+            PermissionField::DenyRun,
+            PermissionField::AllowRun,
+            !permissions.allow_run.is_empty(),
+        )?;
+
+        Ok(Self {
+            text: generate_profile(template, permissions)?,
+            template: template.to_string(),
+            permissions: permissions.clone(),
+            rules,
+            tracked: None,
+        })
+    }
+
+    /// Generate the profile for `tracked` on `template`, attributing rules to their
+    /// sources.
+    pub fn with_provenance(template: &str, tracked: &TrackedPermissions) -> Result<Self> {
+        let mut profile = Self::new(template, tracked.permissions())?;
+        profile.tracked = Some(tracked.clone());
+        Ok(profile)
+    }
+
+    /// The profile as passed to the sandbox.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The profile's rules in order.
+    pub fn rules(&self) -> impl Iterator<Item = &Rule> {
+        self.rules.iter().map(|(rule, _)| rule)
+    }
+
+    /// The profile with a comment before every generated rule and allow block entry
+    /// naming its source, e.g. `; from Permissions.allow_read[2] (config: project.toml)`.
+    ///
+    /// For debugging; [`crate::minify_profile`] strips the comments again.
+    pub fn annotated(&self) -> String {
+        let permissions = &self.permissions;
+        // `Self::new` already rendered these permissions, so their paths are valid
+        let checked = "paths were checked when the profile was generated";
+        let sections = [
+            (
+                generate_file_permissions(
+                    "file-read*",
+                    &permissions.allow_read,
+                    &permissions.deny_read,
+                )
+                .expect(checked),
+                PermissionField::DenyRead,
+                PermissionField::AllowRead,
+            ),
+            (
+                generate_file_permissions(
+                    "file-write*",
+                    &permissions.allow_write,
+                    &permissions.deny_write,
+                )
+                .expect(checked),
+                PermissionField::DenyWrite,
+                PermissionField::AllowWrite,
+            ),
+            (
+                generate_network_permissions(permissions.allow_net),
+                PermissionField::AllowNet,
+                PermissionField::AllowNet,
+            ),
+            (
+                if permissions.allow_gpu {
+                    GPU_RULES.to_string()
+                } else {
+                    String::new()
+                },
+                PermissionField::AllowGpu,
+                PermissionField::AllowGpu,
+            ),
+            (
+                generate_run_permissions(&permissions.allow_run, &permissions.deny_run)
+                    .expect(checked),
+                PermissionField::DenyRun,
+                PermissionField::AllowRun,
+            ),
+        ];
+
+        let mut annotated = self.template.clone();
+        for (section, deny, allow) in sections {
+            // the GPU rules are one fixed block, attributed as a whole
+            if allow == PermissionField::AllowGpu {
+                if !section.is_empty() {
+                    self.annotate(&mut annotated, "", allow, 0);
+                    annotated.push_str(&section);
+                }
+                continue;
+            }
+            let (mut denies, mut allows) = (0, 0);
+            for line in section.lines() {
+                let (indent, source) = if line.starts_with("(deny") {
+                    denies += 1;
+                    ("", Some((deny, denies - 1)))
+                } else if line.starts_with("(allow network") {
+                    ("", Some((PermissionField::AllowNet, 0)))
+                } else if line.starts_with("    (") {
+                    allows += 1;
+                    ("    ", Some((allow, allows - 1)))
+                } else {
+                    ("", None)
+                };
+                if let Some((field, index)) = source {
+                    self.annotate(&mut annotated, indent, field, index);
+                }
+                annotated.push_str(line);
+                annotated.push('\n');
+            }
+        }
+        annotated
+    }
+
+    /// Append the `; from <source>` comment for entry `index` of `field`.
+    fn annotate(&self, annotated: &mut String, indent: &str, field: PermissionField, index: usize) {
+        let source = Source::Permission { field, index };
+        write!(annotated, "{indent}; from {source}").expect("writing to a String cannot fail");
+        if let Some(provenance) = self.provenance(source) {
+            write!(annotated, " ({provenance})").expect("writing to a String cannot fail");
+        }
+        annotated.push('\n');
+    }
+
+    /// Every rule with its source, allow blocks split into one entry per path.
+    pub fn attributions(&self) -> Vec<Attribution> {
+        let mut attributions = Vec::new();
+        for (position, (rule, origin)) in self.rules.iter().enumerate() {
+            let sources: Vec<Source> = match *origin {
+                RuleOrigin::Template => vec![Source::Template],
+                RuleOrigin::Entry(field, index) => vec![Source::Permission { field, index }],
+                RuleOrigin::Block(field) => (0..rule.filters.len())
+                    .map(|index| Source::Permission { field, index })
+                    .collect(),
+            };
+            attributions.extend(sources.into_iter().map(|source| Attribution {
+                position,
+                action: rule.action,
+                provenance: self.provenance(source),
+                source,
+            }));
+        }
+        attributions
+    }
+
+    /// The rule deciding `operation`, the last one to match, and where it came from.
+    pub fn explain(&self, operation: Operation) -> Explanation {
+        let path = operation.path();
+        let found = self
+            .rules
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, (rule, _))| rule.matches(operation.name(), path));
+
+        let Some((position, (rule, origin))) = found else {
+            return Explanation {
+                operation,
+                action: None,
+                rule: None,
+                position: None,
+                source: None,
+                provenance: None,
+            };
+        };
+        let source = match *origin {
+            RuleOrigin::Template => Source::Template,
+            RuleOrigin::Entry(field, index) => Source::Permission { field, index },
+            RuleOrigin::Block(field) => Source::Permission {
+                field,
+                index: rule
+                    .filters
+                    .iter()
+                    .position(|filter| filter.matches(path))
+                    .unwrap_or(0),
+            },
+        };
+        Explanation {
+            action: Some(rule.action),
+            rule: Some(rule.clone()),
+            position: Some(position),
+            source: Some(source),
+            provenance: self.provenance(source),
+            operation,
+        }
+    }
+
+    fn provenance(&self, source: Source) -> Option<Provenance> {
+        let tracked = self.tracked.as_ref()?;
+        match source {
+            Source::Template => Some(Provenance::Template),
+            Source::Permission { field, index } => tracked.provenance(field, index).cloned(),
+        }
+    }
+}
+
+/// Add the rules of a generated section: one deny per `deny` entry, then an allow block
+/// for the `allow` entries if there are any.
+fn push_section(
+    rules: &mut Vec<(Rule, RuleOrigin)>,
+    section: &str,
+    deny: PermissionField,
+    allow: PermissionField,
+    has_allows: bool,
+) -> Result<()> {
+    let parsed = parse_rules(section)?;
+    let count = parsed.len();
+    for (index, rule) in parsed.into_iter().enumerate() {
+        let origin = if has_allows && index + 1 == count {
+            RuleOrigin::Block(allow)
+        } else {
+            RuleOrigin::Entry(deny, index)
+        };
+        rules.push((rule, origin));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explain() -> Result<()> {
+        let permissions = Permissions {
+            allow_read: vec![PathBuf::from("/data/a"), PathBuf::from("/data/b")],
+            deny_read: vec![PathBuf::from("/data")],
+            ..Permissions::default()
+        };
+        let profile = Profile::new("(version 1)\n(deny default)\n", &permissions)?;
+
+        let allowed = profile.explain(Operation::Read(PathBuf::from("/data/b")));
+        assert_eq!(allowed.action, Some(Action::Allow));
+        assert_eq!(
+            allowed.source,
+            Some(Source::Permission {
+                field: PermissionField::AllowRead,
+                index: 1
+            })
+        );
+        assert_eq!(
+            profile
+                .explain(Operation::Read(PathBuf::from("/data/c")))
+                .to_string(),
+            "file-read-data /data/c: denied by rule 1 from Permissions.deny_read[0]"
+        );
+        let annotated = profile.annotated();
+        assert!(
+            annotated.contains("    ; from Permissions.allow_read[1]\n    (literal \"/data/b\")")
+        );
+        assert_eq!(
+            crate::minify_profile(&annotated),
+            crate::minify_profile(profile.text())
+        );
+
+        assert_eq!(
+            allowed.rule.unwrap().to_string(),
+            "(allow file-read* (literal \"/data/a\") (literal \"/data/b\"))"
+        );
+
+        let fallback = profile.explain(Operation::Network);
+        assert_eq!(fallback.source, Some(Source::Template));
+        assert_eq!(fallback.position, Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_gpu() -> Result<()> {
+        let mut permissions = Permissions::new();
+        permissions.allow_gpu();
+        let profile = Profile::new("(version 1)\n(deny default)\n", &permissions)?;
+
+        let driver = profile.explain(Operation::Read(PathBuf::from(
+            "/System/Library/Extensions/AGXMetal13_3.bundle",
+        )));
+        assert_eq!(
+            driver.source.map(|source| source.to_string()),
+            Some("Permissions.allow_gpu".to_string())
+        );
+        let annotated = profile.annotated();
+        assert!(annotated.contains("; from Permissions.allow_gpu\n(allow iokit-open"));
+        assert_eq!(
+            crate::minify_profile(&annotated),
+            crate::minify_profile(profile.text())
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use crate::error::{Result, SecureNotebookError};
+
+/// Extension class granting read access to a path.
+pub const READ_EXTENSION_CLASS: &str = "com.apple.app-sandbox.read";
+/// Extension class granting read and write access to a path.
+pub const READ_WRITE_EXTENSION_CLASS: &str = "com.apple.app-sandbox.read-write";
+
+/// Rules allowing a sandboxed process to use extension tokens it has consumed.
+pub fn generate_extension_permissions() -> String {
+    format!(
+        "(allow file-read* (extension \"{READ_EXTENSION_CLASS}\"))\n\
+         (allow file-read* file-write* (extension \"{READ_WRITE_EXTENSION_CLASS}\"))\n"
+    )
+}
+
+#[cfg(target_os = "macos")]
+mod ffi {
+    use std::ffi::{c_char, c_void};
+
+    extern "C" {
+        pub fn sandbox_extension_issue_file(
+            extension_class: *const c_char,
+            path: *const c_char,
+            flags: u32,
+        ) -> *mut c_char;
+        pub fn free(ptr: *mut c_void);
+    }
+}
+
+/// Issue an extension token for `path` that a sandboxed process can consume to gain access.
+#[cfg(target_os = "macos")]
+pub fn issue_file_extension(class: &str, path: &Path) -> Result<String> {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+
+    let nul_error = |_| SecureNotebookError::InvalidPath {
+        path: path.to_path_buf(),
+        reason: "Contains a NUL byte".to_string(),
+    };
+    let class = CString::new(class).map_err(nul_error)?;
+    let path_c = CString::new(path.as_os_str().as_bytes()).map_err(nul_error)?;
+
+    // SAFETY: both arguments are valid NUL terminated strings, and the returned token is
+    // copied before being released with `free`.
+    unsafe {
+        let token = ffi::sandbox_extension_issue_file(class.as_ptr(), path_c.as_ptr(), 0);
+        if token.is_null() {
+            return Err(SecureNotebookError::InvalidPath {
+                path: path.to_path_buf(),
+                reason: "Failed to issue sandbox extension".to_string(),
+            });
+        }
+        let issued = CStr::from_ptr(token).to_string_lossy().into_owned();
+        ffi::free(token.cast());
+        Ok(issued)
+    }
+}
+
+/// Issue an extension token for `path` that a sandboxed process can consume to gain access.
+#[cfg(not(target_os = "macos"))]
+pub fn issue_file_extension(_class: &str, path: &Path) -> Result<String> {
+    Err(SecureNotebookError::Unsupported(format!(
+        "Sandbox extensions are only available on macOS (requested for {})",
+        path.display()
+    )))
+}
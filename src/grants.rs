@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::error::Result;
+use crate::generate_profile;
+use crate::session::{JupyterSession, SessionConfig};
+use crate::Permissions;
+
+/// A single permission that can be granted on top of a base policy.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Grant {
+    Read(PathBuf),
+    Write(PathBuf),
+    Net,
+    Gpu,
+    Run(PathBuf),
+}
+
+impl Grant {
+    /// Add the grant to the given permissions.
+    pub fn apply(&self, permissions: &mut Permissions) {
+        match self {
+            Grant::Read(path) => permissions.allow_read.push(path.clone()),
+            Grant::Write(path) => permissions.allow_write.push(path.clone()),
+            Grant::Net => permissions.allow_net = true,
+            Grant::Gpu => permissions.allow_gpu = true,
+            Grant::Run(program) => permissions.allow_run.push(program.clone()),
+        }
+    }
+}
+
+/// What changed between two policies, as grants.
+///
+/// `added` and `removed` are allows; `denied` and `undenied` are denies that were added
+/// or lifted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PermissionDelta {
+    pub added: Vec<Grant>,
+    pub removed: Vec<Grant>,
+    pub denied: Vec<Grant>,
+    pub undenied: Vec<Grant>,
+}
+
+impl PermissionDelta {
+    /// Compute the delta going from `old` to `new`.
+    pub fn between(old: &Permissions, new: &Permissions) -> Self {
+        let mut delta = Self::default();
+        delta.compare(&old.allow_read, &new.allow_read, Grant::Read, Side::Allow);
+        delta.compare(
+            &old.allow_write,
+            &new.allow_write,
+            Grant::Write,
+            Side::Allow,
+        );
+        delta.compare(&old.allow_run, &new.allow_run, Grant::Run, Side::Allow);
+        delta.compare(&old.deny_read, &new.deny_read, Grant::Read, Side::Deny);
+        delta.compare(&old.deny_write, &new.deny_write, Grant::Write, Side::Deny);
+        delta.compare(&old.deny_run, &new.deny_run, Grant::Run, Side::Deny);
+        match (old.allow_net, new.allow_net) {
+            (false, true) => delta.added.push(Grant::Net),
+            (true, false) => delta.removed.push(Grant::Net),
+            _ => {}
+        }
+        match (old.allow_gpu, new.allow_gpu) {
+            (false, true) => delta.added.push(Grant::Gpu),
+            (true, false) => delta.removed.push(Grant::Gpu),
+            _ => {}
+        }
+        delta
+    }
+
+    /// Whether the policies are equivalent.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.is_addition_only()
+    }
+
+    /// Whether the new policy only allows more than the old one.
+    pub fn is_addition_only(&self) -> bool {
+        self.removed.is_empty() && self.denied.is_empty() && self.undenied.is_empty()
+    }
+
+    fn compare(
+        &mut self,
+        old: &[PathBuf],
+        new: &[PathBuf],
+        grant: fn(PathBuf) -> Grant,
+        side: Side,
+    ) {
+        let (added, removed) = match side {
+            Side::Allow => (&mut self.added, &mut self.removed),
+            Side::Deny => (&mut self.denied, &mut self.undenied),
+        };
+        added.extend(
+            new.iter()
+                .filter(|path| !old.contains(path))
+                .cloned()
+                .map(grant),
+        );
+        removed.extend(
+            old.iter()
+                .filter(|path| !new.contains(path))
+                .cloned()
+                .map(grant),
+        );
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Side {
+    Allow,
+    Deny,
+}
+
+/// A grant that is only valid until `expires_at`.
+#[derive(Debug, Clone)]
+pub struct TimedGrant {
+    pub grant: Grant,
+    pub expires_at: Instant,
+}
+
+/// Base permissions plus grants that expire after a while.
+#[derive(Debug, Clone, Default)]
+pub struct ExpiringPermissions {
+    pub base: Permissions,
+    pub grants: Vec<TimedGrant>,
+}
+
+impl ExpiringPermissions {
+    /// Create expiring permissions on top of a base policy.
+    pub fn new(base: Permissions) -> Self {
+        Self {
+            base,
+            grants: Vec::new(),
+        }
+    }
+
+    /// Add a grant that expires after `ttl` (e.g. network for the first 10 minutes).
+    pub fn grant_for(&mut self, grant: Grant, ttl: Duration) {
+        self.grants.push(TimedGrant {
+            grant,
+            expires_at: Instant::now() + ttl,
+        });
+    }
+
+    /// Permissions in effect at `now`.
+    pub fn effective(&self, now: Instant) -> Permissions {
+        let mut permissions = self.base.clone();
+        for timed in self.grants.iter().filter(|timed| timed.expires_at > now) {
+            timed.grant.apply(&mut permissions);
+        }
+        permissions
+    }
+
+    /// Earliest expiry among the remaining grants.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.grants.iter().map(|timed| timed.expires_at).min()
+    }
+
+    /// Drop grants that have expired at `now`, returning whether any were removed.
+    pub fn prune(&mut self, now: Instant) -> bool {
+        let before = self.grants.len();
+        self.grants.retain(|timed| timed.expires_at > now);
+        self.grants.len() != before
+    }
+}
+
+/// A session whose profile is regenerated, and the server restarted, when grants expire.
+#[derive(Debug)]
+pub struct ExpiringSession {
+    session: JupyterSession,
+    template: String,
+    permissions: ExpiringPermissions,
+}
+
+impl ExpiringSession {
+    /// Start the server under the currently effective permissions.
+    pub fn start(
+        template: &str,
+        permissions: ExpiringPermissions,
+        config: SessionConfig,
+    ) -> Result<Self> {
+        let profile = generate_profile(template, &permissions.effective(Instant::now()))?;
+        let session = JupyterSession::spawn(&profile, config)?;
+
+        Ok(Self {
+            session,
+            template: template.to_string(),
+            permissions,
+        })
+    }
+
+    /// Underlying Jupyter session.
+    pub fn session(&self) -> &JupyterSession {
+        &self.session
+    }
+
+    /// Permissions managed by the session.
+    pub fn permissions(&self) -> &ExpiringPermissions {
+        &self.permissions
+    }
+
+    /// Time left until the next grant expires.
+    pub fn time_until_next_expiry(&self) -> Option<Duration> {
+        self.permissions
+            .next_expiry()
+            .map(|expiry| expiry.saturating_duration_since(Instant::now()))
+    }
+
+    /// Downgrade the session if grants have expired, returning whether it was restarted.
+    pub fn poll(&mut self) -> Result<bool> {
+        let now = Instant::now();
+        if !self.permissions.prune(now) {
+            return Ok(false);
+        }
+
+        let profile = generate_profile(&self.template, &self.permissions.effective(now))?;
+        self.session.restart(&profile)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expired_grants_are_dropped() {
+        let mut permissions = ExpiringPermissions::new(Permissions::new());
+        permissions.grant_for(Grant::Net, Duration::from_secs(600));
+        permissions.grant_for(Grant::Read(PathBuf::from("/tmp")), Duration::from_secs(60));
+
+        let now = Instant::now();
+        let effective = permissions.effective(now);
+        assert!(effective.allow_net);
+        assert_eq!(effective.allow_read, vec![PathBuf::from("/tmp")]);
+
+        let later = now + Duration::from_secs(120);
+        let effective = permissions.effective(later);
+        assert!(effective.allow_net);
+        assert!(effective.allow_read.is_empty());
+
+        assert!(permissions.prune(later));
+        assert_eq!(permissions.grants.len(), 1);
+        assert!(!permissions.prune(later));
+    }
+
+    #[test]
+    fn test_permission_delta() {
+        let mut old = Permissions::new();
+        old.allow_read.push(PathBuf::from("/data"));
+        let mut new = old.clone();
+        assert!(PermissionDelta::between(&old, &new).is_empty());
+
+        new.allow_read.push(PathBuf::from("/models"));
+        new.allow_net = true;
+        new.allow_gpu = true;
+        let delta = PermissionDelta::between(&old, &new);
+        assert_eq!(
+            delta.added,
+            vec![
+                Grant::Read(PathBuf::from("/models")),
+                Grant::Net,
+                Grant::Gpu
+            ]
+        );
+        assert!(delta.is_addition_only());
+
+        new.deny_write.push(PathBuf::from("/data"));
+        let delta = PermissionDelta::between(&old, &new);
+        assert_eq!(delta.denied, vec![Grant::Write(PathBuf::from("/data"))]);
+        assert!(!delta.is_addition_only());
+
+        let delta = PermissionDelta::between(&new, &old);
+        assert_eq!(delta.removed.len(), 3);
+        assert_eq!(delta.undenied.len(), 1);
+    }
+}
@@ -0,0 +1,241 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+
+use crate::api::ApiState;
+use crate::error::{Result, SecureNotebookError};
+use crate::manager::SessionInfo;
+use crate::notebook::{self, CellResult};
+use crate::violations::Violation;
+use crate::Permissions;
+
+/// Types generated from `proto/secure_notebook.proto`.
+pub mod proto {
+    tonic::include_proto!("secure_notebook.v1");
+}
+
+use proto::session_service_server::{SessionService, SessionServiceServer};
+
+/// gRPC implementation of the session-management operations of [`crate::api`].
+#[derive(Clone)]
+pub struct GrpcService {
+    state: Arc<ApiState>,
+}
+
+impl GrpcService {
+    pub fn new(state: Arc<ApiState>) -> Self {
+        Self { state }
+    }
+
+    /// The service, ready to be added to a `tonic` server.
+    pub fn into_server(self) -> SessionServiceServer<Self> {
+        SessionServiceServer::new(self)
+    }
+}
+
+/// Serve the gRPC API on `addr` until the server fails.
+pub async fn serve(state: Arc<ApiState>, addr: SocketAddr) -> Result<()> {
+    tonic::transport::Server::builder()
+        .add_service(GrpcService::new(state).into_server())
+        .serve(addr)
+        .await
+        .map_err(|e| SecureNotebookError::Network(format!("gRPC server failed: {e}")))
+}
+
+/// Run blocking crate calls off the async runtime.
+async fn blocking<T: Send + 'static>(
+    task: impl FnOnce() -> Result<T> + Send + 'static,
+) -> std::result::Result<T, Status> {
+    tokio::task::spawn_blocking(task)
+        .await
+        .map_err(|e| Status::internal(format!("Task failed: {e}")))?
+        .map_err(status)
+}
+
+fn status(error: SecureNotebookError) -> Status {
+    let message = error.to_string();
+    match error {
+        SecureNotebookError::InvalidPath { .. } | SecureNotebookError::InvalidPolicy(_) => {
+            Status::invalid_argument(message)
+        }
+        SecureNotebookError::InvalidState(_) => Status::failed_precondition(message),
+        SecureNotebookError::KernelTimeout(_) => Status::deadline_exceeded(message),
+        SecureNotebookError::Unsupported(_) => Status::unimplemented(message),
+        _ => Status::internal(message),
+    }
+}
+
+#[tonic::async_trait]
+impl SessionService for GrpcService {
+    async fn list_sessions(
+        &self,
+        _request: Request<proto::ListSessionsRequest>,
+    ) -> std::result::Result<Response<proto::ListSessionsResponse>, Status> {
+        let sessions = self.state.list().into_iter().map(Into::into).collect();
+        Ok(Response::new(proto::ListSessionsResponse { sessions }))
+    }
+
+    async fn create_session(
+        &self,
+        request: Request<proto::CreateSessionRequest>,
+    ) -> std::result::Result<Response<proto::CreateSessionResponse>, Status> {
+        let request = request.into_inner();
+        let policy = request.policy.map(Permissions::from).unwrap_or_default();
+        let state = Arc::clone(&self.state);
+        let created = blocking(move || state.create(&request.user, policy)).await?;
+        Ok(Response::new(proto::CreateSessionResponse {
+            session: Some(created.session.into()),
+            token: created.token,
+        }))
+    }
+
+    async fn get_session(
+        &self,
+        request: Request<proto::GetSessionRequest>,
+    ) -> std::result::Result<Response<proto::Session>, Status> {
+        let info = self
+            .state
+            .inspect(&request.into_inner().user)
+            .map_err(status)?;
+        Ok(Response::new(info.into()))
+    }
+
+    async fn terminate_session(
+        &self,
+        request: Request<proto::TerminateSessionRequest>,
+    ) -> std::result::Result<Response<proto::TerminateSessionResponse>, Status> {
+        let user = request.into_inner().user;
+        let state = Arc::clone(&self.state);
+        blocking(move || state.terminate(&user)).await?;
+        Ok(Response::new(proto::TerminateSessionResponse {}))
+    }
+
+    async fn execute(
+        &self,
+        request: Request<proto::ExecuteRequest>,
+    ) -> std::result::Result<Response<proto::CellResult>, Status> {
+        let request = request.into_inner();
+        let state = Arc::clone(&self.state);
+        let result = blocking(move || state.execute(&request.user, &request.code)).await?;
+        Ok(Response::new(result.into()))
+    }
+
+    async fn list_violations(
+        &self,
+        _request: Request<proto::ListViolationsRequest>,
+    ) -> std::result::Result<Response<proto::ListViolationsResponse>, Status> {
+        let violations = self
+            .state
+            .violations()
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        Ok(Response::new(proto::ListViolationsResponse { violations }))
+    }
+}
+
+fn paths(paths: Vec<String>) -> Vec<PathBuf> {
+    paths.into_iter().map(PathBuf::from).collect()
+}
+
+impl From<proto::Policy> for Permissions {
+    fn from(policy: proto::Policy) -> Self {
+        Self {
+            allow_read: paths(policy.allow_read),
+            deny_read: paths(policy.deny_read),
+            allow_write: paths(policy.allow_write),
+            deny_write: paths(policy.deny_write),
+            allow_net: policy.allow_net,
+            allow_gpu: policy.allow_gpu,
+            allow_run: paths(policy.allow_run),
+            deny_run: paths(policy.deny_run),
+        }
+    }
+}
+
+impl From<SessionInfo> for proto::Session {
+    fn from(info: SessionInfo) -> Self {
+        Self {
+            user: info.user,
+            dir: info.dir.to_string_lossy().into_owned(),
+            port: info.port.into(),
+            url: info.url,
+            pid: info.pid,
+            running: info.running,
+            fingerprint: info.fingerprint,
+        }
+    }
+}
+
+impl From<notebook::ExecutionError> for proto::ExecutionError {
+    fn from(error: notebook::ExecutionError) -> Self {
+        Self {
+            ename: error.ename,
+            evalue: error.evalue,
+            traceback: error.traceback,
+        }
+    }
+}
+
+impl From<notebook::Output> for proto::Output {
+    fn from(output: notebook::Output) -> Self {
+        use proto::output::Kind;
+
+        let kind = match output {
+            notebook::Output::Stream { name, text } => Kind::Stream(proto::Stream { name, text }),
+            notebook::Output::Rich { data, .. } => Kind::Rich(proto::RichOutput {
+                // JSON values, e.g. `application/json`, are sent serialized.
+                data: data
+                    .into_iter()
+                    .map(|(mime, value)| match value {
+                        serde_json::Value::String(text) => (mime, text),
+                        value => (mime, value.to_string()),
+                    })
+                    .collect(),
+            }),
+            notebook::Output::Error(error) => Kind::Error(error.into()),
+        };
+        Self { kind: Some(kind) }
+    }
+}
+
+impl From<CellResult> for proto::CellResult {
+    fn from(result: CellResult) -> Self {
+        Self {
+            stdout: result.stdout,
+            stderr: result.stderr,
+            outputs: result.outputs.into_iter().map(Into::into).collect(),
+            error: result.error.map(Into::into),
+            duration_ms: result.duration.as_millis().try_into().unwrap_or(u64::MAX),
+        }
+    }
+}
+
+impl From<Violation> for proto::Violation {
+    fn from(violation: Violation) -> Self {
+        Self {
+            process: violation.process,
+            pid: violation.pid,
+            operation: violation.operation,
+            target: violation.target,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_from_proto() {
+        let policy = proto::Policy {
+            allow_read: vec!["/data".to_string()],
+            allow_net: true,
+            ..proto::Policy::default()
+        };
+        let permissions = Permissions::from(policy);
+        assert_eq!(permissions.allow_read, [PathBuf::from("/data")]);
+        assert!(permissions.allow_net);
+    }
+}
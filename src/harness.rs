@@ -0,0 +1,116 @@
+use std::path::Path;
+use std::time::Duration;
+
+use crate::error::{Result, SecureNotebookError};
+use crate::heartbeat::HeartbeatMonitor;
+use crate::session::{
+    kernel_connection_file, runtime_dir, sandboxed_command, start_kernel, TOKEN_ENV,
+};
+use crate::tokens::ServerToken;
+use crate::watchdog::{CellOutcome, RunReport, Watchdog};
+use crate::wire::{ConnectionInfo, KernelClient};
+
+/// Time given to the Jupyter server to start up.
+pub const STARTUP_DELAY: Duration = Duration::from_secs(5);
+
+/// Port the server listens on, so the harness knows where to start its kernel.
+pub const SERVER_PORT: u16 = 8888;
+
+/// Start `jupyter-server` under `profile`, start a kernel on it and connect a client to
+/// that kernel.
+///
+/// This assumes `jupyter-server` is in `PATH`. The server gets a random token and
+/// is left running; it is meant for integration tests, not for production sessions.
+///
+/// ```no_run
+/// # async fn example() -> secure_notebook::Result<()> {
+/// use secure_notebook::harness::{run_code, setup_jupyter_server};
+/// use secure_notebook::{generate_profile, Permissions, DEFAULT_SANDBOX_PROFILE};
+///
+/// let profile = generate_profile(DEFAULT_SANDBOX_PROFILE, &Permissions::new())?;
+/// let client = setup_jupyter_server(&profile).await?;
+/// assert!(run_code(&client, "open('/etc/master.passwd').read()").await.is_err());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn setup_jupyter_server(profile: &str) -> Result<KernelClient> {
+    let mut command = sandboxed_command(profile, Path::new("jupyter-server"));
+    let token = ServerToken::generate()?;
+    command.args([
+        "--no-browser".to_string(),
+        format!("--port={SERVER_PORT}"),
+        "--ServerApp.port_retries=0".to_string(),
+    ]);
+    command.env(TOKEN_ENV, token.secret());
+
+    tokio::process::Command::from(command)
+        .spawn()
+        .map_err(|source| SecureNotebookError::SpawnFailed {
+            program: "jupyter-server".into(),
+            source,
+        })?;
+
+    // Give the server some time to start up
+    tokio::time::sleep(STARTUP_DELAY).await;
+
+    // Connect to a kernel of this server, not whichever kernel started last
+    let id = start_kernel(
+        &format!("http://localhost:{SERVER_PORT}"),
+        Some(token.secret()),
+    )?;
+    KernelClient::connect(ConnectionInfo::load(&kernel_connection_file(
+        runtime_dir(),
+        &id,
+    )?)?)
+}
+
+/// Execute `code` in the kernel, failing if the kernel reports an error.
+///
+/// Sandbox denials surface as Python exceptions (`PermissionError`), so a denied
+/// operation makes this return [`SecureNotebookError::KernelError`].
+pub async fn run_code(client: &KernelClient, code: &str) -> Result<()> {
+    let reply = client.execute(code, None)?;
+
+    // Check for errors in the reply
+    if reply
+        .content
+        .get("status")
+        .and_then(|status| status.as_str())
+        == Some("error")
+    {
+        return Err(SecureNotebookError::KernelError(format!(
+            "Execution error: {:?}",
+            reply
+                .content
+                .get("evalue")
+                .and_then(|evalue| evalue.as_str())
+        )));
+    }
+
+    Ok(())
+}
+
+/// Execute `cells` in order under `watchdog`, stopping once it has killed the server.
+///
+/// Failed cells do not stop the run; their errors are recorded in the report.
+pub async fn run_cells(client: &KernelClient, cells: &[&str], watchdog: &Watchdog) -> RunReport {
+    for (index, code) in cells.iter().enumerate() {
+        if watchdog.is_expired() {
+            break;
+        }
+        watchdog.start_cell(index);
+        let outcome = match run_code(client, code).await {
+            Ok(()) => CellOutcome::Completed,
+            Err(error) => CellOutcome::Failed(error.to_string()),
+        };
+        watchdog.finish_cell(outcome);
+    }
+    watchdog.report()
+}
+
+/// Monitor the heartbeat of the kernel `client` is connected to.
+///
+/// The kernel is reported unresponsive once it misses heartbeats for `timeout`.
+pub fn monitor_heartbeat(client: &KernelClient, timeout: Duration) -> Result<HeartbeatMonitor> {
+    Ok(HeartbeatMonitor::start(client.heartbeat()?, timeout))
+}
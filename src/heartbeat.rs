@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How often the monitor re-evaluates liveness when no heartbeat arrives.
+const TICK: Duration = Duration::from_millis(50);
+
+/// Whether a kernel is still alive.
+///
+/// The heartbeat channel is answered by its own thread in the kernel, so a kernel busy
+/// computing keeps beating. Missing heartbeats mean the kernel is hung or was killed,
+/// e.g. by the sandbox.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Liveness {
+    Alive,
+    /// No heartbeat within the timeout.
+    Unresponsive,
+    /// The heartbeat channel closed.
+    Dead,
+}
+
+type Callback = Box<dyn FnMut(Liveness) + Send>;
+
+struct Shared {
+    liveness: Liveness,
+    last_beat: Instant,
+    callbacks: Vec<Callback>,
+}
+
+impl Shared {
+    fn set(&mut self, liveness: Liveness) {
+        if self.liveness != liveness {
+            self.liveness = liveness;
+            for callback in &mut self.callbacks {
+                callback(liveness);
+            }
+        }
+    }
+}
+
+/// Tracks the heartbeats of one kernel.
+pub struct HeartbeatMonitor {
+    shared: Arc<Mutex<Shared>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl std::fmt::Debug for HeartbeatMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HeartbeatMonitor")
+            .field("liveness", &self.liveness())
+            .finish_non_exhaustive()
+    }
+}
+
+impl HeartbeatMonitor {
+    /// Watch `beats`, which yields one message per heartbeat answered by the kernel.
+    ///
+    /// The kernel is considered unresponsive once no heartbeat arrived for `timeout`.
+    pub fn start(beats: Receiver<()>, timeout: Duration) -> Self {
+        let shared = Arc::new(Mutex::new(Shared {
+            liveness: Liveness::Alive,
+            last_beat: Instant::now(),
+            callbacks: Vec::new(),
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let shared = Arc::clone(&shared);
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let received = beats.recv_timeout(TICK);
+                    let mut shared = shared.lock().expect("heartbeat state poisoned");
+                    match received {
+                        Ok(()) => {
+                            shared.last_beat = Instant::now();
+                            shared.set(Liveness::Alive);
+                        }
+                        Err(RecvTimeoutError::Timeout) => {
+                            if shared.last_beat.elapsed() > timeout {
+                                shared.set(Liveness::Unresponsive);
+                            }
+                        }
+                        Err(RecvTimeoutError::Disconnected) => {
+                            shared.set(Liveness::Dead);
+                            break;
+                        }
+                    }
+                }
+            })
+        };
+
+        Self {
+            shared,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Current liveness.
+    pub fn liveness(&self) -> Liveness {
+        self.lock().liveness
+    }
+
+    /// Time since the last heartbeat.
+    pub fn since_last_beat(&self) -> Duration {
+        self.lock().last_beat.elapsed()
+    }
+
+    /// Call `callback` whenever the liveness changes.
+    ///
+    /// It runs on the monitor thread with the monitor locked, so it must not call back
+    /// into the monitor.
+    pub fn on_change(&self, callback: impl FnMut(Liveness) + Send + 'static) {
+        self.lock().callbacks.push(Box::new(callback));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Shared> {
+        self.shared.lock().expect("heartbeat state poisoned")
+    }
+}
+
+impl Drop for HeartbeatMonitor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Heartbeat monitors for every managed kernel, by name.
+#[derive(Debug, Default)]
+pub struct KernelHeartbeats {
+    monitors: BTreeMap<String, HeartbeatMonitor>,
+}
+
+impl KernelHeartbeats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start monitoring kernel `name`, replacing any previous monitor for it.
+    pub fn add(&mut self, name: &str, beats: Receiver<()>, timeout: Duration) -> &HeartbeatMonitor {
+        let monitor = HeartbeatMonitor::start(beats, timeout);
+        self.monitors.insert(name.to_string(), monitor);
+        &self.monitors[name]
+    }
+
+    /// Stop monitoring kernel `name`.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.monitors.remove(name).is_some()
+    }
+
+    /// Monitor for kernel `name`.
+    pub fn get(&self, name: &str) -> Option<&HeartbeatMonitor> {
+        self.monitors.get(name)
+    }
+
+    /// Liveness of every kernel.
+    pub fn statuses(&self) -> BTreeMap<String, Liveness> {
+        self.monitors
+            .iter()
+            .map(|(name, monitor)| (name.clone(), monitor.liveness()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_liveness_transitions() {
+        let (sender, beats) = mpsc::channel();
+        let monitor = HeartbeatMonitor::start(beats, Duration::from_millis(100));
+        let (changes, changed) = mpsc::channel();
+        monitor.on_change(move |liveness| {
+            let _ = changes.send(liveness);
+        });
+
+        let wait = Duration::from_secs(5);
+        assert_eq!(changed.recv_timeout(wait), Ok(Liveness::Unresponsive));
+        sender.send(()).unwrap();
+        assert_eq!(changed.recv_timeout(wait), Ok(Liveness::Alive));
+        drop(sender);
+        assert_eq!(changed.recv_timeout(wait), Ok(Liveness::Dead));
+        assert_eq!(monitor.liveness(), Liveness::Dead);
+    }
+}
@@ -0,0 +1,256 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::grants::{Grant, PermissionDelta};
+use crate::Permissions;
+
+/// The policy one run of a notebook or session used.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyRun {
+    /// [`Permissions::fingerprint_hex`] of `permissions`.
+    pub fingerprint: String,
+    /// Unix time in seconds.
+    pub started_at: u64,
+    pub permissions: Permissions,
+}
+
+/// How a run's policy differs from the one of the run before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyChange {
+    pub previous: PolicyRun,
+    pub current: PolicyRun,
+    pub delta: PermissionDelta,
+}
+
+impl fmt::Display for PolicyChange {
+    /// E.g. `requires 3 more paths and network access than 7 days ago`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let age = age(self
+            .current
+            .started_at
+            .saturating_sub(self.previous.started_at));
+        let mut sentences = Vec::new();
+        let added_paths = path_count(&self.delta.added);
+        let access = access(&self.delta.added);
+        if added_paths > 0 {
+            let access: String = access
+                .iter()
+                .map(|access| format!(" and {access}"))
+                .collect();
+            sentences.push(format!(
+                "requires {added_paths} more {}{access} than {age}",
+                plural(added_paths, "path", "paths")
+            ));
+        } else if !access.is_empty() {
+            sentences.push(format!(
+                "requires {}, which it did not {age}",
+                access.join(" and ")
+            ));
+        }
+        if let Some(removed) = describe(&self.delta.removed) {
+            sentences.push(format!("no longer requires {removed}"));
+        }
+        let denies = self.delta.denied.len() + self.delta.undenied.len();
+        if denies > 0 {
+            sentences.push(format!(
+                "changes {denies} {}",
+                plural(denies, "deny", "denies")
+            ));
+        }
+        f.write_str(&sentences.join("; "))
+    }
+}
+
+fn path_count(grants: &[Grant]) -> usize {
+    grants
+        .iter()
+        .filter(|grant| !matches!(grant, Grant::Net | Grant::Gpu))
+        .count()
+}
+
+/// `network access` and `GPU access`, for those of `grants`.
+fn access(grants: &[Grant]) -> Vec<&'static str> {
+    [(Grant::Net, "network access"), (Grant::Gpu, "GPU access")]
+        .into_iter()
+        .filter(|(grant, _)| grants.contains(grant))
+        .map(|(_, access)| access)
+        .collect()
+}
+
+/// `3 paths and network access`, `None` for no grants.
+fn describe(grants: &[Grant]) -> Option<String> {
+    let paths = path_count(grants);
+    let mut parts = Vec::new();
+    if paths > 0 {
+        parts.push(format!("{paths} {}", plural(paths, "path", "paths")));
+    }
+    parts.extend(access(grants).into_iter().map(str::to_string));
+    (!parts.is_empty()).then(|| parts.join(" and "))
+}
+
+fn plural<'a>(count: usize, one: &'a str, many: &'a str) -> &'a str {
+    if count == 1 {
+        one
+    } else {
+        many
+    }
+}
+
+/// `seconds` ago, in the largest whole unit.
+fn age(seconds: u64) -> String {
+    let (count, unit) = match seconds {
+        0..=59 => return "the previous run".to_string(),
+        60..=3_599 => (seconds / 60, "minute"),
+        3_600..=86_399 => (seconds / 3_600, "hour"),
+        86_400..=1_209_599 => (seconds / 86_400, "day"),
+        _ => (seconds / 604_800, "week"),
+    };
+    format!("{count} {unit}{} ago", if count == 1 { "" } else { "s" })
+}
+
+/// Policies of past runs, one JSON line per run in a file per notebook or user.
+#[derive(Debug, Clone)]
+pub struct RunHistory {
+    dir: PathBuf,
+}
+
+impl RunHistory {
+    /// Keep the history in `dir`, creating it if needed.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).io_context(|| format!("Failed to create {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        if key.is_empty() || key.contains(['/', '\0']) || key.starts_with('.') {
+            return Err(SecureNotebookError::InvalidPath {
+                path: PathBuf::from(key),
+                reason: "history keys must be plain file names".to_string(),
+            });
+        }
+        Ok(self.dir.join(format!("{key}.jsonl")))
+    }
+
+    /// Every recorded run under `key`, oldest first.
+    pub fn runs(&self, key: &str) -> Result<Vec<PolicyRun>> {
+        let path = self.path(key)?;
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => {
+                return Err(error).io_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+
+    /// Record a run under `key` starting now, returning how its policy differs from the
+    /// previous run's, if it does.
+    pub fn record(&self, key: &str, permissions: &Permissions) -> Result<Option<PolicyChange>> {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        self.record_at(key, permissions, started_at)
+    }
+
+    /// Like [`Self::record`], for a run that started at `started_at` (Unix seconds).
+    pub fn record_at(
+        &self,
+        key: &str,
+        permissions: &Permissions,
+        started_at: u64,
+    ) -> Result<Option<PolicyChange>> {
+        let previous = self.runs(key)?.pop();
+        let current = PolicyRun {
+            fingerprint: permissions.fingerprint_hex(),
+            started_at,
+            permissions: permissions.clone(),
+        };
+
+        let path = self.path(key)?;
+        let mut line = serde_json::to_string(&current)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .io_context(|| format!("Failed to write {}", path.display()))?;
+
+        Ok(previous
+            .filter(|previous| previous.fingerprint != current.fingerprint)
+            .map(|previous| PolicyChange {
+                delta: PermissionDelta::between(
+                    &previous.permissions.canonicalized(),
+                    &current.permissions.canonicalized(),
+                ),
+                previous,
+                current,
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_change_since_last_run() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let history = RunHistory::open(dir.path())?;
+        let mut permissions = Permissions::new();
+        permissions.allow_read.push(PathBuf::from("/data"));
+        assert_eq!(history.record_at("alice", &permissions, 0)?, None);
+
+        let week = 7 * 86_400;
+        assert_eq!(history.record_at("alice", &permissions, week)?, None);
+
+        permissions.allow_read.push(PathBuf::from("/models"));
+        permissions.allow_write.push(PathBuf::from("/out"));
+        permissions.allow_run.push(PathBuf::from("/usr/bin/git"));
+        permissions.allow_net = true;
+        let change = history
+            .record_at("alice", &permissions, 2 * week)?
+            .expect("the policy changed");
+        assert_eq!(
+            change.to_string(),
+            "requires 3 more paths and network access than 7 days ago"
+        );
+        assert_eq!(history.runs("alice")?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_policy_change_summary() {
+        let run = |started_at| PolicyRun {
+            fingerprint: String::new(),
+            started_at,
+            permissions: Permissions::new(),
+        };
+        let change = PolicyChange {
+            previous: run(0),
+            current: run(3 * 3_600),
+            delta: PermissionDelta {
+                added: vec![Grant::Net, Grant::Gpu],
+                removed: vec![Grant::Read(PathBuf::from("/data"))],
+                ..PermissionDelta::default()
+            },
+        };
+        assert_eq!(
+            change.to_string(),
+            "requires network access and GPU access, which it did not 3 hours ago; \
+             no longer requires 1 path"
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::templates::for_kernel;
+use crate::{generate_profile, Permissions, DEFAULT_SANDBOX_PROFILE};
+
+/// Contents of a `kernel.json`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KernelSpec {
+    pub argv: Vec<String>,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub language: String,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(flatten)]
+    pub rest: BTreeMap<String, serde_json::Value>,
+}
+
+/// An installed kernel with the permissions it needs to start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SandboxedKernelSpec {
+    /// Kernel name, the name of its directory.
+    pub name: String,
+    pub resource_dir: PathBuf,
+    pub spec: KernelSpec,
+    pub template: &'static str,
+    /// Executables in `argv` allowed to run; the resource directory and paths in `env`
+    /// allowed to be read.
+    pub permissions: Permissions,
+}
+
+impl SandboxedKernelSpec {
+    /// Read the kernelspec in `resource_dir`.
+    pub fn read(resource_dir: &Path) -> Result<Self> {
+        let path = resource_dir.join("kernel.json");
+        let contents = std::fs::read_to_string(&path)
+            .io_context(|| format!("Failed to read {}", path.display()))?;
+        let spec: KernelSpec = serde_json::from_str(&contents)
+            .map_err(|e| SecureNotebookError::InvalidPolicy(format!("{}: {e}", path.display())))?;
+        let name = resource_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(Self::new(name, resource_dir.to_path_buf(), spec))
+    }
+
+    /// Tailor a profile to `spec`, picking the template by language and then by name.
+    pub fn new(name: String, resource_dir: PathBuf, spec: KernelSpec) -> Self {
+        let kernel = for_kernel(&spec.language)
+            .or_else(|_| for_kernel(&name))
+            .ok();
+        let mut permissions = Permissions::new();
+        if let Some(kernel) = &kernel {
+            permissions.allow_run.extend_from_slice(&kernel.allow_run);
+        }
+        if let Some(program) = spec.argv.first() {
+            permissions.allow_run.push(resolve_program(program));
+        }
+        permissions.allow_read.push(resource_dir.clone());
+        permissions.allow_read.extend(env_paths(&spec.env));
+
+        Self {
+            name,
+            resource_dir,
+            spec,
+            template: kernel.map_or(DEFAULT_SANDBOX_PROFILE, |kernel| kernel.template),
+            permissions,
+        }
+    }
+
+    /// Profile for the kernel. Add to [`Self::permissions`] first to grant more.
+    pub fn generate_profile(&self) -> Result<String> {
+        generate_profile(self.template, &self.permissions)
+    }
+}
+
+/// Directories `jupyter kernelspec list` searches, most specific first: `JUPYTER_PATH`,
+/// the user's data directories, then the system ones.
+pub fn kernelspec_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = std::env::var_os("JUPYTER_PATH")
+        .map(|paths| std::env::split_paths(&paths).collect())
+        .unwrap_or_default();
+    if let Some(data) = std::env::var_os("JUPYTER_DATA_DIR") {
+        dirs.push(PathBuf::from(data));
+    }
+    if let Some(home) = std::env::var_os("HOME").map(PathBuf::from) {
+        dirs.push(home.join("Library/Jupyter"));
+        dirs.push(home.join(".local/share/jupyter"));
+    }
+    if let Some(prefix) =
+        std::env::var_os("CONDA_PREFIX").or_else(|| std::env::var_os("VIRTUAL_ENV"))
+    {
+        dirs.push(PathBuf::from(prefix).join("share/jupyter"));
+    }
+    dirs.extend(
+        [
+            "/opt/homebrew/share/jupyter",
+            "/usr/local/share/jupyter",
+            "/usr/share/jupyter",
+        ]
+        .map(PathBuf::from),
+    );
+    dirs.into_iter().map(|dir| dir.join("kernels")).collect()
+}
+
+/// Every installed kernel, with a tailored profile each.
+///
+/// A kernel name found in several directories resolves to the first, as in Jupyter.
+/// Unreadable kernelspecs are skipped.
+pub fn discover_kernels() -> Vec<SandboxedKernelSpec> {
+    discover_kernels_in(&kernelspec_dirs())
+}
+
+/// [`discover_kernels`] over `dirs`, each holding one directory per kernel.
+pub fn discover_kernels_in(dirs: &[PathBuf]) -> Vec<SandboxedKernelSpec> {
+    let mut kernels: BTreeMap<String, SandboxedKernelSpec> = BTreeMap::new();
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        let mut resource_dirs: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        resource_dirs.sort();
+        for resource_dir in resource_dirs {
+            if let Ok(kernel) = SandboxedKernelSpec::read(&resource_dir) {
+                kernels.entry(kernel.name.clone()).or_insert(kernel);
+            }
+        }
+    }
+    kernels.into_values().collect()
+}
+
+/// `program` as an absolute path, looked up on `PATH` if it is a bare name.
+fn resolve_program(program: &str) -> PathBuf {
+    let path = Path::new(program);
+    if path.is_absolute() || path.components().count() > 1 {
+        return path.to_path_buf();
+    }
+    std::env::var_os("PATH")
+        .and_then(|paths| {
+            std::env::split_paths(&paths)
+                .map(|dir| dir.join(program))
+                .find(|candidate| candidate.is_file())
+        })
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Absolute paths in environment values, including each entry of `:` separated lists.
+fn env_paths(env: &BTreeMap<String, String>) -> Vec<PathBuf> {
+    env.values()
+        .flat_map(|value| value.split(':'))
+        .filter(|entry| entry.starts_with('/'))
+        .map(PathBuf::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_discover_kernels() -> Result<()> {
+        let user = tempdir().unwrap();
+        let system = tempdir().unwrap();
+        for (root, name, argv) in [
+            (user.path(), "ir", r#"["/opt/R/bin/R", "--slave"]"#),
+            (system.path(), "ir", r#"["/usr/bin/R"]"#),
+            (
+                system.path(),
+                "python3",
+                r#"["/usr/bin/python3", "-m", "ipykernel"]"#,
+            ),
+        ] {
+            std::fs::create_dir_all(root.join(name))?;
+            std::fs::write(
+                root.join(name).join("kernel.json"),
+                format!(
+                    r#"{{"argv": {argv}, "language": "R", "env": {{"R_LIBS": "/opt/lib:rel"}}}}"#
+                ),
+            )?;
+        }
+
+        let kernels =
+            discover_kernels_in(&[user.path().to_path_buf(), system.path().to_path_buf()]);
+        assert_eq!(kernels.len(), 2);
+        let ir = &kernels[0];
+        assert_eq!(ir.template, crate::templates::IRKERNEL);
+        assert!(ir
+            .permissions
+            .allow_run
+            .contains(&PathBuf::from("/opt/R/bin/R")));
+        assert!(ir
+            .permissions
+            .allow_read
+            .contains(&PathBuf::from("/opt/lib")));
+        assert!(!ir.permissions.allow_read.contains(&PathBuf::from("rel")));
+        Ok(())
+    }
+}
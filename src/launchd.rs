@@ -0,0 +1,208 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::backend::SANDBOX_EXEC_PATH;
+use crate::error::{IoContext, Result};
+use crate::sbpl_path;
+use crate::session::SessionConfig;
+
+/// When launchd starts the job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobScope {
+    /// At login, as the user (`~/Library/LaunchAgents`).
+    Agent,
+    /// At boot, as root unless a user is set (`/Library/LaunchDaemons`).
+    Daemon,
+}
+
+impl JobScope {
+    /// Directory the job definition is installed in.
+    pub fn dir(&self, home: &Path) -> PathBuf {
+        match self {
+            Self::Agent => home.join("Library/LaunchAgents"),
+            Self::Daemon => PathBuf::from("/Library/LaunchDaemons"),
+        }
+    }
+}
+
+/// A launchd job running the Jupyter server under a sandbox profile file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LaunchdJob {
+    /// Reverse-DNS job label, e.g. `com.example.secure-notebook`.
+    pub label: String,
+    pub scope: JobScope,
+    /// Where the profile is written, passed to `sandbox-exec -f`.
+    pub profile_path: PathBuf,
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub working_dir: Option<PathBuf>,
+    /// File receiving the server's stdout and stderr.
+    pub log_path: Option<PathBuf>,
+    /// Restart the server whenever it exits.
+    pub keep_alive: bool,
+}
+
+impl LaunchdJob {
+    /// Job running the server described by `config` under the profile at `profile_path`.
+    pub fn new(label: &str, scope: JobScope, profile_path: &Path, config: &SessionConfig) -> Self {
+        Self {
+            label: label.to_string(),
+            scope,
+            profile_path: profile_path.to_path_buf(),
+            program: config.program.clone(),
+            args: config.args.clone(),
+            env: config.env.clone(),
+            working_dir: None,
+            log_path: None,
+            keep_alive: true,
+        }
+    }
+
+    /// Path of the job definition.
+    pub fn plist_path(&self, home: &Path) -> PathBuf {
+        self.scope.dir(home).join(format!("{}.plist", self.label))
+    }
+
+    /// The job definition as a property list.
+    ///
+    /// Fails for paths that are not valid UTF-8, which a property list cannot hold.
+    pub fn render(&self) -> Result<String> {
+        let paths = PlistPaths {
+            profile: sbpl_path(&self.profile_path)?,
+            program: sbpl_path(&self.program)?,
+            working_dir: self.working_dir.as_deref().map(sbpl_path).transpose()?,
+            log: self.log_path.as_deref().map(sbpl_path).transpose()?,
+        };
+        let mut plist = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+             \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+             <plist version=\"1.0\">\n<dict>\n",
+        );
+        self.write_body(&mut plist, &paths)
+            .expect("writing to a String cannot fail");
+        plist.push_str("</dict>\n</plist>\n");
+        Ok(plist)
+    }
+
+    fn write_body(&self, plist: &mut String, paths: &PlistPaths) -> std::fmt::Result {
+        writeln!(
+            plist,
+            "  <key>Label</key>\n  <string>{}</string>",
+            escape(&self.label)
+        )?;
+
+        let arguments = [SANDBOX_EXEC_PATH, "-f", paths.profile, paths.program]
+            .into_iter()
+            .chain(self.args.iter().map(String::as_str));
+        writeln!(plist, "  <key>ProgramArguments</key>\n  <array>")?;
+        for argument in arguments {
+            writeln!(plist, "    <string>{}</string>", escape(argument))?;
+        }
+        writeln!(plist, "  </array>")?;
+
+        if !self.env.is_empty() {
+            writeln!(plist, "  <key>EnvironmentVariables</key>\n  <dict>")?;
+            for (name, value) in &self.env {
+                writeln!(
+                    plist,
+                    "    <key>{}</key>\n    <string>{}</string>",
+                    escape(name),
+                    escape(value)
+                )?;
+            }
+            writeln!(plist, "  </dict>")?;
+        }
+        if let Some(dir) = paths.working_dir {
+            writeln!(
+                plist,
+                "  <key>WorkingDirectory</key>\n  <string>{}</string>",
+                escape(dir)
+            )?;
+        }
+        if let Some(log) = paths.log {
+            let log = escape(log);
+            writeln!(
+                plist,
+                "  <key>StandardOutPath</key>\n  <string>{log}</string>"
+            )?;
+            writeln!(
+                plist,
+                "  <key>StandardErrorPath</key>\n  <string>{log}</string>"
+            )?;
+        }
+        writeln!(plist, "  <key>RunAtLoad</key>\n  <true/>")?;
+        writeln!(
+            plist,
+            "  <key>KeepAlive</key>\n  <{}/>",
+            if self.keep_alive { "true" } else { "false" }
+        )
+    }
+
+    /// Write `profile` and the job definition, returning the definition's path.
+    ///
+    /// Load it with `launchctl bootstrap gui/$UID <path>` (or `system` for daemons).
+    pub fn install(&self, profile: &str, home: &Path) -> Result<PathBuf> {
+        std::fs::write(&self.profile_path, profile)
+            .io_context(|| format!("Failed to write {}", self.profile_path.display()))?;
+
+        let path = self.plist_path(home);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .io_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        std::fs::write(&path, self.render()?)
+            .io_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// Paths of a [`LaunchdJob`], checked to be valid UTF-8.
+struct PlistPaths<'a> {
+    profile: &'a str,
+    program: &'a str,
+    working_dir: Option<&'a str>,
+    log: Option<&'a str>,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let config = SessionConfig {
+            env: vec![(
+                "HTTPS_PROXY".to_string(),
+                "http://127.0.0.1:3128".to_string(),
+            )],
+            ..SessionConfig::default()
+        };
+        let mut job = LaunchdJob::new(
+            "com.example.notebook",
+            JobScope::Agent,
+            Path::new("/Users/me/.notebook/profile.sb"),
+            &config,
+        );
+        let plist = job.render()?;
+
+        assert!(plist.contains("<string>/usr/bin/sandbox-exec</string>\n    <string>-f</string>\n    <string>/Users/me/.notebook/profile.sb</string>\n    <string>jupyter-server</string>"));
+        assert!(plist.contains("<key>HTTPS_PROXY</key>"));
+        assert_eq!(
+            job.plist_path(Path::new("/Users/me")),
+            Path::new("/Users/me/Library/LaunchAgents/com.example.notebook.plist")
+        );
+
+        job.log_path = Some(PathBuf::from(std::ffi::OsStr::from_bytes(b"/tmp/\xff.log")));
+        assert!(job.render().is_err());
+        Ok(())
+    }
+}
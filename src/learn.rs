@@ -0,0 +1,109 @@
+//! Learning mode: run a notebook with everything allowed but reported, and turn what it
+//! did into a minimal policy.
+
+use std::path::Path;
+
+use crate::error::Result;
+use crate::evaluator::{evaluate, parse_rules, Action};
+use crate::limits::consolidate;
+use crate::violations::Violation;
+use crate::Permissions;
+
+/// Rules appended to the template in learning mode. Every operation is allowed and
+/// logged, so one run observes everything the notebook needs.
+pub const LEARNING_RULES: &str = "; learning mode\n(allow (with report) default)\n";
+
+/// The smallest policy allowing `observations` on top of `template`.
+///
+/// Operations the template already allows are left out, as are operations no grant
+/// covers.
+pub fn synthesize_policy(template: &str, observations: &[Violation]) -> Result<Permissions> {
+    let rules = parse_rules(template)?;
+    let mut permissions = Permissions::new();
+    for observation in observations {
+        let path = observation.target.as_deref().map(Path::new);
+        if evaluate(&rules, &observation.operation, path) == Some(Action::Allow) {
+            continue;
+        }
+        if let Some(grant) = observation.to_grant() {
+            grant.apply(&mut permissions);
+        }
+    }
+    Ok(consolidate(&permissions))
+}
+
+#[cfg(feature = "client")]
+pub use recording::record_notebook;
+
+#[cfg(feature = "client")]
+mod recording {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use super::{synthesize_policy, LEARNING_RULES};
+    use crate::error::Result;
+    use crate::notebook::{Notebook, NotebookSession};
+    use crate::session::{JupyterSession, SessionConfig};
+    use crate::violations::ViolationMonitor;
+    use crate::Permissions;
+
+    /// How long to keep reading the log after the run, which lags behind the kernel.
+    const LOG_GRACE: Duration = Duration::from_secs(2);
+
+    /// Run every cell of `notebook` in learning mode and synthesize its policy.
+    ///
+    /// Cells that raise do not stop the recording; their operations are still observed.
+    pub fn record_notebook(
+        notebook: &Path,
+        template: &str,
+        config: SessionConfig,
+    ) -> Result<Permissions> {
+        let notebook = Notebook::load(notebook)?;
+        let mut monitor = ViolationMonitor::start_observing()?;
+        let mut server = JupyterSession::spawn(&format!("{template}{LEARNING_RULES}"), config)?;
+
+        let ran = NotebookSession::start(&server).and_then(|kernel| kernel.run_all(&notebook));
+        let stopped = server.shutdown();
+        let mut observations = Vec::new();
+        while let Some(observation) = monitor.next_timeout(LOG_GRACE) {
+            observations.push(observation);
+        }
+        monitor.stop()?;
+        ran?;
+        stopped?;
+
+        synthesize_policy(template, &observations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn observed(operation: &str, target: &str) -> Violation {
+        Violation {
+            process: "python3".to_string(),
+            pid: 1,
+            operation: operation.to_string(),
+            target: Some(target.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_synthesize_policy() -> Result<()> {
+        let template = "(version 1)\n(deny default)\n(allow file-read* (subpath \"/usr\"))\n";
+        let observations = [
+            observed("file-read-data", "/usr/lib/libz.dylib"),
+            observed("file-read-data", "/data"),
+            observed("file-read-data", "/data"),
+            observed("file-write-create", "/tmp/out.csv"),
+            observed("mach-lookup", "com.apple.system.logger"),
+        ];
+        let permissions = synthesize_policy(template, &observations)?;
+        assert_eq!(permissions.allow_read, [PathBuf::from("/data")]);
+        assert_eq!(permissions.allow_write, [PathBuf::from("/tmp/out.csv")]);
+        assert!(!permissions.allow_net);
+        Ok(())
+    }
+}
@@ -1,16 +1,113 @@
 // `cp /System/Library/Sandbox/Profiles/* sb_references``
 
 pub mod acess_types;
+#[cfg(feature = "api")]
+pub mod api;
+pub mod approval;
+pub mod audit;
+pub mod authproxy;
+pub mod backend;
+pub mod cache;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod comm;
+pub mod compliance;
+pub mod diagnostics;
+pub mod diff;
+pub mod dns;
+pub mod encryption;
+pub mod endpoint_security;
+pub mod error;
+pub mod escapes;
+pub mod evaluator;
+pub mod explain;
+pub mod extension;
+pub mod grants;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "harness")]
+pub mod harness;
+pub mod heartbeat;
+pub mod history;
+pub mod kernelspec;
+pub mod launchd;
+pub mod learn;
+pub mod limits;
+pub mod lint;
+pub mod manager;
+pub mod manifest;
+pub mod netguard;
+#[cfg(feature = "napi")]
+pub mod node;
+pub mod notebook;
+pub mod optimizer;
+pub mod pf;
+pub mod phases;
+pub mod policy;
+pub mod policy_client;
+pub mod ports;
+pub mod presets;
+pub mod quota;
+pub mod probe;
+pub mod provenance;
+pub mod pty;
+pub mod pyproject;
+pub mod remote;
+pub mod resources;
+pub mod session;
+pub mod signing;
+pub mod snapshot;
+pub mod strictness;
+pub mod supervisor;
+pub mod swapping;
+pub mod tcc;
 pub mod templates;
+#[cfg(feature = "proptest")]
+pub mod testing;
+pub mod tokens;
+pub mod trust;
+pub mod violations;
+#[cfg(feature = "client")]
+pub mod wire;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod watch;
+pub mod watchdog;
+pub mod workspace;
 
 use serde::{Serialize, Deserialize};
-use anyhow::Result;
-use std::path::PathBuf;
+pub use error::{Result, SecureNotebookError};
+pub use strictness::Strictness;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::{Path, PathBuf};
 
 pub const DEFAULT_SANDBOX_PROFILE: &str = include_str!("notebook_defaults.sb");
 
+/// Rules for Metal and MPS compute: the GPU driver's user clients, IOSurface for
+/// sharing buffers, the shader compiler service and the driver bundles it loads.
+pub const GPU_RULES: &str = "(allow iokit-open
+    (iokit-connection \"IOAccelerator\")
+    (iokit-user-client-class \"AGXDeviceUserClient\")
+    (iokit-user-client-class \"AGXSharedUserClient\")
+    (iokit-user-client-class \"IOAccelerationUserClient\")
+    (iokit-user-client-class \"AppleGraphicsControlClient\")
+    (iokit-user-client-class \"IOSurfaceRootUserClient\")
+    (iokit-user-client-class \"IOSurfaceSendRight\")
+)
+(allow iokit-get-properties)
+(allow mach-lookup
+    (global-name \"com.apple.MTLCompilerService\")
+    (global-name \"com.apple.gpumemd.source\")
+    (global-name \"com.apple.PowerManagement.control\")
+)
+(allow file-read* (subpath \"/System/Library/Extensions\"))
+(allow user-preference-read (preference-domain \"com.apple.Metal\"))
+";
+
 /// Permissions struct to hold allowed and denied permissions.
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Permissions {
     pub allow_read: Vec<PathBuf>,
     pub deny_read: Vec<PathBuf>,
@@ -18,6 +115,8 @@ pub struct Permissions {
     pub deny_write: Vec<PathBuf>,
     pub allow_net: bool,
     // pub deny_net: bool,
+    /// Metal GPU access, see [`GPU_RULES`].
+    pub allow_gpu: bool,
     pub allow_run: Vec<PathBuf>,
     pub deny_run: Vec<PathBuf>,
 }
@@ -52,11 +151,36 @@ impl Permissions {
         Ok(())
     }
 
+    /// Allow reading `path` and deny writing it.
+    ///
+    /// Allows win over denies, so a broader `allow_write` covering `path` still lets
+    /// it be written; [`lint::lint_permissions`] warns about that.
+    pub fn read_only(&mut self, path: PathBuf) -> Result<()> {
+        sbpl_path(&path)?;
+        self.allow_read.push(path.clone());
+        self.deny_write.push(path);
+        Ok(())
+    }
+
+    /// Deny reading and writing `path`. As with [`Permissions::read_only`], a broader
+    /// allow covering `path` still wins.
+    pub fn no_access(&mut self, path: PathBuf) -> Result<()> {
+        sbpl_path(&path)?;
+        self.deny_read.push(path.clone());
+        self.deny_write.push(path);
+        Ok(())
+    }
+
     /// Allow network access.
     fn allow_net(&mut self) {
         self.allow_net = true;
     }
 
+    /// Allow Metal GPU compute, e.g. for PyTorch's MPS backend.
+    pub fn allow_gpu(&mut self) {
+        self.allow_gpu = true;
+    }
+
     /// Allow execution of specified programs (supports glob patterns).
     fn allow_run(&mut self, programs: Vec<PathBuf>) {
         self.allow_run = programs;
@@ -66,55 +190,265 @@ impl Permissions {
     fn deny_run(&mut self, programs: Vec<PathBuf>) {
         self.deny_run = programs;
     }
+
+    /// Copy of the permissions with every list sorted and deduplicated, so that
+    /// equivalent permissions compare and hash the same.
+    pub fn canonicalized(&self) -> Self {
+        let canonical = |paths: &[PathBuf]| {
+            let mut paths = paths.to_vec();
+            paths.sort();
+            paths.dedup();
+            paths
+        };
+
+        Self {
+            allow_read: canonical(&self.allow_read),
+            deny_read: canonical(&self.deny_read),
+            allow_write: canonical(&self.allow_write),
+            deny_write: canonical(&self.deny_write),
+            allow_net: self.allow_net,
+            allow_gpu: self.allow_gpu,
+            allow_run: canonical(&self.allow_run),
+            deny_run: canonical(&self.deny_run),
+        }
+    }
+
+    /// SHA-256 over the canonicalized permissions, a stable identifier for
+    /// correlating runs, caches and audit records.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        let canonical = self.canonicalized();
+        let mut hasher = Sha256::new();
+
+        let mut hash_paths = |name: &str, paths: &[PathBuf]| {
+            hasher.update(name.as_bytes());
+            hasher.update((paths.len() as u64).to_le_bytes());
+            for path in paths {
+                let bytes = path.as_os_str().as_encoded_bytes();
+                hasher.update((bytes.len() as u64).to_le_bytes());
+                hasher.update(bytes);
+            }
+        };
+        hash_paths("allow_read", &canonical.allow_read);
+        hash_paths("deny_read", &canonical.deny_read);
+        hash_paths("allow_write", &canonical.allow_write);
+        hash_paths("deny_write", &canonical.deny_write);
+        hash_paths("allow_run", &canonical.allow_run);
+        hash_paths("deny_run", &canonical.deny_run);
+        hasher.update(b"allow_net");
+        hasher.update([canonical.allow_net as u8]);
+        // only when set, so fingerprints recorded before the field existed still match
+        if canonical.allow_gpu {
+            hasher.update(b"allow_gpu");
+        }
+
+        hasher.finalize().into()
+    }
+
+    /// Hex encoded [`Permissions::fingerprint`].
+    pub fn fingerprint_hex(&self) -> String {
+        self.fingerprint()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
 }
 
-pub fn validate_paths(paths: Vec<PathBuf>) -> Result<Vec<PathBuf>, std::io::Error> {
+/// Check that every path exists and can be written into a rule, see [`sbpl_path`].
+///
+/// On wasm32 there is no host filesystem to check against, so paths are taken as given.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn validate_paths(paths: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
     paths
         .into_iter()
         .map(|path| {
+            sbpl_path(&path)?;
             if path.exists() {
                 Ok(path)
             } else {
-                Err(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    format!("Path does not exist: {}", path.display()),
-                ))
+                Err(SecureNotebookError::InvalidPath {
+                    path,
+                    reason: "Path does not exist".to_string(),
+                })
             }
         })
         .collect()
 }
 
+#[cfg(target_arch = "wasm32")]
+pub fn validate_paths(paths: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+    check_paths(&paths)?;
+    Ok(paths)
+}
+
+/// `path` as written into a rule.
+///
+/// Rules are text, so a path that is not valid UTF-8 is an error: converting it lossily
+/// would produce a rule that never matches, silently dropping a deny.
+pub fn sbpl_path(path: &Path) -> Result<&str> {
+    path.to_str().ok_or_else(|| SecureNotebookError::InvalidPath {
+        path: path.to_path_buf(),
+        reason: "Path is not valid UTF-8, so no rule can match it".to_string(),
+    })
+}
+
+/// Check every path with [`sbpl_path`].
+fn check_paths<'a>(paths: impl IntoIterator<Item = &'a PathBuf>) -> Result<()> {
+    for path in paths {
+        sbpl_path(path)?;
+    }
+    Ok(())
+}
+
+/// Check every path in `permissions` with [`sbpl_path`], before rendering any rule.
+pub(crate) fn check_permission_paths(permissions: &Permissions) -> Result<()> {
+    check_paths(
+        permissions
+            .allow_read
+            .iter()
+            .chain(&permissions.deny_read)
+            .chain(&permissions.allow_write)
+            .chain(&permissions.deny_write)
+            .chain(&permissions.allow_run)
+            .chain(&permissions.deny_run),
+    )
+}
+
 /// Function to generate the sandbox profile based on permissions.
 pub fn generate_profile(template: &str, permissions: &Permissions) -> Result<String> {
-    let mut profile = String::from(template);
+    let mut profile = String::with_capacity(profile_capacity(template, permissions));
+    generate_profile_to(&mut profile, template, permissions)?;
+    Ok(profile)
+}
+
+/// Function to generate the sandbox profile with the template tuned to `strictness`.
+pub fn generate_profile_with_strictness(
+    template: &str,
+    permissions: &Permissions,
+    strictness: Strictness,
+) -> Result<String> {
+    let rules = strictness.rules();
+    let mut profile =
+        String::with_capacity(profile_capacity(template, permissions) + rules.len());
+    profile.push_str(template);
+    profile.push_str(rules);
+    generate_profile_to(&mut profile, "", permissions)?;
+    Ok(profile)
+}
+
+/// Upper bound on the rendered profile length, so it can be built without reallocating.
+pub fn profile_capacity(template: &str, permissions: &Permissions) -> usize {
+    // `(deny file-write* (subpath "` + `"))\n`, the longest per-path overhead
+    const RULE_OVERHEAD: usize = 40;
+    // `(allow file-write*\n` ... `)\n` and `(allow network*)\n`
+    const BLOCK_OVERHEAD: usize = 24;
+
+    let paths = [
+        &permissions.allow_read,
+        &permissions.deny_read,
+        &permissions.allow_write,
+        &permissions.deny_write,
+        &permissions.allow_run,
+        &permissions.deny_run,
+    ];
+    let rules: usize = paths
+        .iter()
+        .flat_map(|paths| paths.iter())
+        .map(|path| path.as_os_str().len() + RULE_OVERHEAD)
+        .sum();
+
+    let gpu = if permissions.allow_gpu { GPU_RULES.len() } else { 0 };
+    template.len() + rules + gpu + 4 * BLOCK_OVERHEAD
+}
+
+/// Function to generate the sandbox profile into a writer, without building
+/// intermediate strings for each rule.
+pub fn generate_profile_to<W: fmt::Write>(
+    writer: &mut W,
+    template: &str,
+    permissions: &Permissions,
+) -> Result<()> {
+    check_permission_paths(permissions)?;
+    write_profile(writer, template, permissions).map_err(|_| SecureNotebookError::Io {
+        context: "Failed to write profile".to_string(),
+        source: std::io::Error::other("formatter error"),
+    })
+}
+
+/// Function to stream the sandbox profile into an `io::Write` sink, e.g. a file.
+pub fn write_profile_to<W: std::io::Write>(
+    writer: W,
+    template: &str,
+    permissions: &Permissions,
+) -> Result<()> {
+    check_permission_paths(permissions)?;
+    let mut adapter = IoAdapter {
+        inner: writer,
+        error: None,
+    };
+    if write_profile(&mut adapter, template, permissions).is_err() {
+        let source = adapter
+            .error
+            .unwrap_or_else(|| std::io::Error::other("formatter error"));
+        return Err(SecureNotebookError::Io {
+            context: "Failed to write profile".to_string(),
+            source,
+        });
+    }
+    Ok(())
+}
+
+/// Bridges `fmt::Write` emitters onto an `io::Write` sink, keeping the I/O error.
+struct IoAdapter<W> {
+    inner: W,
+    error: Option<std::io::Error>,
+}
+
+impl<W: std::io::Write> fmt::Write for IoAdapter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+fn write_profile<W: fmt::Write>(
+    writer: &mut W,
+    template: &str,
+    permissions: &Permissions,
+) -> fmt::Result {
+    writer.write_str(template)?;
 
     // Generate file read permissions
-    profile.push_str(&generate_file_permissions(
+    write_file_permissions(
+        writer,
         "file-read*",
         &permissions.allow_read,
         &permissions.deny_read,
-    ));
+    )?;
 
     // Generate file write permissions
-    profile.push_str(&generate_file_permissions(
+    write_file_permissions(
+        writer,
         "file-write*",
         &permissions.allow_write,
         &permissions.deny_write,
-    ));
+    )?;
 
     // Generate network permissions
-    profile.push_str(&generate_network_permissions(
+    write_network_permissions(
+        writer,
         permissions.allow_net,
         // permissions.deny_net,
-    ));
+    )?;
 
-    // Generate process execution permissions
-    profile.push_str(&generate_run_permissions(
-        &permissions.allow_run,
-        &permissions.deny_run,
-    ));
+    // Generate GPU permissions
+    if permissions.allow_gpu {
+        writer.write_str(GPU_RULES)?;
+    }
 
-    Ok(profile)
+    // Generate process execution permissions
+    write_run_permissions(writer, &permissions.allow_run, &permissions.deny_run)
 }
 
 /// Helper function to generate file permissions.
@@ -122,70 +456,105 @@ pub fn generate_file_permissions(
     access_type: &str,
     allow_paths: &[PathBuf],
     deny_paths: &[PathBuf],
-) -> String {
+) -> Result<String> {
+    check_paths(allow_paths.iter().chain(deny_paths))?;
     let mut statement = String::new();
+    write_file_permissions(&mut statement, access_type, allow_paths, deny_paths)
+        .expect("paths were checked");
+    Ok(statement)
+}
 
+/// Helper function to write file permissions.
+pub fn write_file_permissions<W: fmt::Write>(
+    writer: &mut W,
+    access_type: &str,
+    allow_paths: &[PathBuf],
+    deny_paths: &[PathBuf],
+) -> fmt::Result {
     for path in deny_paths {
-        statement.push_str(&format!(
-            "(deny {} (subpath \"{}\"))\n",
-            access_type,
-            path.to_string_lossy().to_string()
-        ));
+        writer.write_str("(deny ")?;
+        writer.write_str(access_type)?;
+        writer.write_str(" (subpath \"")?;
+        write_path(writer, path)?;
+        writer.write_str("\"))\n")?;
     }
 
     if !allow_paths.is_empty() {
-        statement.push_str(&format!("(allow {}\n", access_type));
+        writer.write_str("(allow ")?;
+        writer.write_str(access_type)?;
+        writer.write_str("\n")?;
         for path in allow_paths {
             let file_type = if path.is_dir() { "subpath" } else { "literal" };
-            statement.push_str(&format!(
-                "    ({} \"{}\")\n",
-                file_type,
-                path.to_string_lossy().to_string()
-            ));
+            writer.write_str("    (")?;
+            writer.write_str(file_type)?;
+            writer.write_str(" \"")?;
+            write_path(writer, path)?;
+            writer.write_str("\")\n")?;
         }
-        statement.push_str(")\n");
+        writer.write_str(")\n")?;
     }
 
-    statement
+    Ok(())
 }
 
 /// Helper function to generate network permissions.
-fn generate_network_permissions(allow_net: bool) -> String {
+pub fn generate_network_permissions(allow_net: bool) -> String {
     let mut statement = String::new();
+    write_network_permissions(&mut statement, allow_net)
+        .expect("writing to a String cannot fail");
+    statement
+}
 
+/// Helper function to write network permissions.
+fn write_network_permissions<W: fmt::Write>(writer: &mut W, allow_net: bool) -> fmt::Result {
     if allow_net {
-        statement.push_str("(allow network*)\n");
+        writer.write_str("(allow network*)\n")?;
     }
     // else if deny_net {
-    //     statement.push_str("(deny network*)\n");
+    //     writer.write_str("(deny network*)\n")?;
     // }
 
-    statement
+    Ok(())
 }
 
 /// Helper function to generate process execution permissions.
-fn generate_run_permissions(allow_progs: &[PathBuf], deny_progs: &[PathBuf]) -> String {
+fn generate_run_permissions(allow_progs: &[PathBuf], deny_progs: &[PathBuf]) -> Result<String> {
+    check_paths(allow_progs.iter().chain(deny_progs))?;
     let mut statement = String::new();
+    write_run_permissions(&mut statement, allow_progs, deny_progs)
+        .expect("paths were checked");
+    Ok(statement)
+}
 
+/// Helper function to write process execution permissions.
+fn write_run_permissions<W: fmt::Write>(
+    writer: &mut W,
+    allow_progs: &[PathBuf],
+    deny_progs: &[PathBuf],
+) -> fmt::Result {
     for prog in deny_progs {
-        statement.push_str(&format!(
-            "(deny process-exec (literal \"{}\"))\n",
-            prog.to_string_lossy().to_string()
-        ));
+        writer.write_str("(deny process-exec (literal \"")?;
+        write_path(writer, prog)?;
+        writer.write_str("\"))\n")?;
     }
 
     if !allow_progs.is_empty() {
-        statement.push_str("(allow process-exec\n");
+        writer.write_str("(allow process-exec\n")?;
         for prog in allow_progs {
-            statement.push_str(&format!(
-                "    (literal \"{}\")\n",
-                prog.to_string_lossy().to_string()
-            ));
+            writer.write_str("    (literal \"")?;
+            write_path(writer, prog)?;
+            writer.write_str("\")\n")?;
         }
-        statement.push_str(")\n");
+        writer.write_str(")\n")?;
     }
 
-    statement
+    Ok(())
+}
+
+/// Helper function to write a path without allocating. Fails on paths that are not
+/// valid UTF-8, which callers reject up front with [`sbpl_path`].
+fn write_path<W: fmt::Write>(writer: &mut W, path: &Path) -> fmt::Result {
+    writer.write_str(path.to_str().ok_or(fmt::Error)?)
 }
 
 /// Function to minify the sandbox profile.
@@ -213,11 +582,7 @@ pub fn minify_profile(profile: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use jupyter_client::Client;
-    use std::collections::HashMap;
-    use std::time::Duration;
     use tempfile::tempdir;
-    use tokio;
 
     #[test]
     fn test_minify_profile() {
@@ -233,6 +598,65 @@ mod tests {
         assert_eq!(minified, "(version 1) (deny default) (allow file-read*)");
     }
 
+    #[test]
+    fn test_fingerprint_is_canonical() {
+        let mut first = Permissions::new();
+        first.allow_run = vec![PathBuf::from("python"), PathBuf::from("jupyter")];
+        let mut second = Permissions::new();
+        second.allow_run = vec![PathBuf::from("jupyter"), PathBuf::from("python")];
+        assert_eq!(first.fingerprint(), second.fingerprint());
+
+        second.deny_run = second.allow_run.clone();
+        second.allow_run.clear();
+        assert_ne!(first.fingerprint(), second.fingerprint());
+        assert_eq!(first.fingerprint_hex().len(), 64);
+    }
+
+    #[test]
+    fn test_read_only_and_no_access() -> Result<()> {
+        use crate::evaluator::{evaluate_profile, Action};
+
+        let mut permissions = Permissions::new();
+        permissions.read_only(PathBuf::from("/data/raw"))?;
+        permissions.no_access(PathBuf::from("/data/secrets"))?;
+        let profile = generate_profile("(version 1)\n(allow default)\n", &permissions)?;
+
+        let decide = |operation, path| evaluate_profile(&profile, operation, Some(Path::new(path)));
+        assert_eq!(decide("file-read-data", "/data/raw/a.csv")?, Some(Action::Allow));
+        assert_eq!(decide("file-write-data", "/data/raw/a.csv")?, Some(Action::Deny));
+        assert_eq!(decide("file-read-data", "/data/secrets/key")?, Some(Action::Deny));
+        assert_eq!(decide("file-write-data", "/data/secrets/key")?, Some(Action::Deny));
+        Ok(())
+    }
+
+    #[test]
+    fn test_gpu_permissions() -> Result<()> {
+        let mut permissions = Permissions::new();
+        assert!(!generate_profile("(version 1)\n", &permissions)?.contains("iokit-open"));
+
+        permissions.allow_gpu();
+        let profile = generate_profile("(version 1)\n", &permissions)?;
+        assert!(profile.contains("(iokit-user-client-class \"IOSurfaceRootUserClient\")"));
+        assert!(profile.len() <= profile_capacity("(version 1)\n", &permissions));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_path_is_an_error() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut permissions = Permissions::new();
+        permissions.deny_read = vec![PathBuf::from(OsStr::from_bytes(b"/data/caf\xe9"))];
+        assert!(matches!(
+            generate_profile("(version 1)\n", &permissions),
+            Err(SecureNotebookError::InvalidPath { .. })
+        ));
+        assert!(write_profile_to(Vec::new(), "(version 1)\n", &permissions).is_err());
+        assert!(validate_paths(permissions.deny_read.clone()).is_err());
+    }
+
     #[test]
     fn test_nonexistent_path() {
         let result =
@@ -241,14 +665,65 @@ mod tests {
     }
 
     #[test]
-    fn test_file_permissions_generation() {
+    fn test_file_permissions_generation() -> Result<()> {
         let allow_paths = vec![PathBuf::from("/tmp/allowed")];
         let deny_paths = vec![PathBuf::from("/tmp/denied")];
-        let permissions = generate_file_permissions("file-read*", &allow_paths, &deny_paths);
+        let permissions = generate_file_permissions("file-read*", &allow_paths, &deny_paths)?;
 
         assert!(permissions.contains("(deny file-read* (subpath \"/tmp/denied\"))"));
         assert!(permissions.contains("(allow file-read*)"));
         assert!(permissions.contains("(subpath \"/tmp/allowed\")"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_profile_to_io_sink() -> Result<()> {
+        let mut permissions = Permissions::new();
+        permissions.allow_run = vec![PathBuf::from("python")];
+        let template = "(version 1)\n(deny default)\n";
+
+        let mut streamed = Vec::new();
+        write_profile_to(&mut streamed, template, &permissions)?;
+        assert_eq!(
+            String::from_utf8(streamed).unwrap(),
+            generate_profile(template, &permissions)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_capacity_is_an_upper_bound() -> Result<()> {
+        let mut permissions = Permissions::new();
+        permissions.deny_write = (0..100)
+            .map(|i| PathBuf::from(format!("/data/set/{i}")))
+            .collect();
+        permissions.allow_run = vec![PathBuf::from("python")];
+        permissions.allow_net = true;
+        let template = "(version 1)\n(deny default)\n";
+
+        let profile = generate_profile(template, &permissions)?;
+        assert!(profile.len() <= profile_capacity(template, &permissions));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strictness_precedes_permissions() -> Result<()> {
+        let mut permissions = Permissions::new();
+        permissions.allow_run.push(PathBuf::from("/bin/sh"));
+        let profile = generate_profile_with_strictness(
+            "(version 1)\n",
+            &permissions,
+            Strictness::Paranoid,
+        )?;
+        let fork = profile.find("(deny process-fork)").unwrap();
+        assert!(fork < profile.find("/bin/sh").unwrap());
+        assert_eq!(
+            generate_profile_with_strictness("(version 1)\n", &permissions, Strictness::Standard)?,
+            generate_profile("(version 1)\n", &permissions)?
+        );
+        Ok(())
     }
 
     #[test]
@@ -261,15 +736,16 @@ mod tests {
     }
 
     #[test]
-    fn test_run_permissions_generation() {
+    fn test_run_permissions_generation() -> Result<()> {
         let allow_progs = vec![PathBuf::from("jupyter"), PathBuf::from("python")];
         let deny_progs = vec![PathBuf::from("bash")];
-        let permissions = generate_run_permissions(&allow_progs, &deny_progs);
+        let permissions = generate_run_permissions(&allow_progs, &deny_progs)?;
 
         assert!(permissions.contains("(deny process-exec (literal \"bash\"))"));
         assert!(permissions.contains("(allow process-exec"));
         assert!(permissions.contains("(literal \"jupyter\")"));
         assert!(permissions.contains("(literal \"python\")"));
+        Ok(())
     }
 
     #[test]
@@ -304,58 +780,11 @@ mod tests {
     // end to end test -ish section
     // testing the sandbox with a real kernel
 
-    async fn setup_jupyter_server(profile: &str) -> Client {
-        // Start the Jupyter server (this assumes jupyter-server is in PATH)
-
-        if let Err(e) = tokio::process::Command::new("sandbox-exec")
-            .arg("-p")
-            .arg(format!("'{profile}'"))
-            .arg("jupyter-server")
-            .arg("--no-browser")
-            .arg("--IdentityProvider.token")
-            .arg("''")
-            .spawn()
-        {
-            println!("Failed to start Jupyter server: {:?}", e);
-        };
-
-        // Give the server some time to start up
-        tokio::time::sleep(Duration::from_secs(5)).await;
-
-        // Connect to the server
-        Client::existing().expect("Failed to connect to Jupyter server")
-    }
-
-    async fn run_code(client: &Client, code: &str) -> Result<()> {
-        println!("Running code: {code}");
-        let command = jupyter_client::commands::Command::Execute {
-            code: code.to_string(),
-            silent: false,
-            store_history: true,
-            user_expressions: HashMap::new(),
-            allow_stdin: true,
-            stop_on_error: false,
-        };
-
-        let response = client
-            .send_shell_command(command)
-            .map_err(|e| anyhow::anyhow!(e))?;
-
-        // Check for errors in the response
-        if let jupyter_client::responses::Response::Shell(
-            jupyter_client::responses::ShellResponse::Execute { content, .. },
-        ) = response
-        {
-            if content.status == jupyter_client::responses::Status::Error {
-                return Err(anyhow::anyhow!("Execution error: {:?}", content.evalue));
-            }
-        }
-
-        Ok(())
-    }
-
+    #[cfg(feature = "harness")]
     #[tokio::test]
     async fn test_jupyter_permissions() -> Result<(), anyhow::Error> {
+        use crate::harness::{run_code, setup_jupyter_server};
+
         let temp_dir = tempdir()?;
         let allowed_path = temp_dir.path().join("allowed");
         let denied_path = temp_dir.path().join("denied");
@@ -374,7 +803,7 @@ mod tests {
         let profile = generate_profile(template, &permissions)?;
         let minified_profile = minify_profile(&profile);
 
-        let jupyter_client = setup_jupyter_server(&minified_profile).await;
+        let jupyter_client = setup_jupyter_server(&minified_profile).await?;
 
         // Test allowed read
         let allowed_read_code = format!(
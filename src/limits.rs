@@ -0,0 +1,138 @@
+use std::path::PathBuf;
+
+use crate::error::{Result, SecureNotebookError};
+use crate::{generate_profile, profile_capacity, Permissions};
+
+/// Largest profile, in bytes, generated by default.
+///
+/// The profile is handed to `sandbox-exec` as a single argument, which has to fit in
+/// `ARG_MAX` together with the environment, and the profile compiler slows down badly
+/// well before that.
+pub const MAX_PROFILE_SIZE: usize = 256 * 1024;
+
+/// Estimated size of the rules generated for `permissions`, excluding the template.
+///
+/// This is an upper bound; the minified profile passed to `sandbox-exec` is smaller.
+pub fn estimate_profile_size(permissions: &Permissions) -> usize {
+    profile_capacity("", permissions)
+}
+
+/// A profile generated within a size limit.
+#[derive(Debug, Clone)]
+pub struct FittedProfile {
+    pub profile: String,
+    /// Permissions the profile was generated from.
+    pub permissions: Permissions,
+    /// Whether the permissions had to be consolidated to fit.
+    pub consolidated: bool,
+}
+
+/// Generate a profile no larger than `limit`, consolidating the permissions if needed.
+///
+/// Consolidation never widens access: it only drops duplicate paths and paths already
+/// covered by a directory in the same list. If that is not enough,
+/// [`SecureNotebookError::ProfileTooLarge`] is returned.
+pub fn fit_profile(
+    template: &str,
+    permissions: &Permissions,
+    limit: usize,
+) -> Result<FittedProfile> {
+    let mut permissions = permissions.clone();
+    let mut consolidated = false;
+
+    if template.len() + estimate_profile_size(&permissions) > limit {
+        permissions = consolidate(&permissions);
+        consolidated = true;
+    }
+
+    let profile = generate_profile(template, &permissions)?;
+    if profile.len() > limit {
+        return Err(SecureNotebookError::ProfileTooLarge {
+            size: profile.len(),
+            limit,
+        });
+    }
+
+    Ok(FittedProfile {
+        profile,
+        permissions,
+        consolidated,
+    })
+}
+
+/// Drop duplicate paths, and paths covered by a directory in the same list.
+pub fn consolidate(permissions: &Permissions) -> Permissions {
+    Permissions {
+        allow_read: consolidate_paths(&permissions.allow_read),
+        deny_read: consolidate_paths(&permissions.deny_read),
+        allow_write: consolidate_paths(&permissions.allow_write),
+        deny_write: consolidate_paths(&permissions.deny_write),
+        allow_net: permissions.allow_net,
+        allow_gpu: permissions.allow_gpu,
+        // programs are matched literally, so only duplicates can go
+        allow_run: dedup(&permissions.allow_run),
+        deny_run: dedup(&permissions.deny_run),
+    }
+}
+
+fn consolidate_paths(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut paths = dedup(paths);
+    // parents sort before their children
+    paths.sort();
+
+    let mut kept: Vec<PathBuf> = Vec::with_capacity(paths.len());
+    for path in paths {
+        let covered = kept
+            .iter()
+            .any(|root| path.starts_with(root) && root.is_dir());
+        if !covered {
+            kept.push(path);
+        }
+    }
+    kept
+}
+
+fn dedup(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut seen = std::collections::HashSet::with_capacity(paths.len());
+    paths
+        .iter()
+        .filter(|path| seen.insert(path.as_path()))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_consolidate_drops_covered_paths() {
+        let dir = tempdir().unwrap();
+        let nested = dir.path().join("nested");
+        let mut permissions = Permissions::new();
+        permissions.allow_read = vec![
+            nested.clone(),
+            dir.path().to_path_buf(),
+            nested.join("file.csv"),
+            dir.path().to_path_buf(),
+        ];
+
+        let consolidated = consolidate(&permissions);
+        assert_eq!(consolidated.allow_read, vec![dir.path().to_path_buf()]);
+    }
+
+    #[test]
+    fn test_fit_profile_too_large() {
+        let mut permissions = Permissions::new();
+        permissions.deny_read = (0..100)
+            .map(|index| PathBuf::from(format!("/data/{index}")))
+            .collect();
+
+        assert!(fit_profile("(version 1)\n", &permissions, MAX_PROFILE_SIZE).is_ok());
+        assert!(matches!(
+            fit_profile("(version 1)\n", &permissions, 1024),
+            Err(SecureNotebookError::ProfileTooLarge { limit: 1024, .. })
+        ));
+    }
+}
@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::evaluator::{parse_rules, Action, Filter};
+use crate::policy::LayeredPolicy;
+use crate::Permissions;
+
+/// How serious a finding is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found in a policy or profile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Finding {
+    fn warning(message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message,
+        }
+    }
+
+    fn error(message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{severity}: {}", self.message)
+    }
+}
+
+/// Check a policy for paths the sandbox cannot match, redundant entries, overly broad
+/// allows, and allows that override its own denies.
+pub fn lint_permissions(permissions: &Permissions) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let fields = [
+        ("allow_read", &permissions.allow_read),
+        ("deny_read", &permissions.deny_read),
+        ("allow_write", &permissions.allow_write),
+        ("deny_write", &permissions.deny_write),
+        ("allow_run", &permissions.allow_run),
+        ("deny_run", &permissions.deny_run),
+    ];
+    for (field, paths) in fields {
+        for (index, path) in paths.iter().enumerate() {
+            if !path.is_absolute() {
+                findings.push(Finding::error(format!(
+                    "{field}: {} is relative; the sandbox only matches absolute paths",
+                    path.display()
+                )));
+            }
+            if path.to_str().is_none() {
+                findings.push(Finding::error(format!(
+                    "{field}: {} is not valid UTF-8; no rule can match it",
+                    path.display()
+                )));
+            }
+            if paths[..index].contains(path) {
+                findings.push(Finding::warning(format!(
+                    "{field}: {} is listed more than once",
+                    path.display()
+                )));
+            }
+        }
+    }
+    for (field, paths) in [
+        ("allow_read", &permissions.allow_read),
+        ("allow_write", &permissions.allow_write),
+    ] {
+        if paths.iter().any(|path| path == Path::new("/")) {
+            findings.push(Finding::warning(format!(
+                "{field}: / allows the whole file system"
+            )));
+        }
+    }
+
+    let denies = Permissions {
+        deny_read: permissions.deny_read.clone(),
+        deny_write: permissions.deny_write.clone(),
+        deny_run: permissions.deny_run.clone(),
+        ..Permissions::default()
+    };
+    let allows = Permissions {
+        allow_read: permissions.allow_read.clone(),
+        allow_write: permissions.allow_write.clone(),
+        allow_run: permissions.allow_run.clone(),
+        ..Permissions::default()
+    };
+    for conflict in LayeredPolicy::new(denies, allows).conflicts() {
+        let message = if conflict.path == conflict.denied_by {
+            format!(
+                "{}: {} is both allowed and denied; the allow wins",
+                conflict.access_type,
+                conflict.path.display()
+            )
+        } else {
+            format!(
+                "{}: allowing {} overrides the deny of {}",
+                conflict.access_type,
+                conflict.path.display(),
+                conflict.denied_by.display()
+            )
+        };
+        findings.push(Finding::warning(message));
+    }
+    findings
+}
+
+/// Check a raw profile for syntax errors, a missing version and blanket allows.
+pub fn lint_profile(profile: &str) -> Vec<Finding> {
+    let rules = match parse_rules(profile) {
+        Ok(rules) => rules,
+        Err(error) => return vec![Finding::error(error.to_string())],
+    };
+
+    let mut findings = Vec::new();
+    if !profile.contains("(version 1)") {
+        findings.push(Finding::error("missing (version 1)".to_string()));
+    }
+    for rule in &rules {
+        if rule.action != Action::Allow {
+            continue;
+        }
+        if rule.filters.is_empty() && rule.operations.iter().any(|op| op == "default") {
+            findings.push(Finding::warning(
+                "(allow default) allows everything that is not denied".to_string(),
+            ));
+        }
+        let root = Filter::Subpath(PathBuf::from("/"));
+        if rule.filters.contains(&root) {
+            findings.push(Finding::warning(format!(
+                "({} (subpath \"/\")) allows the whole file system",
+                rule.operations.join(" ")
+            )));
+        }
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_permissions() {
+        let permissions = Permissions {
+            allow_read: vec![PathBuf::from("data"), PathBuf::from("/secrets/public")],
+            deny_read: vec![PathBuf::from("/secrets")],
+            allow_run: vec![PathBuf::from("/bin/sh"), PathBuf::from("/bin/sh")],
+            ..Permissions::default()
+        };
+        let findings = lint_permissions(&permissions);
+        assert_eq!(findings.len(), 3);
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert!(findings[2]
+            .message
+            .contains("overrides the deny of /secrets"));
+        assert!(lint_permissions(&Permissions::new()).is_empty());
+    }
+
+    #[test]
+    fn test_lint_profile() {
+        assert_eq!(lint_profile("(version 1)\n(allow default").len(), 1);
+        let findings = lint_profile("(allow default)\n");
+        assert_eq!(findings[0].message, "missing (version 1)");
+        assert_eq!(findings[1].severity, Severity::Warning);
+        assert!(lint_profile(crate::DEFAULT_SANDBOX_PROFILE).is_empty());
+    }
+}
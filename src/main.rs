@@ -0,0 +1,380 @@
+//! `secure-notebook` command line tool.
+
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use secure_notebook::audit::AuditLog;
+use secure_notebook::backend::DEFAULT_BACKENDS;
+use secure_notebook::compliance::{compliance_report, Control};
+use secure_notebook::diff::{diff_profiles, Change};
+use secure_notebook::error::{Result, SecureNotebookError};
+use secure_notebook::evaluator::Action;
+use secure_notebook::explain::{Operation, Profile};
+use secure_notebook::lint::{lint_permissions, lint_profile};
+use secure_notebook::manifest::PolicyManifest;
+use secure_notebook::policy::load_policy;
+use secure_notebook::presets::TERMINAL_RULES;
+use secure_notebook::pty::interact;
+use secure_notebook::session::{spawn_sandboxed, spawn_sandboxed_in_pty, SessionConfig};
+use secure_notebook::tcc::preflight;
+use secure_notebook::watch::{PolicyWatcher, WATCH_INTERVAL};
+use secure_notebook::{generate_profile, DEFAULT_SANDBOX_PROFILE};
+
+const USAGE: &str = "\
+usage: secure-notebook <command> [arguments]
+
+commands:
+  lint <file>...             check policies (.toml, .json) or profiles (.sb), failing on
+                             findings
+  diff [--json] <old> <new>  rules added and removed between two profiles or policies,
+                             failing if there are any
+  explain --policy <file> [--template <file.sb>] <read|write|exec|network> [path]
+                             the rule deciding an operation, failing if it is denied
+  record [--template <file.sb>] [-o <policy.toml>] <notebook.ipynb>
+                             run a notebook in learning mode and write the policy it
+                             needs
+  audit [--log <dir>] [--json] --session <id>
+                             the policy, violations and file changes of a past run
+  watch [--template <file.sb>] --policy <file>
+                             run a server, restarting it whenever the policy changes
+  compliance [--template <file.sb>] [--json] --policy <file>
+                             which compliance controls a policy satisfies, failing if
+                             any is not
+  manifest [--template <file.sb>] --policy <file>
+                             the resolved policy as a CycloneDX-style JSON manifest
+  run [--template <file.sb>] [--pty] --policy <file> -- <program> [argument]...
+                             run a program under the policy, on a pseudo-terminal
+                             for interactive programs with --pty";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("secure-notebook: {error}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<ExitCode> {
+    match args.split_first() {
+        Some((command, rest)) if command == "lint" => lint(rest),
+        Some((command, rest)) if command == "diff" => diff(rest),
+        Some((command, rest)) if command == "explain" => explain(rest),
+        Some((command, rest)) if command == "record" => record(rest),
+        Some((command, rest)) if command == "audit" => audit(rest),
+        Some((command, rest)) if command == "watch" => watch(rest),
+        Some((command, rest)) if command == "compliance" => compliance(rest),
+        Some((command, rest)) if command == "manifest" => manifest(rest),
+        Some((command, rest)) if command == "run" => run_program(rest),
+        Some((command, _)) if command == "-h" || command == "--help" => {
+            println!("{USAGE}");
+            Ok(ExitCode::SUCCESS)
+        }
+        _ => Err(usage()),
+    }
+}
+
+fn usage() -> SecureNotebookError {
+    SecureNotebookError::InvalidState(USAGE.to_string())
+}
+
+/// Whether `path` is a raw profile rather than a policy.
+fn is_profile(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "sb")
+}
+
+fn read(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).map_err(|source| SecureNotebookError::Io {
+        context: format!("Failed to read {}", path.display()),
+        source,
+    })
+}
+
+fn lint(files: &[String]) -> Result<ExitCode> {
+    if files.is_empty() {
+        return Err(usage());
+    }
+    let mut clean = true;
+    for file in files {
+        let path = Path::new(file);
+        let findings = if is_profile(path) {
+            lint_profile(&read(path)?)
+        } else {
+            lint_permissions(&load_policy(path)?)
+        };
+        for finding in &findings {
+            println!("{}: {finding}", path.display());
+        }
+        clean &= findings.is_empty();
+    }
+    Ok(if clean {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// The profile in a `.sb` file, or generated on the default template from a policy.
+fn load_profile(path: &Path) -> Result<String> {
+    if is_profile(path) {
+        read(path)
+    } else {
+        generate_profile(DEFAULT_SANDBOX_PROFILE, &load_policy(path)?)
+    }
+}
+
+fn diff(args: &[String]) -> Result<ExitCode> {
+    let json = args.iter().any(|arg| arg == "--json");
+    let files: Vec<&String> = args.iter().filter(|arg| *arg != "--json").collect();
+    let [old, new] = files[..] else {
+        return Err(usage());
+    };
+    let changes = diff_profiles(
+        &load_profile(Path::new(old))?,
+        &load_profile(Path::new(new))?,
+    )?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&changes)?);
+    } else {
+        let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        for change in &changes {
+            match (color, change.change) {
+                (true, Change::Added) => println!("\x1b[32m{change}\x1b[0m"),
+                (true, Change::Removed) => println!("\x1b[31m{change}\x1b[0m"),
+                (false, _) => println!("{change}"),
+            }
+        }
+    }
+    Ok(if changes.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Arguments split into `--name value` options and positional arguments.
+struct Args<'a> {
+    options: Vec<(&'a str, &'a str)>,
+    positional: Vec<&'a str>,
+}
+
+impl<'a> Args<'a> {
+    /// Split `args`, taking a value after each of the option `names`.
+    fn parse(args: &'a [String], names: &[&str]) -> Result<Self> {
+        let mut parsed = Args {
+            options: Vec::new(),
+            positional: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if names.contains(&arg.as_str()) {
+                let value = args.next().ok_or_else(usage)?;
+                parsed.options.push((arg, value));
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn option(&self, name: &str) -> Option<&'a str> {
+        self.options
+            .iter()
+            .find(|(option, _)| *option == name)
+            .map(|(_, value)| *value)
+    }
+}
+
+fn explain(args: &[String]) -> Result<ExitCode> {
+    let args = Args::parse(args, &["--policy", "--template"])?;
+    let policy = args.option("--policy").ok_or_else(usage)?;
+    let template = template(&args)?;
+    let operation = match args.positional[..] {
+        ["read", path] => Operation::Read(PathBuf::from(path)),
+        ["write", path] => Operation::Write(PathBuf::from(path)),
+        ["exec", path] => Operation::Exec(PathBuf::from(path)),
+        ["network"] => Operation::Network,
+        _ => return Err(usage()),
+    };
+
+    let profile = Profile::new(&template, &load_policy(Path::new(policy))?)?;
+    let explanation = profile.explain(operation);
+    println!("{explanation}");
+    if let Some(rule) = &explanation.rule {
+        println!("  {rule}");
+    }
+    Ok(match explanation.action {
+        Some(Action::Allow) => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    })
+}
+
+/// The template in `--template`, or the default one.
+fn template(args: &Args) -> Result<String> {
+    match args.option("--template") {
+        Some(template) => read(Path::new(template)),
+        None => Ok(DEFAULT_SANDBOX_PROFILE.to_string()),
+    }
+}
+
+#[cfg(feature = "client")]
+fn record(args: &[String]) -> Result<ExitCode> {
+    use secure_notebook::learn::record_notebook;
+    use secure_notebook::policy::{render_policy, save_policy};
+
+    let args = Args::parse(args, &["--template", "-o"])?;
+    let [notebook] = args.positional[..] else {
+        return Err(usage());
+    };
+    let permissions = record_notebook(
+        Path::new(notebook),
+        &template(&args)?,
+        SessionConfig::default(),
+    )?;
+    match args.option("-o") {
+        Some(output) => save_policy(Path::new(output), &permissions)?,
+        None => print!("{}", render_policy(&permissions, true)?),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(not(feature = "client"))]
+fn record(_args: &[String]) -> Result<ExitCode> {
+    Err(SecureNotebookError::Unsupported(
+        "record needs secure-notebook built with the client feature".to_string(),
+    ))
+}
+
+fn audit(args: &[String]) -> Result<ExitCode> {
+    let args = Args::parse(args, &["--session", "--log"])?;
+    let session = args.option("--session").ok_or_else(usage)?;
+    let json = match args.positional[..] {
+        [] => false,
+        ["--json"] => true,
+        _ => return Err(usage()),
+    };
+    let dir = match args.option("--log") {
+        Some(dir) => PathBuf::from(dir),
+        None => AuditLog::default_dir().ok_or_else(|| {
+            SecureNotebookError::InvalidState("HOME is not set; pass --log".to_string())
+        })?,
+    };
+
+    let record = AuditLog::open(dir)?.load(session)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&record)?);
+    } else {
+        print!("{record}");
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn watch(args: &[String]) -> Result<ExitCode> {
+    let args = Args::parse(args, &["--policy", "--template"])?;
+    let policy = args.option("--policy").ok_or_else(usage)?;
+    if !args.positional.is_empty() {
+        return Err(usage());
+    }
+
+    if let Some(home) = std::env::var_os("HOME") {
+        for requirement in preflight(&load_policy(Path::new(policy))?, Path::new(&home)) {
+            eprintln!("warning: {requirement}");
+        }
+    }
+    let mut watcher = PolicyWatcher::start(policy, &template(&args)?, SessionConfig::default())?;
+    eprintln!("watching {policy}");
+    watcher.watch(WATCH_INTERVAL, |event| match event {
+        Ok(permissions) => eprintln!("restarted under {}", permissions.fingerprint_hex()),
+        Err(error) => eprintln!("kept the previous policy: {error}"),
+    })?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn compliance(args: &[String]) -> Result<ExitCode> {
+    let args = Args::parse(args, &["--policy", "--template"])?;
+    let policy = args.option("--policy").ok_or_else(usage)?;
+    let json = match args.positional[..] {
+        [] => false,
+        ["--json"] => true,
+        _ => return Err(usage()),
+    };
+    let home = std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| SecureNotebookError::InvalidState("HOME is not set".to_string()))?;
+
+    let report = compliance_report(
+        &template(&args)?,
+        &load_policy(Path::new(policy))?,
+        &Control::ALL,
+        &home,
+    )?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{report}");
+    }
+    Ok(if report.all_satisfied() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn manifest(args: &[String]) -> Result<ExitCode> {
+    let args = Args::parse(args, &["--policy", "--template"])?;
+    let policy = args.option("--policy").ok_or_else(usage)?;
+    if !args.positional.is_empty() {
+        return Err(usage());
+    }
+    let template_name = args
+        .option("--template")
+        .and_then(|template| Path::new(template).file_stem())
+        .map_or("default".into(), |name| name.to_string_lossy());
+
+    let manifest = PolicyManifest::new(
+        &template_name,
+        &template(&args)?,
+        &[],
+        &load_policy(Path::new(policy))?,
+    )?;
+    println!("{}", manifest.to_json()?);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_program(args: &[String]) -> Result<ExitCode> {
+    let split = args.iter().position(|arg| arg == "--").ok_or_else(usage)?;
+    let options = Args::parse(&args[..split], &["--policy", "--template"])?;
+    let policy = options.option("--policy").ok_or_else(usage)?;
+    let pty = match options.positional[..] {
+        [] => false,
+        ["--pty"] => true,
+        _ => return Err(usage()),
+    };
+    let Some((program, program_args)) = args[split + 1..].split_first() else {
+        return Err(usage());
+    };
+
+    let permissions = load_policy(Path::new(policy))?;
+    if let Some(home) = std::env::var_os("HOME") {
+        for requirement in preflight(&permissions, Path::new(&home)) {
+            eprintln!("warning: {requirement}");
+        }
+    }
+    let mut profile = generate_profile(&template(&options)?, &permissions)?;
+    let program = Path::new(program);
+    let status = if pty {
+        profile.push_str(TERMINAL_RULES);
+        let (mut guard, master) =
+            spawn_sandboxed_in_pty(&profile, program, program_args, DEFAULT_BACKENDS)?;
+        interact(&mut guard, master)?
+    } else {
+        spawn_sandboxed(&profile, program, program_args, DEFAULT_BACKENDS)?.wait()?
+    };
+    Ok(status
+        .code()
+        .map_or(ExitCode::FAILURE, |code| ExitCode::from(code as u8)))
+}
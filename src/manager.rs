@@ -0,0 +1,295 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::history::{PolicyChange, RunHistory};
+use crate::ports::PortAllocation;
+use crate::session::{JupyterSession, SessionConfig};
+use crate::tokens::{ServerToken, TokenStore};
+use crate::{generate_profile, Permissions};
+
+/// Ports handed out to sessions unless configured otherwise.
+pub const DEFAULT_PORTS: Range<u16> = 8900..9900;
+
+/// Public view of a managed session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub user: String,
+    /// The user's private directory, which the server is rooted in.
+    pub dir: PathBuf,
+    pub port: u16,
+    pub url: String,
+    pub pid: u32,
+    pub running: bool,
+    /// Fingerprint of the permissions the session runs under.
+    pub fingerprint: String,
+    /// How the permissions differ from the user's previous session, when history is kept.
+    #[serde(default)]
+    pub policy_change: Option<String>,
+}
+
+#[derive(Debug)]
+struct Tenant {
+    session: JupyterSession,
+    dir: PathBuf,
+    ports: PortAllocation,
+    token: ServerToken,
+    fingerprint: String,
+    policy_change: Option<PolicyChange>,
+}
+
+/// Runs the notebook servers of many users side by side.
+///
+/// Every user gets a directory under `root`, ports, a token and a profile of their own.
+/// The profile only allows inbound connections on the user's ports.
+/// The profile denies the rest of `root`, so users cannot read each other's files.
+/// Tokens are not part of [`SessionInfo`]; hand them out with [`SessionManager::token`]
+/// only to callers allowed to use the session.
+#[derive(Debug)]
+pub struct SessionManager {
+    root: PathBuf,
+    template: String,
+    config: SessionConfig,
+    ports: Range<u16>,
+    tokens: Option<TokenStore>,
+    history: Option<RunHistory>,
+    tenants: BTreeMap<String, Tenant>,
+}
+
+impl SessionManager {
+    /// Manage sessions for users under `root`, built from `template` and `config`.
+    ///
+    /// `config.args` are extended with the per-user ports, token and root directory.
+    pub fn new(root: impl Into<PathBuf>, template: &str, config: SessionConfig) -> Self {
+        Self {
+            root: root.into(),
+            template: template.to_string(),
+            config,
+            ports: DEFAULT_PORTS,
+            tokens: None,
+            history: None,
+            tenants: BTreeMap::new(),
+        }
+    }
+
+    /// Hand out ports from `ports` instead of [`DEFAULT_PORTS`].
+    pub fn with_ports(mut self, ports: Range<u16>) -> Self {
+        self.ports = ports;
+        self
+    }
+
+    /// Persist session tokens in `store`, e.g. for a proxy running in another process.
+    pub fn with_token_store(mut self, store: TokenStore) -> Self {
+        self.tokens = Some(store);
+        self
+    }
+
+    /// Record the policy of every session in `history`, so changes between a user's
+    /// sessions are reported in [`SessionInfo::policy_change`].
+    pub fn with_history(mut self, history: RunHistory) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Directory holding every user's directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Start a session for `user` with `permissions` plus access to their own directory.
+    pub fn start(&mut self, user: &str, permissions: &Permissions) -> Result<SessionInfo> {
+        validate_user(user)?;
+        if self.tenants.contains_key(user) {
+            return Err(SecureNotebookError::InvalidState(format!(
+                "A session for {user} is already running"
+            )));
+        }
+
+        let dir = self.root.join(user);
+        std::fs::create_dir_all(&dir)
+            .io_context(|| format!("Failed to create {}", dir.display()))?;
+        let permissions = tenant_permissions(&self.root, &dir, permissions);
+        let taken: Vec<u16> = self
+            .tenants
+            .values()
+            .flat_map(|tenant| tenant.ports.all())
+            .collect();
+        let ports = PortAllocation::allocate(self.ports.clone(), &taken)?;
+        let mut profile = generate_profile(&self.template, &permissions)?;
+        profile.push_str(&ports.profile_rules());
+
+        let token = ServerToken::generate()?;
+        let config = self.tenant_config(&dir, &ports, &token);
+
+        let session = JupyterSession::spawn(&profile, config)?;
+        if let Some(store) = &self.tokens {
+            store.save(user, &token)?;
+        }
+        let policy_change = match &self.history {
+            Some(history) => history.record(user, &permissions)?,
+            None => None,
+        };
+        self.tenants.insert(
+            user.to_string(),
+            Tenant {
+                session,
+                dir,
+                ports,
+                token,
+                fingerprint: permissions.fingerprint_hex(),
+                policy_change,
+            },
+        );
+        Ok(self.inspect(user).expect("session was just added"))
+    }
+
+    /// Every session, ordered by user.
+    pub fn list(&mut self) -> Vec<SessionInfo> {
+        let users: Vec<String> = self.tenants.keys().cloned().collect();
+        users.iter().filter_map(|user| self.inspect(user)).collect()
+    }
+
+    /// Session of `user`, if there is one.
+    pub fn inspect(&mut self, user: &str) -> Option<SessionInfo> {
+        let tenant = self.tenants.get_mut(user)?;
+        Some(SessionInfo {
+            user: user.to_string(),
+            dir: tenant.dir.clone(),
+            port: tenant.ports.server,
+            url: tenant.session.config().url.clone(),
+            pid: tenant.session.pid(),
+            running: tenant.session.is_running(),
+            fingerprint: tenant.fingerprint.clone(),
+            policy_change: tenant.policy_change.as_ref().map(PolicyChange::to_string),
+        })
+    }
+
+    /// How `user`'s current session's permissions differ from their previous session's.
+    pub fn policy_change(&self, user: &str) -> Option<&PolicyChange> {
+        self.tenants.get(user)?.policy_change.as_ref()
+    }
+
+    /// Token of `user`'s session. Only give it to callers allowed to use the session.
+    pub fn token(&self, user: &str) -> Option<&ServerToken> {
+        self.tenants.get(user).map(|tenant| &tenant.token)
+    }
+
+    /// Restart `user`'s session with a new token, invalidating the old one.
+    ///
+    /// Jupyter reads its token at startup, so rotating it restarts the server.
+    pub fn rotate_token(&mut self, user: &str) -> Result<&ServerToken> {
+        let token = ServerToken::generate()?;
+        let tenant = self.tenants.get(user).ok_or_else(|| no_session(user))?;
+        let config = self.tenant_config(&tenant.dir, &tenant.ports, &token);
+
+        let tenant = self.tenants.get_mut(user).expect("tenant was just found");
+        let profile = tenant.session.profile().to_string();
+        tenant.session.restart_with(&profile, config)?;
+        if let Some(store) = &self.tokens {
+            store.save(user, &token)?;
+        }
+        tenant.token = token;
+        Ok(&tenant.token)
+    }
+
+    /// Shut down the session of `user`. Their directory is kept.
+    pub fn terminate(&mut self, user: &str) -> Result<()> {
+        let mut tenant = self.tenants.remove(user).ok_or_else(|| no_session(user))?;
+        if let Some(store) = &self.tokens {
+            store.remove(user)?;
+        }
+        tenant.session.shutdown()
+    }
+
+    /// Shut down every session, returning the first error after trying all of them.
+    pub fn terminate_all(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        for (user, mut tenant) in std::mem::take(&mut self.tenants) {
+            let mut stopped = tenant.session.shutdown();
+            if let Some(store) = &self.tokens {
+                stopped = stopped.and(store.remove(&user));
+            }
+            if result.is_ok() {
+                result = stopped;
+            }
+        }
+        result
+    }
+
+    fn tenant_config(
+        &self,
+        dir: &Path,
+        ports: &PortAllocation,
+        token: &ServerToken,
+    ) -> SessionConfig {
+        let mut config = self.config.clone();
+        config.args.extend(ports.server_args());
+        config
+            .args
+            .push(format!("--ServerApp.root_dir={}", dir.display()));
+        config.set_token(token.secret());
+        config.url = format!("http://localhost:{}", ports.server);
+        config
+    }
+}
+
+/// User names become directory names, so keep them to a safe alphabet.
+fn validate_user(user: &str) -> Result<()> {
+    let valid = !user.is_empty()
+        && !user.starts_with('.')
+        && user
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(SecureNotebookError::InvalidPolicy(format!(
+            "Invalid user name {user:?}"
+        )))
+    }
+}
+
+/// `permissions` with the other users' directories denied and `dir` allowed.
+///
+/// Allows are emitted after denies, so `dir` stays accessible under the denied `root`.
+fn tenant_permissions(root: &Path, dir: &Path, permissions: &Permissions) -> Permissions {
+    let mut permissions = permissions.clone();
+    permissions.deny_read.push(root.to_path_buf());
+    permissions.deny_write.push(root.to_path_buf());
+    permissions.allow_read.push(dir.to_path_buf());
+    permissions.allow_write.push(dir.to_path_buf());
+    permissions
+}
+
+fn no_session(user: &str) -> SecureNotebookError {
+    SecureNotebookError::InvalidState(format!("No session for {user}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_user() {
+        assert!(validate_user("alice_1").is_ok());
+        assert!(validate_user("..").is_err());
+        assert!(validate_user("a/b").is_err());
+        assert!(validate_user("").is_err());
+    }
+
+    #[test]
+    fn test_tenant_permissions_isolate_users() {
+        let permissions = tenant_permissions(
+            Path::new("/srv/notebooks"),
+            Path::new("/srv/notebooks/alice"),
+            &Permissions::new(),
+        );
+        assert_eq!(permissions.deny_read, [PathBuf::from("/srv/notebooks")]);
+        assert_eq!(
+            permissions.allow_write,
+            [PathBuf::from("/srv/notebooks/alice")]
+        );
+    }
+}
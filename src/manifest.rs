@@ -0,0 +1,284 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::Result;
+use crate::presets::Preset;
+use crate::signing::encode_hex;
+use crate::tokens::random_bytes;
+use crate::{generate_profile, sbpl_path, Permissions};
+
+/// CycloneDX specification version the manifest follows.
+pub const SPEC_VERSION: &str = "1.5";
+
+/// A CycloneDX-style bill of materials for a sandbox policy: the template, the presets
+/// and the resolved permissions that went into a run's profile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyManifest {
+    pub bom_format: String,
+    pub spec_version: String,
+    pub serial_number: String,
+    pub version: u32,
+    pub metadata: ManifestMetadata,
+    pub components: Vec<Component>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ManifestMetadata {
+    /// ISO 8601, UTC.
+    pub timestamp: String,
+    pub tools: Vec<Tool>,
+    /// The generated profile.
+    pub component: Component,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Tool {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Component {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(rename = "bom-ref")]
+    pub bom_ref: String,
+    pub name: String,
+    pub version: String,
+    pub hashes: Vec<Hash>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Hash {
+    pub alg: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Property {
+    pub name: String,
+    pub value: String,
+}
+
+impl PolicyManifest {
+    /// Describe the profile generated from `template`, `presets` and `permissions`.
+    ///
+    /// The presets are applied on top of `permissions`, and their rules appended, the
+    /// same way [`Preset::generate_profile`] does.
+    pub fn new(
+        template_name: &str,
+        template: &str,
+        presets: &[Preset],
+        permissions: &Permissions,
+    ) -> Result<Self> {
+        let mut resolved = permissions.clone();
+        for preset in presets {
+            preset.apply(&mut resolved);
+        }
+        let mut profile = generate_profile(template, &resolved)?;
+        for preset in presets {
+            profile.push_str(&preset.rules);
+        }
+
+        let mut components = vec![Component::data(
+            format!("template:{template_name}"),
+            template_name,
+            &sha256(template),
+            template,
+            Vec::new(),
+        )];
+        for preset in presets {
+            let mut contents = serde_json::to_string(&preset.permissions)?;
+            contents.push_str(&preset.rules);
+            let mut properties = permission_properties(&preset.permissions)?;
+            properties.extend(preset.hosts.iter().map(|host| property("host", host)));
+            components.push(Component::data(
+                format!("preset:{}", preset.name),
+                &preset.name,
+                env!("CARGO_PKG_VERSION"),
+                &contents,
+                properties,
+            ));
+        }
+        components.push(Component::data(
+            "policy".to_string(),
+            "policy",
+            &resolved.fingerprint_hex(),
+            &serde_json::to_string(&resolved)?,
+            permission_properties(&resolved)?,
+        ));
+
+        Ok(Self {
+            bom_format: "CycloneDX".to_string(),
+            spec_version: SPEC_VERSION.to_string(),
+            serial_number: serial_number()?,
+            version: 1,
+            metadata: ManifestMetadata {
+                timestamp: iso8601(SystemTime::now()),
+                tools: vec![Tool {
+                    name: env!("CARGO_PKG_NAME").to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                }],
+                component: Component::data(
+                    "profile".to_string(),
+                    "profile",
+                    &sha256(&profile),
+                    &profile,
+                    Vec::new(),
+                ),
+            },
+            components,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl Component {
+    fn data(
+        bom_ref: String,
+        name: &str,
+        version: &str,
+        contents: &str,
+        properties: Vec<Property>,
+    ) -> Self {
+        Self {
+            kind: "data".to_string(),
+            bom_ref,
+            name: name.to_string(),
+            version: version.to_string(),
+            hashes: vec![Hash {
+                alg: "SHA-256".to_string(),
+                content: sha256(contents),
+            }],
+            properties,
+        }
+    }
+}
+
+fn property(name: &str, value: &str) -> Property {
+    Property {
+        name: format!("secure-notebook:{name}"),
+        value: value.to_string(),
+    }
+}
+
+/// One property per grant, named after the [`Permissions`] field.
+fn permission_properties(permissions: &Permissions) -> Result<Vec<Property>> {
+    let fields: [(&str, &[PathBuf]); 6] = [
+        ("allow_read", &permissions.allow_read),
+        ("deny_read", &permissions.deny_read),
+        ("allow_write", &permissions.allow_write),
+        ("deny_write", &permissions.deny_write),
+        ("allow_run", &permissions.allow_run),
+        ("deny_run", &permissions.deny_run),
+    ];
+    let mut properties = fields
+        .iter()
+        .flat_map(|(name, paths)| {
+            paths
+                .iter()
+                .map(move |path| Ok(property(name, sbpl_path(path)?)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    properties.push(property("allow_net", &permissions.allow_net.to_string()));
+    properties.push(property("allow_gpu", &permissions.allow_gpu.to_string()));
+    Ok(properties)
+}
+
+fn sha256(contents: &str) -> String {
+    encode_hex(&Sha256::digest(contents.as_bytes()))
+}
+
+/// A random version 4 UUID URN.
+fn serial_number() -> Result<String> {
+    let mut bytes: [u8; 16] = random_bytes()?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = encode_hex(&bytes);
+    Ok(format!(
+        "urn:uuid:{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+/// `time` as `YYYY-MM-DDTHH:MM:SSZ`.
+pub(crate) fn iso8601(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (days, seconds) = (seconds / 86_400, seconds % 86_400);
+
+    // days since the epoch to a civil date, after Howard Hinnant's `civil_from_days`
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3_600,
+        seconds % 3_600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::offline;
+    use std::time::Duration;
+
+    #[test]
+    fn test_iso8601() {
+        assert_eq!(iso8601(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            iso8601(UNIX_EPOCH + Duration::from_secs(1_709_251_199)),
+            "2024-02-29T23:59:59Z"
+        );
+    }
+
+    #[test]
+    fn test_manifest_lists_template_presets_and_policy() -> Result<()> {
+        let mut permissions = Permissions::new();
+        permissions.allow_read.push(PathBuf::from("/data"));
+        let manifest = PolicyManifest::new(
+            "default",
+            "(version 1)\n(deny default)\n",
+            &[offline(&[PathBuf::from("/opt/wheels")])?],
+            &permissions,
+        )?;
+
+        let refs: Vec<&str> = manifest
+            .components
+            .iter()
+            .map(|component| component.bom_ref.as_str())
+            .collect();
+        assert_eq!(refs, ["template:default", "preset:offline", "policy"]);
+        let policy = &manifest.components[2];
+        assert!(policy.properties.contains(&property("allow_read", "/data")));
+        assert!(policy
+            .properties
+            .contains(&property("allow_read", "/opt/wheels")));
+        assert!(policy.properties.contains(&property("allow_gpu", "false")));
+        assert!(manifest.serial_number.starts_with("urn:uuid:"));
+        Ok(())
+    }
+}
@@ -0,0 +1,650 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::error::{IoContext, Result};
+use crate::presets::DENY_REMOTE_NETWORK;
+
+/// Longest request head the proxy accepts.
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// Hostnames the notebook may reach through the proxy.
+///
+/// A pattern is either an exact hostname or `*.domain`, which matches every subdomain of
+/// `domain` but not `domain` itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostAllowlist {
+    patterns: Vec<String>,
+}
+
+impl HostAllowlist {
+    /// Allowlist of the given patterns.
+    pub fn new<I, S>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            patterns: patterns
+                .into_iter()
+                .map(|pattern| pattern.as_ref().to_ascii_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Whether `host` may be reached.
+    pub fn allows(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.patterns
+            .iter()
+            .any(|pattern| match pattern.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .is_some_and(|prefix| prefix.ends_with('.')),
+                None => *pattern == host,
+            })
+    }
+}
+
+/// Caps on the traffic a session may send through the guard.
+///
+/// They apply to the upstream direction, the one data is exfiltrated through.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EgressLimits {
+    /// Most bytes per second sent upstream, across all connections.
+    pub bytes_per_second: Option<u64>,
+    /// Most bytes sent upstream over the lifetime of the guard.
+    pub total_bytes: Option<u64>,
+    /// Most connections (HTTPS, SOCKS) or requests (plain HTTP) per minute.
+    pub requests_per_minute: Option<u32>,
+}
+
+/// Shared accounting of the traffic sent through a guard.
+#[derive(Debug)]
+pub(crate) struct EgressLimiter {
+    limits: EgressLimits,
+    state: Mutex<LimiterState>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    window_start: Instant,
+    requests: u32,
+    sent: u64,
+    /// Bytes that can be sent right away, refilled at `bytes_per_second`.
+    allowance: f64,
+    refilled_at: Instant,
+}
+
+impl EgressLimiter {
+    pub(crate) fn new(limits: EgressLimits) -> Self {
+        let now = Instant::now();
+        Self {
+            limits,
+            state: Mutex::new(LimiterState {
+                window_start: now,
+                requests: 0,
+                sent: 0,
+                allowance: limits.bytes_per_second.unwrap_or(0) as f64,
+                refilled_at: now,
+            }),
+        }
+    }
+
+    /// Count a new request, returning whether it is within the rate limit.
+    pub(crate) fn admit(&self) -> bool {
+        let Some(limit) = self.limits.requests_per_minute else {
+            return true;
+        };
+        let mut state = self.state.lock().expect("egress limiter poisoned");
+        if state.window_start.elapsed() >= Duration::from_secs(60) {
+            state.window_start = Instant::now();
+            state.requests = 0;
+        }
+        if state.requests >= limit {
+            return false;
+        }
+        state.requests += 1;
+        true
+    }
+
+    /// Account for `bytes` sent upstream, sleeping to stay within the bandwidth cap.
+    ///
+    /// Fails once the total quota is used up.
+    pub(crate) fn consume(&self, bytes: usize) -> io::Result<()> {
+        let wait = {
+            let mut state = self.state.lock().expect("egress limiter poisoned");
+            state.sent += bytes as u64;
+            if self
+                .limits
+                .total_bytes
+                .is_some_and(|total| state.sent > total)
+            {
+                return Err(io::Error::other("egress quota exceeded"));
+            }
+
+            let Some(rate) = self.limits.bytes_per_second else {
+                return Ok(());
+            };
+            let rate = rate as f64;
+            let now = Instant::now();
+            let refill = now.duration_since(state.refilled_at).as_secs_f64() * rate;
+            state.allowance = (state.allowance + refill).min(rate) - bytes as f64;
+            state.refilled_at = now;
+            if state.allowance < 0.0 {
+                Duration::from_secs_f64(-state.allowance / rate)
+            } else {
+                Duration::ZERO
+            }
+        };
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn sent(&self) -> u64 {
+        self.state.lock().expect("egress limiter poisoned").sent
+    }
+}
+
+/// Protocol spoken by the proxy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyProtocol {
+    /// HTTP forward proxy, handling `CONNECT` for HTTPS.
+    Http,
+    /// SOCKS5 proxy, for protocols other than HTTP such as database drivers or SSH.
+    Socks5,
+}
+
+/// Local forward proxy enforcing a [`HostAllowlist`].
+///
+/// The kernel is pointed at the proxy through [`NetGuard::env`], and the profile denies
+/// every other outbound connection with [`NetGuard::profile_rules`], so hosts can be
+/// allowed by name rather than by address.
+#[derive(Debug)]
+pub struct NetGuard {
+    addr: SocketAddr,
+    protocol: ProxyProtocol,
+    denied: Arc<Mutex<Vec<String>>>,
+    limiter: Arc<EgressLimiter>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl NetGuard {
+    /// Start an HTTP proxy on a free loopback port.
+    pub fn start(allowlist: HostAllowlist) -> Result<Self> {
+        Self::start_with(allowlist, ProxyProtocol::Http)
+    }
+
+    /// Start a proxy speaking `protocol` on a free loopback port.
+    pub fn start_with(allowlist: HostAllowlist, protocol: ProxyProtocol) -> Result<Self> {
+        Self::start_limited(allowlist, protocol, EgressLimits::default())
+    }
+
+    /// Start a proxy speaking `protocol` that also enforces `limits`.
+    pub fn start_limited(
+        allowlist: HostAllowlist,
+        protocol: ProxyProtocol,
+        limits: EgressLimits,
+    ) -> Result<Self> {
+        let listener =
+            TcpListener::bind("127.0.0.1:0").io_context(|| "Failed to bind the proxy")?;
+        let addr = listener
+            .local_addr()
+            .io_context(|| "Failed to get the proxy address")?;
+
+        let allowlist = Arc::new(allowlist);
+        let denied = Arc::new(Mutex::new(Vec::new()));
+        let limiter = Arc::new(EgressLimiter::new(limits));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let denied = Arc::clone(&denied);
+            let limiter = Arc::clone(&limiter);
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let Ok(stream) = stream else { continue };
+                    let allowlist = Arc::clone(&allowlist);
+                    let denied = Arc::clone(&denied);
+                    let limiter = Arc::clone(&limiter);
+                    std::thread::spawn(move || {
+                        let _ = match protocol {
+                            ProxyProtocol::Http => {
+                                handle_http(stream, &allowlist, &denied, limiter)
+                            }
+                            ProxyProtocol::Socks5 => {
+                                handle_socks5(stream, &allowlist, &denied, limiter)
+                            }
+                        };
+                    });
+                }
+            })
+        };
+
+        Ok(Self {
+            addr,
+            protocol,
+            denied,
+            limiter,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Address the proxy listens on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Protocol the proxy speaks.
+    pub fn protocol(&self) -> ProxyProtocol {
+        self.protocol
+    }
+
+    /// Environment variables pointing clients in the kernel at the proxy.
+    pub fn env(&self) -> Vec<(String, String)> {
+        let url = match self.protocol {
+            ProxyProtocol::Http => format!("http://{}", self.addr),
+            // resolve names through the proxy, so the allowlist sees them
+            ProxyProtocol::Socks5 => format!("socks5h://{}", self.addr),
+        };
+        let mut env = Vec::new();
+        for name in ["HTTP_PROXY", "HTTPS_PROXY", "ALL_PROXY"] {
+            env.push((name.to_string(), url.clone()));
+            env.push((name.to_ascii_lowercase(), url.clone()));
+        }
+        env.push((
+            "NO_PROXY".to_string(),
+            "localhost,127.0.0.1,::1".to_string(),
+        ));
+        env.push((
+            "no_proxy".to_string(),
+            "localhost,127.0.0.1,::1".to_string(),
+        ));
+        env
+    }
+
+    /// Rules denying outbound connections except to loopback, where the proxy and the
+    /// kernel channels live.
+    ///
+    /// The server connects to the kernels over loopback, so loopback stays open as a
+    /// whole rather than only the proxy port.
+    pub fn profile_rules(&self) -> String {
+        DENY_REMOTE_NETWORK.to_string()
+    }
+
+    /// Bytes sent upstream through the proxy so far.
+    pub fn bytes_sent(&self) -> u64 {
+        self.limiter.sent()
+    }
+
+    /// Hosts the proxy refused, in order.
+    pub fn denied(&self) -> Vec<String> {
+        self.denied.lock().expect("denied hosts poisoned").clone()
+    }
+
+    /// Stop accepting connections. Open tunnels run until their peers close them.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            // wake up the accept loop
+            let _ = TcpStream::connect(self.addr);
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for NetGuard {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn handle_http(
+    client: TcpStream,
+    allowlist: &HostAllowlist,
+    denied: &Mutex<Vec<String>>,
+    limiter: Arc<EgressLimiter>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(client.try_clone()?);
+    let head = read_head(&mut reader)?;
+    let mut client = client;
+
+    let request_line = head.lines().next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return respond(&mut client, "400 Bad Request");
+    };
+
+    let (host, port, forwarded_head) = if method.eq_ignore_ascii_case("CONNECT") {
+        let Some((host, port)) = split_host_port(target, 443) else {
+            return respond(&mut client, "400 Bad Request");
+        };
+        (host, port, None)
+    } else {
+        let Some(rest) = target.strip_prefix("http://") else {
+            return respond(&mut client, "400 Bad Request");
+        };
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let Some((host, port)) = split_host_port(authority, 80) else {
+            return respond(&mut client, "400 Bad Request");
+        };
+        let path = if path.is_empty() { "/" } else { path };
+        (host, port, Some(rewrite_head(&head, method, path, version)))
+    };
+
+    if !allowlist.allows(&host) {
+        denied.lock().expect("denied hosts poisoned").push(host);
+        return respond(&mut client, "403 Forbidden");
+    }
+    if !limiter.admit() {
+        return respond(&mut client, "429 Too Many Requests");
+    }
+
+    let mut upstream = match TcpStream::connect((host.as_str(), port)) {
+        Ok(upstream) => upstream,
+        Err(_) => return respond(&mut client, "502 Bad Gateway"),
+    };
+    match forwarded_head {
+        Some(head) => {
+            limiter.consume(head.len())?;
+            upstream.write_all(head.as_bytes())?
+        }
+        None => client.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")?,
+    }
+
+    // bytes the client sent after the head are still buffered in the reader
+    let buffered = reader.buffer().to_vec();
+    limiter.consume(buffered.len())?;
+    upstream.write_all(&buffered)?;
+    tunnel(reader.into_inner(), client, upstream, limiter)
+}
+
+/// Read the request head, up to and including the empty line.
+pub(crate) fn read_head<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut head = String::new();
+    loop {
+        let read = reader.read_line(&mut head)?;
+        if read == 0 || head.ends_with("\r\n\r\n") || head.ends_with("\n\n") {
+            return Ok(head);
+        }
+        if head.len() > MAX_HEAD_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request head too large",
+            ));
+        }
+    }
+}
+
+/// Turn an absolute-form proxy request into an origin-form request for the server.
+fn rewrite_head(head: &str, method: &str, path: &str, version: &str) -> String {
+    let mut rewritten = format!("{method} {path} {version}\r\n");
+    for line in head.lines().skip(1) {
+        let name = line.split(':').next().unwrap_or_default().trim();
+        if line.is_empty()
+            || name.eq_ignore_ascii_case("proxy-connection")
+            || name.eq_ignore_ascii_case("proxy-authorization")
+            || name.eq_ignore_ascii_case("connection")
+        {
+            continue;
+        }
+        rewritten.push_str(line);
+        rewritten.push_str("\r\n");
+    }
+    rewritten.push_str("Connection: close\r\n\r\n");
+    rewritten
+}
+
+pub(crate) fn split_host_port(authority: &str, default_port: u16) -> Option<(String, u16)> {
+    if let Some(rest) = authority.strip_prefix('[') {
+        // [ipv6]:port
+        let (host, rest) = rest.split_once(']')?;
+        let port = match rest.strip_prefix(':') {
+            Some(port) => port.parse().ok()?,
+            None => default_port,
+        };
+        return Some((host.to_string(), port));
+    }
+    match authority.rsplit_once(':') {
+        Some((host, port)) => Some((host.to_string(), port.parse().ok()?)),
+        None if !authority.is_empty() => Some((authority.to_string(), default_port)),
+        None => None,
+    }
+}
+
+pub(crate) fn respond(client: &mut TcpStream, status: &str) -> io::Result<()> {
+    write!(
+        client,
+        "HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+    )?;
+    client.flush()
+}
+
+// SOCKS5 reply codes, RFC 1928 section 6
+const SOCKS_SUCCEEDED: u8 = 0x00;
+const SOCKS_NOT_ALLOWED: u8 = 0x02;
+const SOCKS_HOST_UNREACHABLE: u8 = 0x04;
+const SOCKS_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+const SOCKS_ADDRESS_NOT_SUPPORTED: u8 = 0x08;
+
+fn handle_socks5(
+    mut client: TcpStream,
+    allowlist: &HostAllowlist,
+    denied: &Mutex<Vec<String>>,
+    limiter: Arc<EgressLimiter>,
+) -> io::Result<()> {
+    // greeting: version, method count, methods
+    let mut header = [0u8; 2];
+    client.read_exact(&mut header)?;
+    if header[0] != 5 {
+        return Ok(());
+    }
+    let mut methods = vec![0u8; usize::from(header[1])];
+    client.read_exact(&mut methods)?;
+    if !methods.contains(&0) {
+        // no acceptable methods
+        return client.write_all(&[5, 0xff]);
+    }
+    client.write_all(&[5, 0])?;
+
+    // request: version, command, reserved, address type
+    let mut request = [0u8; 4];
+    client.read_exact(&mut request)?;
+    let host = match request[3] {
+        1 => {
+            let mut octets = [0u8; 4];
+            client.read_exact(&mut octets)?;
+            std::net::Ipv4Addr::from(octets).to_string()
+        }
+        3 => {
+            let mut len = [0u8; 1];
+            client.read_exact(&mut len)?;
+            let mut name = vec![0u8; usize::from(len[0])];
+            client.read_exact(&mut name)?;
+            String::from_utf8_lossy(&name).into_owned()
+        }
+        4 => {
+            let mut octets = [0u8; 16];
+            client.read_exact(&mut octets)?;
+            std::net::Ipv6Addr::from(octets).to_string()
+        }
+        _ => return socks_reply(&mut client, SOCKS_ADDRESS_NOT_SUPPORTED),
+    };
+    let mut port = [0u8; 2];
+    client.read_exact(&mut port)?;
+    let port = u16::from_be_bytes(port);
+
+    // only CONNECT
+    if request[1] != 1 {
+        return socks_reply(&mut client, SOCKS_COMMAND_NOT_SUPPORTED);
+    }
+    if !allowlist.allows(&host) {
+        denied.lock().expect("denied hosts poisoned").push(host);
+        return socks_reply(&mut client, SOCKS_NOT_ALLOWED);
+    }
+    if !limiter.admit() {
+        return socks_reply(&mut client, SOCKS_NOT_ALLOWED);
+    }
+
+    let upstream = match TcpStream::connect((host.as_str(), port)) {
+        Ok(upstream) => upstream,
+        Err(_) => return socks_reply(&mut client, SOCKS_HOST_UNREACHABLE),
+    };
+    socks_reply(&mut client, SOCKS_SUCCEEDED)?;
+    tunnel(client.try_clone()?, client, upstream, limiter)
+}
+
+fn socks_reply(client: &mut TcpStream, code: u8) -> io::Result<()> {
+    // bound address is not meaningful here, report 0.0.0.0:0
+    client.write_all(&[5, code, 0, 1, 0, 0, 0, 0, 0, 0])?;
+    client.flush()
+}
+
+/// Copy bytes both ways until either side closes, applying `limiter` upstream.
+pub(crate) fn tunnel(
+    client_reader: TcpStream,
+    mut client_writer: TcpStream,
+    upstream: TcpStream,
+    limiter: Arc<EgressLimiter>,
+) -> io::Result<()> {
+    let mut upstream_writer = upstream.try_clone()?;
+    let mut client_reader = client_reader;
+    let upload = std::thread::spawn(move || {
+        let shutdown = match copy_limited(&mut client_reader, &mut upstream_writer, &limiter) {
+            Ok(()) => Shutdown::Write,
+            // over quota: drop the connection altogether
+            Err(_) => Shutdown::Both,
+        };
+        let _ = upstream_writer.shutdown(shutdown);
+    });
+
+    let mut upstream_reader = upstream;
+    let _ = io::copy(&mut upstream_reader, &mut client_writer);
+    let _ = client_writer.shutdown(Shutdown::Write);
+    let _ = upload.join();
+    Ok(())
+}
+
+fn copy_limited<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    limiter: &EgressLimiter,
+) -> io::Result<()> {
+    let mut buffer = [0u8; 16 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        limiter.consume(read)?;
+        writer.write_all(&buffer[..read])?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowlist() {
+        let allowlist = HostAllowlist::new(["pypi.org", "*.githubusercontent.com"]);
+        assert!(allowlist.allows("PyPI.org"));
+        assert!(allowlist.allows("raw.githubusercontent.com"));
+        assert!(!allowlist.allows("githubusercontent.com"));
+        assert!(!allowlist.allows("evilgithubusercontent.com"));
+        assert!(!allowlist.allows("example.com"));
+    }
+
+    #[test]
+    fn test_proxy_enforces_allowlist() -> Result<()> {
+        let upstream = TcpListener::bind("127.0.0.1:0")?;
+        let upstream_port = upstream.local_addr()?.port();
+        std::thread::spawn(move || {
+            if let Ok((mut stream, _)) = upstream.accept() {
+                let _ = stream.write_all(b"hello");
+            }
+        });
+
+        let guard = NetGuard::start(HostAllowlist::new(["127.0.0.1"]))?;
+        let connect = |target: &str| -> io::Result<String> {
+            let mut stream = TcpStream::connect(guard.addr())?;
+            write!(
+                stream,
+                "CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n\r\n"
+            )?;
+            stream.shutdown(Shutdown::Write)?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            Ok(response)
+        };
+
+        assert!(connect("blocked.example:443")?.starts_with("HTTP/1.1 403"));
+        let response = connect(&format!("127.0.0.1:{upstream_port}"))?;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("hello"));
+        assert_eq!(guard.denied(), vec!["blocked.example".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_socks5_enforces_allowlist() -> Result<()> {
+        let upstream = TcpListener::bind("127.0.0.1:0")?;
+        let upstream_port = upstream.local_addr()?.port();
+        std::thread::spawn(move || {
+            if let Ok((mut stream, _)) = upstream.accept() {
+                let _ = stream.write_all(b"hello");
+            }
+        });
+
+        let guard = NetGuard::start_with(HostAllowlist::new(["127.0.0.1"]), ProxyProtocol::Socks5)?;
+        let connect = |host: &str, port: u16| -> io::Result<Vec<u8>> {
+            let mut stream = TcpStream::connect(guard.addr())?;
+            let mut request = vec![5, 1, 0, 5, 1, 0, 3, host.len() as u8];
+            request.extend_from_slice(host.as_bytes());
+            request.extend_from_slice(&port.to_be_bytes());
+            stream.write_all(&request)?;
+            stream.shutdown(Shutdown::Write)?;
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response)?;
+            Ok(response)
+        };
+
+        let response = connect("blocked.example", 5432)?;
+        assert_eq!(&response[..4], &[5, 0, 5, SOCKS_NOT_ALLOWED]);
+        let response = connect("127.0.0.1", upstream_port)?;
+        assert_eq!(&response[..4], &[5, 0, 5, SOCKS_SUCCEEDED]);
+        assert!(response.ends_with(b"hello"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_egress_limiter() {
+        let limiter = EgressLimiter::new(EgressLimits {
+            total_bytes: Some(10),
+            requests_per_minute: Some(2),
+            ..EgressLimits::default()
+        });
+        assert!(limiter.admit());
+        assert!(limiter.admit());
+        assert!(!limiter.admit());
+
+        assert!(limiter.consume(8).is_ok());
+        assert!(limiter.consume(5).is_err());
+        assert_eq!(limiter.sent(), 13);
+    }
+}
@@ -0,0 +1,197 @@
+//! N-API bindings, so a JupyterLab extension can show the kernel's permissions and
+//! live sandbox denials.
+//!
+//! Build with `--features napi` and load the resulting library as a Node addon.
+
+use napi_derive::napi;
+use std::path::PathBuf;
+
+use crate::violations::{Violation, ViolationMonitor};
+use crate::{generate_profile, Permissions, DEFAULT_SANDBOX_PROFILE};
+
+fn to_napi(error: crate::SecureNotebookError) -> napi::Error {
+    napi::Error::from_reason(error.to_string())
+}
+
+fn paths(paths: Vec<String>) -> Vec<PathBuf> {
+    paths.into_iter().map(PathBuf::from).collect()
+}
+
+fn strings(paths: &[PathBuf]) -> Vec<String> {
+    paths
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Permissions as a plain JavaScript object.
+#[napi(object)]
+#[derive(Debug, Clone, Default)]
+pub struct JsPermissions {
+    pub allow_read: Vec<String>,
+    pub deny_read: Vec<String>,
+    pub allow_write: Vec<String>,
+    pub deny_write: Vec<String>,
+    pub allow_net: bool,
+    pub allow_gpu: bool,
+    pub allow_run: Vec<String>,
+    pub deny_run: Vec<String>,
+}
+
+impl From<&Permissions> for JsPermissions {
+    fn from(permissions: &Permissions) -> Self {
+        Self {
+            allow_read: strings(&permissions.allow_read),
+            deny_read: strings(&permissions.deny_read),
+            allow_write: strings(&permissions.allow_write),
+            deny_write: strings(&permissions.deny_write),
+            allow_net: permissions.allow_net,
+            allow_gpu: permissions.allow_gpu,
+            allow_run: strings(&permissions.allow_run),
+            deny_run: strings(&permissions.deny_run),
+        }
+    }
+}
+
+impl From<JsPermissions> for Permissions {
+    fn from(permissions: JsPermissions) -> Self {
+        Self {
+            allow_read: paths(permissions.allow_read),
+            deny_read: paths(permissions.deny_read),
+            allow_write: paths(permissions.allow_write),
+            deny_write: paths(permissions.deny_write),
+            allow_net: permissions.allow_net,
+            allow_gpu: permissions.allow_gpu,
+            allow_run: paths(permissions.allow_run),
+            deny_run: paths(permissions.deny_run),
+        }
+    }
+}
+
+/// A sandbox denial as a plain JavaScript object.
+#[napi(object)]
+#[derive(Debug, Clone)]
+pub struct JsViolation {
+    pub process: String,
+    pub pid: u32,
+    pub operation: String,
+    pub target: Option<String>,
+}
+
+impl From<Violation> for JsViolation {
+    fn from(violation: Violation) -> Self {
+        Self {
+            process: violation.process,
+            pid: violation.pid,
+            operation: violation.operation,
+            target: violation.target,
+        }
+    }
+}
+
+/// Builds a policy from JavaScript, one grant at a time.
+#[napi]
+#[derive(Debug, Default)]
+pub struct PolicyBuilder {
+    permissions: Permissions,
+}
+
+#[napi]
+impl PolicyBuilder {
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from existing permissions.
+    #[napi(factory)]
+    pub fn from_permissions(permissions: JsPermissions) -> Self {
+        Self {
+            permissions: permissions.into(),
+        }
+    }
+
+    #[napi]
+    pub fn allow_read(&mut self, path: String) {
+        self.permissions.allow_read.push(path.into());
+    }
+
+    #[napi]
+    pub fn deny_read(&mut self, path: String) {
+        self.permissions.deny_read.push(path.into());
+    }
+
+    #[napi]
+    pub fn allow_write(&mut self, path: String) {
+        self.permissions.allow_write.push(path.into());
+    }
+
+    #[napi]
+    pub fn deny_write(&mut self, path: String) {
+        self.permissions.deny_write.push(path.into());
+    }
+
+    #[napi]
+    pub fn allow_net(&mut self, allow: bool) {
+        self.permissions.allow_net = allow;
+    }
+
+    #[napi]
+    pub fn allow_gpu(&mut self, allow: bool) {
+        self.permissions.allow_gpu = allow;
+    }
+
+    #[napi]
+    pub fn allow_run(&mut self, program: String) {
+        self.permissions.allow_run.push(program.into());
+    }
+
+    #[napi]
+    pub fn deny_run(&mut self, program: String) {
+        self.permissions.deny_run.push(program.into());
+    }
+
+    /// The permissions built so far.
+    #[napi]
+    pub fn permissions(&self) -> JsPermissions {
+        (&self.permissions).into()
+    }
+
+    /// Profile for the permissions, on `template` or the default profile.
+    #[napi]
+    pub fn generate_profile(&self, template: Option<String>) -> napi::Result<String> {
+        let template = template.as_deref().unwrap_or(DEFAULT_SANDBOX_PROFILE);
+        generate_profile(template, &self.permissions).map_err(to_napi)
+    }
+}
+
+/// Live sandbox denials, polled from JavaScript.
+#[napi]
+#[derive(Debug)]
+pub struct ViolationStream {
+    monitor: ViolationMonitor,
+}
+
+#[napi]
+impl ViolationStream {
+    /// Start streaming denials from the unified log.
+    #[napi(factory)]
+    pub fn start() -> napi::Result<Self> {
+        Ok(Self {
+            monitor: ViolationMonitor::start().map_err(to_napi)?,
+        })
+    }
+
+    /// Denials reported since the last call, without blocking the event loop.
+    #[napi]
+    pub fn drain(&self) -> Vec<JsViolation> {
+        std::iter::from_fn(|| self.monitor.try_next())
+            .map(JsViolation::from)
+            .collect()
+    }
+
+    #[napi]
+    pub fn stop(&mut self) -> napi::Result<()> {
+        self.monitor.stop().map_err(to_napi)
+    }
+}
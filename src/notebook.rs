@@ -0,0 +1,902 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::signing::encode_hex;
+use crate::Permissions;
+
+/// Tag of the cell holding a notebook's default parameters.
+pub const PARAMETERS_TAG: &str = "parameters";
+/// Tag of the cell [`Notebook::parameterize`] inserts.
+pub const INJECTED_PARAMETERS_TAG: &str = "injected-parameters";
+/// Key of the [`ExecutionRecord`] in a notebook's metadata.
+pub const EXECUTION_METADATA_KEY: &str = "secure_notebook";
+
+/// A notebook in the Jupyter `.ipynb` format.
+///
+/// Fields this crate does not use are kept, so a notebook saves back unchanged.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Notebook {
+    pub cells: Vec<NotebookCell>,
+    #[serde(flatten)]
+    pub rest: Map<String, Value>,
+}
+
+/// A cell of a [`Notebook`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotebookCell {
+    /// `code`, `markdown` or `raw`.
+    pub cell_type: String,
+    pub source: Source,
+    #[serde(default)]
+    pub metadata: Value,
+    #[serde(flatten)]
+    pub rest: Map<String, Value>,
+}
+
+/// Cell source, stored either as one string or as a list of lines.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Source {
+    Text(String),
+    Lines(Vec<String>),
+}
+
+impl Source {
+    /// The source as one string.
+    pub fn text(&self) -> String {
+        match self {
+            Self::Text(text) => text.clone(),
+            Self::Lines(lines) => lines.concat(),
+        }
+    }
+}
+
+impl NotebookCell {
+    /// A code cell with `tags`.
+    pub fn code(source: &str, tags: &[&str]) -> Self {
+        Self {
+            cell_type: "code".to_string(),
+            source: Source::Text(source.to_string()),
+            metadata: serde_json::json!({ "tags": tags }),
+            rest: Map::from_iter([
+                ("outputs".to_string(), Value::Array(Vec::new())),
+                ("execution_count".to_string(), Value::Null),
+            ]),
+        }
+    }
+
+    /// Whether the cell's metadata lists `tag`.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        let Value::Object(metadata) = &self.metadata else {
+            return false;
+        };
+        matches!(metadata.get("tags"), Some(Value::Array(tags))
+            if tags.iter().any(|t| matches!(t, Value::String(t) if t == tag)))
+    }
+
+    /// Replace the cell's outputs with those of `result`.
+    pub fn set_outputs(&mut self, result: &CellResult) {
+        let outputs = result.outputs.iter().map(Output::to_nbformat).collect();
+        self.rest
+            .insert("outputs".to_string(), Value::Array(outputs));
+    }
+}
+
+impl Notebook {
+    /// Parse a notebook from its JSON representation.
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json)
+            .map_err(|e| SecureNotebookError::InvalidState(format!("Invalid notebook: {e}")))
+    }
+
+    /// Read a notebook file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .io_context(|| format!("Failed to read notebook {}", path.display()))?;
+        Self::from_json(&json)
+    }
+
+    /// Write the notebook to `path`.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| SecureNotebookError::InvalidState(format!("Invalid notebook: {e}")))?;
+        std::fs::write(path, json)
+            .io_context(|| format!("Failed to write notebook {}", path.display()))
+    }
+
+    /// Insert `parameters` as Python assignments after the cell tagged `parameters`.
+    ///
+    /// Like papermill, the assignments go into a new cell tagged `injected-parameters`,
+    /// replacing one left by an earlier run, or at the top if no cell is tagged.
+    pub fn parameterize(&mut self, parameters: &BTreeMap<String, Value>) -> Result<()> {
+        let mut source = String::from("# Parameters\n");
+        for (name, value) in parameters {
+            if !is_identifier(name) {
+                return Err(SecureNotebookError::InvalidPolicy(format!(
+                    "Invalid parameter name {name:?}"
+                )));
+            }
+            source.push_str(&format!("{name} = {}\n", python_literal(value)));
+        }
+        let cell = NotebookCell::code(&source, &[INJECTED_PARAMETERS_TAG]);
+
+        if let Some(index) = self
+            .cells
+            .iter()
+            .position(|cell| cell.has_tag(INJECTED_PARAMETERS_TAG))
+        {
+            self.cells[index] = cell;
+        } else {
+            let index = self
+                .cells
+                .iter()
+                .position(|cell| cell.has_tag(PARAMETERS_TAG))
+                .map_or(0, |index| index + 1);
+            self.cells.insert(index, cell);
+        }
+        Ok(())
+    }
+
+    /// Store `record` in the notebook's metadata, replacing one from an earlier run.
+    pub fn set_execution_record(&mut self, record: &ExecutionRecord) -> Result<()> {
+        let record = serde_json::to_value(record)?;
+        let metadata = self
+            .rest
+            .entry("metadata".to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+        match metadata {
+            Value::Object(metadata) => {
+                metadata.insert(EXECUTION_METADATA_KEY.to_string(), record);
+                Ok(())
+            }
+            _ => Err(SecureNotebookError::InvalidState(
+                "Invalid notebook: metadata is not an object".to_string(),
+            )),
+        }
+    }
+
+    /// The conditions the notebook was last executed under, if it was executed sandboxed.
+    pub fn execution_record(&self) -> Option<ExecutionRecord> {
+        let record = self.rest.get("metadata")?.get(EXECUTION_METADATA_KEY)?;
+        serde_json::from_value(record.clone()).ok()
+    }
+
+    /// Source of every code cell, in order.
+    pub fn code_cells(&self) -> Vec<String> {
+        self.cells
+            .iter()
+            .filter(|cell| cell.cell_type == "code")
+            .map(|cell| cell.source.text())
+            .collect()
+    }
+}
+
+/// The sandbox conditions a notebook was executed under, so an `.ipynb` can be traced
+/// back to them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionRecord {
+    /// [`Permissions::fingerprint_hex`] of the policy.
+    pub policy_fingerprint: String,
+    /// SHA-256 of the profile template, hex encoded.
+    pub template_fingerprint: String,
+    /// Version of this crate.
+    pub secure_notebook_version: String,
+    /// macOS version of the host, when it could be determined.
+    pub macos_version: Option<String>,
+    /// Unix time in seconds.
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+}
+
+impl ExecutionRecord {
+    /// Record a run starting now under `template` and `permissions`.
+    pub fn start(template: &str, permissions: &Permissions) -> Self {
+        Self {
+            policy_fingerprint: permissions.fingerprint_hex(),
+            template_fingerprint: encode_hex(&Sha256::digest(template.as_bytes())),
+            secure_notebook_version: env!("CARGO_PKG_VERSION").to_string(),
+            macos_version: macos_version(),
+            started_at: unix_now(),
+            finished_at: None,
+        }
+    }
+
+    /// Mark the run as finished now.
+    pub fn finish(&mut self) {
+        self.finished_at = Some(unix_now());
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// `sw_vers -productVersion`, e.g. `14.5`.
+#[cfg(target_os = "macos")]
+fn macos_version() -> Option<String> {
+    let output = std::process::Command::new("sw_vers")
+        .arg("-productVersion")
+        .output()
+        .ok()?;
+    let version = String::from_utf8(output.stdout).ok()?;
+    (output.status.success() && !version.trim().is_empty()).then(|| version.trim().to_string())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn macos_version() -> Option<String> {
+    None
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `value` written as a Python literal.
+fn python_literal(value: &Value) -> String {
+    match value {
+        Value::Null => "None".to_string(),
+        Value::Bool(true) => "True".to_string(),
+        Value::Bool(false) => "False".to_string(),
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => python_string(text),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(python_literal).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(entries) => {
+            let entries: Vec<String> = entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", python_string(key), python_literal(value)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+    }
+}
+
+pub(crate) fn python_string(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '\\' => literal.push_str("\\\\"),
+            '"' => literal.push_str("\\\""),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            c if c.is_control() => literal.push_str(&format!("\\u{:04x}", c as u32)),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// An error raised by the code in a cell.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionError {
+    /// Exception name, e.g. `PermissionError`.
+    pub ename: String,
+    pub evalue: String,
+    pub traceback: Vec<String>,
+}
+
+/// Something a cell produced, in the order the kernel sent it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Output {
+    /// Text written to `stdout` or `stderr`.
+    Stream {
+        name: String,
+        text: String,
+    },
+    /// A displayed value or the cell's result, by MIME type. Values are kept as the
+    /// kernel sent them: text for most types, JSON for `application/json` and the like.
+    Rich {
+        data: BTreeMap<String, Value>,
+        /// Set for the cell's result (`execute_result`), not for displayed values.
+        #[serde(default)]
+        execution_count: Option<u64>,
+    },
+    Error(ExecutionError),
+}
+
+impl Output {
+    /// Bytes of text the output holds, as counted by [`OutputLimits`].
+    #[cfg(any(feature = "client", test))]
+    fn size(&self) -> usize {
+        match self {
+            Self::Stream { text, .. } => text.len(),
+            Self::Rich { data, .. } => data
+                .iter()
+                .map(|(mime, value)| match value {
+                    Value::String(text) => mime.len() + text.len(),
+                    value => mime.len() + value.to_string().len(),
+                })
+                .sum(),
+            Self::Error(error) => {
+                error.evalue.len() + error.traceback.iter().map(String::len).sum::<usize>()
+            }
+        }
+    }
+
+    /// The output in the `.ipynb` format.
+    pub fn to_nbformat(&self) -> Value {
+        match self {
+            Self::Stream { name, text } => serde_json::json!({
+                "output_type": "stream",
+                "name": name,
+                "text": text,
+            }),
+            Self::Rich {
+                data,
+                execution_count: Some(count),
+            } => serde_json::json!({
+                "output_type": "execute_result",
+                "execution_count": count,
+                "data": data,
+                "metadata": {},
+            }),
+            Self::Rich {
+                data,
+                execution_count: None,
+            } => serde_json::json!({
+                "output_type": "display_data",
+                "data": data,
+                "metadata": {},
+            }),
+            Self::Error(error) => serde_json::json!({
+                "output_type": "error",
+                "ename": error.ename,
+                "evalue": error.evalue,
+                "traceback": error.traceback,
+            }),
+        }
+    }
+}
+
+/// Output appended to `stderr` where a cell's output was cut off by [`OutputLimits`].
+pub const TRUNCATION_MARKER: &str = "\n[secure-notebook: output truncated]\n";
+
+/// Caps on what is kept of one cell's output, so a cell printing gigabytes can't
+/// exhaust the memory of the process collecting it.
+///
+/// Output past either cap, errors included, is dropped and replaced by a single
+/// [`TRUNCATION_MARKER`]. The cell's first error is still kept after the marker, without
+/// its traceback, so the cell reads as failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLimits {
+    /// Most bytes of stream text and rich output data kept.
+    pub max_bytes: usize,
+    /// Most outputs kept.
+    pub max_messages: usize,
+}
+
+impl Default for OutputLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: 16 * 1024 * 1024,
+            max_messages: 10_000,
+        }
+    }
+}
+
+/// Result of executing one cell.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CellResult {
+    pub stdout: String,
+    pub stderr: String,
+    pub outputs: Vec<Output>,
+    pub error: Option<ExecutionError>,
+    pub duration: Duration,
+    /// Whether output was dropped because of [`OutputLimits`].
+    #[serde(default)]
+    pub truncated: bool,
+    /// Bytes counted against [`OutputLimits::max_bytes`] so far.
+    #[serde(skip)]
+    captured: usize,
+}
+
+impl CellResult {
+    /// Whether the cell ran without raising.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Add `output`, or as much of it as `limits` allow.
+    #[cfg(any(feature = "client", test))]
+    fn push(&mut self, output: Output, limits: &OutputLimits) {
+        if self.truncated {
+            return self.keep_error(output, limits);
+        }
+        let size = output.size();
+        if self.outputs.len() < limits.max_messages && self.captured + size <= limits.max_bytes {
+            self.captured += size;
+            return self.record(output);
+        }
+        let output = match output {
+            Output::Stream { name, text } => {
+                let kept = prefix(&text, limits.max_bytes.saturating_sub(self.captured));
+                if !kept.is_empty() && self.outputs.len() < limits.max_messages {
+                    self.record(Output::Stream {
+                        name,
+                        text: kept.to_string(),
+                    });
+                }
+                None
+            }
+            output => Some(output),
+        };
+        self.truncated = true;
+        self.record(Output::Stream {
+            name: "stderr".to_string(),
+            text: TRUNCATION_MARKER.to_string(),
+        });
+        if let Some(output) = output {
+            self.keep_error(output, limits);
+        }
+    }
+
+    /// Past the limits, keep only the cell's first error: its name and as much of its
+    /// message as [`OutputLimits::max_bytes`] allows.
+    #[cfg(any(feature = "client", test))]
+    fn keep_error(&mut self, output: Output, limits: &OutputLimits) {
+        let Output::Error(error) = output else {
+            return;
+        };
+        if self.error.is_none() {
+            self.record(Output::Error(ExecutionError {
+                evalue: prefix(&error.evalue, limits.max_bytes).to_string(),
+                traceback: Vec::new(),
+                ..error
+            }));
+        }
+    }
+
+    #[cfg(any(feature = "client", test))]
+    fn record(&mut self, output: Output) {
+        match &output {
+            Output::Stream { name, text } if name == "stdout" => self.stdout.push_str(text),
+            Output::Stream { text, .. } => self.stderr.push_str(text),
+            Output::Error(error) => self.error = Some(error.clone()),
+            Output::Rich { .. } => {}
+        }
+        self.outputs.push(output);
+    }
+}
+
+/// The longest prefix of `text` of at most `max` bytes that ends on a char boundary.
+#[cfg(any(feature = "client", test))]
+fn prefix(text: &str, max: usize) -> &str {
+    let mut end = max.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(feature = "client")]
+pub use self::client::NotebookSession;
+
+#[cfg(feature = "client")]
+mod client {
+    use serde_json::{json, Value};
+    use std::collections::BTreeMap;
+    use std::path::Path;
+    use std::sync::mpsc::Receiver;
+    use std::time::{Duration, Instant};
+
+    use super::{CellResult, ExecutionError, ExecutionRecord, Notebook, Output, OutputLimits};
+    use crate::error::{Result, SecureNotebookError};
+    use crate::session::JupyterSession;
+    use crate::wire::{ConnectionInfo, KernelClient, Message};
+
+    /// How long [`NotebookSession::new`] waits for each probe of the iopub subscription.
+    const SUBSCRIBE_PROBE: Duration = Duration::from_millis(250);
+
+    /// Runs code in a sandboxed kernel and collects what it produces.
+    #[derive(Debug)]
+    pub struct NotebookSession {
+        client: KernelClient,
+        iopub: Receiver<Message>,
+        timeout: Duration,
+        limits: OutputLimits,
+    }
+
+    impl NotebookSession {
+        /// Start a kernel on `server` and connect to it, so cells run under the server's
+        /// profile.
+        pub fn start(server: &JupyterSession) -> Result<Self> {
+            let id = server.start_kernel()?;
+            Self::connect_to(ConnectionInfo::load(&server.kernel_connection_file(&id)?)?)
+        }
+
+        /// Where the kernel listens.
+        pub fn connection(&self) -> &ConnectionInfo {
+            self.client.info()
+        }
+
+        /// Connect to the kernel described by `info`.
+        pub fn connect_to(info: ConnectionInfo) -> Result<Self> {
+            Self::new(KernelClient::connect(info)?)
+        }
+
+        /// Drive the kernel `client` is connected to.
+        pub fn new(client: KernelClient) -> Result<Self> {
+            let iopub = client.iopub_subscribe()?;
+            let session = Self {
+                client,
+                iopub,
+                timeout: Duration::from_secs(60),
+                limits: OutputLimits::default(),
+            };
+            session.await_subscription()?;
+            Ok(session)
+        }
+
+        /// Wait until the iopub subscription is in effect, so no output of the first
+        /// cell is missed: probe with `kernel_info_request`s until one's status arrives.
+        fn await_subscription(&self) -> Result<()> {
+            let deadline = Instant::now() + self.timeout;
+            while Instant::now() < deadline {
+                let probe = self.client.send_shell("kernel_info_request", json!({}))?;
+                let probed = Instant::now() + SUBSCRIBE_PROBE;
+                while let Some(wait) = probed.checked_duration_since(Instant::now()) {
+                    match self.iopub.recv_timeout(wait) {
+                        Ok(message) if message.parent_id() == Some(probe.as_str()) => {
+                            self.client.shell_reply(&probe, Some(self.timeout))?;
+                            return Ok(());
+                        }
+                        Ok(_) => continue,
+                        Err(_) => break,
+                    }
+                }
+            }
+            Err(SecureNotebookError::KernelTimeout(self.timeout))
+        }
+
+        /// Give up on a cell once the kernel is silent for `timeout`.
+        pub fn with_timeout(mut self, timeout: Duration) -> Self {
+            self.timeout = timeout;
+            self
+        }
+
+        /// Keep at most `limits` of each cell's output.
+        pub fn with_output_limits(mut self, limits: OutputLimits) -> Self {
+            self.limits = limits;
+            self
+        }
+
+        /// Execute `code` and collect its outputs until the kernel is idle again.
+        ///
+        /// Errors raised by the code, including sandbox denials, are reported in the
+        /// result rather than as an `Err`.
+        pub fn run_cell(&self, code: &str) -> Result<CellResult> {
+            let started = Instant::now();
+            let request = self.client.send_shell(
+                "execute_request",
+                json!({
+                    "code": code,
+                    "silent": false,
+                    "store_history": true,
+                    "user_expressions": {},
+                    "allow_stdin": false,
+                    "stop_on_error": true,
+                }),
+            )?;
+
+            let mut result = CellResult::default();
+            loop {
+                let message = self
+                    .iopub
+                    .recv_timeout(self.timeout)
+                    .map_err(|_| SecureNotebookError::KernelTimeout(self.timeout))?;
+                // Outputs of earlier requests, or of other clients of the kernel.
+                if message.parent_id() != Some(request.as_str()) {
+                    continue;
+                }
+                let content = &message.content;
+                let output = match message.msg_type() {
+                    "status" => {
+                        if text(content, "execution_state") == "idle" {
+                            break;
+                        }
+                        continue;
+                    }
+                    "stream" => Output::Stream {
+                        name: text(content, "name"),
+                        text: text(content, "text"),
+                    },
+                    "execute_result" | "display_data" => Output::Rich {
+                        data: mime_bundle(content.get("data")),
+                        execution_count: content.get("execution_count").and_then(Value::as_u64),
+                    },
+                    "error" => Output::Error(error(content)),
+                    _ => continue,
+                };
+                // Past the limits, messages are still drained until idle, just not kept.
+                result.push(output, &self.limits);
+            }
+
+            let reply = self.client.shell_reply(&request, Some(self.timeout))?;
+            if text(&reply.content, "status") == "error" && result.error.is_none() {
+                result.error = Some(ExecutionError {
+                    ename: "Error".to_string(),
+                    evalue: text(&reply.content, "evalue"),
+                    traceback: Vec::new(),
+                });
+            }
+            result.duration = started.elapsed();
+            Ok(result)
+        }
+
+        /// Execute every code cell of `notebook` in order, stopping after the first error.
+        pub fn run_all(&self, notebook: &Notebook) -> Result<Vec<CellResult>> {
+            let mut results = Vec::new();
+            for code in notebook.code_cells() {
+                let result = self.run_cell(&code)?;
+                let failed = !result.is_ok();
+                results.push(result);
+                if failed {
+                    break;
+                }
+            }
+            Ok(results)
+        }
+
+        /// Like [`Self::run_all`], storing each cell's outputs in `notebook`.
+        pub fn run_notebook(&self, notebook: &mut Notebook) -> Result<Vec<CellResult>> {
+            let mut results = Vec::new();
+            for cell in notebook
+                .cells
+                .iter_mut()
+                .filter(|cell| cell.cell_type == "code")
+            {
+                let result = self.run_cell(&cell.source.text())?;
+                cell.set_outputs(&result);
+                let failed = !result.is_ok();
+                results.push(result);
+                if failed {
+                    break;
+                }
+            }
+            Ok(results)
+        }
+
+        /// Run the notebook at `input` with `parameters` injected, saving it with its
+        /// outputs to `output`, papermill style.
+        ///
+        /// The output is saved even if a cell fails, so the error can be inspected.
+        pub fn execute_notebook(
+            &self,
+            input: &Path,
+            output: &Path,
+            parameters: &BTreeMap<String, Value>,
+        ) -> Result<Vec<CellResult>> {
+            self.execute(input, output, parameters, None)
+        }
+
+        /// Like [`Self::execute_notebook`], storing `record`, finished when the run ends,
+        /// in the output's metadata.
+        pub fn execute_notebook_with_record(
+            &self,
+            input: &Path,
+            output: &Path,
+            parameters: &BTreeMap<String, Value>,
+            record: ExecutionRecord,
+        ) -> Result<Vec<CellResult>> {
+            self.execute(input, output, parameters, Some(record))
+        }
+
+        fn execute(
+            &self,
+            input: &Path,
+            output: &Path,
+            parameters: &BTreeMap<String, Value>,
+            record: Option<ExecutionRecord>,
+        ) -> Result<Vec<CellResult>> {
+            let mut notebook = Notebook::load(input)?;
+            notebook.parameterize(parameters)?;
+            let results = self.run_notebook(&mut notebook);
+            if let Some(mut record) = record {
+                record.finish();
+                notebook.set_execution_record(&record)?;
+            }
+            notebook.save(output)?;
+            results
+        }
+    }
+
+    fn mime_bundle(data: Option<&Value>) -> BTreeMap<String, Value> {
+        data.and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+            .map(|(mime, value)| (mime.clone(), value.clone()))
+            .collect()
+    }
+
+    /// The string field `key` of a message's content, or empty if missing.
+    fn text(content: &Value, key: &str) -> String {
+        content
+            .get(key)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    fn error(content: &Value) -> ExecutionError {
+        ExecutionError {
+            ename: text(content, "ename"),
+            evalue: text(content, "evalue"),
+            traceback: content
+                .get("traceback")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_result_collects_streams() {
+        let limits = OutputLimits::default();
+        let mut result = CellResult::default();
+        result.push(
+            Output::Stream {
+                name: "stdout".to_string(),
+                text: "hello\n".to_string(),
+            },
+            &limits,
+        );
+        result.push(
+            Output::Error(ExecutionError {
+                ename: "PermissionError".to_string(),
+                evalue: "[Errno 1] Operation not permitted".to_string(),
+                traceback: Vec::new(),
+            }),
+            &limits,
+        );
+
+        assert_eq!(result.stdout, "hello\n");
+        assert_eq!(result.outputs.len(), 2);
+        assert!(!result.is_ok());
+        assert!(!result.truncated);
+    }
+
+    #[test]
+    fn test_rich_output_to_nbformat() {
+        let data = BTreeMap::from([(
+            "application/json".to_string(),
+            serde_json::json!({ "epochs": 3 }),
+        )]);
+        let result = Output::Rich {
+            data: data.clone(),
+            execution_count: Some(7),
+        }
+        .to_nbformat();
+        assert_eq!(result["output_type"], "execute_result");
+        assert_eq!(result["execution_count"], 7);
+        assert_eq!(result["data"]["application/json"]["epochs"], 3);
+
+        let display = Output::Rich {
+            data,
+            execution_count: None,
+        }
+        .to_nbformat();
+        assert_eq!(display["output_type"], "display_data");
+        assert!(display.get("execution_count").is_none());
+    }
+
+    #[test]
+    fn test_cell_result_truncates_output() {
+        let limits = OutputLimits {
+            max_bytes: 10,
+            max_messages: 3,
+        };
+        let stream = |text: &str| Output::Stream {
+            name: "stdout".to_string(),
+            text: text.to_string(),
+        };
+        let mut result = CellResult::default();
+        result.push(stream("epoch 1\n"), &limits);
+        result.push(stream("epoch 2\n"), &limits);
+        result.push(stream("epoch 3\n"), &limits);
+        assert_eq!(result.stdout, "epoch 1\nep");
+        assert!(result.truncated);
+        assert_eq!(result.stderr, TRUNCATION_MARKER);
+
+        let mut result = CellResult::default();
+        for _ in 0..5 {
+            result.push(stream("."), &limits);
+        }
+        assert_eq!(result.stdout, "...");
+        assert_eq!(result.outputs.len(), 4);
+
+        // A cell raising in a loop: errors count too, and only the first is kept.
+        let error = |evalue: &str| {
+            Output::Error(ExecutionError {
+                ename: "ValueError".to_string(),
+                evalue: evalue.to_string(),
+                traceback: vec!["Traceback (most recent call last):".to_string(); 100],
+            })
+        };
+        let mut result = CellResult::default();
+        for _ in 0..100 {
+            result.push(error("bad value in a very long message"), &limits);
+        }
+        assert!(result.truncated);
+        assert_eq!(result.outputs.len(), 2);
+        assert_eq!(result.stderr, TRUNCATION_MARKER);
+        let kept = result.error.expect("the first error is kept");
+        assert_eq!(kept.evalue, "bad value ");
+        assert!(kept.traceback.is_empty());
+    }
+
+    #[test]
+    fn test_parameterize_after_parameters_cell() -> Result<()> {
+        let mut parameters_cell = NotebookCell::code("alpha = 0.1\n", &[]);
+        parameters_cell.metadata = Value::Object(Map::from_iter([(
+            "tags".to_string(),
+            Value::Array(vec![Value::String(PARAMETERS_TAG.to_string())]),
+        )]));
+        let mut notebook = Notebook {
+            cells: vec![parameters_cell, NotebookCell::code("print(alpha)", &[])],
+            rest: Map::new(),
+        };
+
+        let parameters = BTreeMap::from([
+            ("alpha".to_string(), Value::String("a \"b\"".to_string())),
+            ("enabled".to_string(), Value::Bool(true)),
+        ]);
+        notebook.parameterize(&parameters)?;
+
+        assert_eq!(notebook.cells.len(), 3);
+        assert_eq!(
+            notebook.cells[1].source.text(),
+            "# Parameters\nalpha = \"a \\\"b\\\"\"\nenabled = True\n"
+        );
+        assert!(notebook
+            .parameterize(&BTreeMap::from([("not valid".to_string(), Value::Null)]))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_lines() {
+        let source = Source::Lines(vec!["x = 1\n".to_string(), "print(x)".to_string()]);
+        assert_eq!(source.text(), "x = 1\nprint(x)");
+    }
+
+    #[test]
+    fn test_execution_record_in_metadata() -> Result<()> {
+        let mut notebook = Notebook {
+            cells: Vec::new(),
+            rest: Map::new(),
+        };
+        let mut record = ExecutionRecord::start("(version 1)\n", &Permissions::new());
+        record.finish();
+        notebook.set_execution_record(&record)?;
+
+        let Some(Value::Object(metadata)) = notebook.rest.get("metadata") else {
+            panic!("metadata was not created");
+        };
+        assert!(metadata.contains_key(EXECUTION_METADATA_KEY));
+        assert!(record.finished_at >= Some(record.started_at));
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::error::Result;
+use crate::limits::consolidate;
+use crate::{
+    check_permission_paths, profile_capacity, write_file_permissions, write_network_permissions,
+    write_path, write_run_permissions, Permissions,
+};
+
+/// Remove rules that cannot change a decision.
+///
+/// Duplicate paths and paths below a directory already in the same list are dropped.
+pub fn optimize(permissions: &Permissions) -> Permissions {
+    consolidate(permissions)
+}
+
+/// Generate the profile for the optimized permissions, grouping deny rules for the same
+/// operation into a single rule.
+///
+/// The result evaluates the same as [`crate::generate_profile`] but is smaller and has
+/// fewer rules for the sandbox to walk.
+pub fn generate_optimized_profile(template: &str, permissions: &Permissions) -> Result<String> {
+    let permissions = optimize(permissions);
+    check_permission_paths(&permissions)?;
+    let mut profile = String::with_capacity(profile_capacity(template, &permissions));
+    write_optimized_profile(&mut profile, template, &permissions).expect("paths were checked");
+    Ok(profile)
+}
+
+fn write_optimized_profile<W: fmt::Write>(
+    writer: &mut W,
+    template: &str,
+    permissions: &Permissions,
+) -> fmt::Result {
+    writer.write_str(template)?;
+
+    write_deny_block(writer, "file-read*", "subpath", &permissions.deny_read)?;
+    write_file_permissions(writer, "file-read*", &permissions.allow_read, &[])?;
+
+    write_deny_block(writer, "file-write*", "subpath", &permissions.deny_write)?;
+    write_file_permissions(writer, "file-write*", &permissions.allow_write, &[])?;
+
+    write_network_permissions(writer, permissions.allow_net)?;
+    if permissions.allow_gpu {
+        writer.write_str(crate::GPU_RULES)?;
+    }
+
+    write_deny_block(writer, "process-exec", "literal", &permissions.deny_run)?;
+    write_run_permissions(writer, &permissions.allow_run, &[])
+}
+
+fn write_deny_block<W: fmt::Write>(
+    writer: &mut W,
+    operation: &str,
+    filter: &str,
+    paths: &[PathBuf],
+) -> fmt::Result {
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    writer.write_str("(deny ")?;
+    writer.write_str(operation)?;
+    writer.write_str("\n")?;
+    for path in paths {
+        writer.write_str("    (")?;
+        writer.write_str(filter)?;
+        writer.write_str(" \"")?;
+        write_path(writer, path)?;
+        writer.write_str("\")\n")?;
+    }
+    writer.write_str(")\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::evaluate_profile;
+    use crate::generate_profile;
+
+    #[test]
+    fn test_optimized_profile_evaluates_the_same() -> Result<()> {
+        let mut permissions = Permissions::new();
+        permissions.deny_read = vec![
+            PathBuf::from("/secrets"),
+            PathBuf::from("/keys"),
+            PathBuf::from("/keys"),
+        ];
+        permissions.allow_read = vec![PathBuf::from("/data/a.csv"), PathBuf::from("/data/a.csv")];
+        permissions.deny_run = vec![PathBuf::from("/usr/bin/ssh")];
+
+        let template = "(version 1)\n(deny default)\n";
+        let original = generate_profile(template, &permissions)?;
+        let optimized = generate_optimized_profile(template, &permissions)?;
+        assert!(optimized.len() < original.len());
+
+        for (operation, path) in [
+            ("file-read-data", "/secrets/x"),
+            ("file-read-data", "/keys"),
+            ("file-read-data", "/data/a.csv"),
+            ("file-read-data", "/data/b.csv"),
+            ("process-exec", "/usr/bin/ssh"),
+        ] {
+            let path = Some(std::path::Path::new(path));
+            assert_eq!(
+                evaluate_profile(&original, operation, path)?,
+                evaluate_profile(&optimized, operation, path)?,
+                "{operation} {path:?}"
+            );
+        }
+        Ok(())
+    }
+}
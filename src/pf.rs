@@ -0,0 +1,199 @@
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+
+/// Parent anchor that the stock macOS `/etc/pf.conf` already evaluates.
+pub const ANCHOR_PARENT: &str = "com.apple";
+
+/// Whose traffic the anchor applies to.
+///
+/// pf matches `user` and `group` on the owner of the local socket, so the notebook has to
+/// run as a dedicated user or group for the rules to apply to it alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PfScope {
+    User(u32),
+    Group(u32),
+}
+
+/// Destination the notebook may connect to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EgressRule {
+    /// Address or CIDR range, e.g. `10.0.0.0/8`.
+    pub network: String,
+    /// Allowed destination ports; any port when empty.
+    pub ports: Vec<u16>,
+}
+
+/// pf anchor restricting egress of the sandboxed notebook to specific IP ranges.
+///
+/// Seatbelt can only allow or deny network access as a whole in practice, so this is
+/// meant to be loaded next to a profile that allows `network*`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PfAnchor {
+    pub name: String,
+    pub scope: PfScope,
+    pub allow: Vec<EgressRule>,
+    /// Let DNS queries through to any resolver.
+    pub allow_dns: bool,
+}
+
+impl PfAnchor {
+    /// Anchor that blocks all egress for `scope` until destinations are allowed.
+    pub fn new(name: &str, scope: PfScope) -> Self {
+        Self {
+            name: name.to_string(),
+            scope,
+            allow: Vec::new(),
+            allow_dns: true,
+        }
+    }
+
+    /// Allow connections to `network` on `ports` (any port when empty).
+    pub fn allow(mut self, network: &str, ports: &[u16]) -> Result<Self> {
+        validate_network(network)?;
+        self.allow.push(EgressRule {
+            network: network.to_string(),
+            ports: ports.to_vec(),
+        });
+        Ok(self)
+    }
+
+    /// Full path of the anchor, under [`ANCHOR_PARENT`].
+    pub fn path(&self) -> String {
+        format!("{ANCHOR_PARENT}/secure_notebook.{}", self.name)
+    }
+
+    /// Render the anchor rules.
+    pub fn render(&self) -> String {
+        let scope = match self.scope {
+            PfScope::User(uid) => format!("user {uid}"),
+            PfScope::Group(gid) => format!("group {gid}"),
+        };
+
+        let mut rules = format!("# secure_notebook egress rules for {scope}\n");
+        if self.allow_dns {
+            let _ = writeln!(
+                rules,
+                "pass out quick proto {{ tcp udp }} from any to any port 53 {scope} keep state"
+            );
+        }
+        for rule in &self.allow {
+            let ports = match rule.ports.as_slice() {
+                [] => String::new(),
+                [port] => format!(" port {port}"),
+                ports => {
+                    let ports: Vec<String> = ports.iter().map(u16::to_string).collect();
+                    format!(" port {{ {} }}", ports.join(" "))
+                }
+            };
+            let _ = writeln!(
+                rules,
+                "pass out quick proto {{ tcp udp }} from any to {}{ports} {scope} keep state",
+                rule.network
+            );
+        }
+        let _ = writeln!(
+            rules,
+            "block drop out quick proto {{ tcp udp }} from any to any {scope}"
+        );
+        rules
+    }
+
+    /// Load the rules into the anchor with `pfctl`. Needs root, and pf has to be enabled.
+    pub fn load(&self) -> Result<()> {
+        let mut child = Command::new("pfctl")
+            .args(["-a", &self.path(), "-f", "-"])
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|source| SecureNotebookError::SpawnFailed {
+                program: PathBuf::from("pfctl"),
+                source,
+            })?;
+        child
+            .stdin
+            .take()
+            .ok_or_else(|| SecureNotebookError::InvalidState("pfctl has no stdin".to_string()))?
+            .write_all(self.render().as_bytes())
+            .io_context(|| "Failed to write pf rules")?;
+        run_pfctl(child)
+    }
+
+    /// Remove the rules from the anchor.
+    pub fn flush(&self) -> Result<()> {
+        let child = Command::new("pfctl")
+            .args(["-a", &self.path(), "-F", "rules"])
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|source| SecureNotebookError::SpawnFailed {
+                program: PathBuf::from("pfctl"),
+                source,
+            })?;
+        run_pfctl(child)
+    }
+}
+
+fn run_pfctl(child: std::process::Child) -> Result<()> {
+    let output = child
+        .wait_with_output()
+        .io_context(|| "Failed to wait for pfctl")?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(SecureNotebookError::InvalidState(format!(
+            "pfctl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+fn validate_network(network: &str) -> Result<()> {
+    let invalid = || SecureNotebookError::InvalidPolicy(format!("Invalid network: {network}"));
+    let (address, prefix) = match network.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix)),
+        None => (network, None),
+    };
+    let address: IpAddr = address.parse().map_err(|_| invalid())?;
+    if let Some(prefix) = prefix {
+        let prefix: u8 = prefix.parse().map_err(|_| invalid())?;
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        if prefix > max {
+            return Err(invalid());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_anchor() -> Result<()> {
+        let anchor = PfAnchor::new("churn", PfScope::User(502))
+            .allow("10.0.0.0/8", &[443, 5432])?
+            .allow("203.0.113.7", &[])?;
+
+        assert_eq!(anchor.path(), "com.apple/secure_notebook.churn");
+        let rules = anchor.render();
+        assert!(rules.contains(
+            "pass out quick proto { tcp udp } from any to 10.0.0.0/8 port { 443 5432 } user 502 keep state\n"
+        ));
+        assert!(rules.contains("to 203.0.113.7 user 502 keep state\n"));
+        assert!(
+            rules.ends_with("block drop out quick proto { tcp udp } from any to any user 502\n")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_network() {
+        let anchor = PfAnchor::new("churn", PfScope::Group(20));
+        assert!(anchor.clone().allow("10.0.0.0/33", &[]).is_err());
+        assert!(anchor.allow("example.com", &[]).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+
+use crate::error::{Result, SecureNotebookError};
+use crate::netguard::{HostAllowlist, NetGuard};
+use crate::session::{sandboxed_command, JupyterSession, SessionConfig};
+use crate::{generate_profile, Permissions};
+
+/// Phase of a two-phase execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Broader permissions for installing packages and downloading data.
+    Setup,
+    /// Strict permissions for running the notebook itself.
+    Run,
+}
+
+/// A session that starts under a permissive setup profile and is locked down
+/// to a strict profile before the actual notebook run.
+#[derive(Debug)]
+pub struct TwoPhaseSession {
+    session: JupyterSession,
+    setup_profile: String,
+    run_profile: String,
+    phase: Phase,
+}
+
+impl TwoPhaseSession {
+    /// Generate both profiles and start the server under the setup profile.
+    pub fn start(
+        template: &str,
+        setup: &Permissions,
+        run: &Permissions,
+        config: SessionConfig,
+    ) -> Result<Self> {
+        let setup_profile = generate_profile(template, setup)?;
+        let run_profile = generate_profile(template, run)?;
+        let session = JupyterSession::spawn(&setup_profile, config)?;
+
+        Ok(Self {
+            session,
+            setup_profile,
+            run_profile,
+            phase: Phase::Setup,
+        })
+    }
+
+    /// Current phase.
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Underlying Jupyter session.
+    pub fn session(&self) -> &JupyterSession {
+        &self.session
+    }
+
+    /// Profile used during the setup phase.
+    pub fn setup_profile(&self) -> &str {
+        &self.setup_profile
+    }
+
+    /// Profile used during the run phase.
+    pub fn run_profile(&self) -> &str {
+        &self.run_profile
+    }
+
+    /// Run a setup command (e.g. `pip install`) under the setup profile.
+    pub fn run_setup_command<I, S>(&self, program: &Path, args: I) -> Result<ExitStatus>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        if self.phase != Phase::Setup {
+            return Err(SecureNotebookError::InvalidState(
+                "Setup commands are not allowed after lockdown".to_string(),
+            ));
+        }
+
+        sandboxed_command(&self.setup_profile, program)
+            .args(args)
+            .status()
+            .map_err(|source| SecureNotebookError::SpawnFailed {
+                program: program.to_path_buf(),
+                source,
+            })
+    }
+
+    /// End the setup phase and restart the server under the strict run profile.
+    pub fn lockdown(&mut self) -> Result<()> {
+        if self.phase == Phase::Run {
+            return Ok(());
+        }
+
+        self.session.restart(&self.run_profile)?;
+        self.phase = Phase::Run;
+        Ok(())
+    }
+}
+
+/// A virtualenv to install a `requirements.txt` into before a locked-down run.
+#[derive(Debug, Clone)]
+pub struct Preparation {
+    /// The virtualenv, with `bin/python` and pip in it.
+    pub env: PathBuf,
+    pub requirements: PathBuf,
+    /// Package indexes pip may reach, e.g. [`crate::presets::pypi`]'s allowlist.
+    pub indexes: HostAllowlist,
+}
+
+impl Preparation {
+    /// Reads of the requirements and writes to the env only.
+    pub fn setup_permissions(&self) -> Permissions {
+        Permissions {
+            allow_read: vec![self.env.clone(), self.requirements.clone()],
+            allow_write: vec![self.env.clone()],
+            ..Permissions::default()
+        }
+    }
+
+    /// `run` with the env readable.
+    pub fn run_permissions(&self, run: &Permissions) -> Permissions {
+        let mut permissions = run.clone();
+        permissions.allow_read.push(self.env.clone());
+        permissions
+    }
+
+    /// `pip install -r` the requirements under [`Self::setup_permissions`], with the
+    /// network limited to the indexes through a [`NetGuard`].
+    pub fn install(&self, template: &str) -> Result<()> {
+        let python = self.env.join("bin/python");
+        if !python.is_file() {
+            return Err(SecureNotebookError::InvalidPath {
+                path: self.env.clone(),
+                reason: "not a virtualenv: bin/python is missing".to_string(),
+            });
+        }
+
+        let guard = NetGuard::start(self.indexes.clone())?;
+        let mut profile = generate_profile(template, &self.setup_permissions())?;
+        profile.push_str(&guard.profile_rules());
+        let status = sandboxed_command(&profile, &python)
+            .args(["-m", "pip", "install", "--no-cache-dir", "--requirement"])
+            .arg(&self.requirements)
+            .envs(guard.env())
+            .status()
+            .map_err(|source| SecureNotebookError::SpawnFailed {
+                program: python.clone(),
+                source,
+            });
+        let refused = guard.denied();
+        guard.stop();
+
+        let status = status?;
+        if status.success() {
+            return Ok(());
+        }
+        let mut message = format!(
+            "pip install -r {} failed with {status}",
+            self.requirements.display()
+        );
+        if !refused.is_empty() {
+            message.push_str(&format!("; hosts refused: {}", refused.join(", ")));
+        }
+        Err(SecureNotebookError::InvalidState(message))
+    }
+}
+
+#[cfg(feature = "client")]
+pub use self::client::prepare_and_run;
+
+#[cfg(feature = "client")]
+mod client {
+    use std::collections::BTreeMap;
+    use std::path::Path;
+
+    use super::Preparation;
+    use crate::error::Result;
+    use crate::notebook::{CellResult, NotebookSession};
+    use crate::session::{JupyterSession, SessionConfig};
+    use crate::{generate_profile, Permissions};
+
+    /// Install `preparation`'s requirements, then run the notebook at `input` against the
+    /// env under `run` alone, saving it with its outputs to `output`.
+    pub fn prepare_and_run(
+        template: &str,
+        preparation: &Preparation,
+        run: &Permissions,
+        input: &Path,
+        output: &Path,
+        mut config: SessionConfig,
+    ) -> Result<Vec<CellResult>> {
+        preparation.install(template)?;
+
+        let profile = generate_profile(template, &preparation.run_permissions(run))?;
+        config.env.push((
+            "VIRTUAL_ENV".to_string(),
+            preparation.env.to_string_lossy().into_owned(),
+        ));
+        let mut server = JupyterSession::spawn(&profile, config)?;
+        // A kernel of the locked-down server, not whichever kernel started last.
+        let results = NotebookSession::start(&server)
+            .and_then(|kernel| kernel.execute_notebook(input, output, &BTreeMap::new()));
+        let stopped = server.shutdown();
+        let results = results?;
+        stopped?;
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preparation_permissions() {
+        let preparation = Preparation {
+            env: PathBuf::from("/work/.venv"),
+            requirements: PathBuf::from("/work/requirements.txt"),
+            indexes: HostAllowlist::new(["pypi.org"]),
+        };
+        let setup = preparation.setup_permissions();
+        assert_eq!(setup.allow_write, vec![PathBuf::from("/work/.venv")]);
+        assert!(!setup.allow_net);
+
+        let run = preparation.run_permissions(&Permissions::default());
+        assert!(run.allow_write.is_empty());
+        assert_eq!(run.allow_read, vec![PathBuf::from("/work/.venv")]);
+    }
+}
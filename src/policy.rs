@@ -0,0 +1,293 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::{generate_file_permissions, generate_profile, generate_run_permissions, Permissions};
+
+/// Version of the policy format this crate reads and writes.
+pub const POLICY_VERSION: u64 = 1;
+
+/// Upgrades a policy document by one version: `MIGRATIONS[n - 1]` takes version `n`
+/// to `n + 1`, so there is one fewer than [`POLICY_VERSION`].
+type Migration = fn(&mut Map<String, Value>) -> Result<()>;
+const MIGRATIONS: &[Migration] = &[];
+
+/// Parse a policy document, as TOML if `is_toml` and as JSON otherwise.
+///
+/// Documents from older versions are migrated to the current one. Documents without a
+/// `version` predate the field and are read as version 1.
+pub fn parse_policy(contents: &str, is_toml: bool) -> Result<Permissions> {
+    let document: Value = if is_toml {
+        toml::from_str(contents)?
+    } else {
+        serde_json::from_str(contents)?
+    };
+    let Value::Object(mut document) = document else {
+        return Err(SecureNotebookError::InvalidPolicy(
+            "A policy must be a table of permissions".to_string(),
+        ));
+    };
+
+    let version = policy_version(document.remove("version"))?;
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        migration(&mut document)?;
+    }
+    Ok(serde_json::from_value(Value::Object(document))?)
+}
+
+fn policy_version(version: Option<Value>) -> Result<u64> {
+    let Some(version) = version else {
+        return Ok(1);
+    };
+    match version.as_u64() {
+        Some(version @ 1..=POLICY_VERSION) => Ok(version),
+        Some(version) if version > POLICY_VERSION => {
+            Err(SecureNotebookError::InvalidPolicy(format!(
+                "Policy version {version} is newer than the latest version this build \
+                 understands ({POLICY_VERSION}), upgrade secure_notebook to read it"
+            )))
+        }
+        _ => Err(SecureNotebookError::InvalidPolicy(format!(
+            "Policy version must be a positive integer, got {version}"
+        ))),
+    }
+}
+
+/// Load a policy file, picking the format from its extension (`.toml` or JSON).
+pub fn load_policy(path: &Path) -> Result<Permissions> {
+    let contents = std::fs::read_to_string(path)
+        .io_context(|| format!("Failed to read policy {}", path.display()))?;
+    parse_policy(&contents, is_toml(path))
+        .map_err(|e| SecureNotebookError::InvalidPolicy(format!("{}: {e}", path.display())))
+}
+
+/// A policy document as written: the current version followed by the permissions.
+#[derive(Serialize)]
+struct VersionedPolicy<'a> {
+    version: u64,
+    #[serde(flatten)]
+    permissions: &'a Permissions,
+}
+
+/// Render a policy document at the current version, as TOML if `is_toml` and as JSON
+/// otherwise.
+pub fn render_policy(permissions: &Permissions, is_toml: bool) -> Result<String> {
+    let document = VersionedPolicy {
+        version: POLICY_VERSION,
+        permissions,
+    };
+    if is_toml {
+        Ok(toml::to_string_pretty(&document)?)
+    } else {
+        Ok(serde_json::to_string_pretty(&document)?)
+    }
+}
+
+/// Write a policy file, picking the format from its extension like [`load_policy`].
+pub fn save_policy(path: &Path, permissions: &Permissions) -> Result<()> {
+    let contents = render_policy(permissions, is_toml(path))?;
+    std::fs::write(path, contents)
+        .io_context(|| format!("Failed to write policy {}", path.display()))
+}
+
+/// Whether the policy file at `path` is TOML.
+pub fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "toml")
+}
+
+/// An allow rule from the user overlay that the admin base policy denies.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Conflict {
+    /// Access type of the rule, e.g. `file-read*`.
+    pub access_type: String,
+    /// Path the overlay tried to allow.
+    pub path: PathBuf,
+    /// Base deny rule covering the path.
+    pub denied_by: PathBuf,
+}
+
+/// Two-layer policy: an admin-provided base whose denies cannot be overridden,
+/// and a user overlay on top of it.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct LayeredPolicy {
+    pub base: Permissions,
+    pub overlay: Permissions,
+}
+
+impl LayeredPolicy {
+    /// Create a layered policy from an admin base and a user overlay.
+    pub fn new(base: Permissions, overlay: Permissions) -> Self {
+        Self { base, overlay }
+    }
+
+    /// Overlay allows that try to override base denies.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = Vec::new();
+        collect_conflicts(
+            "file-read*",
+            &self.overlay.allow_read,
+            &self.base.deny_read,
+            &mut conflicts,
+        );
+        collect_conflicts(
+            "file-write*",
+            &self.overlay.allow_write,
+            &self.base.deny_write,
+            &mut conflicts,
+        );
+        for program in &self.overlay.allow_run {
+            if self.base.deny_run.contains(program) {
+                conflicts.push(Conflict {
+                    access_type: "process-exec".to_string(),
+                    path: program.clone(),
+                    denied_by: program.clone(),
+                });
+            }
+        }
+        conflicts
+    }
+
+    /// Merge both layers, dropping overlay allows that conflict with base denies.
+    ///
+    /// Network and GPU access need both layers to allow them, so the overlay cannot
+    /// enable what the base leaves off.
+    pub fn merge(&self) -> Permissions {
+        let base = &self.base;
+        let overlay = &self.overlay;
+
+        Permissions {
+            allow_read: merge_allows(&base.allow_read, &overlay.allow_read, &base.deny_read),
+            deny_read: merge_lists(&base.deny_read, &overlay.deny_read),
+            allow_write: merge_allows(&base.allow_write, &overlay.allow_write, &base.deny_write),
+            deny_write: merge_lists(&base.deny_write, &overlay.deny_write),
+            allow_net: base.allow_net && overlay.allow_net,
+            allow_gpu: base.allow_gpu && overlay.allow_gpu,
+            allow_run: merge_lists(&base.allow_run, &overlay.allow_run)
+                .into_iter()
+                .filter(|program| !base.deny_run.contains(program))
+                .collect(),
+            deny_run: merge_lists(&base.deny_run, &overlay.deny_run),
+        }
+    }
+
+    /// Generate the profile for the merged policy.
+    ///
+    /// The base denies are emitted again after every other rule, since the last
+    /// matching rule wins in a sandbox profile.
+    pub fn generate_profile(&self, template: &str) -> Result<String> {
+        let mut profile = generate_profile(template, &self.merge())?;
+
+        profile.push_str("; Admin denies, these cannot be overridden\n");
+        profile.push_str(&generate_file_permissions(
+            "file-read*",
+            &[],
+            &self.base.deny_read,
+        )?);
+        profile.push_str(&generate_file_permissions(
+            "file-write*",
+            &[],
+            &self.base.deny_write,
+        )?);
+        profile.push_str(&generate_run_permissions(&[], &self.base.deny_run)?);
+
+        Ok(profile)
+    }
+}
+
+fn collect_conflicts(
+    access_type: &str,
+    allow_paths: &[PathBuf],
+    deny_paths: &[PathBuf],
+    conflicts: &mut Vec<Conflict>,
+) {
+    for path in allow_paths {
+        if let Some(denied_by) = covering_path(path, deny_paths) {
+            conflicts.push(Conflict {
+                access_type: access_type.to_string(),
+                path: path.clone(),
+                denied_by: denied_by.clone(),
+            });
+        }
+    }
+}
+
+fn covering_path<'a>(path: &Path, roots: &'a [PathBuf]) -> Option<&'a PathBuf> {
+    roots.iter().find(|root| path.starts_with(root))
+}
+
+fn merge_lists(base: &[PathBuf], overlay: &[PathBuf]) -> Vec<PathBuf> {
+    let mut merged = base.to_vec();
+    for path in overlay {
+        if !merged.contains(path) {
+            merged.push(path.clone());
+        }
+    }
+    merged
+}
+
+fn merge_allows(base: &[PathBuf], overlay: &[PathBuf], base_denies: &[PathBuf]) -> Vec<PathBuf> {
+    let allowed: Vec<PathBuf> = overlay
+        .iter()
+        .filter(|path| covering_path(path, base_denies).is_none())
+        .cloned()
+        .collect();
+    merge_lists(base, &allowed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlay_cannot_override_base_denies() {
+        let mut base = Permissions::new();
+        base.deny_read.push(PathBuf::from("/secrets"));
+        base.deny_run.push(PathBuf::from("/bin/sh"));
+
+        let mut overlay = Permissions::new();
+        overlay.allow_read.push(PathBuf::from("/secrets/keys"));
+        overlay.allow_read.push(PathBuf::from("/data"));
+        overlay.allow_run.push(PathBuf::from("/bin/sh"));
+        overlay.allow_net = true;
+        overlay.allow_gpu = true;
+
+        let policy = LayeredPolicy::new(base, overlay);
+        assert_eq!(policy.conflicts().len(), 2);
+
+        let merged = policy.merge();
+        assert_eq!(merged.allow_read, vec![PathBuf::from("/data")]);
+        assert!(merged.allow_run.is_empty());
+        assert!(!merged.allow_net);
+        assert!(!merged.allow_gpu);
+
+        let mut both = policy.clone();
+        both.base.allow_net = true;
+        assert!(both.merge().allow_net);
+        both.overlay.allow_net = false;
+        assert!(!both.merge().allow_net);
+
+        let profile = policy.generate_profile("(version 1)\n").unwrap();
+        let (_, tail) = profile.split_once("; Admin denies").unwrap();
+        assert!(tail.contains("(deny file-read* (subpath \"/secrets\"))"));
+        assert!(tail.contains("(deny process-exec (literal \"/bin/sh\"))"));
+    }
+
+    #[test]
+    fn test_policy_versions() -> Result<()> {
+        let unversioned = parse_policy(r#"{"allow_net": true}"#, false)?;
+        assert!(unversioned.allow_net);
+        let current = parse_policy(&render_policy(&unversioned, false)?, false)?;
+        assert_eq!(current, unversioned);
+
+        let newer = parse_policy(r#"{"version": 99, "allow_net": true}"#, false);
+        assert!(
+            matches!(newer, Err(SecureNotebookError::InvalidPolicy(message))
+            if message.contains("newer"))
+        );
+        assert!(parse_policy(r#"{"version": 0}"#, false).is_err());
+        assert_eq!(MIGRATIONS.len() as u64, POLICY_VERSION - 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+use ed25519_dalek::VerifyingKey;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use crate::error::{Result, SecureNotebookError};
+use crate::remote::RemotePolicy;
+use crate::Permissions;
+
+/// Identifies the policy of one notebook of one team.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PolicyTarget {
+    pub team: String,
+    pub notebook: String,
+}
+
+impl PolicyTarget {
+    pub fn new(team: &str, notebook: &str) -> Self {
+        Self {
+            team: team.to_string(),
+            notebook: notebook.to_string(),
+        }
+    }
+
+    /// Reject names that could escape their URL segment or cache directory.
+    fn validate(&self) -> Result<()> {
+        validate_name("team", &self.team)?;
+        validate_name("notebook", &self.notebook)
+    }
+}
+
+/// Same rules as user names: no separators, no leading dot.
+fn validate_name(kind: &str, name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(SecureNotebookError::InvalidPolicy(format!(
+            "Invalid {kind} name {name:?}"
+        )))
+    }
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters.
+fn encode_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// A policy obtained from the policy service.
+#[derive(Debug, Clone)]
+pub struct SyncedPolicy {
+    pub permissions: Permissions,
+    /// The service was unreachable and the last known-good policy was used.
+    pub stale: bool,
+    pub synced_at: SystemTime,
+}
+
+/// Client for a central policy service serving
+/// `<base_url>/teams/<team>/notebooks/<notebook>/policy.json`.
+#[derive(Debug, Clone)]
+pub struct PolicyClient {
+    base_url: String,
+    cache_dir: PathBuf,
+    key: Option<VerifyingKey>,
+}
+
+impl PolicyClient {
+    /// Create a client caching policies in `cache_dir`.
+    pub fn new(base_url: &str, cache_dir: PathBuf) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            cache_dir,
+            key: None,
+        }
+    }
+
+    /// Require every policy to be signed by `key`.
+    pub fn verify_with(mut self, key: VerifyingKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// URL of the policy for `target`, with the names percent-encoded.
+    pub fn policy_url(&self, target: &PolicyTarget) -> String {
+        format!(
+            "{}/teams/{}/notebooks/{}/policy.json",
+            self.base_url,
+            encode_segment(&target.team),
+            encode_segment(&target.notebook)
+        )
+    }
+
+    /// Fetch the policy for `target`, falling back to the last known-good copy when offline.
+    pub fn get(&self, target: &PolicyTarget) -> Result<SyncedPolicy> {
+        let remote = self.remote(target)?;
+        match remote.fetch() {
+            Ok(fetched) => Ok(SyncedPolicy {
+                permissions: fetched.permissions,
+                stale: false,
+                synced_at: SystemTime::now(),
+            }),
+            Err(fetch_error) => {
+                let permissions = remote.load_cached().map_err(|_| fetch_error)?;
+                Ok(SyncedPolicy {
+                    permissions,
+                    stale: true,
+                    synced_at: SystemTime::now(),
+                })
+            }
+        }
+    }
+
+    /// Periodically sync the policies of `targets` in a background thread.
+    pub fn spawn_sync(&self, targets: Vec<PolicyTarget>, interval: Duration) -> PolicySync {
+        let policies = Arc::new(Mutex::new(HashMap::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let client = self.clone();
+        let thread = {
+            let policies = Arc::clone(&policies);
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    for target in &targets {
+                        if let Ok(policy) = client.get(target) {
+                            policies
+                                .lock()
+                                .expect("policy cache poisoned")
+                                .insert(target.clone(), policy);
+                        }
+                    }
+                    std::thread::park_timeout(interval);
+                }
+            })
+        };
+
+        PolicySync {
+            policies,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    fn remote(&self, target: &PolicyTarget) -> Result<RemotePolicy> {
+        target.validate()?;
+        let cache_dir = self.cache_dir.join(&target.team).join(&target.notebook);
+        let remote = RemotePolicy::new(&self.policy_url(target), cache_dir)?;
+        Ok(match self.key {
+            Some(key) => remote.verify_with(key),
+            None => remote,
+        })
+    }
+}
+
+/// Handle to a background policy sync.
+#[derive(Debug)]
+pub struct PolicySync {
+    policies: Arc<Mutex<HashMap<PolicyTarget, SyncedPolicy>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PolicySync {
+    /// Latest synced policy for `target`.
+    pub fn get(&self, target: &PolicyTarget) -> Option<SyncedPolicy> {
+        self.policies
+            .lock()
+            .expect("policy cache poisoned")
+            .get(target)
+            .cloned()
+    }
+
+    /// Stop syncing and wait for the background thread.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for PolicySync {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_url() {
+        let client = PolicyClient::new("https://policies.example.com/", std::env::temp_dir());
+        let target = PolicyTarget::new("ml", "churn");
+        assert_eq!(
+            client.policy_url(&target),
+            "https://policies.example.com/teams/ml/notebooks/churn/policy.json"
+        );
+
+        let target = PolicyTarget::new("a/b", "q?x#y");
+        assert_eq!(
+            client.policy_url(&target),
+            "https://policies.example.com/teams/a%2Fb/notebooks/q%3Fx%23y/policy.json"
+        );
+    }
+
+    #[test]
+    fn test_rejects_unsafe_names() {
+        let cache_dir = std::env::temp_dir().join("policy-client-unsafe-names");
+        let client = PolicyClient::new("http://127.0.0.1:9", cache_dir.clone());
+        for (team, notebook) in [
+            ("..", "churn"),
+            ("ml", ".."),
+            ("a/b", "churn"),
+            ("ml", "a/b"),
+        ] {
+            let err = client.get(&PolicyTarget::new(team, notebook)).unwrap_err();
+            assert!(matches!(err, SecureNotebookError::InvalidPolicy(_)));
+        }
+        assert!(!cache_dir.exists());
+
+        assert!(PolicyTarget::new("ml", "churn.v2").validate().is_ok());
+        assert!(PolicyTarget::new("", "churn").validate().is_err());
+    }
+}
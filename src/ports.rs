@@ -0,0 +1,126 @@
+use std::fmt::Write;
+use std::net::TcpListener;
+use std::ops::Range;
+
+use crate::error::{Result, SecureNotebookError};
+
+/// Ports of a kernel's ZMQ channels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelPorts {
+    pub shell: u16,
+    pub iopub: u16,
+    pub stdin: u16,
+    pub control: u16,
+    pub hb: u16,
+}
+
+impl KernelPorts {
+    /// Every channel's port.
+    pub fn all(&self) -> [u16; 5] {
+        [self.shell, self.iopub, self.stdin, self.control, self.hb]
+    }
+}
+
+/// Loopback ports for a server and its kernel, chosen before launch.
+///
+/// Fixing the ports up front lets the profile allow binding and accepting connections
+/// on exactly these ports and nothing else. Every kernel of the server uses the same
+/// channel ports, so the server can run one kernel at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortAllocation {
+    pub server: u16,
+    pub kernel: KernelPorts,
+}
+
+impl PortAllocation {
+    /// Pick free loopback ports from `range`, skipping those in `taken`.
+    pub fn allocate(range: Range<u16>, taken: &[u16]) -> Result<Self> {
+        let mut free = range
+            .clone()
+            .filter(|port| !taken.contains(port))
+            .filter(|port| TcpListener::bind(("127.0.0.1", *port)).is_ok());
+        let mut next = || {
+            free.next().ok_or_else(|| {
+                SecureNotebookError::InvalidState(format!(
+                    "Not enough free ports in {}..{}",
+                    range.start, range.end
+                ))
+            })
+        };
+
+        Ok(Self {
+            server: next()?,
+            kernel: KernelPorts {
+                shell: next()?,
+                iopub: next()?,
+                stdin: next()?,
+                control: next()?,
+                hb: next()?,
+            },
+        })
+    }
+
+    /// Every allocated port, server first.
+    pub fn all(&self) -> Vec<u16> {
+        let mut ports = vec![self.server];
+        ports.extend(self.kernel.all());
+        ports
+    }
+
+    /// Jupyter server arguments binding the server and the kernel channels to the ports.
+    pub fn server_args(&self) -> Vec<String> {
+        let kernel = &self.kernel;
+        vec![
+            "--ServerApp.ip=127.0.0.1".to_string(),
+            format!("--ServerApp.port={}", self.server),
+            // do not fall back to a random port the profile does not allow
+            "--ServerApp.port_retries=0".to_string(),
+            "--KernelManager.ip=127.0.0.1".to_string(),
+            format!("--KernelManager.shell_port={}", kernel.shell),
+            format!("--KernelManager.iopub_port={}", kernel.iopub),
+            format!("--KernelManager.stdin_port={}", kernel.stdin),
+            format!("--KernelManager.control_port={}", kernel.control),
+            format!("--KernelManager.hb_port={}", kernel.hb),
+        ]
+    }
+
+    /// Rules allowing binds and inbound connections only on the allocated ports.
+    ///
+    /// Append them after the template, which usually allows all inbound traffic.
+    pub fn profile_rules(&self) -> String {
+        let mut rules = String::from(
+            "(deny network-bind (local ip \"*:*\"))\n\
+             (deny network-inbound (local ip \"*:*\"))\n",
+        );
+        for port in self.all() {
+            writeln!(
+                rules,
+                "(allow network-bind network-inbound (local ip \"localhost:{port}\"))"
+            )
+            .expect("writing to a String cannot fail");
+        }
+        rules
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_skips_taken_ports() -> Result<()> {
+        let busy = TcpListener::bind("127.0.0.1:0")?;
+        let busy_port = busy.local_addr()?.port();
+        let start = busy_port.saturating_sub(3);
+        let allocation = PortAllocation::allocate(start..start.saturating_add(40), &[start])?;
+
+        let ports = allocation.all();
+        assert_eq!(ports.len(), 6);
+        assert!(!ports.contains(&start));
+        assert!(!ports.contains(&busy_port));
+        assert!(allocation
+            .profile_rules()
+            .contains(&format!("(local ip \"localhost:{}\")", allocation.server)));
+        Ok(())
+    }
+}
@@ -0,0 +1,821 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::netguard::HostAllowlist;
+use crate::{generate_file_permissions, generate_profile, sbpl_path, Permissions};
+
+/// Rules cutting off every outbound connection except loopback, which Jupyter needs to
+/// reach its kernels.
+pub const DENY_REMOTE_NETWORK: &str = "(deny network-outbound (remote ip \"*:*\"))\n\
+                                       (allow network-outbound (remote ip \"localhost:*\"))\n";
+
+/// A bundle of permissions, network hosts and settings for a common setup.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Preset {
+    pub name: String,
+    pub permissions: Permissions,
+    /// Hosts to allow through the [`crate::netguard::NetGuard`].
+    pub hosts: Vec<String>,
+    /// Rules appended after the generated ones.
+    pub rules: String,
+    /// Environment variables for the server and its kernels.
+    pub env: Vec<(String, String)>,
+}
+
+impl Preset {
+    /// Add the preset's permissions to `permissions`.
+    pub fn apply(&self, permissions: &mut Permissions) {
+        let preset = &self.permissions;
+        permissions.allow_read.extend_from_slice(&preset.allow_read);
+        permissions.deny_read.extend_from_slice(&preset.deny_read);
+        permissions
+            .allow_write
+            .extend_from_slice(&preset.allow_write);
+        permissions.deny_write.extend_from_slice(&preset.deny_write);
+        permissions.allow_net |= preset.allow_net;
+        permissions.allow_gpu |= preset.allow_gpu;
+        permissions.allow_run.extend_from_slice(&preset.allow_run);
+        permissions.deny_run.extend_from_slice(&preset.deny_run);
+    }
+
+    /// Allowlist for the network guard.
+    pub fn allowlist(&self) -> HostAllowlist {
+        HostAllowlist::new(&self.hosts)
+    }
+
+    /// Generate the profile for `template` plus the preset.
+    pub fn generate_profile(&self, template: &str) -> Result<String> {
+        let mut profile = generate_profile(template, &self.permissions)?;
+        profile.push_str(&self.rules);
+        Ok(profile)
+    }
+}
+
+/// Rules letting an interactive program use the pseudo-terminal it runs on, see
+/// [`crate::pty`]. Terminals are `/dev/ttysNNN` on macOS, and `/dev/tty` is the
+/// process's own.
+pub const TERMINAL_RULES: &str = "(allow file-read* file-write* file-ioctl\n    \
+                                  (literal \"/dev/tty\")\n    \
+                                  (regex #\"^/dev/ttys[0-9]+$\")\n)\n";
+
+/// Access to the controlling terminal, for programs run with a PTY.
+pub fn terminal() -> Preset {
+    Preset {
+        name: "terminal".to_string(),
+        rules: TERMINAL_RULES.to_string(),
+        ..Preset::default()
+    }
+}
+
+/// Reads virtually every process needs to start: the dyld shared cache, the system
+/// libraries and frameworks it links, locale data and `/dev/urandom`.
+///
+/// A base for custom templates that start from `(deny default)`.
+pub fn system_essentials() -> Preset {
+    Preset {
+        name: "system-essentials".to_string(),
+        permissions: Permissions {
+            allow_read: [
+                // dyld shared cache: in the OS cryptex since macOS 13, before that here
+                "/System/Volumes/Preboot/Cryptexes/OS/System/Library/dyld",
+                "/System/Library/dyld",
+                "/private/var/db/dyld",
+                "/usr/lib",
+                "/System/Library/Frameworks",
+                "/usr/share/locale",
+                "/usr/share/icu",
+                "/dev/urandom",
+            ]
+            .map(PathBuf::from)
+            .to_vec(),
+            ..Permissions::default()
+        },
+        hosts: Vec::new(),
+        rules: String::new(),
+        env: Vec::new(),
+    }
+}
+
+/// Rules for Accelerate, BNNS and MLX: the CPU topology and feature sysctls they size
+/// their thread pools and pick kernels by, and the compiler services behind them.
+const ACCELERATE_RULES: &str = "(allow sysctl-read
+    (sysctl-name \"hw.ncpu\")
+    (sysctl-name \"hw.activecpu\")
+    (sysctl-name \"hw.physicalcpu\")
+    (sysctl-name \"hw.logicalcpu\")
+    (sysctl-name \"hw.memsize\")
+    (sysctl-name \"hw.cachelinesize\")
+    (sysctl-name \"hw.l1dcachesize\")
+    (sysctl-name \"hw.l2cachesize\")
+    (sysctl-name \"hw.nperflevels\")
+    (sysctl-name-prefix \"hw.perflevel\")
+    (sysctl-name-prefix \"hw.optional.\")
+    (sysctl-name \"kern.osproductversion\")
+)
+(allow mach-lookup
+    (global-name \"com.apple.cvmsServ\")
+    (global-name \"com.apple.MTLCompilerService\")
+)
+";
+
+/// Accelerate, BNNS and MLX, which MLX runs on the GPU through Metal.
+pub fn accelerate() -> Preset {
+    Preset {
+        name: "accelerate".to_string(),
+        permissions: Permissions {
+            allow_read: [
+                "/System/Library/Frameworks/Accelerate.framework",
+                "/System/Library/Frameworks/Metal.framework",
+                "/System/Library/Frameworks/MetalPerformanceShaders.framework",
+                "/System/Library/Frameworks/MetalPerformanceShadersGraph.framework",
+                "/System/Library/PrivateFrameworks/GPUCompiler.framework",
+            ]
+            .map(PathBuf::from)
+            .to_vec(),
+            allow_gpu: true,
+            ..Permissions::default()
+        },
+        hosts: Vec::new(),
+        rules: ACCELERATE_RULES.to_string(),
+        env: Vec::new(),
+    }
+}
+
+/// No network at all, with read access to local package mirrors or wheelhouses.
+///
+/// pip is pointed at the mirrors with `PIP_NO_INDEX` and `PIP_FIND_LINKS`, so
+/// `pip install` works without reaching an index.
+pub fn offline(mirrors: &[PathBuf]) -> Result<Preset> {
+    let find_links = mirrors
+        .iter()
+        .map(|mirror| sbpl_path(mirror))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Preset {
+        name: "offline".to_string(),
+        permissions: Permissions {
+            allow_read: mirrors.to_vec(),
+            ..Permissions::default()
+        },
+        hosts: Vec::new(),
+        rules: DENY_REMOTE_NETWORK.to_string(),
+        env: vec![
+            ("PIP_NO_INDEX".to_string(), "1".to_string()),
+            ("PIP_FIND_LINKS".to_string(), find_links.join(" ")),
+        ],
+    })
+}
+
+/// PyPI through the network guard, with the pip cache writable.
+pub fn pypi(home: &Path) -> Preset {
+    Preset {
+        name: "pypi".to_string(),
+        permissions: Permissions {
+            allow_write: vec![home.join("Library/Caches/pip")],
+            ..Permissions::default()
+        },
+        hosts: vec!["pypi.org".to_string(), "files.pythonhosted.org".to_string()],
+        rules: DENY_REMOTE_NETWORK.to_string(),
+        env: Vec::new(),
+    }
+}
+
+/// Anaconda and conda-forge through the network guard, with the package caches writable.
+///
+/// `conda_root` is the base installation, e.g. `~/miniforge3`.
+pub fn conda(home: &Path, conda_root: &Path) -> Preset {
+    Preset {
+        name: "conda".to_string(),
+        permissions: Permissions {
+            allow_read: vec![conda_root.to_path_buf(), home.join(".condarc")],
+            allow_write: vec![conda_root.join("pkgs"), home.join(".conda")],
+            ..Permissions::default()
+        },
+        hosts: [
+            "conda.anaconda.org",
+            "repo.anaconda.com",
+            "anaconda.org",
+            "api.anaconda.org",
+            // CDN behind conda.anaconda.org
+            "binstar-cio-packages-prod.s3.amazonaws.com",
+        ]
+        .map(String::from)
+        .to_vec(),
+        rules: DENY_REMOTE_NETWORK.to_string(),
+        env: Vec::new(),
+    }
+}
+
+/// IRkernel: R itself, the user library and profile, history, and the compilers
+/// `install.packages` builds sources with.
+///
+/// `r_home` is `R.home()`, e.g. `/Library/Frameworks/R.framework/Resources`.
+pub fn irkernel(home: &Path, r_home: &Path) -> Preset {
+    Preset {
+        name: "irkernel".to_string(),
+        permissions: Permissions {
+            allow_read: vec![
+                r_home.to_path_buf(),
+                home.join(".Rprofile"),
+                home.join(".Renviron"),
+                home.join(".R"),
+            ],
+            allow_write: vec![
+                home.join("Library/R"),
+                home.join(".Rhistory"),
+                home.join(".local/share/jupyter/runtime"),
+            ],
+            allow_run: vec![
+                r_home.join("bin/R"),
+                r_home.join("bin/Rscript"),
+                r_home.join("bin/exec/R"),
+                PathBuf::from("/bin/sh"),
+                PathBuf::from("/usr/bin/make"),
+                PathBuf::from("/usr/bin/clang"),
+                PathBuf::from("/usr/bin/clang++"),
+                PathBuf::from("/opt/gfortran/bin/gfortran"),
+            ],
+            ..Permissions::default()
+        },
+        hosts: Vec::new(),
+        rules: String::new(),
+        env: Vec::new(),
+    }
+}
+
+/// IJulia: Julia itself and the depot holding packages, precompile caches, preferences
+/// and the REPL history.
+///
+/// `julia_home` is `Sys.BINDIR`'s parent, e.g.
+/// `/Applications/Julia-1.10.app/Contents/Resources/julia`.
+pub fn ijulia(home: &Path, julia_home: &Path) -> Result<Preset> {
+    let depot = home.join(".julia");
+    Ok(Preset {
+        name: "ijulia".to_string(),
+        permissions: Permissions {
+            allow_read: vec![julia_home.to_path_buf()],
+            allow_write: vec![
+                depot.join("compiled"),
+                depot.join("logs"),
+                depot.join("prefs"),
+                depot.join("scratchspaces"),
+                depot.join("packages"),
+                depot.join("artifacts"),
+                depot.join("registries"),
+                depot.join("environments"),
+                home.join(".local/share/jupyter/runtime"),
+            ],
+            allow_run: vec![julia_home.join("bin/julia"), PathBuf::from("/bin/sh")],
+            ..Permissions::default()
+        },
+        hosts: Vec::new(),
+        rules: String::new(),
+        env: vec![(
+            "JULIA_DEPOT_PATH".to_string(),
+            sbpl_path(&depot)?.to_string(),
+        )],
+    })
+}
+
+/// Directories under `home` most users consider private: the Desktop, Documents and
+/// Pictures folders, and iCloud Drive along with the app folders synced through it.
+pub fn personal_directories(home: &Path) -> Vec<PathBuf> {
+    [
+        "Desktop",
+        "Documents",
+        "Pictures",
+        "Library/Mobile Documents",
+        "Library/CloudStorage",
+    ]
+    .iter()
+    .map(|dir| home.join(dir))
+    .collect()
+}
+
+/// Denies reading and writing [`personal_directories`].
+///
+/// The denies go in [`Preset::rules`], after the generated rules, so they hold even
+/// when a policy allows all of `home`.
+pub fn personal(home: &Path) -> Result<Preset> {
+    let dirs = personal_directories(home);
+    let mut rules = generate_file_permissions("file-read*", &[], &dirs)?;
+    rules.push_str(&generate_file_permissions("file-write*", &[], &dirs)?);
+    Ok(Preset {
+        name: "personal".to_string(),
+        permissions: Permissions {
+            deny_read: dirs.clone(),
+            deny_write: dirs,
+            ..Permissions::default()
+        },
+        hosts: Vec::new(),
+        rules,
+        env: Vec::new(),
+    })
+}
+
+/// Denies reading and writing `~/Downloads`, a common staging area for sensitive files.
+///
+/// `overrides` are paths under it the notebook legitimately processes, such as a
+/// downloaded dataset; they are allowed again after the deny. Pass `~/Downloads` itself
+/// to lift the deny entirely.
+pub fn downloads(home: &Path, overrides: &[PathBuf]) -> Result<Preset> {
+    let dir = [home.join("Downloads")];
+    let mut rules = generate_file_permissions("file-read*", &[], &dir)?;
+    rules.push_str(&generate_file_permissions("file-write*", &[], &dir)?);
+    rules.push_str(&generate_file_permissions("file-read*", overrides, &[])?);
+    rules.push_str(&generate_file_permissions("file-write*", overrides, &[])?);
+    Ok(Preset {
+        name: "downloads".to_string(),
+        permissions: Permissions {
+            deny_read: dir.to_vec(),
+            deny_write: dir.to_vec(),
+            ..Permissions::default()
+        },
+        hosts: Vec::new(),
+        rules,
+        env: Vec::new(),
+    })
+}
+
+/// Write operations that rewrite a file's history rather than add to it: deleting or
+/// renaming it away, and changing its mode, owner, ACL or flags.
+const REWRITE_OPERATIONS: &str = "file-write-unlink file-write-mode file-write-owner \
+                                  file-write-acl file-write-flags";
+
+/// Writable log directories whose files can be created and written but never deleted,
+/// renamed or re-flagged, so experiment logs outlive the notebook that wrote them.
+///
+/// Seatbelt has no operation for truncation, so an existing log could still be opened
+/// with `O_TRUNC`. Setting the append-only flag (`chflags uappnd`) on logs that must
+/// not be rewritten closes that gap, since the preset denies clearing it.
+pub fn append_only(logs: &[PathBuf]) -> Result<Preset> {
+    Ok(Preset {
+        name: "append-only".to_string(),
+        permissions: Permissions {
+            allow_read: logs.to_vec(),
+            allow_write: logs.to_vec(),
+            ..Permissions::default()
+        },
+        hosts: Vec::new(),
+        rules: path_rules(&format!("deny {REWRITE_OPERATIONS}"), "subpath", logs)?,
+        env: Vec::new(),
+    })
+}
+
+/// Paths that can't be deleted or renamed away, even under an `allow_write` tree, so a
+/// notebook can update its outputs next to an input dataset it can never remove.
+///
+/// Only deletion is denied; combine with [`Permissions::read_only`] to also keep the
+/// contents from being rewritten.
+pub fn undeletable(paths: &[PathBuf]) -> Result<Preset> {
+    Ok(Preset {
+        name: "undeletable".to_string(),
+        rules: path_rules("deny file-write-unlink", "subpath", paths)?,
+        ..Preset::default()
+    })
+}
+
+/// Paths whose files can be written but not `chmod`ed, `chown`ed or given new ACLs, for
+/// write directories shared with other tooling that relies on their permissions.
+pub fn fixed_permissions(paths: &[PathBuf]) -> Result<Preset> {
+    Ok(Preset {
+        name: "fixed-permissions".to_string(),
+        rules: path_rules(
+            "deny file-write-mode file-write-owner file-write-acl",
+            "subpath",
+            paths,
+        )?,
+        ..Preset::default()
+    })
+}
+
+/// `access_type` granted on `dirs` and at most `depth` levels below them, e.g. depth 1
+/// for a directory and its direct entries.
+///
+/// A `subpath` reaches every descendant, so reading a project root's metadata would
+/// also expose vendored trees nested deep inside it. The limit is emitted as `regex`
+/// filters, one path component per level.
+pub fn depth_limited(access_type: &str, dirs: &[PathBuf], depth: usize) -> Result<Preset> {
+    let mut rules = String::new();
+    if !dirs.is_empty() {
+        rules.push_str(&format!("(allow {access_type}\n"));
+        for dir in dirs {
+            let root = sbpl_path(dir)?.trim_end_matches('/');
+            rules.push_str(&format!(
+                "    (regex #\"^{}(/[^/]+){{0,{depth}}}$\")\n",
+                regex_escape(root)
+            ));
+        }
+        rules.push_str(")\n");
+    }
+    Ok(Preset {
+        name: "depth-limited".to_string(),
+        rules,
+        ..Preset::default()
+    })
+}
+
+/// `text` with the characters special to SBPL regexes escaped.
+fn regex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        if "\\.^$|?*+()[]{}".contains(character) {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+    escaped
+}
+
+/// Output directories where new files can be created and written, but the files already
+/// there when the preset is built can't be modified, and nothing can be deleted, so
+/// earlier results stay as they were.
+///
+/// Rebuild the preset before each run so the previous run's outputs are covered too.
+pub fn create_only(dirs: &[PathBuf]) -> Result<Preset> {
+    let mut existing = Vec::new();
+    for dir in dirs {
+        existing.extend(existing_files(dir)?);
+    }
+    let mut rules = path_rules(&format!("deny {REWRITE_OPERATIONS}"), "subpath", dirs)?;
+    rules.push_str(&path_rules("deny file-write*", "literal", &existing)?);
+    Ok(Preset {
+        name: "create-only".to_string(),
+        permissions: Permissions {
+            allow_read: dirs.to_vec(),
+            allow_write: dirs.to_vec(),
+            ..Permissions::default()
+        },
+        hosts: Vec::new(),
+        rules,
+        env: Vec::new(),
+    })
+}
+
+/// Every file under `dir`, without following symlinks. Empty if `dir` doesn't exist.
+fn existing_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    let entries =
+        std::fs::read_dir(dir).io_context(|| format!("Failed to list {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry.io_context(|| format!("Failed to list {}", dir.display()))?;
+        let file_type = entry
+            .file_type()
+            .io_context(|| format!("Failed to stat {}", entry.path().display()))?;
+        if file_type.is_dir() {
+            files.extend(existing_files(&entry.path())?);
+        } else {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
+
+/// Where Homebrew is installed: `HOMEBREW_PREFIX` if set, otherwise `/opt/homebrew` on
+/// Apple silicon or `/usr/local` on Intel, whichever has `bin/brew`.
+pub fn homebrew_prefix() -> Option<PathBuf> {
+    if let Some(prefix) = std::env::var_os("HOMEBREW_PREFIX") {
+        return Some(PathBuf::from(prefix));
+    }
+    ["/opt/homebrew", "/usr/local"]
+        .map(PathBuf::from)
+        .into_iter()
+        .find(|prefix| prefix.join("bin/brew").exists())
+}
+
+/// Interpreters and compiled extensions installed with Homebrew under `prefix`, see
+/// [`homebrew_prefix`].
+///
+/// `bin` links into the kegs under `Cellar`, and `opt` holds the stable links
+/// extensions are built against, so all of them are readable and `bin` and `Cellar`
+/// executable.
+pub fn homebrew(prefix: &Path) -> Result<Preset> {
+    let exec = [prefix.join("bin"), prefix.join("Cellar")];
+    Ok(Preset {
+        name: "homebrew".to_string(),
+        permissions: Permissions {
+            allow_read: vec![
+                prefix.join("bin"),
+                prefix.join("lib"),
+                prefix.join("Cellar"),
+                prefix.join("opt"),
+            ],
+            ..Permissions::default()
+        },
+        hosts: Vec::new(),
+        rules: exec_rules(&exec)?,
+        env: Vec::new(),
+    })
+}
+
+/// The Nix store, which every Nix-managed interpreter, library and tool lives in.
+pub const NIX_STORE: &str = "/nix/store";
+
+/// Read and exec on the whole Nix store.
+pub fn nix() -> Preset {
+    store_preset("nix", vec![PathBuf::from(NIX_STORE)]).expect("the store path is valid UTF-8")
+}
+
+/// Read and exec on the closure of `store_path`, e.g. the derivation output of a
+/// `python3.withPackages` environment, as listed by `nix-store --query --requisites`.
+pub fn nix_closure(store_path: &Path) -> Result<Preset> {
+    let output = Command::new("nix-store")
+        .arg("--query")
+        .arg("--requisites")
+        .arg(store_path)
+        .output()
+        .map_err(|source| SecureNotebookError::SpawnFailed {
+            program: PathBuf::from("nix-store"),
+            source,
+        })?;
+    if !output.status.success() {
+        return Err(SecureNotebookError::InvalidState(format!(
+            "nix-store failed on {}: {}",
+            store_path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let closure = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect();
+    store_preset("nix-closure", closure)
+}
+
+fn store_preset(name: &str, paths: Vec<PathBuf>) -> Result<Preset> {
+    Ok(Preset {
+        name: name.to_string(),
+        rules: exec_rules(&paths)?,
+        permissions: Permissions {
+            allow_read: paths,
+            ..Permissions::default()
+        },
+        hosts: Vec::new(),
+        env: Vec::new(),
+    })
+}
+
+/// `(allow process-exec ...)` for everything under `dirs`; `allow_run` only takes
+/// single programs.
+pub(crate) fn exec_rules(dirs: &[PathBuf]) -> Result<String> {
+    path_rules("allow process-exec", "subpath", dirs)
+}
+
+/// `rule` limited to `paths` by `filter`, `subpath` or `literal`, or nothing when there
+/// are none, since a rule without filters matches every path.
+fn path_rules(rule: &str, filter: &str, paths: &[PathBuf]) -> Result<String> {
+    if paths.is_empty() {
+        return Ok(String::new());
+    }
+    let mut rules = format!("({rule}\n");
+    for path in paths {
+        rules.push_str(&format!("    ({filter} \"{}\")\n", sbpl_path(path)?));
+    }
+    rules.push_str(")\n");
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_preset() -> Result<()> {
+        let preset = offline(&[PathBuf::from("/opt/wheelhouse")])?;
+        let profile = preset.generate_profile("(version 1)\n(allow default)\n")?;
+
+        assert!(profile.contains("(deny network-outbound (remote ip \"*:*\"))"));
+        assert!(profile.contains("(literal \"/opt/wheelhouse\")"));
+        assert!(preset
+            .env
+            .contains(&("PIP_FIND_LINKS".to_string(), "/opt/wheelhouse".to_string())));
+        Ok(())
+    }
+
+    #[test]
+    fn test_conda_preset() {
+        let preset = conda(Path::new("/Users/me"), Path::new("/Users/me/miniforge3"));
+        assert!(preset.allowlist().allows("conda.anaconda.org"));
+        assert!(!preset.allowlist().allows("pypi.org"));
+        assert!(preset
+            .permissions
+            .allow_write
+            .contains(&PathBuf::from("/Users/me/miniforge3/pkgs")));
+    }
+
+    #[test]
+    fn test_kernel_presets() -> Result<()> {
+        let home = Path::new("/Users/me");
+        let r = irkernel(home, Path::new("/Library/Frameworks/R.framework/Resources"));
+        assert!(r.permissions.allow_run.contains(&PathBuf::from(
+            "/Library/Frameworks/R.framework/Resources/bin/R"
+        )));
+
+        let julia = ijulia(home, Path::new("/opt/julia"))?;
+        let profile = julia.generate_profile(crate::templates::IJULIA)?;
+        assert!(profile.contains("(allow dynamic-code-generation)"));
+        assert!(profile.contains("\"/Users/me/.julia/compiled\""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_personal_preset_overrides_home() -> Result<()> {
+        let home = Path::new("/Users/me");
+        let preset = personal(home)?;
+        let mut permissions = Permissions {
+            allow_read: vec![home.to_path_buf()],
+            ..Permissions::default()
+        };
+        preset.apply(&mut permissions);
+        let mut profile = generate_profile("(version 1)\n", &permissions)?;
+        profile.push_str(&preset.rules);
+
+        let allow = profile.find("\"/Users/me\"").unwrap();
+        let deny = profile
+            .rfind("(deny file-read* (subpath \"/Users/me/Documents\"))")
+            .unwrap();
+        assert!(deny > allow);
+        assert!(profile.contains("\"/Users/me/Library/Mobile Documents\""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_downloads_override() -> Result<()> {
+        let home = Path::new("/Users/me");
+        let denied = downloads(home, &[])?;
+        assert!(denied
+            .rules
+            .contains("(deny file-write* (subpath \"/Users/me/Downloads\"))"));
+        assert!(!denied.rules.contains("(allow"));
+
+        let dataset = home.join("Downloads/survey.csv");
+        let overridden = downloads(home, &[dataset])?;
+        let deny = overridden.rules.find("(deny file-read*").unwrap();
+        let allow = overridden
+            .rules
+            .find("(literal \"/Users/me/Downloads/survey.csv\")")
+            .unwrap();
+        assert!(allow > deny);
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_only_logs() -> Result<()> {
+        use crate::evaluator::{evaluate_profile, Action};
+
+        let dir = tempfile::tempdir().unwrap();
+        let logs = dir.path().to_path_buf();
+        let preset = append_only(std::slice::from_ref(&logs))?;
+        let profile = preset.generate_profile("(version 1)\n(deny default)\n")?;
+
+        let run = logs.join("run-1.log");
+        let decide = |operation| evaluate_profile(&profile, operation, Some(&run));
+        assert_eq!(decide("file-write-create")?, Some(Action::Allow));
+        assert_eq!(decide("file-write-data")?, Some(Action::Allow));
+        assert_eq!(decide("file-write-unlink")?, Some(Action::Deny));
+        assert_eq!(decide("file-write-flags")?, Some(Action::Deny));
+        assert!(append_only(&[])?.rules.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_only_outputs() -> Result<()> {
+        use crate::evaluator::{evaluate_profile, Action};
+
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = dir.path().to_path_buf();
+        std::fs::create_dir(outputs.join("run-1"))?;
+        std::fs::write(outputs.join("run-1/results.csv"), "accuracy\n0.91\n")?;
+        let preset = create_only(std::slice::from_ref(&outputs))?;
+        let profile = preset.generate_profile("(version 1)\n(deny default)\n")?;
+
+        let decide = |operation, path: PathBuf| evaluate_profile(&profile, operation, Some(&path));
+        let previous = outputs.join("run-1/results.csv");
+        assert_eq!(
+            decide("file-write-data", previous.clone())?,
+            Some(Action::Deny)
+        );
+        assert_eq!(decide("file-write-unlink", previous)?, Some(Action::Deny));
+        let next = outputs.join("run-2/results.csv");
+        assert_eq!(
+            decide("file-write-create", next.clone())?,
+            Some(Action::Allow)
+        );
+        assert_eq!(decide("file-write-data", next)?, Some(Action::Allow));
+        Ok(())
+    }
+
+    #[test]
+    fn test_undeletable_dataset() -> Result<()> {
+        use crate::evaluator::{evaluate_profile, Action};
+
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().to_path_buf();
+        let dataset = project.join("data/survey.csv");
+        let preset = undeletable(std::slice::from_ref(&dataset))?;
+        let mut profile = generate_profile(
+            "(version 1)\n(deny default)\n",
+            &Permissions {
+                allow_write: vec![project.clone()],
+                ..Permissions::default()
+            },
+        )?;
+        profile.push_str(&preset.rules);
+
+        let decide = |operation, path: &Path| evaluate_profile(&profile, operation, Some(path));
+        assert_eq!(decide("file-write-data", &dataset)?, Some(Action::Allow));
+        assert_eq!(decide("file-write-unlink", &dataset)?, Some(Action::Deny));
+        let output = project.join("outputs/plot.png");
+        assert_eq!(decide("file-write-unlink", &output)?, Some(Action::Allow));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fixed_permissions() -> Result<()> {
+        use crate::evaluator::{evaluate_profile, Action};
+
+        let dir = tempfile::tempdir().unwrap();
+        let shared = dir.path().to_path_buf();
+        let preset = fixed_permissions(std::slice::from_ref(&shared))?;
+        let mut profile = generate_profile(
+            "(version 1)\n(deny default)\n",
+            &Permissions {
+                allow_write: vec![shared.clone()],
+                ..Permissions::default()
+            },
+        )?;
+        profile.push_str(&preset.rules);
+
+        let script = shared.join("run.sh");
+        let decide = |operation| evaluate_profile(&profile, operation, Some(&script));
+        assert_eq!(decide("file-write-data")?, Some(Action::Allow));
+        assert_eq!(decide("file-write-mode")?, Some(Action::Deny));
+        assert_eq!(decide("file-write-owner")?, Some(Action::Deny));
+        Ok(())
+    }
+
+    #[test]
+    fn test_depth_limited() -> Result<()> {
+        let preset = depth_limited(
+            "file-read-metadata",
+            &[PathBuf::from("/Users/me/my.project/")],
+            2,
+        )?;
+        assert_eq!(
+            preset.rules,
+            "(allow file-read-metadata\n    (regex #\"^/Users/me/my\\.project(/[^/]+){0,2}$\")\n)\n"
+        );
+        use crate::evaluator::{evaluate_profile, Action};
+        let stat = |path: &str| {
+            evaluate_profile(&preset.rules, "file-read-metadata", Some(Path::new(path)))
+        };
+        assert_eq!(stat("/Users/me/my.project/a/b")?, Some(Action::Allow));
+        assert_eq!(stat("/Users/me/my.project/a/b/c")?, None);
+        assert!(depth_limited("file-read*", &[], 1)?.rules.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_system_essentials() -> Result<()> {
+        let profile = system_essentials().generate_profile("(version 1)\n(deny default)\n")?;
+        assert!(profile.contains("\"/usr/lib\""));
+        assert!(profile.contains("\"/System/Library/Frameworks\""));
+        assert!(profile.contains("(literal \"/dev/urandom\")"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_homebrew_preset() -> Result<()> {
+        let preset = homebrew(Path::new("/opt/homebrew"))?;
+        assert!(preset
+            .permissions
+            .allow_read
+            .contains(&PathBuf::from("/opt/homebrew/lib")));
+        assert!(preset
+            .rules
+            .contains("    (subpath \"/opt/homebrew/Cellar\")\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_nix_preset() -> Result<()> {
+        let profile = nix().generate_profile("(version 1)\n(deny default)\n")?;
+        assert!(profile.contains("\"/nix/store\""));
+        assert!(profile.contains("(allow process-exec\n    (subpath \"/nix/store\")\n)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_accelerate_preset() -> Result<()> {
+        let profile = accelerate().generate_profile("(version 1)\n(deny default)\n")?;
+        assert!(profile.contains("(sysctl-name-prefix \"hw.optional.\")"));
+        assert!(profile.contains("\"/System/Library/Frameworks/Accelerate.framework\""));
+        assert!(profile.contains(crate::GPU_RULES));
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::approval::{is_allowed, is_denied};
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::grants::Grant;
+use crate::session::sandboxed_command;
+use crate::Permissions;
+
+/// Host contacted by the network probe.
+pub const NET_PROBE_URL: &str = "https://example.com";
+/// Name of the file created (and removed again) by write probes on directories.
+pub const WRITE_PROBE_FILE: &str = ".secure_notebook_probe";
+/// Longest a single probe may run before it is killed.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What happened, or should happen, when an operation is attempted in the sandbox.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    Allowed,
+    Denied,
+    /// The operation failed for a reason unrelated to the sandbox, e.g. a missing file.
+    Inconclusive,
+}
+
+/// An operation to attempt inside the sandbox, with the outcome the policy implies.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Probe {
+    pub grant: Grant,
+    pub expected: Outcome,
+}
+
+/// Result of a single probe.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeResult {
+    pub grant: Grant,
+    pub expected: Outcome,
+    pub observed: Outcome,
+    /// Stderr of the probe, useful when it did not behave as expected.
+    pub detail: String,
+}
+
+impl ProbeResult {
+    /// Whether the sandbox behaved as the policy says it should.
+    pub fn passed(&self) -> bool {
+        self.expected == self.observed
+    }
+}
+
+/// Pass/fail report of a probe run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeReport {
+    pub results: Vec<ProbeResult>,
+}
+
+impl ProbeReport {
+    /// Whether every probe behaved as expected.
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(ProbeResult::passed)
+    }
+
+    /// Probes that did not behave as expected.
+    pub fn failures(&self) -> impl Iterator<Item = &ProbeResult> {
+        self.results.iter().filter(|result| !result.passed())
+    }
+}
+
+/// Probes for every path and program declared in `permissions`.
+///
+/// A denied path is expected to be denied even when an allowed parent covers it, since
+/// that is what the policy asks for; a profile that lets it through fails the probe.
+pub fn plan_probes(permissions: &Permissions) -> Vec<Probe> {
+    let paths = |allow: &[PathBuf], deny: &[PathBuf], to_grant: fn(PathBuf) -> Grant| {
+        allow
+            .iter()
+            .chain(deny)
+            .map(move |path| to_grant(path.clone()))
+            .collect::<Vec<_>>()
+    };
+
+    let mut grants = paths(&permissions.allow_read, &permissions.deny_read, Grant::Read);
+    grants.extend(paths(
+        &permissions.allow_write,
+        &permissions.deny_write,
+        Grant::Write,
+    ));
+    grants.extend(paths(
+        &permissions.allow_run,
+        &permissions.deny_run,
+        Grant::Run,
+    ));
+    grants.push(Grant::Net);
+
+    grants
+        .into_iter()
+        .map(|grant| Probe {
+            expected: expected_outcome(permissions, &grant),
+            grant,
+        })
+        .collect()
+}
+
+/// Outcome the policy implies for `grant`.
+pub fn expected_outcome(permissions: &Permissions, grant: &Grant) -> Outcome {
+    if !is_denied(permissions, grant) && is_allowed(permissions, grant) {
+        Outcome::Allowed
+    } else {
+        Outcome::Denied
+    }
+}
+
+/// Check that `profile` enforces `permissions` on this machine.
+pub fn probe(profile: &str, permissions: &Permissions) -> Result<ProbeReport> {
+    run_probes(profile, &plan_probes(permissions))
+}
+
+/// Attempt each probe in a fresh process running under `profile`.
+///
+/// Run probes execute the program with `--version`. Write probes on an existing file
+/// only touch its timestamps; on a directory they create [`WRITE_PROBE_FILE`] and
+/// remove it again.
+pub fn run_probes(profile: &str, probes: &[Probe]) -> Result<ProbeReport> {
+    let mut results = Vec::with_capacity(probes.len());
+    for probe in probes {
+        let (observed, detail) = attempt(profile, &probe.grant)?;
+        results.push(ProbeResult {
+            grant: probe.grant.clone(),
+            expected: probe.expected,
+            observed,
+            detail,
+        });
+    }
+    Ok(ProbeReport { results })
+}
+
+fn attempt(profile: &str, grant: &Grant) -> Result<(Outcome, String)> {
+    let mut command = match grant {
+        Grant::Read(path) if path.is_dir() => probe_command(profile, "/bin/ls", [path]),
+        Grant::Read(path) => probe_command(profile, "/bin/cat", [path]),
+        Grant::Write(path) if path.is_dir() => {
+            probe_command(profile, "/usr/bin/touch", [path.join(WRITE_PROBE_FILE)])
+        }
+        Grant::Write(path) => {
+            probe_command(profile, "/usr/bin/touch", [Path::new("-c"), path.as_path()])
+        }
+        Grant::Net => probe_command(
+            profile,
+            "/usr/bin/curl",
+            ["-sS", "-m", "5", "-o", "/dev/null", NET_PROBE_URL],
+        ),
+        Grant::Gpu => {
+            return Err(SecureNotebookError::Unsupported(
+                "GPU access cannot be probed with a command".to_string(),
+            ))
+        }
+        Grant::Run(program) => {
+            let mut command = sandboxed_command(profile, program);
+            command.arg("--version");
+            command
+        }
+    };
+
+    let (success, stderr) = run_with_timeout(&mut command, PROBE_TIMEOUT)?;
+
+    if let Grant::Write(path) = grant {
+        let probe_file = path.join(WRITE_PROBE_FILE);
+        if path.is_dir() && probe_file.exists() {
+            std::fs::remove_file(&probe_file)
+                .io_context(|| format!("Failed to remove {}", probe_file.display()))?;
+        }
+    }
+
+    Ok((classify(success, &stderr), stderr))
+}
+
+fn probe_command<I, S>(profile: &str, program: &str, args: I) -> Command
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    let mut command = sandboxed_command(profile, Path::new(program));
+    command.args(args);
+    command
+}
+
+/// Run `command`, killing it after `timeout`. Returns whether it succeeded and its stderr.
+pub(crate) fn run_with_timeout(command: &mut Command, timeout: Duration) -> Result<(bool, String)> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|source| SecureNotebookError::SpawnFailed {
+            program: PathBuf::from("sandbox-exec"),
+            source,
+        })?;
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait().io_context(|| "Failed to wait for probe")? {
+            break Some(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    let mut stderr = String::new();
+    if let Some(mut pipe) = child.stderr.take() {
+        use std::io::Read;
+        let _ = pipe.read_to_string(&mut stderr);
+    }
+    Ok((status.is_some_and(|status| status.success()), stderr))
+}
+
+/// Decide whether a failed probe was stopped by the sandbox.
+pub fn classify(success: bool, stderr: &str) -> Outcome {
+    if success {
+        Outcome::Allowed
+    } else if stderr.contains("Operation not permitted") || stderr.contains("Permission denied") {
+        Outcome::Denied
+    } else {
+        Outcome::Inconclusive
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_probes_deny_wins() {
+        let mut permissions = Permissions::new();
+        permissions.allow_read.push(PathBuf::from("/data"));
+        permissions.deny_read.push(PathBuf::from("/data/secret"));
+
+        let probes = plan_probes(&permissions);
+        let expected = |grant: &Grant| {
+            probes
+                .iter()
+                .find(|probe| &probe.grant == grant)
+                .map(|probe| probe.expected)
+        };
+        assert_eq!(
+            expected(&Grant::Read(PathBuf::from("/data"))),
+            Some(Outcome::Allowed)
+        );
+        assert_eq!(
+            expected(&Grant::Read(PathBuf::from("/data/secret"))),
+            Some(Outcome::Denied)
+        );
+        assert_eq!(expected(&Grant::Net), Some(Outcome::Denied));
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify(true, ""), Outcome::Allowed);
+        assert_eq!(
+            classify(false, "cat: /etc/master.passwd: Operation not permitted\n"),
+            Outcome::Denied
+        );
+        assert_eq!(
+            classify(false, "cat: /nope: No such file or directory\n"),
+            Outcome::Inconclusive
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::explain::PermissionField;
+use crate::grants::Grant;
+use crate::policy::load_policy;
+use crate::presets::Preset;
+use crate::Permissions;
+
+/// Where a permission, and so the rules generated from it, came from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "name", rename_all = "lowercase")]
+pub enum Provenance {
+    Template,
+    /// A [`Preset`], by name.
+    Preset(String),
+    /// A policy file.
+    Config(PathBuf),
+    /// A [`Grant`] made at runtime.
+    Grant,
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Template => f.write_str("template"),
+            Self::Preset(name) => write!(f, "preset: {name}"),
+            Self::Config(path) => write!(f, "config: {}", path.display()),
+            Self::Grant => f.write_str("runtime grant"),
+        }
+    }
+}
+
+/// Permissions assembled from several sources, remembering the source of every entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackedPermissions {
+    permissions: Permissions,
+    sources: HashMap<(PermissionField, usize), Provenance>,
+}
+
+impl TrackedPermissions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `permissions`, attributing each of their entries to `provenance`.
+    ///
+    /// `allow_net` keeps the source that first enabled it.
+    pub fn add(&mut self, permissions: &Permissions, provenance: Provenance) {
+        let fields = [
+            (PermissionField::AllowRead, &permissions.allow_read),
+            (PermissionField::DenyRead, &permissions.deny_read),
+            (PermissionField::AllowWrite, &permissions.allow_write),
+            (PermissionField::DenyWrite, &permissions.deny_write),
+            (PermissionField::AllowRun, &permissions.allow_run),
+            (PermissionField::DenyRun, &permissions.deny_run),
+        ];
+        for (field, paths) in fields {
+            let list = self.field_mut(field);
+            let start = list.len();
+            list.extend_from_slice(paths);
+            for index in start..start + paths.len() {
+                self.sources.insert((field, index), provenance.clone());
+            }
+        }
+        if permissions.allow_net && !self.permissions.allow_net {
+            self.permissions.allow_net = true;
+            self.sources
+                .insert((PermissionField::AllowNet, 0), provenance.clone());
+        }
+        if permissions.allow_gpu && !self.permissions.allow_gpu {
+            self.permissions.allow_gpu = true;
+            self.sources
+                .insert((PermissionField::AllowGpu, 0), provenance);
+        }
+    }
+
+    /// Add the permissions of `preset`.
+    pub fn add_preset(&mut self, preset: &Preset) {
+        self.add(&preset.permissions, Provenance::Preset(preset.name.clone()));
+    }
+
+    /// Load and add the policy file at `path`.
+    pub fn add_config(&mut self, path: &Path) -> Result<()> {
+        let permissions = load_policy(path)?;
+        self.add(&permissions, Provenance::Config(path.to_path_buf()));
+        Ok(())
+    }
+
+    /// Add a runtime grant.
+    pub fn add_grant(&mut self, grant: &Grant) {
+        let mut permissions = Permissions::new();
+        grant.apply(&mut permissions);
+        self.add(&permissions, Provenance::Grant);
+    }
+
+    /// The combined permissions.
+    pub fn permissions(&self) -> &Permissions {
+        &self.permissions
+    }
+
+    /// Source of entry `index` of `field`.
+    pub fn provenance(&self, field: PermissionField, index: usize) -> Option<&Provenance> {
+        self.sources.get(&(field, index))
+    }
+
+    fn field_mut(&mut self, field: PermissionField) -> &mut Vec<PathBuf> {
+        let permissions = &mut self.permissions;
+        match field {
+            PermissionField::AllowRead => &mut permissions.allow_read,
+            PermissionField::DenyRead => &mut permissions.deny_read,
+            PermissionField::AllowWrite => &mut permissions.allow_write,
+            PermissionField::DenyWrite => &mut permissions.deny_write,
+            PermissionField::AllowRun => &mut permissions.allow_run,
+            PermissionField::DenyRun => &mut permissions.deny_run,
+            PermissionField::AllowNet | PermissionField::AllowGpu => {
+                unreachable!("{field} is not a list")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::explain::{Operation, Profile, Source};
+
+    #[test]
+    fn test_explain_attributes_grants() -> Result<()> {
+        let mut tracked = TrackedPermissions::new();
+        tracked.add_preset(&crate::presets::offline(&[PathBuf::from("/opt/wheels")])?);
+        tracked.add_grant(&Grant::Read(PathBuf::from("/data")));
+
+        let profile = Profile::with_provenance("(version 1)\n(deny default)\n", &tracked)?;
+        let explanation = profile.explain(Operation::Read(PathBuf::from("/data")));
+        assert_eq!(
+            explanation.source,
+            Some(Source::Permission {
+                field: PermissionField::AllowRead,
+                index: 1
+            })
+        );
+        assert_eq!(explanation.provenance, Some(Provenance::Grant));
+
+        assert!(profile
+            .annotated()
+            .contains("; from Permissions.allow_read[0] (preset: offline)\n"));
+
+        let report = profile.attributions();
+        assert_eq!(report[0].provenance, Some(Provenance::Template));
+        assert_eq!(
+            report[1].provenance,
+            Some(Provenance::Preset("offline".to_string()))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+use std::fs::File;
+use std::io::{IsTerminal, Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::session::SandboxGuard;
+
+/// A pseudo-terminal for running interactive programs, such as debuggers or REPLs with
+/// line editing, under the sandbox.
+///
+/// The program gets the terminal side as its stdio and controlling terminal, so
+/// `Ctrl-C` and job control reach it as signals. The host keeps the other side.
+#[derive(Debug)]
+pub struct Pty {
+    master: File,
+    terminal: File,
+}
+
+impl Pty {
+    /// Open a new pseudo-terminal.
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    pub fn open() -> Result<Self> {
+        use std::os::fd::FromRawFd;
+
+        let (mut master, mut terminal) = (-1, -1);
+        // SAFETY: openpty only writes the two descriptors; name, termios and size are
+        // optional and passed as null.
+        let opened = unsafe {
+            ffi::openpty(
+                &mut master,
+                &mut terminal,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                std::ptr::null(),
+            )
+        };
+        if opened != 0 {
+            return Err(SecureNotebookError::Io {
+                context: "Failed to open a pseudo-terminal".to_string(),
+                source: std::io::Error::last_os_error(),
+            });
+        }
+        for fd in [master, terminal] {
+            // SAFETY: fd was just returned by openpty. Keeps both out of the sandboxed
+            // program, which only gets the dup'ed stdio.
+            unsafe { ffi::fcntl(fd, ffi::F_SETFD, ffi::FD_CLOEXEC) };
+        }
+        // SAFETY: both descriptors are open and owned by nothing else.
+        Ok(unsafe {
+            Self {
+                master: File::from_raw_fd(master),
+                terminal: File::from_raw_fd(terminal),
+            }
+        })
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    pub fn open() -> Result<Self> {
+        Err(SecureNotebookError::Unsupported(
+            "Pseudo-terminals are only supported on macOS and Linux".to_string(),
+        ))
+    }
+
+    /// Size the terminal like the one the host is attached to, if any, so full-screen
+    /// programs lay themselves out correctly.
+    pub fn match_host_size(&self) -> Result<()> {
+        if !std::io::stdin().is_terminal() {
+            return Ok(());
+        }
+        let output = Command::new("stty")
+            .arg("size")
+            .stdin(Stdio::inherit())
+            .output()
+            .io_context(|| "Failed to read the terminal size")?;
+        let size = String::from_utf8_lossy(&output.stdout);
+        let Some((rows, columns)) = size.trim().split_once(' ') else {
+            return Ok(());
+        };
+        Command::new("stty")
+            .args(["rows", rows, "cols", columns])
+            .stdin(self.stdio()?)
+            .status()
+            .io_context(|| "Failed to size the pseudo-terminal")?;
+        Ok(())
+    }
+
+    /// Run `command` on the terminal: as its stdin, stdout and stderr, and as the
+    /// controlling terminal of a new session it leads.
+    pub fn attach(&self, command: &mut Command) -> Result<()> {
+        command
+            .stdin(self.stdio()?)
+            .stdout(self.stdio()?)
+            .stderr(self.stdio()?);
+        #[cfg(any(target_os = "macos", target_os = "linux"))]
+        // SAFETY: setsid and ioctl are async-signal-safe and the closure doesn't allocate.
+        unsafe {
+            std::os::unix::process::CommandExt::pre_exec(command, || {
+                if ffi::setsid() < 0 || ffi::ioctl(0, ffi::TIOCSCTTY, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    /// The host side, once the program is attached.
+    pub fn into_master(self) -> File {
+        self.master
+    }
+
+    fn stdio(&self) -> Result<Stdio> {
+        Ok(self
+            .terminal
+            .try_clone()
+            .io_context(|| "Failed to duplicate the pseudo-terminal")?
+            .into())
+    }
+}
+
+/// Connect the host terminal to `master` until the program behind `guard` exits.
+///
+/// The host terminal is put in raw mode meanwhile, so keystrokes, including `Ctrl-C`,
+/// go to the program's terminal as typed and it does its own echo and line editing.
+pub fn interact(guard: &mut SandboxGuard, mut master: File) -> Result<ExitStatus> {
+    let _raw = RawMode::enter()?;
+    let mut input = master
+        .try_clone()
+        .io_context(|| "Failed to duplicate the pseudo-terminal")?;
+    // Blocked on stdin until the next keystroke; it ends with the process.
+    std::thread::spawn(move || {
+        let _ = std::io::copy(&mut std::io::stdin(), &mut input);
+    });
+
+    let mut stdout = std::io::stdout();
+    let mut buffer = [0; 4096];
+    // Reading fails with EIO, or returns 0 on macOS, once the program closes the terminal.
+    while let Ok(read @ 1..) = master.read(&mut buffer) {
+        stdout
+            .write_all(&buffer[..read])
+            .and_then(|()| stdout.flush())
+            .io_context(|| "Failed to write program output")?;
+    }
+    guard.wait()
+}
+
+/// The host terminal's settings while in raw mode, restored on drop.
+struct RawMode {
+    saved: Option<String>,
+}
+
+impl RawMode {
+    fn enter() -> Result<Self> {
+        if !std::io::stdin().is_terminal() {
+            return Ok(Self { saved: None });
+        }
+        let saved = Command::new("stty")
+            .arg("-g")
+            .stdin(Stdio::inherit())
+            .output()
+            .io_context(|| "Failed to read the terminal settings")?;
+        Command::new("stty")
+            .args(["raw", "-echo"])
+            .stdin(Stdio::inherit())
+            .status()
+            .io_context(|| "Failed to put the terminal in raw mode")?;
+        Ok(Self {
+            saved: Some(String::from_utf8_lossy(&saved.stdout).trim().to_string()),
+        })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Some(saved) = &self.saved {
+            let _ = Command::new("stty")
+                .arg(saved)
+                .stdin(Stdio::inherit())
+                .status();
+        }
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+mod ffi {
+    use std::ffi::{c_char, c_int, c_ulong, c_void};
+
+    pub const F_SETFD: c_int = 2;
+    pub const FD_CLOEXEC: c_int = 1;
+    #[cfg(target_os = "macos")]
+    pub const TIOCSCTTY: c_ulong = 0x2000_7461;
+    #[cfg(target_os = "linux")]
+    pub const TIOCSCTTY: c_ulong = 0x540E;
+
+    #[cfg_attr(target_os = "linux", link(name = "util"))]
+    extern "C" {
+        pub fn openpty(
+            master: *mut c_int,
+            terminal: *mut c_int,
+            name: *mut c_char,
+            termios: *const c_void,
+            size: *const c_void,
+        ) -> c_int;
+        pub fn setsid() -> c_int;
+        pub fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+        pub fn fcntl(fd: c_int, command: c_int, ...) -> c_int;
+    }
+}
+
+#[cfg(all(test, any(target_os = "macos", target_os = "linux")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_program_runs_on_the_terminal() -> Result<()> {
+        let mut command = Command::new("sh");
+        command.args(["-c", "test -t 0 && test -t 1 && echo interactive"]);
+        let (mut guard, mut master) = SandboxGuard::spawn_in_pty(command, Pty::open()?)?;
+
+        let mut output = Vec::new();
+        let mut buffer = [0; 256];
+        while let Ok(read @ 1..) = master.read(&mut buffer) {
+            output.extend_from_slice(&buffer[..read]);
+        }
+        assert!(guard.wait()?.success());
+        assert!(String::from_utf8_lossy(&output).contains("interactive"));
+        Ok(())
+    }
+}
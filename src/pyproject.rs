@@ -0,0 +1,163 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::presets::{exec_rules, Preset};
+use crate::Permissions;
+
+/// Tool managing a project's virtualenv.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EnvManager {
+    Poetry,
+    Uv,
+}
+
+impl EnvManager {
+    /// The manager of the project in `project`: by lockfile, then by its table in
+    /// `pyproject.toml`. `None` for projects managed some other way.
+    pub fn detect(project: &Path) -> Result<Option<Self>> {
+        if project.join("uv.lock").is_file() {
+            return Ok(Some(Self::Uv));
+        }
+        if project.join("poetry.lock").is_file() {
+            return Ok(Some(Self::Poetry));
+        }
+        let manifest = project.join("pyproject.toml");
+        if !manifest.is_file() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(&manifest)
+            .io_context(|| format!("Failed to read {}", manifest.display()))?;
+        let table: toml::Table = toml::from_str(&contents)?;
+        let tool = table.get("tool");
+        Ok(if tool.and_then(|tool| tool.get("uv")).is_some() {
+            Some(Self::Uv)
+        } else if tool.and_then(|tool| tool.get("poetry")).is_some() {
+            Some(Self::Poetry)
+        } else {
+            None
+        })
+    }
+}
+
+/// A project's virtualenv and the interpreter it was created from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectEnv {
+    pub manager: EnvManager,
+    pub path: PathBuf,
+    /// The base installation the env's `python` links to, from `home` in `pyvenv.cfg`.
+    pub base_prefix: Option<PathBuf>,
+}
+
+impl ProjectEnv {
+    /// Find the virtualenv of the poetry or uv project in `project`.
+    ///
+    /// uv uses `UV_PROJECT_ENVIRONMENT` or `.venv`. Poetry uses `.venv` when configured
+    /// in-project, and is otherwise asked with `poetry env info --path`. `None` if the
+    /// project isn't managed by either or its env hasn't been created yet.
+    pub fn detect(project: &Path) -> Result<Option<Self>> {
+        let Some(manager) = EnvManager::detect(project)? else {
+            return Ok(None);
+        };
+        let path = match manager {
+            EnvManager::Uv => std::env::var_os("UV_PROJECT_ENVIRONMENT")
+                .map(|env| project.join(env))
+                .unwrap_or_else(|| project.join(".venv")),
+            EnvManager::Poetry if project.join(".venv").is_dir() => project.join(".venv"),
+            EnvManager::Poetry => match poetry_env(project)? {
+                Some(path) => path,
+                None => return Ok(None),
+            },
+        };
+        if !path.join("pyvenv.cfg").is_file() {
+            return Ok(None);
+        }
+        let base_prefix = base_prefix(&path)?;
+        Ok(Some(Self {
+            manager,
+            path,
+            base_prefix,
+        }))
+    }
+
+    /// Read and exec on the env and its base interpreter, with `VIRTUAL_ENV` set so
+    /// kernels pick the env up.
+    pub fn preset(&self) -> Result<Preset> {
+        let mut roots = vec![self.path.clone()];
+        roots.extend(self.base_prefix.clone());
+        let exec: Vec<PathBuf> = roots.iter().map(|root| root.join("bin")).collect();
+        let name = match self.manager {
+            EnvManager::Poetry => "poetry",
+            EnvManager::Uv => "uv",
+        };
+        Ok(Preset {
+            name: name.to_string(),
+            permissions: Permissions {
+                allow_read: roots,
+                ..Permissions::default()
+            },
+            hosts: Vec::new(),
+            rules: exec_rules(&exec)?,
+            env: vec![(
+                "VIRTUAL_ENV".to_string(),
+                self.path.to_string_lossy().into_owned(),
+            )],
+        })
+    }
+}
+
+/// The env path poetry reports for `project`, `None` if it has none yet.
+fn poetry_env(project: &Path) -> Result<Option<PathBuf>> {
+    let output = Command::new("poetry")
+        .args(["env", "info", "--path"])
+        .current_dir(project)
+        .output()
+        .map_err(|source| SecureNotebookError::SpawnFailed {
+            program: PathBuf::from("poetry"),
+            source,
+        })?;
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok((output.status.success() && !path.is_empty()).then(|| PathBuf::from(path)))
+}
+
+/// The parent of the `home = <prefix>/bin` line of the env's `pyvenv.cfg`.
+fn base_prefix(env: &Path) -> Result<Option<PathBuf>> {
+    let config = env.join("pyvenv.cfg");
+    let contents = std::fs::read_to_string(&config)
+        .io_context(|| format!("Failed to read {}", config.display()))?;
+    Ok(contents.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        let bin = Path::new(value.trim());
+        (key.trim() == "home").then(|| bin.parent().unwrap_or(bin).to_path_buf())
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_detect_uv_env() -> Result<()> {
+        let project = tempdir().unwrap();
+        assert_eq!(ProjectEnv::detect(project.path())?, None);
+
+        std::fs::write(project.path().join("uv.lock"), "version = 1\n")?;
+        std::fs::create_dir(project.path().join(".venv"))?;
+        std::fs::write(
+            project.path().join(".venv/pyvenv.cfg"),
+            "home = /opt/python/3.12/bin\nversion_info = 3.12.4\n",
+        )?;
+        let env = ProjectEnv::detect(project.path())?.unwrap();
+        assert_eq!(env.manager, EnvManager::Uv);
+        assert_eq!(env.base_prefix, Some(PathBuf::from("/opt/python/3.12")));
+
+        let preset = env.preset()?;
+        assert!(preset
+            .permissions
+            .allow_read
+            .contains(&project.path().join(".venv")));
+        assert!(preset.rules.contains("(subpath \"/opt/python/3.12/bin\")"));
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::session::{JupyterSession, SessionConfig};
+use crate::{generate_profile, Permissions};
+
+/// What to do once the quota is exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaAction {
+    /// Stop the server and its kernels.
+    Abort,
+    /// Restart the server with every write permission removed.
+    BlockWrites,
+}
+
+/// Tracks bytes added under a set of directories since the watcher started.
+#[derive(Debug, Clone)]
+pub struct DiskUsage {
+    paths: Vec<PathBuf>,
+    baseline: u64,
+}
+
+impl DiskUsage {
+    /// Start tracking `paths`, taking their current size as the baseline.
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let baseline = total_size(&paths);
+        Self { paths, baseline }
+    }
+
+    /// Bytes added since the watcher started. Deleting files gives the space back.
+    pub fn bytes_written(&self) -> u64 {
+        total_size(&self.paths).saturating_sub(self.baseline)
+    }
+}
+
+/// Size of every file under `paths`. Unreadable entries count as empty.
+pub fn total_size(paths: &[PathBuf]) -> u64 {
+    paths.iter().map(|path| size_of(path)).sum()
+}
+
+fn size_of(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| size_of(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// A session whose `allow_write` directories are limited to `limit` new bytes.
+#[derive(Debug)]
+pub struct QuotaSession {
+    session: JupyterSession,
+    template: String,
+    permissions: Permissions,
+    usage: DiskUsage,
+    limit: u64,
+    action: QuotaAction,
+    exceeded: bool,
+}
+
+impl QuotaSession {
+    /// Start the server, tracking writes under `permissions.allow_write`.
+    pub fn start(
+        template: &str,
+        permissions: Permissions,
+        limit: u64,
+        action: QuotaAction,
+        config: SessionConfig,
+    ) -> Result<Self> {
+        let profile = generate_profile(template, &permissions)?;
+        let usage = DiskUsage::new(permissions.allow_write.clone());
+        let session = JupyterSession::spawn(&profile, config)?;
+
+        Ok(Self {
+            session,
+            template: template.to_string(),
+            permissions,
+            usage,
+            limit,
+            action,
+            exceeded: false,
+        })
+    }
+
+    /// Underlying Jupyter session.
+    pub fn session(&self) -> &JupyterSession {
+        &self.session
+    }
+
+    /// Bytes written under the tracked directories so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.usage.bytes_written()
+    }
+
+    /// Whether the quota has been exceeded and the action taken.
+    pub fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+
+    /// Check usage and act if the quota is exceeded, returning whether it acted now.
+    ///
+    /// Call this periodically; usage is measured by walking the directories.
+    pub fn poll(&mut self) -> Result<bool> {
+        if self.exceeded || self.usage.bytes_written() <= self.limit {
+            return Ok(false);
+        }
+
+        match self.action {
+            QuotaAction::Abort => self.session.stop()?,
+            QuotaAction::BlockWrites => {
+                let mut read_only = self.permissions.clone();
+                // keep what was written readable
+                let written = std::mem::take(&mut read_only.allow_write);
+                read_only.allow_read.extend(written);
+                let profile = generate_profile(&self.template, &read_only)?;
+                self.session.restart(&profile)?;
+            }
+        }
+        self.exceeded = true;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_disk_usage() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("before.txt"), [0u8; 100]).unwrap();
+
+        let usage = DiskUsage::new(vec![dir.path().to_path_buf()]);
+        assert_eq!(usage.bytes_written(), 0);
+
+        fs::create_dir(dir.path().join("nested")).unwrap();
+        fs::write(dir.path().join("nested/after.bin"), [0u8; 250]).unwrap();
+        assert_eq!(usage.bytes_written(), 250);
+    }
+}
@@ -0,0 +1,163 @@
+use ed25519_dalek::VerifyingKey;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::policy::parse_policy;
+use crate::signing::{encode_hex, verify};
+use crate::Permissions;
+
+/// A policy document served over HTTPS, cached locally by ETag.
+///
+/// The detached signature is fetched from `<url>.sig`.
+#[derive(Debug, Clone)]
+pub struct RemotePolicy {
+    url: String,
+    cache_dir: PathBuf,
+    key: Option<VerifyingKey>,
+}
+
+/// A fetched policy and whether it came from the local cache.
+#[derive(Debug, Clone)]
+pub struct FetchedPolicy {
+    pub permissions: Permissions,
+    pub etag: Option<String>,
+    /// The server answered `304 Not Modified`.
+    pub from_cache: bool,
+}
+
+impl RemotePolicy {
+    /// Create a loader for `url`, caching documents in `cache_dir`.
+    pub fn new(url: &str, cache_dir: PathBuf) -> Result<Self> {
+        if !url.starts_with("https://") {
+            return Err(SecureNotebookError::InvalidPolicy(format!(
+                "Policy URL must use HTTPS: {url}"
+            )));
+        }
+        Ok(Self {
+            url: url.to_string(),
+            cache_dir,
+            key: None,
+        })
+    }
+
+    /// Require the document to be signed by `key`.
+    pub fn verify_with(mut self, key: VerifyingKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// URL of the policy document.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Fetch the policy, reusing the cached copy if the server reports it unchanged.
+    pub fn fetch(&self) -> Result<FetchedPolicy> {
+        let cached_etag = std::fs::read_to_string(self.cache_path("etag")).ok();
+
+        let mut request = ureq::get(&self.url);
+        if let Some(etag) = &cached_etag {
+            request = request.set("If-None-Match", etag);
+        }
+        let response = request.call().map_err(|e| {
+            SecureNotebookError::Network(format!("Failed to fetch policy from {}: {e}", self.url))
+        })?;
+
+        if response.status() == 304 {
+            return Ok(FetchedPolicy {
+                permissions: self.load_cached()?,
+                etag: cached_etag,
+                from_cache: true,
+            });
+        }
+
+        let etag = response.header("ETag").map(str::to_string);
+        let body = response
+            .into_string()
+            .io_context(|| "Failed to read policy body")?;
+        let signature = match &self.key {
+            Some(key) => {
+                let signature = ureq::get(&format!("{}.sig", self.url))
+                    .call()?
+                    .into_string()
+                    .io_context(|| "Failed to read policy signature")?;
+                verify(body.as_bytes(), &signature, key)?;
+                Some(signature)
+            }
+            None => None,
+        };
+        let permissions = parse_policy(&body, self.is_toml())?;
+
+        self.store(&body, signature.as_deref(), etag.as_deref())?;
+        Ok(FetchedPolicy {
+            permissions,
+            etag,
+            from_cache: false,
+        })
+    }
+
+    /// Load the cached copy of the policy, verifying it again if a key is set.
+    pub fn load_cached(&self) -> Result<Permissions> {
+        let body = std::fs::read_to_string(self.cache_path("policy"))
+            .io_context(|| format!("No cached policy for {}", self.url))?;
+        if let Some(key) = &self.key {
+            let signature = std::fs::read_to_string(self.cache_path("sig")).map_err(|_| {
+                SecureNotebookError::Signature(format!("No cached signature for {}", self.url))
+            })?;
+            verify(body.as_bytes(), &signature, key)?;
+        }
+        parse_policy(&body, self.is_toml())
+    }
+
+    fn store(&self, body: &str, signature: Option<&str>, etag: Option<&str>) -> Result<()> {
+        std::fs::create_dir_all(&self.cache_dir)
+            .io_context(|| format!("Failed to create {}", self.cache_dir.display()))?;
+        std::fs::write(self.cache_path("policy"), body)?;
+        if let Some(signature) = signature {
+            std::fs::write(self.cache_path("sig"), signature)?;
+        }
+        match etag {
+            Some(etag) => std::fs::write(self.cache_path("etag"), etag)?,
+            None => {
+                let _ = std::fs::remove_file(self.cache_path("etag"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Cache file for the URL, named by its SHA-256 so the name is stable across
+    /// toolchains.
+    fn cache_path(&self, extension: &str) -> PathBuf {
+        let digest = encode_hex(&Sha256::digest(self.url.as_bytes()));
+        self.cache_dir.join(format!("{digest}.{extension}"))
+    }
+
+    fn is_toml(&self) -> bool {
+        self.url.ends_with(".toml")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requires_https() {
+        let cache_dir = std::env::temp_dir();
+        assert!(RemotePolicy::new("http://example.com/policy.toml", cache_dir.clone()).is_err());
+        assert!(RemotePolicy::new("https://example.com/policy.toml", cache_dir).is_ok());
+    }
+
+    #[test]
+    fn test_cache_path_is_stable() -> Result<()> {
+        let remote = RemotePolicy::new("https://example.com/policy.toml", PathBuf::from("/cache"))?;
+        let path = remote.cache_path("etag");
+        let name = path.file_name().unwrap().to_string_lossy();
+        assert!(path.starts_with("/cache"));
+        assert!(name.ends_with(".etag"));
+        assert_eq!(name.len(), 64 + ".etag".len());
+        assert_eq!(path, remote.cache_path("etag"));
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+use std::process::Command;
+use std::time::Duration;
+
+/// Resource limits applied to the Jupyter server, and inherited by its kernels.
+///
+/// Filesystem and network rules do not stop a notebook from exhausting the host, so
+/// these are set with `setrlimit` between `fork` and `exec`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// CPU time per process (`RLIMIT_CPU`); the process gets `SIGXCPU` when it runs out.
+    pub cpu_time: Option<Duration>,
+    /// Address space per process in bytes (`RLIMIT_AS`).
+    pub memory: Option<u64>,
+    /// Open file descriptors per process (`RLIMIT_NOFILE`).
+    pub open_files: Option<u64>,
+    /// Processes of the user (`RLIMIT_NPROC`). This counts every process the user runs,
+    /// not only the notebook's, so leave headroom.
+    pub processes: Option<u64>,
+    /// Scheduling priority, from -20 (highest) to 20 (lowest), like `nice`.
+    pub nice: Option<i32>,
+}
+
+impl ResourceLimits {
+    /// Whether no limit is set.
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Apply the limits to the process `command` starts.
+    #[cfg(unix)]
+    pub fn apply(&self, command: &mut Command) {
+        use std::os::unix::process::CommandExt;
+
+        if self.is_unlimited() {
+            return;
+        }
+        let limits = *self;
+        // SAFETY: the closure only calls `setrlimit` and `setpriority`, which are
+        // async-signal-safe, and builds its error without allocating.
+        unsafe {
+            command.pre_exec(move || limits.apply_to_current_process());
+        }
+    }
+
+    /// Apply the limits to the process `command` starts.
+    #[cfg(not(unix))]
+    pub fn apply(&self, _command: &mut Command) {}
+
+    #[cfg(unix)]
+    fn apply_to_current_process(&self) -> std::io::Result<()> {
+        let limits = [
+            (ffi::RLIMIT_CPU, self.cpu_time.map(|time| time.as_secs())),
+            (ffi::RLIMIT_AS, self.memory),
+            (ffi::RLIMIT_NOFILE, self.open_files),
+            (ffi::RLIMIT_NPROC, self.processes),
+        ];
+        for (resource, limit) in limits {
+            let Some(limit) = limit else { continue };
+            let rlimit = ffi::Rlimit {
+                rlim_cur: limit,
+                rlim_max: limit,
+            };
+            // SAFETY: `rlimit` is a valid, initialized struct for the call.
+            if unsafe { ffi::setrlimit(resource, &rlimit) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        if let Some(nice) = self.nice {
+            // SAFETY: plain syscall on the current process.
+            if unsafe { ffi::setpriority(ffi::PRIO_PROCESS, 0, nice) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+mod ffi {
+    use std::ffi::c_int;
+
+    #[repr(C)]
+    pub struct Rlimit {
+        pub rlim_cur: u64,
+        pub rlim_max: u64,
+    }
+
+    pub const RLIMIT_CPU: c_int = 0;
+    pub const PRIO_PROCESS: c_int = 0;
+
+    #[cfg(target_os = "macos")]
+    pub const RLIMIT_AS: c_int = 5;
+    #[cfg(target_os = "macos")]
+    pub const RLIMIT_NPROC: c_int = 7;
+    #[cfg(target_os = "macos")]
+    pub const RLIMIT_NOFILE: c_int = 8;
+
+    #[cfg(not(target_os = "macos"))]
+    pub const RLIMIT_NPROC: c_int = 6;
+    #[cfg(not(target_os = "macos"))]
+    pub const RLIMIT_NOFILE: c_int = 7;
+    #[cfg(not(target_os = "macos"))]
+    pub const RLIMIT_AS: c_int = 9;
+
+    extern "C" {
+        pub fn setrlimit(resource: c_int, rlim: *const Rlimit) -> c_int;
+        pub fn setpriority(which: c_int, who: u32, prio: c_int) -> c_int;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_open_files_limit_is_applied() {
+        let limits = ResourceLimits {
+            open_files: Some(64),
+            ..ResourceLimits::default()
+        };
+        let mut command = Command::new("/bin/sh");
+        command.args(["-c", "ulimit -n"]);
+        limits.apply(&mut command);
+
+        let output = command.output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "64");
+    }
+}
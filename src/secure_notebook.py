@@ -0,0 +1,92 @@
+# Kernel side of the `secure_notebook` comm target.
+# Executed in the kernel at session start so notebook code can call
+# `secure_notebook.request("read", "/data/x.csv")` and wait for the supervisor's answer.
+# It also registers the `%request_permission` magic, e.g. `%request_permission net api.github.com`.
+# Network grants cover all hosts; the host is only shown to the operator.
+import asyncio
+import ctypes
+import sys
+import time
+import types
+import uuid
+
+from comm import create_comm
+
+_responses = {}
+_comm = None
+
+
+def _on_msg(msg):
+    data = msg["content"]["data"]
+    _responses[data["request_id"]] = data
+
+
+def _get_comm():
+    global _comm
+    if _comm is None:
+        _comm = create_comm(target_name="secure_notebook")
+        _comm.on_msg(_on_msg)
+    return _comm
+
+
+def _consume(token):
+    libsandbox = ctypes.CDLL("/usr/lib/system/libsystem_sandbox.dylib")
+    libsandbox.sandbox_extension_consume.argtypes = [ctypes.c_char_p]
+    libsandbox.sandbox_extension_consume.restype = ctypes.c_int64
+    if libsandbox.sandbox_extension_consume(token.encode()) < 0:
+        raise PermissionError("failed to consume sandbox extension")
+
+
+def _poll_once():
+    # The shell channel is blocked while a cell runs, so process one message by hand.
+    kernel = get_ipython().kernel  # noqa: F821
+    result = kernel.do_one_iteration()
+    if asyncio.iscoroutine(result):
+        asyncio.get_event_loop().run_until_complete(result)
+
+
+def request(kind, target=None, timeout=300, magic=None):
+    """Ask the supervisor for a permission (`read`, `write`, `net` or `run`)."""
+    request_id = uuid.uuid4().hex
+    _get_comm().send(
+        {"request_id": request_id, "kind": kind, "target": target, "magic": magic}
+    )
+
+    deadline = time.monotonic() + timeout
+    while request_id not in _responses:
+        if time.monotonic() > deadline:
+            raise TimeoutError(f"no answer for {kind} {target or ''}")
+        _poll_once()
+        time.sleep(0.05)
+
+    response = _responses.pop(request_id)
+    if response.get("error"):
+        raise RuntimeError(response["error"])
+    if not response["granted"]:
+        raise PermissionError(f"{kind} {target or ''} was not granted")
+    if response.get("token"):
+        _consume(response["token"])
+    return True
+
+
+def request_permission(line):
+    """`%request_permission <kind> [target]`: ask for a permission before using it."""
+    kind, _, target = line.strip().partition(" ")
+    if not kind:
+        raise ValueError("usage: %request_permission read|write|net|run [target]")
+    request(kind, target.strip() or None, magic=line.strip())
+
+
+def load_ipython_extension(ipython):
+    ipython.register_magic_function(request_permission, "line", "request_permission")
+
+
+secure_notebook = types.ModuleType("secure_notebook")
+secure_notebook.request = request
+secure_notebook.load_ipython_extension = load_ipython_extension
+sys.modules["secure_notebook"] = secure_notebook
+
+try:
+    load_ipython_extension(get_ipython())  # noqa: F821
+except NameError:
+    pass  # not running under IPython; `%load_ext secure_notebook` registers it later
@@ -0,0 +1,737 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::backend::{sandboxed_command_with, Backend, DEFAULT_BACKENDS};
+use crate::diagnostics::parse_launch_error;
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::minify_profile;
+use crate::pty::Pty;
+use crate::resources::ResourceLimits;
+use crate::tokens::ServerToken;
+
+/// Configuration for launching a sandboxed Jupyter server.
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// Program started under the sandbox.
+    pub program: PathBuf,
+    /// Arguments passed to the program.
+    pub args: Vec<String>,
+    /// Time given to the server to start up before the session is handed out.
+    pub startup_delay: Duration,
+    /// Sandbox backends to try, in order of preference.
+    pub backends: Vec<Backend>,
+    /// Extra environment variables, inherited by the kernels (e.g. proxy settings).
+    pub env: Vec<(String, String)>,
+    /// CPU, memory and process limits, inherited by the kernels.
+    pub limits: ResourceLimits,
+    /// Base URL the server listens on, used to ask it to shut down.
+    pub url: String,
+    /// Time the server gets to exit after each shutdown step before escalating.
+    pub shutdown_timeout: Duration,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            program: PathBuf::from("jupyter-server"),
+            args: vec!["--no-browser".to_string()],
+            startup_delay: Duration::from_secs(5),
+            backends: DEFAULT_BACKENDS.to_vec(),
+            env: Vec::new(),
+            limits: ResourceLimits::default(),
+            url: "http://localhost:8888".to_string(),
+            shutdown_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Environment variable the Jupyter server reads the token clients must present from.
+///
+/// Unlike an argument, it can't be read by other users with `ps`.
+pub const TOKEN_ENV: &str = "JUPYTER_TOKEN";
+
+/// Jupyter server option setting the token, still honoured when passed explicitly.
+const TOKEN_ARG: &str = "--IdentityProvider.token";
+
+impl SessionConfig {
+    /// Whether the environment or the arguments set a token, even an empty one.
+    fn sets_token(&self) -> bool {
+        self.env.iter().any(|(name, _)| name == TOKEN_ENV)
+            || self
+                .args
+                .iter()
+                .any(|arg| arg == TOKEN_ARG || arg.starts_with(&format!("{TOKEN_ARG}=")))
+    }
+
+    /// Pass `token` to the server through [`TOKEN_ENV`], replacing any previous one.
+    pub fn set_token(&mut self, token: &str) {
+        self.env.retain(|(name, _)| name != TOKEN_ENV);
+        self.env.push((TOKEN_ENV.to_string(), token.to_string()));
+    }
+
+    /// Token set through [`TOKEN_ENV`], or passed with `--IdentityProvider.token`, if any.
+    pub fn token(&self) -> Option<&str> {
+        if let Some((_, token)) = self.env.iter().find(|(name, _)| name == TOKEN_ENV) {
+            return Some(token.as_str()).filter(|token| !token.is_empty());
+        }
+        let mut args = self.args.iter();
+        while let Some(arg) = args.next() {
+            if arg == TOKEN_ARG {
+                return args
+                    .next()
+                    .map(String::as_str)
+                    .filter(|token| !token.is_empty());
+            }
+            if let Some(token) = arg.strip_prefix(&format!("{TOKEN_ARG}=")) {
+                return Some(token).filter(|token| !token.is_empty());
+            }
+        }
+        None
+    }
+}
+
+/// Build a command that runs `program` under `sandbox-exec` with the given profile.
+pub fn sandboxed_command(profile: &str, program: &Path) -> Command {
+    let mut command = Command::new("sandbox-exec");
+    command.arg("-p").arg(minify_profile(profile)).arg(program);
+    command
+}
+
+/// A Jupyter server running under a sandbox profile.
+#[derive(Debug)]
+pub struct JupyterSession {
+    child: Child,
+    profile: String,
+    config: SessionConfig,
+}
+
+impl JupyterSession {
+    /// Spawn a Jupyter server under the given profile.
+    ///
+    /// Unless `config` sets a token, a random one is generated; read it with
+    /// [`Self::token`]. Pass an empty token explicitly to run without authentication.
+    pub fn spawn(profile: &str, mut config: SessionConfig) -> Result<Self> {
+        if !config.sets_token() {
+            let token = ServerToken::generate()?;
+            config.set_token(token.secret());
+        }
+        let child = spawn_server(profile, &config)?;
+        Ok(Self {
+            child,
+            profile: profile.to_string(),
+            config,
+        })
+    }
+
+    /// Profile the server is currently running under.
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
+
+    /// Configuration the session was started with.
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    /// Token clients must present to the server, if it requires one.
+    pub fn token(&self) -> Option<&str> {
+        self.config.token()
+    }
+
+    /// Process id of the sandboxed server.
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Whether the sandboxed server is still running.
+    pub fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    /// Exit status of the server, if it has exited.
+    pub fn try_wait(&mut self) -> Result<Option<ExitStatus>> {
+        self.child
+            .try_wait()
+            .io_context(|| "Failed to check Jupyter server")
+    }
+
+    /// Stop the server and start it again under a new profile.
+    ///
+    /// Kernels inherit the sandbox of the server that started them, so changing the
+    /// profile means restarting the whole server.
+    pub fn restart(&mut self, profile: &str) -> Result<()> {
+        self.stop()?;
+        self.child = spawn_server(profile, &self.config)?;
+        self.profile = profile.to_string();
+        Ok(())
+    }
+
+    /// Like [`Self::restart`], but shutting the server down through [`Self::shutdown`]
+    /// first, so kernels get the chance to exit cleanly.
+    pub fn graceful_restart(&mut self, profile: &str) -> Result<()> {
+        self.shutdown()?;
+        self.child = spawn_server(profile, &self.config)?;
+        self.profile = profile.to_string();
+        Ok(())
+    }
+
+    /// Shut the server and its kernels down, escalating until they are gone.
+    ///
+    /// The server is first asked to shut down through `/api/shutdown`. If it is still
+    /// running after `shutdown_timeout`, its process group and kernels get `SIGTERM`,
+    /// then `SIGKILL` after another `shutdown_timeout`.
+    pub fn shutdown(&mut self) -> Result<()> {
+        if !self.is_running() {
+            return self.stop();
+        }
+
+        let mut request = ureq::post(&format!("{}/api/shutdown", self.config.url))
+            .timeout(self.config.shutdown_timeout);
+        if let Some(token) = self.config.token() {
+            request = request.set("Authorization", &format!("token {token}"));
+        }
+        // The server may be wedged or not listening yet; signals take over below.
+        let _ = request.call();
+
+        for signal in ["TERM", "KILL"] {
+            if self.wait_timeout(self.config.shutdown_timeout)? {
+                return Ok(());
+            }
+            signal_tree(self.pid(), signal);
+        }
+        self.child
+            .wait()
+            .io_context(|| "Failed to wait for Jupyter server")?;
+        Ok(())
+    }
+
+    /// Start a kernel on the server and return its id.
+    ///
+    /// The server starts it, so the kernel runs under the session's profile. Connect to
+    /// it through [`Self::kernel_connection_file`], never through whichever kernel
+    /// happens to have started last on the machine.
+    pub fn start_kernel(&self) -> Result<String> {
+        start_kernel(&self.config.url, self.config.token())
+    }
+
+    /// Connection file of the kernel `id` started by this server, in the runtime
+    /// directory the server was given, or [`runtime_dir`].
+    pub fn kernel_connection_file(&self, id: &str) -> Result<PathBuf> {
+        let dir = self
+            .config
+            .env
+            .iter()
+            .find(|(name, _)| name == "JUPYTER_RUNTIME_DIR")
+            .map(|(_, dir)| PathBuf::from(dir))
+            .or_else(runtime_dir);
+        kernel_connection_file(dir, id)
+    }
+
+    /// Wait up to `timeout` for the server to exit, returning whether it did.
+    fn wait_timeout(&mut self, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self
+                .child
+                .try_wait()
+                .io_context(|| "Failed to check Jupyter server")?
+                .is_some()
+            {
+                return Ok(true);
+            }
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+            std::thread::sleep(SHUTDOWN_POLL);
+        }
+    }
+
+    /// Like [`Self::restart`], also switching to a new configuration, e.g. to rotate the
+    /// token.
+    pub fn restart_with(&mut self, profile: &str, config: SessionConfig) -> Result<()> {
+        self.stop()?;
+        self.child = spawn_server(profile, &config)?;
+        self.profile = profile.to_string();
+        self.config = config;
+        Ok(())
+    }
+
+    /// Kill the server, its kernels and everything they started, and wait for the
+    /// server to exit. Unlike [`Self::shutdown`], nothing gets to exit cleanly.
+    pub fn kill_tree(&mut self) -> Result<()> {
+        kill_tree(self.pid());
+        self.child
+            .wait()
+            .io_context(|| "Failed to wait for Jupyter server")?;
+        Ok(())
+    }
+
+    /// Kill the server, its kernels and everything they started, and wait for the
+    /// server to exit.
+    ///
+    /// Kernels run in sessions of their own, so killing only the server would leave them
+    /// running under the profile being replaced.
+    pub fn stop(&mut self) -> Result<()> {
+        if self.is_running() {
+            kill_tree(self.pid());
+        }
+        self.child
+            .wait()
+            .io_context(|| "Failed to wait for Jupyter server")?;
+        Ok(())
+    }
+}
+
+impl Drop for JupyterSession {
+    /// Kill whatever is left so dropped sessions do not leak servers or kernels.
+    fn drop(&mut self) {
+        if self.is_running() {
+            kill_tree(self.pid());
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// A sandboxed process that is killed, with everything it started, when dropped.
+///
+/// Temporary files and directories registered with [`Self::remove_on_drop`], such as
+/// profiles or scratch directories, are removed after the process is gone, so a panic
+/// or early return in the host never leaves an orphaned server or its leftovers.
+#[derive(Debug)]
+pub struct SandboxGuard {
+    child: Child,
+    cleanup: Vec<PathBuf>,
+}
+
+impl SandboxGuard {
+    /// Spawn `command` in its own process group and guard it.
+    pub fn spawn(mut command: Command) -> Result<Self> {
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        Self::guard(command)
+    }
+
+    /// Spawn `command` on `pty` and guard it, returning the host side of the terminal.
+    ///
+    /// The program leads a session of its own, which also makes it a process group
+    /// leader, as [`Self::spawn`] does.
+    pub fn spawn_in_pty(mut command: Command, pty: Pty) -> Result<(Self, File)> {
+        pty.attach(&mut command)?;
+        let guard = Self::guard(command)?;
+        Ok((guard, pty.into_master()))
+    }
+
+    fn guard(mut command: Command) -> Result<Self> {
+        let child = command
+            .spawn()
+            .map_err(|source| SecureNotebookError::SpawnFailed {
+                program: PathBuf::from(command.get_program()),
+                source,
+            })?;
+        Ok(Self {
+            child,
+            cleanup: Vec::new(),
+        })
+    }
+
+    /// Remove `path`, a file or a directory, once the process is gone.
+    pub fn remove_on_drop(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cleanup.push(path.into());
+        self
+    }
+
+    /// Process id of the sandboxed process.
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Exit status of the process, if it has exited.
+    pub fn try_wait(&mut self) -> Result<Option<ExitStatus>> {
+        self.child
+            .try_wait()
+            .io_context(|| "Failed to check sandboxed process")
+    }
+
+    /// Wait for the process to exit on its own.
+    pub fn wait(&mut self) -> Result<ExitStatus> {
+        self.child
+            .wait()
+            .io_context(|| "Failed to wait for sandboxed process")
+    }
+
+    /// Kill the process and everything it started, and wait for it to exit.
+    pub fn kill_tree(&mut self) -> Result<ExitStatus> {
+        kill_tree(self.pid());
+        self.wait()
+    }
+}
+
+impl Drop for SandboxGuard {
+    fn drop(&mut self) {
+        if matches!(self.child.try_wait(), Ok(None)) {
+            kill_tree(self.pid());
+            let _ = self.child.wait();
+        }
+        for path in &self.cleanup {
+            let _ = if path.is_dir() {
+                std::fs::remove_dir_all(path)
+            } else {
+                std::fs::remove_file(path)
+            };
+        }
+    }
+}
+
+/// Run `program` with `args` under `profile` with the first available backend of
+/// `backends`, killing it when the returned guard is dropped.
+pub fn spawn_sandboxed(
+    profile: &str,
+    program: &Path,
+    args: &[String],
+    backends: &[Backend],
+) -> Result<SandboxGuard> {
+    let mut command = sandboxed_command_with(profile, program, backends)?;
+    command.args(args);
+    SandboxGuard::spawn(command)
+}
+
+/// Like [`spawn_sandboxed`], on a new pseudo-terminal sized like the host's, for
+/// interactive programs. Returns the host side of the terminal, see
+/// [`crate::pty::interact`].
+///
+/// The profile must allow the terminal device, see [`crate::presets::terminal`].
+pub fn spawn_sandboxed_in_pty(
+    profile: &str,
+    program: &Path,
+    args: &[String],
+    backends: &[Backend],
+) -> Result<(SandboxGuard, File)> {
+    let mut command = sandboxed_command_with(profile, program, backends)?;
+    command.args(args);
+    let pty = Pty::open()?;
+    pty.match_host_size()?;
+    SandboxGuard::spawn_in_pty(command, pty)
+}
+
+/// Where Jupyter keeps connection files: `JUPYTER_RUNTIME_DIR`, or the platform's
+/// default under the user's data directory.
+pub fn runtime_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("JUPYTER_RUNTIME_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    let home = PathBuf::from(std::env::var_os("HOME")?);
+    let data = if cfg!(target_os = "macos") {
+        home.join("Library/Jupyter")
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map_or_else(|| home.join(".local/share"), PathBuf::from)
+            .join("jupyter")
+    };
+    Some(data.join("runtime"))
+}
+
+/// How long [`start_kernel`] waits for the server to start a kernel.
+const KERNEL_START_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Ask the server at `url` to start a kernel, returning its id.
+pub(crate) fn start_kernel(url: &str, token: Option<&str>) -> Result<String> {
+    let mut request = ureq::post(&format!("{url}/api/kernels")).timeout(KERNEL_START_TIMEOUT);
+    if let Some(token) = token {
+        request = request.set("Authorization", &format!("token {token}"));
+    }
+    let body = request
+        .send_string("{}")?
+        .into_string()
+        .io_context(|| "Failed to read the started kernel")?;
+    let kernel: serde_json::Value = serde_json::from_str(&body)?;
+    kernel
+        .get("id")
+        .and_then(|id| id.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| {
+            SecureNotebookError::InvalidState(
+                "Jupyter server did not return a kernel id".to_string(),
+            )
+        })
+}
+
+/// Connection file of the kernel `id` in the runtime directory `dir`.
+pub(crate) fn kernel_connection_file(dir: Option<PathBuf>, id: &str) -> Result<PathBuf> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(SecureNotebookError::InvalidState(format!(
+            "Invalid kernel id: {id:?}"
+        )));
+    }
+    let dir = dir.ok_or_else(|| {
+        SecureNotebookError::InvalidState("Can't find the Jupyter runtime directory".to_string())
+    })?;
+    let path = dir.join(format!("kernel-{id}.json"));
+    if !path.is_file() {
+        return Err(SecureNotebookError::InvalidState(format!(
+            "Kernel {id} has no connection file in {}",
+            dir.display()
+        )));
+    }
+    Ok(path)
+}
+
+/// How often [`JupyterSession::shutdown`] checks whether the server has exited.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(50);
+
+/// Send `signal` (e.g. `"TERM"`) to `pid`, every process descended from it, and their
+/// process groups.
+///
+/// Kernels are started in their own sessions and fork helpers such as multiprocessing
+/// workers, so neither the root's group nor its direct children reach all of them.
+/// The tree is listed before anything is signalled, so a dying parent's children are
+/// still found.
+pub fn signal_tree(pid: u32, signal: &str) {
+    let mut targets = Vec::new();
+    for pid in std::iter::once(pid).chain(descendants(pid)) {
+        targets.extend([pid.to_string(), format!("-{pid}")]);
+    }
+    // Fails for pids that aren't group leaders; the others are still signalled.
+    let _ = Command::new("kill")
+        .arg(format!("-{signal}"))
+        .arg("--")
+        .args(targets)
+        .stderr(Stdio::null())
+        .status();
+}
+
+/// `SIGKILL` `pid` and everything descended from it, see [`signal_tree`].
+pub fn kill_tree(pid: u32) {
+    signal_tree(pid, "KILL");
+}
+
+/// Processes descended from `pid`, parents before their children.
+fn descendants(pid: u32) -> Vec<u32> {
+    let Ok(output) = Command::new("pgrep")
+        .args(["-P", &pid.to_string()])
+        .output()
+    else {
+        return Vec::new();
+    };
+    let mut found = Vec::new();
+    for child in String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+    {
+        found.push(child);
+        found.extend(descendants(child));
+    }
+    found
+}
+
+/// Most stderr kept around for diagnosing a failed launch.
+const MAX_CAPTURED_STDERR: usize = 64 * 1024;
+
+/// Helper function to spawn the server and wait for it to start up.
+///
+/// If the process exits during startup, its stderr is parsed into a structured error.
+fn spawn_server(profile: &str, config: &SessionConfig) -> Result<Child> {
+    let mut command = sandboxed_command_with(profile, &config.program, &config.backends)?;
+    config.limits.apply(&mut command);
+    // Own process group, so shutdown can signal everything the server started.
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = command
+        .args(&config.args)
+        .envs(config.env.iter().map(|(name, value)| (name, value)))
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|source| SecureNotebookError::SpawnFailed {
+            program: config.program.clone(),
+            source,
+        })?;
+    let stderr = capture_stderr(&mut child);
+
+    // Give the server some time to start up
+    std::thread::sleep(config.startup_delay);
+
+    if let Some(status) = child
+        .try_wait()
+        .io_context(|| "Failed to check Jupyter server")?
+    {
+        let stderr = String::from_utf8_lossy(&stderr.lock().expect("stderr poisoned")).into_owned();
+        return Err(parse_launch_error(&stderr, profile, status.code()));
+    }
+
+    Ok(child)
+}
+
+/// Drain the child's stderr in the background, keeping the beginning of it.
+///
+/// The pipe has to be drained for the whole lifetime of the server, or it blocks
+/// once the pipe buffer is full.
+fn capture_stderr(child: &mut Child) -> Arc<Mutex<Vec<u8>>> {
+    let captured = Arc::new(Mutex::new(Vec::new()));
+    if let Some(mut stderr) = child.stderr.take() {
+        let captured = Arc::clone(&captured);
+        std::thread::spawn(move || {
+            let mut buffer = [0; 4096];
+            while let Ok(read @ 1..) = stderr.read(&mut buffer) {
+                let mut captured = captured.lock().expect("stderr poisoned");
+                let room = MAX_CAPTURED_STDERR.saturating_sub(captured.len());
+                captured.extend_from_slice(&buffer[..read.min(room)]);
+            }
+        });
+    }
+    captured
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sandboxed_command() {
+        let profile = "(version 1)\n; comment\n(deny default)\n";
+        let command = sandboxed_command(profile, Path::new("jupyter-server"));
+
+        assert_eq!(command.get_program(), "sandbox-exec");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["-p", "(version 1) (deny default)", "jupyter-server"]);
+    }
+
+    #[test]
+    fn test_sandbox_guard_cleans_up() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let profile = dir.path().join("profile.sb");
+        std::fs::write(&profile, "(version 1)\n")?;
+        let mut command = Command::new("sleep");
+        command.arg("30");
+        let guard = SandboxGuard::spawn(command)?.remove_on_drop(&profile);
+        let pid = guard.pid().to_string();
+
+        drop(guard);
+        assert!(!profile.exists());
+        let alive = Command::new("kill").args(["-0", &pid]).status()?;
+        assert!(!alive.success());
+        Ok(())
+    }
+
+    #[test]
+    fn test_kill_tree_reaches_grandchildren() -> Result<()> {
+        // The inner shell runs two commands, so it forks `sleep` rather than exec'ing it.
+        let mut command = Command::new("sh");
+        command.args(["-c", "sh -c 'sleep 30; true' & wait"]);
+        let mut guard = SandboxGuard::spawn(command)?;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while descendants(guard.pid()).len() < 2 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let tree = descendants(guard.pid());
+        assert_eq!(tree.len(), 2);
+
+        assert!(!guard.kill_tree()?.success());
+        assert!(!is_alive(tree[1])?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_restart_kills_old_kernels() -> Result<()> {
+        // Stands in for a server whose kernel forked a worker.
+        let mut command = Command::new("sh");
+        command.args(["-c", "sh -c 'sleep 30; true' & wait"]);
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let mut session = JupyterSession {
+            child: command.spawn()?,
+            profile: String::new(),
+            config: SessionConfig {
+                program: PathBuf::from("sleep"),
+                args: vec!["30".to_string()],
+                startup_delay: Duration::ZERO,
+                ..SessionConfig::default()
+            },
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while descendants(session.pid()).len() < 2 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let tree = descendants(session.pid());
+        assert_eq!(tree.len(), 2);
+
+        // Fails without a sandbox backend, but only after the old server was stopped.
+        let _ = session.restart("(version 1)\n(allow default)\n");
+        for pid in tree {
+            assert!(!is_alive(pid)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_kernel_must_belong_to_the_session() -> Result<()> {
+        let runtime = tempfile::tempdir().unwrap();
+        // A kernel another session, or an unsandboxed Jupyter, started last.
+        std::fs::write(runtime.path().join("kernel-other.json"), "{}")?;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+        drop(listener);
+        let session = JupyterSession {
+            child: Command::new("sleep").arg("30").spawn()?,
+            profile: String::new(),
+            config: SessionConfig {
+                env: vec![(
+                    "JUPYTER_RUNTIME_DIR".to_string(),
+                    runtime.path().display().to_string(),
+                )],
+                url,
+                ..SessionConfig::default()
+            },
+        };
+
+        // No server answering means no kernel, rather than falling back to another one.
+        assert!(session.start_kernel().is_err());
+        assert!(session.kernel_connection_file("mine").is_err());
+        assert!(session.kernel_connection_file("../other").is_err());
+
+        std::fs::write(runtime.path().join("kernel-mine.json"), "{}")?;
+        assert_eq!(
+            session.kernel_connection_file("mine")?,
+            runtime.path().join("kernel-mine.json")
+        );
+        Ok(())
+    }
+
+    /// Whether `pid` is running: not gone, nor a zombie waiting for init to reap it.
+    fn is_alive(pid: u32) -> Result<bool> {
+        let output = Command::new("ps")
+            .args(["-o", "stat=", "-p", &pid.to_string()])
+            .output()?;
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .starts_with(['S', 'R']))
+    }
+
+    #[test]
+    fn test_token() {
+        let mut config = SessionConfig::default();
+        assert_eq!(config.token(), None);
+        assert!(!config.sets_token());
+
+        config.set_token("secret");
+        config.set_token("rotated");
+        assert_eq!(config.token(), Some("rotated"));
+        assert_eq!(config.env, [(TOKEN_ENV.to_string(), "rotated".to_string())]);
+        assert!(!config.args.iter().any(|arg| arg.contains("rotated")));
+
+        config.set_token("");
+        assert_eq!(config.token(), None);
+        assert!(config.sets_token());
+
+        let mut config = SessionConfig {
+            args: vec!["--IdentityProvider.token=secret".to_string()],
+            ..SessionConfig::default()
+        };
+        assert_eq!(config.token(), Some("secret"));
+
+        config.args = vec![TOKEN_ARG.to_string(), String::new()];
+        assert_eq!(config.token(), None);
+        assert!(config.sets_token());
+    }
+}
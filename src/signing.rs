@@ -0,0 +1,129 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::path::{Path, PathBuf};
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::policy::{is_toml, parse_policy};
+use crate::Permissions;
+
+/// Path of the detached signature for `path` (`policy.toml` -> `policy.toml.sig`).
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut signature = path.as_os_str().to_owned();
+    signature.push(".sig");
+    PathBuf::from(signature)
+}
+
+/// Sign `data`, returning the hex encoded signature.
+pub fn sign(data: &[u8], key: &SigningKey) -> String {
+    encode_hex(&key.sign(data).to_bytes())
+}
+
+/// Verify a hex encoded signature over `data`.
+pub fn verify(data: &[u8], signature: &str, key: &VerifyingKey) -> Result<()> {
+    let bytes = decode_hex(signature.trim())?;
+    let signature = Signature::from_slice(&bytes)
+        .map_err(|_| SecureNotebookError::Signature("Malformed signature".to_string()))?;
+    key.verify(data, &signature).map_err(|_| {
+        SecureNotebookError::Signature(
+            "Signature does not match, the content was tampered with or signed by another key"
+                .to_string(),
+        )
+    })
+}
+
+/// Sign a file, writing the detached signature next to it.
+pub fn sign_file(path: &Path, key: &SigningKey) -> Result<PathBuf> {
+    let data = std::fs::read(path).io_context(|| format!("Failed to read {}", path.display()))?;
+    let signature_path = signature_path(path);
+    std::fs::write(&signature_path, sign(&data, key))
+        .io_context(|| format!("Failed to write {}", signature_path.display()))?;
+    Ok(signature_path)
+}
+
+/// Verify a file against its detached signature.
+pub fn verify_file(path: &Path, key: &VerifyingKey) -> Result<Vec<u8>> {
+    let data = std::fs::read(path).io_context(|| format!("Failed to read {}", path.display()))?;
+    let signature_path = signature_path(path);
+    let signature = std::fs::read_to_string(&signature_path).map_err(|_| {
+        SecureNotebookError::Signature(format!(
+            "Refusing to use unsigned file {}: missing {}",
+            path.display(),
+            signature_path.display()
+        ))
+    })?;
+    verify(&data, &signature, key)?;
+    Ok(data)
+}
+
+/// Load a policy file, refusing to return it unless its signature verifies.
+pub fn load_verified(path: &Path, key: &VerifyingKey) -> Result<Permissions> {
+    let data = verify_file(path, key)?;
+    let contents = String::from_utf8(data)
+        .map_err(|_| SecureNotebookError::InvalidPolicy("Policy is not valid UTF-8".to_string()))?;
+    parse_policy(&contents, is_toml(path))
+}
+
+/// Sign a generated profile.
+pub fn sign_profile(profile: &str, key: &SigningKey) -> String {
+    sign(profile.as_bytes(), key)
+}
+
+/// Verify a generated profile before handing it to `sandbox-exec`.
+pub fn verify_profile(profile: &str, signature: &str, key: &VerifyingKey) -> Result<()> {
+    verify(profile.as_bytes(), signature, key)
+}
+
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+pub(crate) fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err(SecureNotebookError::Signature(
+            "Malformed hex signature".to_string(),
+        ));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| SecureNotebookError::Signature("Malformed hex signature".to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_roundtrip() {
+        let bytes = [0u8, 1, 127, 128, 255];
+        assert_eq!(encode_hex(&bytes), "00017f80ff");
+        assert_eq!(decode_hex("00017f80ff").unwrap(), bytes);
+        assert!(decode_hex("0").is_err());
+        assert!(decode_hex("zz").is_err());
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let profile = "(version 1)\n(deny default)\n";
+        let signature = sign_profile(profile, &key);
+
+        assert!(verify_profile(profile, &signature, &key.verifying_key()).is_ok());
+        assert!(verify_profile(
+            "(version 1)\n(allow default)\n",
+            &signature,
+            &key.verifying_key()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_signature_path() {
+        assert_eq!(
+            signature_path(Path::new("/etc/policy.toml")),
+            PathBuf::from("/etc/policy.toml.sig")
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Golden-file tests for generated profiles.
+//!
+//! ```ignore
+//! #[test]
+//! fn notebook_profile() {
+//!     let permissions = my_app::policy();
+//!     secure_notebook::assert_profile_snapshot!(permissions, "notebook");
+//! }
+//! ```
+//!
+//! Snapshots live in `tests/snapshots/<name>.sb` of the calling crate. Missing snapshots
+//! are written on first run; set `UPDATE_SNAPSHOTS=1` to rewrite changed ones.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::{generate_profile, Permissions};
+
+/// Environment variable that makes [`check_snapshot`] rewrite snapshots.
+pub const UPDATE_SNAPSHOTS: &str = "UPDATE_SNAPSHOTS";
+
+/// Profile for the canonicalized permissions, so reordering entries does not change it.
+///
+/// Paths are still rendered as `subpath` or `literal` depending on whether they are
+/// directories on the host.
+pub fn snapshot_profile(template: &str, permissions: &Permissions) -> Result<String> {
+    generate_profile(template, &permissions.canonicalized())
+}
+
+/// Compare `actual` with the snapshot `dir/name.sb`, writing it if it is missing or
+/// [`UPDATE_SNAPSHOTS`] is set.
+///
+/// A mismatch is an [`SecureNotebookError::InvalidState`] holding a line diff.
+pub fn check_snapshot(dir: &Path, name: &str, actual: &str) -> Result<()> {
+    let path = dir.join(format!("{name}.sb"));
+    let update = std::env::var_os(UPDATE_SNAPSHOTS).is_some_and(|value| value != "0");
+    match std::fs::read_to_string(&path) {
+        Ok(expected) if expected == actual => Ok(()),
+        Ok(expected) if !update => Err(SecureNotebookError::InvalidState(format!(
+            "Profile snapshot {} changed (rerun with {UPDATE_SNAPSHOTS}=1 to accept):\n{}",
+            path.display(),
+            line_diff(&expected, actual)
+        ))),
+        _ => {
+            std::fs::create_dir_all(dir)
+                .io_context(|| format!("Failed to create {}", dir.display()))?;
+            std::fs::write(&path, actual)
+                .io_context(|| format!("Failed to write {}", path.display()))
+        }
+    }
+}
+
+/// Unified-style diff of two texts, `-` for lines only in `old` and `+` for lines only
+/// in `new`.
+pub fn line_diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // longest common subsequence lengths of every pair of suffixes
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        let line = if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+            (' ', old[i - 1])
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            j += 1;
+            ('+', new[j - 1])
+        } else {
+            i += 1;
+            ('-', old[i - 1])
+        };
+        writeln!(diff, "{} {}", line.0, line.1).expect("writing to a String cannot fail");
+    }
+    diff
+}
+
+/// Assert that the profile for `permissions` matches the snapshot `name` under the
+/// calling crate's `tests/snapshots`, on [`crate::DEFAULT_SANDBOX_PROFILE`] unless a
+/// template is given first.
+#[macro_export]
+macro_rules! assert_profile_snapshot {
+    ($permissions:expr, $name:expr) => {
+        $crate::assert_profile_snapshot!($crate::DEFAULT_SANDBOX_PROFILE, $permissions, $name)
+    };
+    ($template:expr, $permissions:expr, $name:expr) => {{
+        let profile = $crate::snapshot::snapshot_profile($template, &$permissions)
+            .expect("failed to generate profile");
+        let dir = ::std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+        if let Err(error) = $crate::snapshot::check_snapshot(&dir, $name, &profile) {
+            panic!("{error}");
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_line_diff() {
+        assert_eq!(line_diff("a\nb\nc\n", "a\nc\nd\n"), "  a\n- b\n  c\n+ d\n");
+    }
+
+    #[test]
+    fn test_snapshot_is_written_then_checked() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let mut permissions = Permissions::new();
+        permissions.deny_read = vec![PathBuf::from("/b"), PathBuf::from("/a")];
+        let profile = snapshot_profile("(version 1)\n", &permissions)?;
+        assert!(profile.find("/a").unwrap() < profile.find("/b").unwrap());
+
+        check_snapshot(dir.path(), "policy", &profile)?;
+        check_snapshot(dir.path(), "policy", &profile)?;
+        let error = check_snapshot(dir.path(), "policy", "(version 1)\n").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("- (deny file-read* (subpath \"/a\"))"));
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+/// How far the generated profile leans towards security over compatibility.
+///
+/// The level's rules follow the template and precede the permissions, so they override
+/// the template and explicit permissions still win over them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Strictness {
+    /// No metadata reads under home directories, no temp directories and no forking,
+    /// with every denial reported. Suits single kernels more than a full server.
+    Paranoid,
+    /// The template as is.
+    #[default]
+    Standard,
+    /// Metadata reads, temp directories and forking allowed, and denials not reported.
+    Relaxed,
+}
+
+impl Strictness {
+    /// Rules for the level, inserted between the template and the permissions.
+    pub fn rules(self) -> &'static str {
+        match self {
+            Self::Paranoid => {
+                "; strictness: paranoid\n\
+                 (deny default (with report))\n\
+                 (deny file-read-metadata (subpath \"/Users\"))\n\
+                 (deny file-read* file-write* (subpath \"/private/tmp\") (subpath \"/private/var/folders\"))\n\
+                 (deny process-fork)\n"
+            }
+            Self::Standard => "",
+            Self::Relaxed => {
+                "; strictness: relaxed\n\
+                 (deny default (with no-report))\n\
+                 (allow file-read-metadata)\n\
+                 (allow file-read* file-write* (subpath \"/private/tmp\") (subpath \"/private/var/folders\"))\n\
+                 (allow process-fork)\n"
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+
+use crate::error::Result;
+use crate::session::JupyterSession;
+use crate::violations::{Violation, ViolationMonitor};
+
+/// How crashed servers are restarted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// Restarts allowed before giving up; `0` disables restarting.
+    pub max_restarts: u32,
+    /// Delay before the first restart, doubled after each further crash.
+    pub initial_backoff: Duration,
+    /// Upper bound on the delay between restarts.
+    pub max_backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Delay before restart number `attempt`, counting from zero.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .checked_mul(2u32.saturating_pow(attempt))
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+}
+
+/// A crash of the sandboxed server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Crash {
+    /// Exit code, if the server exited normally.
+    pub code: Option<i32>,
+    /// Signal that killed the server, if any.
+    pub signal: Option<i32>,
+    /// Sandbox denials reported since the previous crash or restart.
+    pub violations: Vec<Violation>,
+}
+
+impl Crash {
+    fn new(status: ExitStatus, violations: Vec<Violation>) -> Self {
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal = None;
+
+        Self {
+            code: status.code(),
+            signal,
+            violations,
+        }
+    }
+
+    /// Human-readable explanation, blaming the last sandbox denial if there was one.
+    pub fn reason(&self) -> String {
+        let exit = match (self.code, self.signal) {
+            (_, Some(signal)) => format!("killed by signal {signal}"),
+            (Some(code), None) => format!("exited with status {code}"),
+            (None, None) => "exited".to_string(),
+        };
+        match self.violations.last() {
+            Some(violation) => format!(
+                "{exit} after the sandbox denied {} {}",
+                violation.operation,
+                violation.target.as_deref().unwrap_or("")
+            )
+            .trim_end()
+            .to_string(),
+            None => exit,
+        }
+    }
+}
+
+/// What the supervisor is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SupervisorState {
+    Running,
+    /// The server crashed and will be restarted once the backoff has passed.
+    Restarting {
+        at: Instant,
+    },
+    /// The server crashed more often than the policy allows.
+    GaveUp,
+}
+
+/// Restarts a crashed server under the same profile, with exponential backoff.
+///
+/// Kernels inherit the server's sandbox, so a restart keeps them under the same
+/// profile. Jupyter restarts individual kernels itself; this covers the server going
+/// down, e.g. when a deny kills it during an import.
+#[derive(Debug)]
+pub struct Supervisor {
+    session: JupyterSession,
+    policy: RestartPolicy,
+    monitor: Option<ViolationMonitor>,
+    violations: Vec<Violation>,
+    crashes: Vec<Crash>,
+    state: SupervisorState,
+}
+
+impl Supervisor {
+    /// Supervise `session`. With a `monitor`, crashes carry the denials that preceded them.
+    pub fn new(
+        session: JupyterSession,
+        policy: RestartPolicy,
+        monitor: Option<ViolationMonitor>,
+    ) -> Self {
+        Self {
+            session,
+            policy,
+            monitor,
+            violations: Vec::new(),
+            crashes: Vec::new(),
+            state: SupervisorState::Running,
+        }
+    }
+
+    /// Supervised session.
+    pub fn session(&self) -> &JupyterSession {
+        &self.session
+    }
+
+    /// Current state.
+    pub fn state(&self) -> SupervisorState {
+        self.state
+    }
+
+    /// Every crash so far, oldest first.
+    pub fn crashes(&self) -> &[Crash] {
+        &self.crashes
+    }
+
+    /// Check the server, returning a crash the first time it is seen.
+    ///
+    /// Call this periodically; restarts happen from here once their backoff has passed.
+    pub fn poll(&mut self) -> Result<Option<Crash>> {
+        if let Some(monitor) = &self.monitor {
+            while let Some(violation) = monitor.try_next() {
+                self.violations.push(violation);
+            }
+        }
+
+        match self.state {
+            SupervisorState::GaveUp => Ok(None),
+            SupervisorState::Restarting { at } => {
+                if Instant::now() >= at {
+                    let profile = self.session.profile().to_string();
+                    self.session.restart(&profile)?;
+                    self.violations.clear();
+                    self.state = SupervisorState::Running;
+                }
+                Ok(None)
+            }
+            SupervisorState::Running => {
+                let Some(status) = self.session.try_wait()? else {
+                    return Ok(None);
+                };
+                let crash = Crash::new(status, std::mem::take(&mut self.violations));
+                let attempt = self.crashes.len() as u32;
+                self.state = if attempt < self.policy.max_restarts {
+                    SupervisorState::Restarting {
+                        at: Instant::now() + self.policy.backoff(attempt),
+                    }
+                } else {
+                    SupervisorState::GaveUp
+                };
+                self.crashes.push(crash.clone());
+                Ok(Some(crash))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let policy = RestartPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5),
+            ..RestartPolicy::default()
+        };
+        assert_eq!(policy.backoff(0), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(4));
+        assert_eq!(policy.backoff(3), Duration::from_secs(5));
+        assert_eq!(policy.backoff(40), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_crash_reason_names_violation() {
+        let crash = Crash {
+            code: None,
+            signal: Some(9),
+            violations: vec![Violation {
+                process: "python3".to_string(),
+                pid: 42,
+                operation: "file-read-data".to_string(),
+                target: Some("/etc/hosts".to_string()),
+            }],
+        };
+        assert_eq!(
+            crash.reason(),
+            "killed by signal 9 after the sandbox denied file-read-data /etc/hosts"
+        );
+    }
+}
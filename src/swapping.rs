@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::error::{Result, SecureNotebookError};
+use crate::notebook::{python_string, Notebook};
+use crate::{sbpl_path, Permissions};
+
+/// Prefix of the tag naming the scope a cell runs in, e.g. `scope:network`.
+pub const SCOPE_TAG_PREFIX: &str = "scope:";
+/// File in the scratch directory that carries variables from one kernel to the next.
+pub const CHECKPOINT_FILE: &str = "checkpoint.pkl";
+
+/// Permissions per scope. Cells without a scope tag run under `default`.
+#[derive(Debug, Clone, Default)]
+pub struct ScopedPolicy {
+    pub default: Permissions,
+    pub scopes: BTreeMap<String, Permissions>,
+}
+
+impl ScopedPolicy {
+    pub fn new(default: Permissions) -> Self {
+        Self {
+            default,
+            scopes: BTreeMap::new(),
+        }
+    }
+
+    /// Add a scope cells can opt into with a `scope:<name>` tag.
+    pub fn with_scope(mut self, name: &str, permissions: Permissions) -> Self {
+        self.scopes.insert(name.to_string(), permissions);
+        self
+    }
+
+    /// Permissions of `scope`, the default ones for `None`.
+    pub fn permissions(&self, scope: Option<&str>) -> Result<&Permissions> {
+        match scope {
+            None => Ok(&self.default),
+            Some(name) => self.scopes.get(name).ok_or_else(|| {
+                SecureNotebookError::InvalidPolicy(format!("Unknown permission scope {name:?}"))
+            }),
+        }
+    }
+}
+
+/// Consecutive code cells sharing a scope, run in one kernel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScopeRun {
+    pub scope: Option<String>,
+    pub cells: Vec<String>,
+}
+
+/// Split the code cells of `notebook` into runs of cells sharing a scope.
+pub fn plan_runs(notebook: &Notebook) -> Vec<ScopeRun> {
+    let mut runs: Vec<ScopeRun> = Vec::new();
+    for cell in notebook
+        .cells
+        .iter()
+        .filter(|cell| cell.cell_type == "code")
+    {
+        let scope = cell_scope(&cell.metadata).map(str::to_string);
+        match runs.last_mut() {
+            Some(run) if run.scope == scope => run.cells.push(cell.source.text()),
+            _ => runs.push(ScopeRun {
+                scope,
+                cells: vec![cell.source.text()],
+            }),
+        }
+    }
+    runs
+}
+
+fn cell_scope(metadata: &serde_json::Value) -> Option<&str> {
+    metadata
+        .get("tags")?
+        .as_array()?
+        .iter()
+        .filter_map(|tag| tag.as_str()?.strip_prefix(SCOPE_TAG_PREFIX))
+        .next()
+}
+
+/// Python that pickles the kernel's variables to `path`, with dill when it is installed.
+///
+/// Modules are recorded by name and imported again on restore. Values that cannot be
+/// pickled are skipped and listed on stderr.
+pub fn checkpoint_code(path: &Path) -> Result<String> {
+    Ok(format!(
+        r#"def __sn_checkpoint(path):
+    import sys, types
+    try:
+        import dill as pickle
+    except ImportError:
+        import pickle
+    state, modules, skipped = {{}}, {{}}, []
+    for name, value in list(globals().items()):
+        if name.startswith("_") or name in ("In", "Out", "exit", "quit", "get_ipython"):
+            continue
+        if isinstance(value, types.ModuleType):
+            modules[name] = value.__name__
+            continue
+        try:
+            pickle.dumps(value)
+        except Exception:
+            skipped.append(name)
+            continue
+        state[name] = value
+    with open(path, "wb") as f:
+        pickle.dump({{"modules": modules, "state": state}}, f)
+    if skipped:
+        print("not carried over:", ", ".join(skipped), file=sys.stderr)
+__sn_checkpoint({})
+del __sn_checkpoint
+"#,
+        python_string(sbpl_path(path)?)
+    ))
+}
+
+/// Python that loads variables written by [`checkpoint_code`] into the kernel.
+pub fn restore_code(path: &Path) -> Result<String> {
+    Ok(format!(
+        r#"def __sn_restore(path):
+    import importlib
+    try:
+        import dill as pickle
+    except ImportError:
+        import pickle
+    with open(path, "rb") as f:
+        checkpoint = pickle.load(f)
+    for name, module in checkpoint["modules"].items():
+        globals()[name] = importlib.import_module(module)
+    globals().update(checkpoint["state"])
+__sn_restore({})
+del __sn_restore
+"#,
+        python_string(sbpl_path(path)?)
+    ))
+}
+
+#[cfg(feature = "client")]
+pub use self::client::run_scoped;
+
+#[cfg(feature = "client")]
+mod client {
+    use std::path::Path;
+
+    use super::{checkpoint_code, plan_runs, restore_code, ScopedPolicy, CHECKPOINT_FILE};
+    use crate::error::{Result, SecureNotebookError};
+    use crate::notebook::{CellResult, Notebook, NotebookSession};
+    use crate::session::{JupyterSession, SessionConfig};
+    use crate::{generate_profile, Permissions};
+
+    /// Run `notebook` with each scope's cells in a kernel of their own.
+    ///
+    /// Whenever the scope changes, the kernel's variables are checkpointed to `scratch`,
+    /// the server is shut down, and a new one is started under the next scope's profile
+    /// and restored from the checkpoint. Each scope's cells run in a kernel started by
+    /// its own server, and the next scope only starts once that kernel is gone.
+    /// Execution stops after the first failing cell.
+    pub fn run_scoped(
+        notebook: &Notebook,
+        template: &str,
+        policy: &ScopedPolicy,
+        scratch: &Path,
+        config: SessionConfig,
+    ) -> Result<Vec<CellResult>> {
+        let runs = plan_runs(notebook);
+        // fail on unknown scopes before anything runs
+        for run in &runs {
+            policy.permissions(run.scope.as_deref())?;
+        }
+
+        let checkpoint = scratch.join(CHECKPOINT_FILE);
+        let mut results = Vec::new();
+        for (index, run) in runs.iter().enumerate() {
+            let permissions = with_scratch(policy.permissions(run.scope.as_deref())?, scratch);
+            let profile = generate_profile(template, &permissions)?;
+            let mut server = JupyterSession::spawn(&profile, config.clone())?;
+
+            let kernel = NotebookSession::start(&server);
+            let connection = kernel
+                .as_ref()
+                .ok()
+                .map(|kernel| kernel.connection().clone());
+            let ran = kernel.and_then(|kernel| {
+                if index > 0 {
+                    expect_ok(kernel.run_cell(&restore_code(&checkpoint)?)?, "restore")?;
+                }
+                for code in &run.cells {
+                    let result = kernel.run_cell(code)?;
+                    let failed = !result.is_ok();
+                    results.push(result);
+                    if failed {
+                        return Ok(false);
+                    }
+                }
+                if index + 1 < runs.len() {
+                    expect_ok(
+                        kernel.run_cell(&checkpoint_code(&checkpoint)?)?,
+                        "checkpoint",
+                    )?;
+                }
+                Ok(true)
+            });
+            let stopped = server.shutdown();
+            let completed = ran?;
+            stopped?;
+            if connection.is_some_and(|connection| connection.is_listening()) {
+                return Err(SecureNotebookError::InvalidState(format!(
+                    "Kernel of scope {:?} is still running after its server shut down",
+                    run.scope.as_deref().unwrap_or("default")
+                )));
+            }
+            if !completed {
+                break;
+            }
+        }
+        Ok(results)
+    }
+
+    /// `permissions` with read and write access to the checkpoint directory.
+    fn with_scratch(permissions: &Permissions, scratch: &Path) -> Permissions {
+        let mut permissions = permissions.clone();
+        permissions.allow_read.push(scratch.to_path_buf());
+        permissions.allow_write.push(scratch.to_path_buf());
+        permissions
+    }
+
+    fn expect_ok(result: CellResult, step: &str) -> Result<()> {
+        match result.error {
+            Some(error) => Err(SecureNotebookError::InvalidState(format!(
+                "Failed to {step} kernel state: {}: {}",
+                error.ename, error.evalue
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notebook::NotebookCell;
+    use serde_json::Map;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_plan_runs() {
+        let notebook = Notebook {
+            cells: vec![
+                NotebookCell::code("import pandas as pd", &[]),
+                NotebookCell::code("df = pd.read_csv(URL)", &["scope:network"]),
+                NotebookCell::code("raw = df.copy()", &["scope:network"]),
+                NotebookCell::code("df.describe()", &["parameters"]),
+            ],
+            rest: Map::new(),
+        };
+        let scopes: Vec<_> = plan_runs(&notebook)
+            .into_iter()
+            .map(|run| (run.scope, run.cells.len()))
+            .collect();
+        assert_eq!(
+            scopes,
+            [(None, 1), (Some("network".to_string()), 2), (None, 1)]
+        );
+    }
+
+    #[test]
+    fn test_unknown_scope() {
+        let policy =
+            ScopedPolicy::new(Permissions::new()).with_scope("network", Permissions::new());
+        assert!(policy.permissions(Some("network")).is_ok());
+        assert!(matches!(
+            policy.permissions(Some("gpu")),
+            Err(SecureNotebookError::InvalidPolicy(_))
+        ));
+    }
+
+    #[test]
+    fn test_checkpoint_code_quotes_path() -> Result<()> {
+        let code = checkpoint_code(&PathBuf::from("/tmp/a \"b\"/checkpoint.pkl"))?;
+        assert!(code.contains(r#"__sn_checkpoint("/tmp/a \"b\"/checkpoint.pkl")"#));
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::Permissions;
+
+/// A privacy consent macOS asks for on top of the sandbox.
+///
+/// TCC checks the responsible process, usually the terminal or app that launched
+/// secure-notebook, so a Seatbelt allow alone doesn't grant these locations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TccService {
+    Desktop,
+    Documents,
+    Downloads,
+    ICloudDrive,
+    FullDiskAccess,
+}
+
+impl TccService {
+    /// Name of the consent in System Settings > Privacy & Security.
+    pub fn setting(self) -> &'static str {
+        match self {
+            Self::Desktop => "Files and Folders > Desktop Folder",
+            Self::Documents => "Files and Folders > Documents Folder",
+            Self::Downloads => "Files and Folders > Downloads Folder",
+            Self::ICloudDrive => "Files and Folders > iCloud Drive",
+            Self::FullDiskAccess => "Full Disk Access",
+        }
+    }
+}
+
+/// Locations under `home`, and system-wide, guarded by each service.
+pub fn protected_locations(home: &Path) -> Vec<(TccService, PathBuf)> {
+    let mut locations = vec![
+        (TccService::Desktop, home.join("Desktop")),
+        (TccService::Documents, home.join("Documents")),
+        (TccService::Downloads, home.join("Downloads")),
+        (
+            TccService::ICloudDrive,
+            home.join("Library/Mobile Documents"),
+        ),
+    ];
+    for dir in [
+        "Library/Mail",
+        "Library/Messages",
+        "Library/Safari",
+        "Library/Cookies",
+        "Library/Application Support/com.apple.TCC",
+    ] {
+        locations.push((TccService::FullDiskAccess, home.join(dir)));
+    }
+    locations.push((
+        TccService::FullDiskAccess,
+        PathBuf::from("/Library/Application Support/com.apple.TCC"),
+    ));
+    locations
+}
+
+/// An allowed path that also needs a TCC consent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TccRequirement {
+    pub service: TccService,
+    /// The policy field granting the path, e.g. `allow_read`.
+    pub field: &'static str,
+    /// The allowed path, which may be a protected location or one of its ancestors.
+    pub path: PathBuf,
+    /// The protected location the path reaches.
+    pub location: PathBuf,
+}
+
+impl fmt::Display for TccRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} reaches {}, which also needs the launching app to have {}",
+            self.field,
+            self.path.display(),
+            self.location.display(),
+            self.service.setting()
+        )
+    }
+}
+
+/// Allows in `permissions` reaching a TCC-protected location, either inside it or
+/// covering it from an ancestor such as `home`.
+pub fn tcc_requirements(permissions: &Permissions, home: &Path) -> Vec<TccRequirement> {
+    let locations = protected_locations(home);
+    let mut requirements = Vec::new();
+    for (field, paths) in [
+        ("allow_read", &permissions.allow_read),
+        ("allow_write", &permissions.allow_write),
+        ("allow_run", &permissions.allow_run),
+    ] {
+        for path in paths {
+            for (service, location) in &locations {
+                if path.starts_with(location) || location.starts_with(path) {
+                    requirements.push(TccRequirement {
+                        service: *service,
+                        field,
+                        path: path.clone(),
+                        location: location.clone(),
+                    });
+                }
+            }
+        }
+    }
+    requirements
+}
+
+/// The [`tcc_requirements`] this process hasn't been granted, to warn about before
+/// launching.
+///
+/// Consent is probed by listing each protected location, which TCC refuses with
+/// `EPERM`. Locations that don't exist are skipped.
+#[cfg(target_os = "macos")]
+pub fn preflight(permissions: &Permissions, home: &Path) -> Vec<TccRequirement> {
+    tcc_requirements(permissions, home)
+        .into_iter()
+        .filter(|requirement| {
+            std::fs::read_dir(&requirement.location)
+                .is_err_and(|error| error.kind() == std::io::ErrorKind::PermissionDenied)
+        })
+        .collect()
+}
+
+/// Without TCC there is nothing to warn about.
+#[cfg(not(target_os = "macos"))]
+pub fn preflight(_permissions: &Permissions, _home: &Path) -> Vec<TccRequirement> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tcc_requirements() {
+        let home = Path::new("/Users/me");
+        let permissions = Permissions {
+            allow_read: vec![home.to_path_buf()],
+            allow_write: vec![home.join("Documents/thesis"), home.join("dev")],
+            ..Permissions::default()
+        };
+        let requirements = tcc_requirements(&permissions, home);
+
+        let read: Vec<TccService> = requirements
+            .iter()
+            .filter(|requirement| requirement.field == "allow_read")
+            .map(|requirement| requirement.service)
+            .collect();
+        assert!(read.contains(&TccService::Desktop));
+        assert!(read.contains(&TccService::FullDiskAccess));
+
+        let write: Vec<&TccRequirement> = requirements
+            .iter()
+            .filter(|requirement| requirement.field == "allow_write")
+            .collect();
+        assert_eq!(write.len(), 1);
+        assert_eq!(write[0].service, TccService::Documents);
+        assert_eq!(write[0].location, home.join("Documents"));
+    }
+}
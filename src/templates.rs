@@ -1,3 +1,91 @@
+use std::path::PathBuf;
+
+use crate::error::{Result, SecureNotebookError};
+use crate::{generate_profile, Permissions, DEFAULT_SANDBOX_PROFILE};
+
+/// Base profile for IRkernel, reading R.framework and Homebrew's R.
+pub const IRKERNEL: &str = include_str!("irkernel.sb");
+
+/// Base profile for IJulia, reading Julia from /Applications or Homebrew and allowing JIT.
+pub const IJULIA: &str = include_str!("ijulia.sb");
+
+/// Language a kernel runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KernelLanguage {
+    Python,
+    R,
+    Julia,
+}
+
+impl KernelLanguage {
+    /// Language for a kernelspec language (`python`, `R`, `julia`) or kernelspec name
+    /// (`python3`, `ir`, `julia-1.10`).
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().to_ascii_lowercase();
+        if name.starts_with("python") {
+            Some(Self::Python)
+        } else if name == "r" || name == "ir" {
+            Some(Self::R)
+        } else if name.starts_with("julia") {
+            Some(Self::Julia)
+        } else {
+            None
+        }
+    }
+}
+
+/// Base template and default exec allowlist for one kernel language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KernelTemplate {
+    pub language: KernelLanguage,
+    pub template: &'static str,
+    /// Interpreter binaries at their usual install locations.
+    pub allow_run: Vec<PathBuf>,
+}
+
+impl KernelTemplate {
+    /// Generate the profile for `permissions` plus the default exec allowlist.
+    pub fn generate_profile(&self, permissions: &Permissions) -> Result<String> {
+        let mut permissions = permissions.clone();
+        permissions.allow_run.extend_from_slice(&self.allow_run);
+        generate_profile(self.template, &permissions)
+    }
+}
+
+/// Template for a kernel, by kernelspec language or name.
+pub fn for_kernel(language_or_kernelspec: &str) -> Result<KernelTemplate> {
+    let language = KernelLanguage::from_name(language_or_kernelspec).ok_or_else(|| {
+        SecureNotebookError::Unsupported(format!(
+            "No template for kernel {language_or_kernelspec:?}"
+        ))
+    })?;
+    let (template, binaries): (_, &[&str]) = match language {
+        KernelLanguage::Python => (
+            DEFAULT_SANDBOX_PROFILE,
+            &[
+                "/usr/bin/python3",
+                "/opt/homebrew/bin/python3",
+                "/usr/local/bin/python3",
+            ],
+        ),
+        KernelLanguage::R => (
+            IRKERNEL,
+            &[
+                "/Library/Frameworks/R.framework/Resources/bin/R",
+                "/Library/Frameworks/R.framework/Resources/bin/exec/R",
+                "/opt/homebrew/bin/R",
+                "/bin/sh",
+            ],
+        ),
+        KernelLanguage::Julia => (IJULIA, &["/opt/homebrew/bin/julia", "/usr/local/bin/julia"]),
+    };
+    Ok(KernelTemplate {
+        language,
+        template,
+        allow_run: binaries.iter().map(PathBuf::from).collect(),
+    })
+}
+
 pub const ALL_ACCESS: &str = "
 (version 1)
 ; Allow everything by default
@@ -36,3 +124,42 @@ pub const NO_ACCESS: &str = "
 // )
 // ' jupyter-server
 // ";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_kernel() -> Result<()> {
+        assert_eq!(for_kernel("python3")?.template, DEFAULT_SANDBOX_PROFILE);
+        assert_eq!(for_kernel("R")?.language, KernelLanguage::R);
+        let julia = for_kernel("julia-1.10")?;
+        assert_eq!(julia.template, IJULIA);
+        assert!(julia
+            .generate_profile(&Permissions::new())?
+            .contains("\"/opt/homebrew/bin/julia\""));
+        assert!(for_kernel("haskell").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_templates_hardening() -> Result<()> {
+        use crate::evaluator::{evaluate_profile, Action};
+
+        let index = std::path::Path::new("/System/Volumes/Data/.Spotlight-V100/store.db");
+        for template in [DEFAULT_SANDBOX_PROFILE, IRKERNEL, IJULIA] {
+            assert!(template.contains("(global-name \"com.apple.metadata.mds\")"));
+            for operation in ["nvram-set", "file-mount", "system-kext-load"] {
+                assert_eq!(
+                    evaluate_profile(template, operation, None)?,
+                    Some(Action::Deny)
+                );
+            }
+            assert_eq!(
+                evaluate_profile(template, "file-read-metadata", Some(index))?,
+                Some(Action::Deny)
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+//! Generators and assertions for property-testing policies with `proptest`.
+//!
+//! ```ignore
+//! use proptest::prelude::*;
+//! use secure_notebook::testing::{arb_permissions, assert_minify_preserves_evaluation};
+//!
+//! proptest! {
+//!     #[test]
+//!     fn minify_is_lossless(permissions in arb_permissions()) {
+//!         let profile = secure_notebook::generate_profile("(version 1)\n", &permissions).unwrap();
+//!         assert_minify_preserves_evaluation(&profile, &permissions)?;
+//!     }
+//! }
+//! ```
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::test_runner::TestCaseError;
+use std::path::{Path, PathBuf};
+
+use crate::evaluator::{evaluate, parse_rules, Action, Rule};
+use crate::grants::Grant;
+use crate::{minify_profile, Permissions};
+
+/// Absolute paths of one to four short lowercase components.
+pub fn arb_path() -> impl Strategy<Value = PathBuf> {
+    vec("[a-z]{1,6}", 1..4).prop_map(|components| {
+        let mut path = PathBuf::from("/");
+        path.extend(components);
+        path
+    })
+}
+
+/// Permissions with a handful of paths in every list.
+pub fn arb_permissions() -> impl Strategy<Value = Permissions> {
+    (
+        vec(arb_path(), 0..4),
+        vec(arb_path(), 0..4),
+        vec(arb_path(), 0..4),
+        vec(arb_path(), 0..4),
+        any::<bool>(),
+        any::<bool>(),
+        vec(arb_path(), 0..3),
+        vec(arb_path(), 0..3),
+    )
+        .prop_map(
+            |(
+                allow_read,
+                deny_read,
+                allow_write,
+                deny_write,
+                allow_net,
+                allow_gpu,
+                allow_run,
+                deny_run,
+            )| {
+                Permissions {
+                    allow_read,
+                    deny_read,
+                    allow_write,
+                    deny_write,
+                    allow_net,
+                    allow_gpu,
+                    allow_run,
+                    deny_run,
+                }
+            },
+        )
+}
+
+/// Any single grant.
+pub fn arb_grant() -> impl Strategy<Value = Grant> {
+    prop_oneof![
+        arb_path().prop_map(Grant::Read),
+        arb_path().prop_map(Grant::Write),
+        Just(Grant::Net),
+        Just(Grant::Gpu),
+        arb_path().prop_map(Grant::Run),
+    ]
+}
+
+/// Operation and target that exercise `grant` in the evaluator.
+pub fn grant_query(grant: &Grant) -> (&'static str, Option<&Path>) {
+    match grant {
+        Grant::Read(path) => ("file-read-data", Some(path)),
+        Grant::Write(path) => ("file-write-data", Some(path)),
+        Grant::Net => ("network-outbound", None),
+        Grant::Gpu => ("iokit-get-properties", None),
+        Grant::Run(program) => ("process-exec", Some(program)),
+    }
+}
+
+/// Every path or program denied by `permissions`, and everything below it, is denied by
+/// `profile`.
+///
+/// Note that the sandbox applies the last matching rule, so this fails for a profile
+/// rendered by [`crate::generate_profile`] when a denied path lies below an allowed one.
+/// [`crate::policy::LayeredPolicy`] re-emits denies last and satisfies it.
+pub fn assert_deny_wins(profile: &str, permissions: &Permissions) -> Result<(), TestCaseError> {
+    let rules = parse(profile)?;
+    let denied = permissions
+        .deny_read
+        .iter()
+        .cloned()
+        .map(Grant::Read)
+        .chain(permissions.deny_write.iter().cloned().map(Grant::Write))
+        .chain(permissions.deny_run.iter().cloned().map(Grant::Run));
+
+    for grant in denied {
+        let (operation, path) = grant_query(&grant);
+        let action = evaluate(&rules, operation, path);
+        if action != Some(Action::Deny) {
+            return Err(TestCaseError::fail(format!(
+                "{grant:?} is denied by the policy but evaluates to {action:?}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Minifying `profile` does not change the decision for any path in `permissions`.
+pub fn assert_minify_preserves_evaluation(
+    profile: &str,
+    permissions: &Permissions,
+) -> Result<(), TestCaseError> {
+    let original = parse(profile)?;
+    let minified = parse(&minify_profile(profile))?;
+
+    let paths = permissions
+        .allow_read
+        .iter()
+        .chain(&permissions.deny_read)
+        .chain(&permissions.allow_write)
+        .chain(&permissions.deny_write)
+        .chain(&permissions.allow_run)
+        .chain(&permissions.deny_run);
+    for path in paths {
+        for grant in [
+            Grant::Read(path.clone()),
+            Grant::Write(path.clone()),
+            Grant::Run(path.clone()),
+        ] {
+            let (operation, path) = grant_query(&grant);
+            let expected = evaluate(&original, operation, path);
+            let actual = evaluate(&minified, operation, path);
+            if expected != actual {
+                return Err(TestCaseError::fail(format!(
+                    "{grant:?} evaluates to {expected:?} but to {actual:?} after minifying"
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse(profile: &str) -> Result<Vec<Rule>, TestCaseError> {
+    parse_rules(profile).map_err(|error| TestCaseError::fail(error.to_string()))
+}
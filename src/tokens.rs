@@ -0,0 +1,173 @@
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+
+/// Bytes of randomness in a generated token.
+const TOKEN_BYTES: usize = 32;
+
+/// A Jupyter server token.
+///
+/// `Debug` and `Display` redact it, so it does not end up in logs by accident.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ServerToken(String);
+
+impl ServerToken {
+    /// Generate a random 256-bit token from the system's random source.
+    pub fn generate() -> Result<Self> {
+        let bytes: [u8; TOKEN_BYTES] = random_bytes()?;
+        Ok(Self(
+            bytes.iter().map(|byte| format!("{byte:02x}")).collect(),
+        ))
+    }
+
+    /// The token itself. Only hand it to callers allowed to use the server.
+    pub fn secret(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether `candidate` is this token, compared in constant time.
+    pub fn matches(&self, candidate: &str) -> bool {
+        let (a, b) = (self.0.as_bytes(), candidate.as_bytes());
+        a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+    }
+}
+
+impl fmt::Debug for ServerToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ServerToken(<redacted>)")
+    }
+}
+
+impl fmt::Display for ServerToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// Tokens persisted in a directory only the current user can read.
+#[derive(Debug, Clone)]
+pub struct TokenStore {
+    dir: PathBuf,
+}
+
+impl TokenStore {
+    /// Store tokens in `dir`, creating it with mode `0700`.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).io_context(|| format!("Failed to create {}", dir.display()))?;
+        set_mode(&dir, 0o700)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf> {
+        if name.is_empty() || name.contains(['/', '\0']) || name.starts_with('.') {
+            return Err(SecureNotebookError::InvalidPath {
+                path: PathBuf::from(name),
+                reason: "token names must be plain file names".to_string(),
+            });
+        }
+        Ok(self.dir.join(name))
+    }
+
+    /// Save `token` under `name`, readable only by the current user.
+    pub fn save(&self, name: &str, token: &ServerToken) -> Result<()> {
+        write_private(&self.path(name)?, token.secret().as_bytes())
+    }
+
+    /// Token saved under `name`, if there is one.
+    pub fn load(&self, name: &str) -> Result<Option<ServerToken>> {
+        let path = self.path(name)?;
+        match fs::read_to_string(&path) {
+            Ok(token) => Ok(Some(ServerToken(token.trim().to_string()))),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error).io_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Delete the token saved under `name`.
+    pub fn remove(&self, name: &str) -> Result<()> {
+        let path = self.path(name)?;
+        match fs::remove_file(&path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                Err(error).io_context(|| format!("Failed to remove {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `N` bytes from the system's random source.
+pub(crate) fn random_bytes<const N: usize>() -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    fs::File::open("/dev/urandom")
+        .and_then(|mut random| random.read_exact(&mut bytes))
+        .io_context(|| "Failed to read /dev/urandom")?;
+    Ok(bytes)
+}
+
+/// Write `contents` to `path`, readable only by the current user.
+pub(crate) fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .io_context(|| format!("Failed to write {}", path.display()))?;
+    // the file may predate this call with looser permissions
+    set_mode(path, 0o600)
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .io_context(|| format!("Failed to restrict {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_generated_tokens_are_redacted() -> Result<()> {
+        let token = ServerToken::generate()?;
+        assert_eq!(token.secret().len(), TOKEN_BYTES * 2);
+        assert_ne!(token, ServerToken::generate()?);
+        assert!(!format!("{token:?}").contains(token.secret()));
+        assert!(token.matches(token.secret()));
+        assert!(!token.matches("guess"));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_store_is_private() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let store = TokenStore::open(dir.path().join("tokens"))?;
+        let token = ServerToken::generate()?;
+        store.save("alice", &token)?;
+
+        assert_eq!(store.load("alice")?, Some(token));
+        let mode = fs::metadata(dir.path().join("tokens/alice"))?
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(store.save("../escape", &ServerToken::generate()?).is_err());
+        store.remove("alice")?;
+        assert_eq!(store.load("alice")?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,295 @@
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::notebook::Notebook;
+use crate::signing::encode_hex;
+use crate::Permissions;
+
+/// Jupyter's trust database, in its data directory.
+pub const TRUST_DB_FILE: &str = "nbsignatures.db";
+/// Key Jupyter signs notebooks with, in its data directory.
+pub const SECRET_FILE: &str = "notebook_secret";
+/// Algorithm recorded with each signature.
+pub const ALGORITHM: &str = "sha256";
+
+/// What to do with a notebook that is not in the trust database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UntrustedPolicy {
+    /// Refuse to run it.
+    Refuse,
+    /// Run it with reads only: no writes, network or programs beyond the template's.
+    Downgrade,
+}
+
+/// Jupyter's data directory: `JUPYTER_DATA_DIR`, or `~/Library/Jupyter`.
+pub fn jupyter_data_dir() -> Option<PathBuf> {
+    std::env::var_os("JUPYTER_DATA_DIR")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Jupyter"))
+        })
+}
+
+/// Signature of `notebook` as Jupyter computes it: an HMAC-SHA256 over every key and
+/// value of the notebook in sorted key order, leaving out its own signature.
+pub fn notebook_signature(notebook: &Notebook, secret: &[u8]) -> Result<String> {
+    let mut notebook = serde_json::to_value(notebook)?;
+    normalize(&mut notebook);
+    let mut message = Vec::new();
+    flatten(&notebook, &mut message);
+    Ok(encode_hex(&hmac_sha256(secret, &message)))
+}
+
+/// The `nbsignatures` table Jupyter keeps trusted notebooks in, read and written with
+/// the `sqlite3` command line tool.
+#[derive(Debug, Clone)]
+pub struct TrustDatabase {
+    db: PathBuf,
+    secret: Vec<u8>,
+}
+
+impl TrustDatabase {
+    /// Open the database and secret in Jupyter's data directory.
+    pub fn open_default() -> Result<Self> {
+        let dir = jupyter_data_dir().ok_or_else(|| {
+            SecureNotebookError::InvalidState("No Jupyter data directory".to_string())
+        })?;
+        Self::open(dir.join(TRUST_DB_FILE), &dir.join(SECRET_FILE))
+    }
+
+    /// Open the database at `db`, signing with the key in `secret`.
+    pub fn open(db: impl Into<PathBuf>, secret: &Path) -> Result<Self> {
+        let secret = std::fs::read(secret)
+            .io_context(|| format!("Failed to read notebook secret {}", secret.display()))?;
+        Ok(Self {
+            db: db.into(),
+            secret,
+        })
+    }
+
+    /// Signature of `notebook` under this database's key.
+    pub fn signature(&self, notebook: &Notebook) -> Result<String> {
+        notebook_signature(notebook, &self.secret)
+    }
+
+    /// Whether `notebook` was trusted, by `jupyter trust` or by saving it in Jupyter.
+    pub fn is_trusted(&self, notebook: &Notebook) -> Result<bool> {
+        if !self.db.exists() {
+            return Ok(false);
+        }
+        let output = self.sqlite(
+            true,
+            &format!(
+                "SELECT 1 FROM nbsignatures WHERE algorithm = '{ALGORITHM}' \
+                 AND signature = '{}' LIMIT 1;",
+                self.signature(notebook)?
+            ),
+        )?;
+        Ok(!output.trim().is_empty())
+    }
+
+    /// Record `notebook` as trusted, like `jupyter trust`.
+    pub fn trust(&self, notebook: &Notebook) -> Result<()> {
+        self.sqlite(
+            false,
+            &format!(
+                "CREATE TABLE IF NOT EXISTS nbsignatures (id integer PRIMARY KEY AUTOINCREMENT, \
+                 algorithm text, signature text, path text, last_seen timestamp);\n\
+                 CREATE INDEX IF NOT EXISTS algosig ON nbsignatures(algorithm, signature);\n\
+                 INSERT INTO nbsignatures (algorithm, signature, last_seen) \
+                 VALUES ('{ALGORITHM}', '{}', datetime('now'));",
+                self.signature(notebook)?
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// Permissions to run `notebook` with: `permissions` if it is trusted, otherwise as
+    /// `untrusted` says.
+    pub fn permissions_for(
+        &self,
+        notebook: &Notebook,
+        permissions: &Permissions,
+        untrusted: UntrustedPolicy,
+    ) -> Result<Permissions> {
+        if self.is_trusted(notebook)? {
+            return Ok(permissions.clone());
+        }
+        match untrusted {
+            UntrustedPolicy::Refuse => Err(SecureNotebookError::Signature(
+                "Notebook is not trusted, run `jupyter trust` on it first".to_string(),
+            )),
+            UntrustedPolicy::Downgrade => Ok(downgrade(permissions)),
+        }
+    }
+
+    fn sqlite(&self, readonly: bool, sql: &str) -> Result<String> {
+        let mut command = Command::new("sqlite3");
+        if readonly {
+            command.arg("-readonly");
+        }
+        let output = command.arg(&self.db).arg(sql).output().map_err(|source| {
+            SecureNotebookError::SpawnFailed {
+                program: PathBuf::from("sqlite3"),
+                source,
+            }
+        })?;
+        if !output.status.success() {
+            return Err(SecureNotebookError::InvalidState(format!(
+                "sqlite3 failed on {}: {}",
+                self.db.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// `permissions` with only its reads and denies left.
+pub fn downgrade(permissions: &Permissions) -> Permissions {
+    Permissions {
+        allow_write: Vec::new(),
+        allow_net: false,
+        allow_gpu: false,
+        allow_run: Vec::new(),
+        ..permissions.clone()
+    }
+}
+
+/// Bring a notebook into the shape nbformat signs: multiline strings joined, and the
+/// signature and other transient metadata removed.
+fn normalize(notebook: &mut Value) {
+    if let Some(Value::Object(metadata)) = notebook.get_mut("metadata") {
+        for key in ["signature", "orig_nbformat", "orig_nbformat_minor"] {
+            metadata.remove(key);
+        }
+    }
+    let Some(Value::Array(cells)) = notebook.get_mut("cells") else {
+        return;
+    };
+    for cell in cells {
+        if let Some(Value::Object(metadata)) = cell.get_mut("metadata") {
+            metadata.remove("trusted");
+        }
+        join_lines(cell.get_mut("source"));
+        if let Some(Value::Object(attachments)) = cell.get_mut("attachments") {
+            attachments.values_mut().for_each(join_bundle);
+        }
+        if let Some(Value::Array(outputs)) = cell.get_mut("outputs") {
+            for output in outputs {
+                join_lines(output.get_mut("text"));
+                if let Some(data) = output.get_mut("data") {
+                    join_bundle(data);
+                }
+            }
+        }
+    }
+}
+
+/// Join the values of a mime bundle, except JSON ones, which are structured.
+fn join_bundle(bundle: &mut Value) {
+    if let Value::Object(bundle) = bundle {
+        for (mime, value) in bundle.iter_mut() {
+            if mime != "application/json" && !mime.ends_with("+json") {
+                join_lines(Some(value));
+            }
+        }
+    }
+}
+
+fn join_lines(value: Option<&mut Value>) {
+    if let Some(value @ Value::Array(_)) = value {
+        let Value::Array(lines) = value.take() else {
+            unreachable!()
+        };
+        let text: String = lines.iter().filter_map(|line| line.as_str()).collect();
+        *value = Value::String(text);
+    }
+}
+
+/// The bytes nbformat feeds the HMAC: keys in sorted order followed by their values,
+/// strings as UTF-8 and everything else as Python prints it.
+fn flatten(value: &Value, message: &mut Vec<u8>) {
+    match value {
+        Value::Object(entries) => {
+            let mut keys: Vec<&String> = entries.keys().collect();
+            keys.sort();
+            for key in keys {
+                message.extend_from_slice(key.as_bytes());
+                flatten(&entries[key], message);
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|item| flatten(item, message)),
+        Value::String(text) => message.extend_from_slice(text.as_bytes()),
+        Value::Null => message.extend_from_slice(b"None"),
+        Value::Bool(true) => message.extend_from_slice(b"True"),
+        Value::Bool(false) => message.extend_from_slice(b"False"),
+        Value::Number(number) => message.extend_from_slice(number.to_string().as_bytes()),
+    }
+}
+
+pub(crate) fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(block.map(|byte| byte ^ 0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|byte| byte ^ 0x5c));
+    outer.update(inner.finalize().as_slice());
+    outer.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_matches_nbformat() -> Result<()> {
+        let notebook = Notebook::from_json(
+            r#"{
+                "cells": [{
+                    "cell_type": "code",
+                    "execution_count": 1,
+                    "metadata": {"trusted": true},
+                    "outputs": [{"name": "stdout", "output_type": "stream", "text": ["3\n"]}],
+                    "source": ["print(1 + 2)"]
+                }],
+                "metadata": {"kernelspec": {"name": "python3"}, "signature": "sha256:00"},
+                "nbformat": 4,
+                "nbformat_minor": 5
+            }"#,
+        )?;
+        assert_eq!(
+            notebook_signature(&notebook, b"secret")?,
+            "b4f5a104a0b1f5715612400087a8fb962ed70c4305a01e8b0fbc1082a7dba4e4"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_downgrade_keeps_reads_only() {
+        let mut permissions = Permissions::new();
+        permissions.allow_read.push(PathBuf::from("/data"));
+        permissions.allow_write.push(PathBuf::from("/data/out"));
+        permissions.deny_read.push(PathBuf::from("/data/secrets"));
+        permissions.allow_net = true;
+        permissions.allow_gpu = true;
+
+        let downgraded = downgrade(&permissions);
+        assert_eq!(downgraded.allow_read, permissions.allow_read);
+        assert_eq!(downgraded.deny_read, permissions.deny_read);
+        assert!(downgraded.allow_write.is_empty());
+        assert!(!downgraded.allow_net);
+        assert!(!downgraded.allow_gpu);
+    }
+}
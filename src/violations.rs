@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::grants::Grant;
+
+/// A sandbox denial reported by the kernel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Violation {
+    /// Name of the process that was denied.
+    pub process: String,
+    /// Process id of the process that was denied.
+    pub pid: u32,
+    /// Sandbox operation that was denied, e.g. `file-read-data`.
+    pub operation: String,
+    /// Target of the operation, usually a path or a network address.
+    pub target: Option<String>,
+}
+
+impl Violation {
+    /// Grant that would have allowed the denied operation, if there is one.
+    pub fn to_grant(&self) -> Option<Grant> {
+        let target = self.target.as_ref().map(PathBuf::from);
+        if self.operation.starts_with("file-read") {
+            target.map(Grant::Read)
+        } else if self.operation.starts_with("file-write") {
+            target.map(Grant::Write)
+        } else if self.operation.starts_with("network") {
+            Some(Grant::Net)
+        } else if self.operation == "process-exec" {
+            target.map(Grant::Run)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parse a `Sandbox: python3(123) deny(1) file-read-data /path` log line.
+pub fn parse_violation(line: &str) -> Option<Violation> {
+    parse_sandbox_line(line, &["deny"])
+}
+
+/// Parse a log line for an operation that was denied or, with `(with report)`, allowed.
+pub fn parse_observation(line: &str) -> Option<Violation> {
+    parse_sandbox_line(line, &["deny", "allow"])
+}
+
+fn parse_sandbox_line(line: &str, actions: &[&str]) -> Option<Violation> {
+    let (_, rest) = line.split_once("Sandbox: ")?;
+    let (process, rest) = rest.split_once('(')?;
+    let (pid, rest) = rest.split_once(')')?;
+    let rest = rest.trim_start();
+    let rest = actions
+        .iter()
+        .find_map(|action| rest.strip_prefix(action))?;
+    let (_, rest) = rest.split_once(')')?;
+    let mut parts = rest.trim().splitn(2, ' ');
+    let operation = parts.next().filter(|op| !op.is_empty())?;
+    let target = parts.next().map(|target| target.trim().to_string());
+
+    Some(Violation {
+        process: process.trim().to_string(),
+        pid: pid.parse().ok()?,
+        operation: operation.to_string(),
+        target,
+    })
+}
+
+/// Streams sandbox violations from the unified log.
+#[derive(Debug)]
+pub struct ViolationMonitor {
+    child: Child,
+    receiver: Receiver<Violation>,
+}
+
+impl ViolationMonitor {
+    /// Start streaming sandbox denials from `log stream`.
+    pub fn start() -> Result<Self> {
+        Self::start_with(parse_violation)
+    }
+
+    /// Start streaming every reported operation, allowed ones included, for learning mode.
+    pub fn start_observing() -> Result<Self> {
+        Self::start_with(parse_observation)
+    }
+
+    fn start_with(parse: fn(&str) -> Option<Violation>) -> Result<Self> {
+        let mut child = Command::new("log")
+            .args([
+                "stream",
+                "--style",
+                "compact",
+                "--predicate",
+                "sender == \"Sandbox\"",
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|source| SecureNotebookError::SpawnFailed {
+                program: PathBuf::from("log"),
+                source,
+            })?;
+
+        let stdout = child.stdout.take().ok_or_else(|| {
+            SecureNotebookError::InvalidState("log stream has no stdout".to_string())
+        })?;
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if let Some(violation) = parse(&line) {
+                    if sender.send(violation).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self { child, receiver })
+    }
+
+    /// Next violation, if one has been reported.
+    pub fn try_next(&self) -> Option<Violation> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Wait up to `timeout` for the next violation.
+    pub fn next_timeout(&self, timeout: Duration) -> Option<Violation> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    /// Stop streaming violations.
+    pub fn stop(&mut self) -> Result<()> {
+        self.child
+            .kill()
+            .io_context(|| "Failed to kill log stream")?;
+        self.child
+            .wait()
+            .io_context(|| "Failed to wait for log stream")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_violation() {
+        let line = "2024-09-20 10:00:00.000 E  kernel[0:1a2b] (Sandbox) Sandbox: python3.12(4242) deny(1) file-read-data /Users/me/secret.txt";
+        let violation = parse_violation(line).unwrap();
+
+        assert_eq!(violation.process, "python3.12");
+        assert_eq!(violation.pid, 4242);
+        assert_eq!(violation.operation, "file-read-data");
+        assert_eq!(violation.target.as_deref(), Some("/Users/me/secret.txt"));
+        assert_eq!(
+            violation.to_grant(),
+            Some(Grant::Read(PathBuf::from("/Users/me/secret.txt")))
+        );
+
+        assert!(parse_violation("kernel: unrelated message").is_none());
+
+        let allowed = line.replace("deny(1)", "allow(1)");
+        assert!(parse_violation(&allowed).is_none());
+        assert_eq!(parse_observation(&allowed), Some(violation));
+    }
+}
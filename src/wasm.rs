@@ -0,0 +1,55 @@
+//! WebAssembly bindings, so a web UI can preview the profile a policy produces before
+//! anything runs on the host.
+//!
+//! Build with `--features wasm --target wasm32-unknown-unknown` and `wasm-bindgen`.
+//! Only pure generation and evaluation is exposed; nothing here touches the host.
+
+use std::path::Path;
+use wasm_bindgen::prelude::*;
+
+use crate::evaluator::{evaluate_profile, Action};
+use crate::policy::parse_policy;
+use crate::{generate_profile, minify_profile, DEFAULT_SANDBOX_PROFILE};
+
+/// Profile for `policy`, TOML if `is_toml` and JSON otherwise, on `template` or the
+/// default profile.
+#[wasm_bindgen(js_name = previewProfile)]
+pub fn preview_profile(
+    policy: &str,
+    is_toml: bool,
+    template: Option<String>,
+    minify: bool,
+) -> Result<String, JsError> {
+    let permissions = parse_policy(policy, is_toml)?;
+    let template = template.as_deref().unwrap_or(DEFAULT_SANDBOX_PROFILE);
+    let profile = generate_profile(template, &permissions)?;
+    Ok(if minify {
+        minify_profile(&profile)
+    } else {
+        profile
+    })
+}
+
+/// Fingerprint of `policy`, as recorded by the host for runs under it.
+#[wasm_bindgen(js_name = policyFingerprint)]
+pub fn policy_fingerprint(policy: &str, is_toml: bool) -> Result<String, JsError> {
+    Ok(parse_policy(policy, is_toml)?.fingerprint_hex())
+}
+
+/// `"allow"` or `"deny"` for `operation` on `path` under `profile`, or `undefined` if
+/// no rule matches.
+#[wasm_bindgen(js_name = evaluateProfile)]
+pub fn evaluate(
+    profile: &str,
+    operation: &str,
+    path: Option<String>,
+) -> Result<Option<String>, JsError> {
+    let action = evaluate_profile(profile, operation, path.as_deref().map(Path::new))?;
+    Ok(action.map(|action| {
+        match action {
+            Action::Allow => "allow",
+            Action::Deny => "deny",
+        }
+        .to_string()
+    }))
+}
@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::error::{IoContext, Result};
+use crate::policy::load_policy;
+use crate::session::{JupyterSession, SessionConfig};
+use crate::{generate_profile, Permissions};
+
+/// How often [`PolicyWatcher::watch`] checks the policy file.
+pub const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A server that is restarted under a new profile whenever its policy file changes.
+#[derive(Debug)]
+pub struct PolicyWatcher {
+    session: JupyterSession,
+    policy: PathBuf,
+    template: String,
+    permissions: Permissions,
+    modified: Option<SystemTime>,
+}
+
+impl PolicyWatcher {
+    /// Start the server under the policy at `policy` on `template`.
+    pub fn start(
+        policy: impl Into<PathBuf>,
+        template: &str,
+        config: SessionConfig,
+    ) -> Result<Self> {
+        let policy = policy.into();
+        let modified = modified(&policy)?;
+        let permissions = load_policy(&policy)?;
+        let session = JupyterSession::spawn(&generate_profile(template, &permissions)?, config)?;
+        Ok(Self {
+            session,
+            policy,
+            template: template.to_string(),
+            permissions,
+            modified,
+        })
+    }
+
+    /// Underlying Jupyter session.
+    pub fn session(&self) -> &JupyterSession {
+        &self.session
+    }
+
+    /// The policy the server currently runs under.
+    pub fn permissions(&self) -> &Permissions {
+        &self.permissions
+    }
+
+    /// Restart the server if the policy file changed, returning whether it did.
+    ///
+    /// Saving the file without changing the policy does not restart the server. If the
+    /// new policy does not load, the error is returned and the server keeps running under
+    /// the old one until the file changes again.
+    pub fn poll(&mut self) -> Result<bool> {
+        let modified = modified(&self.policy)?;
+        if modified == self.modified {
+            return Ok(false);
+        }
+        self.modified = modified;
+
+        let permissions = load_policy(&self.policy)?;
+        if permissions.fingerprint() == self.permissions.fingerprint() {
+            return Ok(false);
+        }
+        let profile = generate_profile(&self.template, &permissions)?;
+        self.session.graceful_restart(&profile)?;
+        self.permissions = permissions;
+        Ok(true)
+    }
+
+    /// Poll every `interval` until the server exits, reporting each restart and each
+    /// policy that failed to load to `on_event`.
+    pub fn watch(
+        &mut self,
+        interval: Duration,
+        mut on_event: impl FnMut(Result<&Permissions>),
+    ) -> Result<()> {
+        while self.session.is_running() {
+            match self.poll() {
+                Ok(true) => on_event(Ok(&self.permissions)),
+                Ok(false) => {}
+                Err(error) => on_event(Err(error)),
+            }
+            std::thread::sleep(interval);
+        }
+        Ok(())
+    }
+
+    /// Shut the server down.
+    pub fn shutdown(&mut self) -> Result<()> {
+        self.session.shutdown()
+    }
+}
+
+/// Modification time of `path`, `None` while it does not exist, e.g. mid-save.
+fn modified(path: &Path) -> Result<Option<SystemTime>> {
+    match std::fs::metadata(path) {
+        Ok(metadata) => Ok(metadata.modified().ok()),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => {
+            Err(error).io_context(|| format!("Failed to check policy {}", path.display()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_modified_tracks_missing_files() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let policy = dir.path().join("policy.toml");
+        assert_eq!(modified(&policy)?, None);
+        std::fs::write(&policy, "allow_net = true\n")?;
+        assert!(modified(&policy)?.is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::session::kill_tree;
+
+/// How often the watchdog checks its deadlines.
+const TICK: Duration = Duration::from_millis(20);
+
+/// Wall-clock limits enforced by a [`Watchdog`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogLimits {
+    /// Longest a single cell may run before the kernel is interrupted.
+    pub cell_timeout: Option<Duration>,
+    /// Longest the whole run may take before the process tree is killed.
+    pub session_timeout: Option<Duration>,
+    /// Time an interrupted cell gets to stop before the process tree is killed.
+    pub grace: Duration,
+}
+
+impl Default for WatchdogLimits {
+    fn default() -> Self {
+        Self {
+            cell_timeout: None,
+            session_timeout: None,
+            grace: Duration::from_secs(5),
+        }
+    }
+}
+
+/// How a cell ended.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CellOutcome {
+    Completed,
+    Failed(String),
+    /// The cell exceeded its timeout and was interrupted.
+    TimedOut,
+}
+
+/// Record of a single cell.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CellReport {
+    pub index: usize,
+    pub elapsed: Duration,
+    pub outcome: CellOutcome,
+}
+
+/// Record of a run, including the timeouts that fired.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunReport {
+    pub cells: Vec<CellReport>,
+    /// The session timeout fired and the process tree was killed.
+    pub session_timed_out: bool,
+    pub elapsed: Duration,
+}
+
+impl RunReport {
+    /// Whether every cell completed.
+    pub fn succeeded(&self) -> bool {
+        !self.session_timed_out
+            && self
+                .cells
+                .iter()
+                .all(|cell| cell.outcome == CellOutcome::Completed)
+    }
+}
+
+#[derive(Debug)]
+struct RunningCell {
+    index: usize,
+    started: Instant,
+    interrupted_at: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct State {
+    started: Instant,
+    cell: Option<RunningCell>,
+    report: RunReport,
+    killed: bool,
+}
+
+/// Enforces per-cell and per-session timeouts on a sandboxed server.
+///
+/// When a cell runs too long the kernels (the server's children) get `SIGINT`, which
+/// Jupyter kernels turn into `KeyboardInterrupt`. If the cell still has not finished after
+/// the grace period, or the session runs out of time, the whole process tree is killed.
+#[derive(Debug)]
+pub struct Watchdog {
+    state: Arc<Mutex<State>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Watch the server with process id `root`.
+    pub fn start(root: u32, limits: WatchdogLimits) -> Self {
+        let state = Arc::new(Mutex::new(State {
+            started: Instant::now(),
+            cell: None,
+            report: RunReport::default(),
+            killed: false,
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let state = Arc::clone(&state);
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    if check(&state, root, &limits) {
+                        break;
+                    }
+                    std::thread::park_timeout(TICK);
+                }
+            })
+        };
+
+        Self {
+            state,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Mark cell `index` as started.
+    pub fn start_cell(&self, index: usize) {
+        self.lock().cell = Some(RunningCell {
+            index,
+            started: Instant::now(),
+            interrupted_at: None,
+        });
+    }
+
+    /// Mark the running cell as finished. A cell that was interrupted is recorded as
+    /// timed out whatever `outcome` says.
+    pub fn finish_cell(&self, outcome: CellOutcome) {
+        let mut state = self.lock();
+        let Some(cell) = state.cell.take() else {
+            return;
+        };
+        let outcome = if cell.interrupted_at.is_some() {
+            CellOutcome::TimedOut
+        } else {
+            outcome
+        };
+        state.report.cells.push(CellReport {
+            index: cell.index,
+            elapsed: cell.started.elapsed(),
+            outcome,
+        });
+    }
+
+    /// Whether the process tree has been killed; no further cells can run.
+    pub fn is_expired(&self) -> bool {
+        self.lock().killed
+    }
+
+    /// Report of the run so far.
+    pub fn report(&self) -> RunReport {
+        let state = self.lock();
+        let mut report = state.report.clone();
+        report.elapsed = state.started.elapsed();
+        report
+    }
+
+    /// Stop watching and return the final report.
+    pub fn finish(mut self) -> RunReport {
+        self.shutdown();
+        self.report()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("watchdog state poisoned")
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Enforce the limits once, returning whether the process tree was killed.
+fn check(state: &Mutex<State>, root: u32, limits: &WatchdogLimits) -> bool {
+    let mut state = state.lock().expect("watchdog state poisoned");
+    let now = Instant::now();
+
+    if limits
+        .session_timeout
+        .is_some_and(|timeout| now.duration_since(state.started) > timeout)
+    {
+        state.report.session_timed_out = true;
+        if let Some(cell) = state.cell.as_mut() {
+            cell.interrupted_at.get_or_insert(now);
+        }
+        state.killed = true;
+        kill_tree(root);
+        return true;
+    }
+
+    let Some(cell) = state.cell.as_mut() else {
+        return false;
+    };
+    match cell.interrupted_at {
+        None if limits
+            .cell_timeout
+            .is_some_and(|timeout| now.duration_since(cell.started) > timeout) =>
+        {
+            cell.interrupted_at = Some(now);
+            signal_children(root, "INT");
+            false
+        }
+        Some(interrupted_at) if now.duration_since(interrupted_at) > limits.grace => {
+            state.killed = true;
+            kill_tree(root);
+            true
+        }
+        _ => false,
+    }
+}
+
+fn signal_children(parent: u32, signal: &str) {
+    let _ = Command::new("pkill")
+        .args([&format!("-{signal}"), "-P", &parent.to_string()])
+        .status();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_timeout_kills_stuck_process() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let watchdog = Watchdog::start(
+            child.id(),
+            WatchdogLimits {
+                cell_timeout: Some(Duration::from_millis(50)),
+                grace: Duration::from_millis(50),
+                ..WatchdogLimits::default()
+            },
+        );
+
+        watchdog.start_cell(0);
+        let status = child.wait().unwrap();
+        assert!(!status.success());
+        watchdog.finish_cell(CellOutcome::Completed);
+
+        let report = watchdog.finish();
+        assert_eq!(report.cells[0].outcome, CellOutcome::TimedOut);
+        assert!(!report.succeeded());
+    }
+}
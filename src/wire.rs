@@ -0,0 +1,602 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::manifest::iso8601;
+use crate::signing::encode_hex;
+use crate::tokens::random_bytes;
+use crate::trust::hmac_sha256;
+
+/// Version of the Jupyter messaging protocol the client speaks.
+pub const PROTOCOL_VERSION: &str = "5.3";
+
+/// How often [`KernelClient::heartbeat`] pings the kernel.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Largest frame accepted from a kernel, so a misbehaving one can't make the client
+/// allocate without bound.
+pub const MAX_FRAME_SIZE: u64 = 64 * 1024 * 1024;
+
+/// How long [`ConnectionInfo::is_listening`] tries to connect.
+const LISTEN_PROBE: Duration = Duration::from_millis(500);
+
+/// Separates the routing identities of a message from its signed parts.
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+/// Where a kernel listens and how its messages are signed, as written to its
+/// `kernel-<id>.json` connection file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionInfo {
+    pub ip: String,
+    pub transport: String,
+    pub shell_port: u16,
+    pub iopub_port: u16,
+    pub stdin_port: u16,
+    pub control_port: u16,
+    pub hb_port: u16,
+    /// HMAC key; messages are unsigned when empty.
+    pub key: String,
+    pub signature_scheme: String,
+}
+
+impl ConnectionInfo {
+    /// Read the connection file at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .io_context(|| format!("Failed to read {}", path.display()))?;
+        let info: Self = serde_json::from_str(&contents)?;
+        if info.transport != "tcp" {
+            return Err(SecureNotebookError::Unsupported(format!(
+                "Kernel transport {} is not supported, only tcp",
+                info.transport
+            )));
+        }
+        if !info.key.is_empty() && info.signature_scheme != "hmac-sha256" {
+            return Err(SecureNotebookError::Unsupported(format!(
+                "Signature scheme {} is not supported, only hmac-sha256",
+                info.signature_scheme
+            )));
+        }
+        Ok(info)
+    }
+
+    /// Whether a kernel still accepts connections on the shell port, e.g. to check that
+    /// it is gone after its server was shut down.
+    pub fn is_listening(&self) -> bool {
+        self.address(self.shell_port)
+            .to_socket_addrs()
+            .into_iter()
+            .flatten()
+            .any(|address| TcpStream::connect_timeout(&address, LISTEN_PROBE).is_ok())
+    }
+
+    fn address(&self, port: u16) -> String {
+        format!("{}:{port}", self.ip)
+    }
+}
+
+/// A Jupyter message. Binary buffers are dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub header: Value,
+    pub parent_header: Value,
+    pub metadata: Value,
+    pub content: Value,
+}
+
+impl Message {
+    /// `msg_type` from the header, e.g. `execute_reply`.
+    pub fn msg_type(&self) -> &str {
+        self.header
+            .get("msg_type")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+    }
+
+    pub fn msg_id(&self) -> &str {
+        self.header
+            .get("msg_id")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+    }
+
+    /// Id of the request this message answers, if any.
+    pub fn parent_id(&self) -> Option<&str> {
+        self.parent_header.get("msg_id").and_then(Value::as_str)
+    }
+}
+
+/// Signs outgoing messages and checks incoming ones for one client session.
+#[derive(Debug, Clone)]
+struct Signer {
+    session: String,
+    key: Vec<u8>,
+}
+
+impl Signer {
+    fn new(key: &str) -> Result<Self> {
+        Ok(Self {
+            session: random_id()?,
+            key: key.as_bytes().to_vec(),
+        })
+    }
+
+    fn message(&self, msg_type: &str, content: Value) -> Result<Message> {
+        Ok(Message {
+            header: json!({
+                "msg_id": random_id()?,
+                "session": self.session,
+                "username": "secure-notebook",
+                "date": iso8601(SystemTime::now()),
+                "msg_type": msg_type,
+                "version": PROTOCOL_VERSION,
+            }),
+            parent_header: json!({}),
+            metadata: json!({}),
+            content,
+        })
+    }
+
+    fn signature(&self, parts: &[Vec<u8>]) -> String {
+        if self.key.is_empty() {
+            return String::new();
+        }
+        encode_hex(&hmac_sha256(&self.key, &parts.concat()))
+    }
+
+    /// The frames of `message`, after the routing identities.
+    fn serialize(&self, message: &Message) -> Result<Vec<Vec<u8>>> {
+        let parts = [
+            &message.header,
+            &message.parent_header,
+            &message.metadata,
+            &message.content,
+        ]
+        .map(serde_json::to_vec)
+        .into_iter()
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+        let mut frames = vec![DELIMITER.to_vec(), self.signature(&parts).into_bytes()];
+        frames.extend(parts);
+        Ok(frames)
+    }
+
+    /// Parse `frames`, rejecting messages whose signature doesn't match.
+    fn deserialize(&self, frames: &[Vec<u8>]) -> Result<Message> {
+        let start = frames
+            .iter()
+            .position(|frame| frame == DELIMITER)
+            .ok_or_else(|| protocol_error("a message without a delimiter"))?;
+        let [signature, header, parent_header, metadata, content, ..] = &frames[start + 1..] else {
+            return Err(protocol_error("a truncated message"));
+        };
+        let parts = [header, parent_header, metadata, content].map(Vec::clone);
+        let expected = self.signature(&parts);
+        let matches = expected.len() == signature.len()
+            && expected
+                .bytes()
+                .zip(signature)
+                .fold(0, |diff, (x, y)| diff | (x ^ y))
+                == 0;
+        if !matches {
+            return Err(protocol_error("a message with an invalid signature"));
+        }
+        Ok(Message {
+            header: serde_json::from_slice(header)?,
+            parent_header: serde_json::from_slice(parent_header)?,
+            metadata: serde_json::from_slice(metadata)?,
+            content: serde_json::from_slice(content)?,
+        })
+    }
+}
+
+fn random_id() -> Result<String> {
+    Ok(encode_hex(&random_bytes::<16>()?))
+}
+
+fn protocol_error(what: &str) -> SecureNotebookError {
+    SecureNotebookError::InvalidState(format!("Kernel sent {what}"))
+}
+
+/// Frame flags of ZMTP 3.0.
+const MORE: u8 = 0x01;
+const LONG: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+
+/// One ZeroMQ connection speaking ZMTP 3.0 with the `NULL` mechanism, which is what a
+/// kernel's loopback channels use. Only the single peer a kernel channel has is
+/// supported.
+#[derive(Debug)]
+struct ZmqSocket {
+    stream: TcpStream,
+}
+
+impl ZmqSocket {
+    /// Connect to `address` as a `socket_type` socket, e.g. `DEALER`.
+    fn connect(address: &str, socket_type: &str) -> Result<Self> {
+        let stream = TcpStream::connect(address)
+            .io_context(|| format!("Failed to connect to the kernel at {address}"))?;
+        let _ = stream.set_nodelay(true);
+        let mut socket = Self { stream };
+        socket.handshake(socket_type)?;
+        Ok(socket)
+    }
+
+    fn handshake(&mut self, socket_type: &str) -> Result<()> {
+        let mut greeting = [0u8; 64];
+        greeting[0] = 0xff;
+        greeting[9] = 0x7f;
+        greeting[10] = 3;
+        greeting[12..16].copy_from_slice(b"NULL");
+        self.write(&greeting)?;
+        let mut peer = [0u8; 64];
+        self.read_exact(&mut peer)?;
+        if peer[0] != 0xff || peer[9] != 0x7f || peer[10] < 3 || &peer[12..16] != b"NULL" {
+            return Err(protocol_error(
+                "a greeting other than ZMTP 3 with NULL security",
+            ));
+        }
+
+        let mut ready = b"\x05READY".to_vec();
+        for (name, value) in [("Socket-Type", socket_type.as_bytes()), ("Identity", b"")] {
+            // Only sockets that can be routed to carry an identity.
+            if name == "Identity" && socket_type == "SUB" {
+                continue;
+            }
+            ready.push(name.len() as u8);
+            ready.extend_from_slice(name.as_bytes());
+            ready.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            ready.extend_from_slice(value);
+        }
+        self.write(&frame(COMMAND, &ready))?;
+        let (flags, command) = self.read_frame()?;
+        if flags & COMMAND == 0 || !command.starts_with(b"\x05READY") {
+            return Err(protocol_error("no READY command"));
+        }
+        Ok(())
+    }
+
+    /// Send `parts` as one message.
+    fn send(&mut self, parts: &[Vec<u8>]) -> Result<()> {
+        let mut message = Vec::new();
+        for (index, part) in parts.iter().enumerate() {
+            let more = if index + 1 < parts.len() { MORE } else { 0 };
+            message.extend(frame(more, part));
+        }
+        self.write(&message)
+    }
+
+    /// Receive the next message, or `None` if none starts within `timeout`.
+    ///
+    /// Only the wait for its first byte is timed, so a message is never cut in half.
+    fn recv(&mut self, timeout: Option<Duration>) -> Result<Option<Vec<Vec<u8>>>> {
+        let mut first = [0u8; 1];
+        self.stream
+            .set_read_timeout(timeout)
+            .io_context(|| "Failed to set the kernel read timeout")?;
+        let waited = self.stream.read_exact(&mut first);
+        self.stream
+            .set_read_timeout(None)
+            .io_context(|| "Failed to set the kernel read timeout")?;
+        match waited {
+            Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(None)
+            }
+            waited => waited.io_context(|| "Failed to read from the kernel")?,
+        }
+
+        let mut parts = Vec::new();
+        let mut flags = first[0];
+        loop {
+            let body = self.read_body(flags)?;
+            if flags & COMMAND == 0 {
+                parts.push(body);
+                if flags & MORE == 0 {
+                    return Ok(Some(parts));
+                }
+            }
+            let mut next = [0u8; 1];
+            self.read_exact(&mut next)?;
+            flags = next[0];
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<(u8, Vec<u8>)> {
+        let mut flags = [0u8; 1];
+        self.read_exact(&mut flags)?;
+        Ok((flags[0], self.read_body(flags[0])?))
+    }
+
+    fn read_body(&mut self, flags: u8) -> Result<Vec<u8>> {
+        let size = if flags & LONG != 0 {
+            let mut size = [0u8; 8];
+            self.read_exact(&mut size)?;
+            u64::from_be_bytes(size)
+        } else {
+            let mut size = [0u8; 1];
+            self.read_exact(&mut size)?;
+            u64::from(size[0])
+        };
+        if size > MAX_FRAME_SIZE {
+            return Err(protocol_error(&format!("a {size}-byte frame")));
+        }
+        let mut body = vec![0; size as usize];
+        self.read_exact(&mut body)?;
+        Ok(body)
+    }
+
+    fn read_exact(&mut self, buffer: &mut [u8]) -> Result<()> {
+        self.stream
+            .read_exact(buffer)
+            .io_context(|| "Failed to read from the kernel")
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.stream
+            .write_all(bytes)
+            .io_context(|| "Failed to write to the kernel")
+    }
+}
+
+/// `body` as a ZMTP frame with `flags`.
+fn frame(flags: u8, body: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(body.len() + 9);
+    match u8::try_from(body.len()) {
+        Ok(size) => frame.extend([flags, size]),
+        Err(_) => {
+            frame.push(flags | LONG);
+            frame.extend_from_slice(&(body.len() as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(body);
+    frame
+}
+
+/// A client for one kernel, speaking the Jupyter messaging protocol over its shell,
+/// control, iopub and heartbeat channels.
+#[derive(Debug)]
+pub struct KernelClient {
+    info: ConnectionInfo,
+    signer: Signer,
+    shell: Mutex<ZmqSocket>,
+    control: Mutex<ZmqSocket>,
+}
+
+impl KernelClient {
+    /// Connect to the kernel described by `info`.
+    pub fn connect(info: ConnectionInfo) -> Result<Self> {
+        Ok(Self {
+            signer: Signer::new(&info.key)?,
+            shell: Mutex::new(ZmqSocket::connect(
+                &info.address(info.shell_port),
+                "DEALER",
+            )?),
+            control: Mutex::new(ZmqSocket::connect(
+                &info.address(info.control_port),
+                "DEALER",
+            )?),
+            info,
+        })
+    }
+
+    /// Where the kernel listens.
+    pub fn info(&self) -> &ConnectionInfo {
+        &self.info
+    }
+
+    /// Send a `msg_type` request on the shell channel, returning its id.
+    pub fn send_shell(&self, msg_type: &str, content: Value) -> Result<String> {
+        send(&self.signer, &mut lock(&self.shell), msg_type, content)
+    }
+
+    /// Wait for the shell reply to the request `msg_id`, skipping replies to others.
+    pub fn shell_reply(&self, msg_id: &str, timeout: Option<Duration>) -> Result<Message> {
+        reply(&self.signer, &mut lock(&self.shell), msg_id, timeout)
+    }
+
+    /// Execute `code` and wait for its `execute_reply`. Outputs arrive on iopub.
+    pub fn execute(&self, code: &str, timeout: Option<Duration>) -> Result<Message> {
+        let msg_id = self.send_shell(
+            "execute_request",
+            json!({
+                "code": code,
+                "silent": false,
+                "store_history": true,
+                "user_expressions": {},
+                "allow_stdin": false,
+                "stop_on_error": false,
+            }),
+        )?;
+        self.shell_reply(&msg_id, timeout)
+    }
+
+    /// Interrupt the running cell through the control channel.
+    pub fn interrupt(&self, timeout: Duration) -> Result<()> {
+        self.control_request("interrupt_request", json!({}), timeout)
+    }
+
+    /// Ask the kernel to shut down, or to restart when `restart` is set.
+    pub fn shutdown(&self, restart: bool, timeout: Duration) -> Result<()> {
+        self.control_request("shutdown_request", json!({ "restart": restart }), timeout)
+    }
+
+    fn control_request(&self, msg_type: &str, content: Value, timeout: Duration) -> Result<()> {
+        let mut control = lock(&self.control);
+        let msg_id = send(&self.signer, &mut control, msg_type, content)?;
+        reply(&self.signer, &mut control, &msg_id, Some(timeout)).map(drop)
+    }
+
+    /// Subscribe to everything the kernel publishes on iopub.
+    ///
+    /// Messages are received on a thread of their own, which ends when the receiver is
+    /// dropped or the kernel goes away. Messages that fail their signature are dropped.
+    pub fn iopub_subscribe(&self) -> Result<Receiver<Message>> {
+        let mut iopub = ZmqSocket::connect(&self.info.address(self.info.iopub_port), "SUB")?;
+        // A ZMTP 3.0 subscription to every topic.
+        iopub.send(&[vec![1]])?;
+        let signer = self.signer.clone();
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            while let Ok(Some(frames)) = iopub.recv(None) {
+                let Ok(message) = signer.deserialize(&frames) else {
+                    continue;
+                };
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(receiver)
+    }
+
+    /// Ping the kernel every [`HEARTBEAT_INTERVAL`], yielding one message per answer,
+    /// for a [`crate::heartbeat::HeartbeatMonitor`].
+    ///
+    /// The channel closes when the connection to the kernel is lost.
+    pub fn heartbeat(&self) -> Result<Receiver<()>> {
+        let mut heartbeat = ZmqSocket::connect(&self.info.address(self.info.hb_port), "REQ")?;
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || loop {
+            let sent = Instant::now();
+            // A REQ request is the payload behind an empty delimiter frame.
+            if heartbeat.send(&[Vec::new(), b"ping".to_vec()]).is_err() {
+                break;
+            }
+            // A hung kernel leaves the request pending; keep waiting for its answer.
+            let answered = loop {
+                match heartbeat.recv(Some(HEARTBEAT_INTERVAL)) {
+                    Ok(Some(_)) => break true,
+                    Ok(None) => continue,
+                    Err(_) => break false,
+                }
+            };
+            if !answered || sender.send(()).is_err() {
+                break;
+            }
+            std::thread::sleep(HEARTBEAT_INTERVAL.saturating_sub(sent.elapsed()));
+        });
+        Ok(receiver)
+    }
+}
+
+fn lock(socket: &Mutex<ZmqSocket>) -> MutexGuard<'_, ZmqSocket> {
+    socket.lock().expect("kernel socket poisoned")
+}
+
+fn send(signer: &Signer, socket: &mut ZmqSocket, msg_type: &str, content: Value) -> Result<String> {
+    let message = signer.message(msg_type, content)?;
+    socket.send(&signer.serialize(&message)?)?;
+    Ok(message.msg_id().to_string())
+}
+
+fn reply(
+    signer: &Signer,
+    socket: &mut ZmqSocket,
+    msg_id: &str,
+    timeout: Option<Duration>,
+) -> Result<Message> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        if remaining == Some(Duration::ZERO) {
+            return Err(SecureNotebookError::KernelTimeout(
+                timeout.unwrap_or_default(),
+            ));
+        }
+        let Some(frames) = socket.recv(remaining)? else {
+            continue;
+        };
+        let message = signer.deserialize(&frames)?;
+        if message.parent_id() == Some(msg_id) {
+            return Ok(message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Accept one connection and answer the handshake like a ZMTP 3 peer.
+    fn peer(listener: TcpListener) -> TcpStream {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut greeting = [0u8; 64];
+        stream.read_exact(&mut greeting).unwrap();
+        stream.write_all(&greeting).unwrap();
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).unwrap();
+        let mut ready = vec![0; header[1] as usize];
+        stream.read_exact(&mut ready).unwrap();
+        assert!(ready.starts_with(b"\x05READY\x0bSocket-Type"));
+        stream.write_all(&frame(COMMAND, b"\x05READY")).unwrap();
+        stream
+    }
+
+    #[test]
+    fn test_is_listening() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let info = ConnectionInfo {
+            ip: "127.0.0.1".to_string(),
+            transport: "tcp".to_string(),
+            shell_port: listener.local_addr()?.port(),
+            iopub_port: 0,
+            stdin_port: 0,
+            control_port: 0,
+            hb_port: 0,
+            key: String::new(),
+            signature_scheme: "hmac-sha256".to_string(),
+        };
+        assert!(info.is_listening());
+        drop(listener);
+        assert!(!info.is_listening());
+        Ok(())
+    }
+
+    #[test]
+    fn test_zmtp_framing() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?.to_string();
+        let server = std::thread::spawn(move || {
+            let mut stream = peer(listener);
+            let mut received = [0u8; 7];
+            stream.read_exact(&mut received).unwrap();
+            stream.write_all(&frame(MORE, b"")).unwrap();
+            stream.write_all(&frame(0, &vec![b'x'; 300])).unwrap();
+            received
+        });
+
+        let mut socket = ZmqSocket::connect(&address, "DEALER")?;
+        socket.send(&[b"a".to_vec(), b"bc".to_vec()])?;
+        let parts = socket.recv(Some(Duration::from_secs(5)))?.unwrap();
+        assert_eq!(parts, [Vec::new(), vec![b'x'; 300]]);
+        assert_eq!(&server.join().unwrap(), b"\x01\x01a\x00\x02bc");
+        Ok(())
+    }
+
+    #[test]
+    fn test_recv_times_out_between_messages() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?.to_string();
+        let server = std::thread::spawn(move || peer(listener));
+
+        let mut socket = ZmqSocket::connect(&address, "REQ")?;
+        let _stream = server.join().unwrap();
+        assert_eq!(socket.recv(Some(Duration::from_millis(20)))?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_frame_sizes() {
+        assert_eq!(frame(0, &[7; 3]), [0, 3, 7, 7, 7]);
+        assert_eq!(
+            frame(MORE, &[0; 256])[..9],
+            [MORE | LONG, 0, 0, 0, 0, 0, 0, 1, 0]
+        );
+    }
+}
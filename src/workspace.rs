@@ -0,0 +1,253 @@
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::encryption::{encrypt_outputs, RecipientKey};
+use crate::error::{IoContext, Result, SecureNotebookError};
+use crate::session::{JupyterSession, SessionConfig};
+use crate::{generate_profile, Permissions};
+
+/// Directory under the workspace root for temporary files.
+pub const SCRATCH_DIR: &str = ".scratch";
+/// Directory under the workspace root for results.
+pub const OUTPUT_DIR: &str = "output";
+/// Environment variable holding the session's scratch directory, see
+/// [`Workspace::with_scratch`].
+pub const SCRATCH_ENV: &str = "SECURE_NOTEBOOK_SCRATCH";
+
+/// Scratch directories created by this process, to keep their names unique.
+static SCRATCH_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// A notebook project: a root directory, a policy and the server that runs it.
+///
+/// Paths in the policy are relative to the root. The root is readable, and only the
+/// scratch and output directories are writable unless the policy says otherwise.
+#[derive(Debug)]
+pub struct Workspace {
+    root: PathBuf,
+    template: String,
+    policy: Permissions,
+    config: SessionConfig,
+    session: Option<JupyterSession>,
+    session_scratch: Option<PathBuf>,
+}
+
+impl Workspace {
+    /// Open the workspace at `root`, creating its scratch and output directories.
+    ///
+    /// Relative paths in `policy` are resolved against `root` and may not leave it.
+    pub fn new(root: impl Into<PathBuf>, template: &str, policy: Permissions) -> Result<Self> {
+        let root = root.into();
+        let root = root
+            .canonicalize()
+            .io_context(|| format!("Failed to open workspace {}", root.display()))?;
+        for dir in [SCRATCH_DIR, OUTPUT_DIR] {
+            let dir = root.join(dir);
+            std::fs::create_dir_all(&dir)
+                .io_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let policy = resolve_policy(&root, policy)?;
+
+        Ok(Self {
+            root,
+            template: template.to_string(),
+            policy,
+            config: SessionConfig::default(),
+            session: None,
+            session_scratch: None,
+        })
+    }
+
+    /// Give the server a fresh temporary directory outside the workspace, writable and
+    /// exported to the kernels as [`SCRATCH_ENV`] and `TMPDIR`. It is removed when the
+    /// workspace is dropped.
+    pub fn with_scratch(mut self) -> Result<Self> {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let dir = std::env::temp_dir().join(format!(
+            "secure-notebook-{}-{}-{}",
+            std::process::id(),
+            since_epoch.as_nanos(),
+            SCRATCH_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir(&dir).io_context(|| format!("Failed to create {}", dir.display()))?;
+        // Rules match the resolved path, e.g. /private/var rather than /var on macOS.
+        let dir = dir
+            .canonicalize()
+            .io_context(|| format!("Failed to resolve {}", dir.display()))?;
+        self.session_scratch = Some(dir);
+        Ok(self)
+    }
+
+    /// Start the server with `config`; the workspace root is added to its arguments.
+    pub fn with_config(mut self, config: SessionConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn scratch_dir(&self) -> PathBuf {
+        self.root.join(SCRATCH_DIR)
+    }
+
+    pub fn output_dir(&self) -> PathBuf {
+        self.root.join(OUTPUT_DIR)
+    }
+
+    /// The directory created by [`Self::with_scratch`], if any.
+    pub fn session_scratch(&self) -> Option<&Path> {
+        self.session_scratch.as_deref()
+    }
+
+    /// Policy with relative paths resolved, before the layout grants are added.
+    pub fn policy(&self) -> &Permissions {
+        &self.policy
+    }
+
+    /// Permissions the server runs under: the policy plus the layout grants.
+    pub fn permissions(&self) -> Permissions {
+        let mut permissions = self.policy.clone();
+        permissions.allow_read.push(self.root.clone());
+        permissions
+            .allow_write
+            .extend([self.scratch_dir(), self.output_dir()]);
+        permissions.allow_write.extend(self.session_scratch.clone());
+        permissions
+    }
+
+    /// Profile the server runs under.
+    pub fn profile(&self) -> Result<String> {
+        generate_profile(&self.template, &self.permissions())
+    }
+
+    /// The server, started on first use.
+    pub fn server(&mut self) -> Result<&mut JupyterSession> {
+        if self.session.is_none() {
+            let mut config = self.config.clone();
+            config
+                .args
+                .push(format!("--ServerApp.root_dir={}", self.root.display()));
+            if let Some(scratch) = &self.session_scratch {
+                let scratch = scratch.display().to_string();
+                config.env.push((SCRATCH_ENV.to_string(), scratch.clone()));
+                config.env.push(("TMPDIR".to_string(), scratch));
+            }
+            self.session = Some(JupyterSession::spawn(&self.profile()?, config)?);
+        }
+        Ok(self.session.as_mut().expect("session was just started"))
+    }
+
+    /// Whether the server has been started.
+    pub fn is_started(&self) -> bool {
+        self.session.is_some()
+    }
+
+    /// Shut the server down, if it was started. The next [`Self::server`] starts it again.
+    pub fn shutdown(&mut self) -> Result<()> {
+        match self.session.take() {
+            Some(mut session) => session.shutdown(),
+            None => Ok(()),
+        }
+    }
+
+    /// Encrypt everything in the output directory for `recipient`, so results never sit
+    /// on disk in the clear once the run is over. Call it after [`Self::shutdown`].
+    pub fn seal_outputs(&self, recipient: &RecipientKey) -> Result<Vec<PathBuf>> {
+        encrypt_outputs(&self.output_dir(), recipient)
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+        if let Some(scratch) = &self.session_scratch {
+            let _ = std::fs::remove_dir_all(scratch);
+        }
+    }
+}
+
+/// Resolve relative paths in `policy` against `root`.
+fn resolve_policy(root: &Path, mut policy: Permissions) -> Result<Permissions> {
+    for paths in [
+        &mut policy.allow_read,
+        &mut policy.deny_read,
+        &mut policy.allow_write,
+        &mut policy.deny_write,
+        &mut policy.allow_run,
+        &mut policy.deny_run,
+    ] {
+        for path in paths.iter_mut() {
+            if path.is_relative() {
+                *path = resolve(root, path)?;
+            }
+        }
+    }
+    Ok(policy)
+}
+
+fn resolve(root: &Path, path: &Path) -> Result<PathBuf> {
+    if path
+        .components()
+        .any(|component| component == Component::ParentDir)
+    {
+        return Err(SecureNotebookError::InvalidPath {
+            path: path.to_path_buf(),
+            reason: "workspace paths may not leave the workspace".to_string(),
+        });
+    }
+    Ok(root.join(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_layout_grants() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let policy = Permissions {
+            allow_read: vec![PathBuf::from("data")],
+            ..Permissions::default()
+        };
+        let workspace = Workspace::new(dir.path(), "(version 1)\n", policy)?;
+
+        assert!(workspace.output_dir().is_dir());
+        let permissions = workspace.permissions();
+        assert!(permissions
+            .allow_read
+            .contains(&workspace.root().join("data")));
+        assert!(permissions.allow_write.contains(&workspace.scratch_dir()));
+        assert!(!workspace.is_started());
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_scratch() -> Result<()> {
+        let dir = tempdir().unwrap();
+        let workspace =
+            Workspace::new(dir.path(), "(version 1)\n", Permissions::default())?.with_scratch()?;
+        let scratch = workspace.session_scratch().unwrap().to_path_buf();
+
+        assert!(scratch.is_dir());
+        assert!(!scratch.starts_with(workspace.root()));
+        assert!(workspace.permissions().allow_write.contains(&scratch));
+        drop(workspace);
+        assert!(!scratch.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_policy_cannot_escape_root() {
+        let dir = tempdir().unwrap();
+        let policy = Permissions {
+            allow_write: vec![PathBuf::from("../elsewhere")],
+            ..Permissions::default()
+        };
+        assert!(Workspace::new(dir.path(), "(version 1)\n", policy).is_err());
+    }
+}